@@ -4,6 +4,8 @@ use super::*;
 pub enum ClassItem {
     Value {
         name: SrcNode<Ident>,
+        /// The item's own generic types, nested within those of the class.
+        gen_scope: GenScopeId,
         ty: SrcNode<TyId>,
     },
     Type {
@@ -16,8 +18,12 @@ pub struct Class {
     pub obligations: Option<Vec<SrcNode<Obligation>>>,
     pub attr: Vec<SrcNode<ast::Attr>>,
     pub gen_scope: GenScopeId,
+    /// The kind of the class's members (i.e: `* -> *` for classes of type constructors, like `Functor`).
+    pub kind: Kind,
     pub assoc: Option<Vec<ClassItem>>,
     pub fields: Option<Vec<ClassItem>>,
+    /// Default implementations of fields, used by members that do not provide their own.
+    pub defaults: Option<HashMap<Ident, TyExpr>>,
 }
 
 impl Class {
@@ -38,10 +44,29 @@ impl Class {
             .expect("Class fields must be known here")
             .iter()
             .find_map(|item| match item {
-                ClassItem::Value { name, ty } if **name == field => Some(ty),
+                ClassItem::Value { name, ty, .. } if **name == field => Some(ty),
                 _ => None,
             })
     }
+
+    /// The generic scope of a field's own generic types.
+    pub fn field_gen_scope(&self, field: Ident) -> Option<GenScopeId> {
+        self.fields
+            .as_ref()
+            .expect("Class fields must be known here")
+            .iter()
+            .find_map(|item| match item {
+                ClassItem::Value { name, gen_scope, .. } if **name == field => Some(*gen_scope),
+                _ => None,
+            })
+    }
+
+    pub fn default(&self, field: Ident) -> Option<&TyExpr> {
+        self.defaults
+            .as_ref()
+            .expect("Class defaults must be known here")
+            .get(&field)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -55,11 +80,16 @@ pub struct Lang {
     pub not: Option<ClassId>,
     pub neg: Option<ClassId>,
     pub eq: Option<ClassId>,
+    pub monad: Option<ClassId>,
+    // The classes that may be derived. Programs that derive nothing need not define them.
+    pub ord: Option<ClassId>,
+    pub debug: Option<ClassId>,
+    pub show: Option<ClassId>,
+    pub default: Option<ClassId>,
 }
 
 #[derive(Default)]
 pub struct Classes {
-    lut: HashMap<Ident, (Span, ClassId)>,
     classes: Vec<Class>,
     members: Vec<Member>,
     member_lut: HashMap<ClassId, Vec<MemberId>>,
@@ -75,35 +105,41 @@ impl Classes {
         self.classes.iter().enumerate().map(|(i, class)| (ClassId(i), class))
     }
 
-    pub fn lookup(&self, name: Ident) -> Option<ClassId> {
-        self.lut.get(&name).map(|(_, id)| *id)
-    }
-
-    pub fn declare(&mut self, name: SrcNode<Ident>, class: Class) -> Result<ClassId, Error> {
+    pub fn declare(&mut self, class: Class) -> ClassId {
         let id = ClassId(self.classes.len());
-        let span = name.span();
-        if let Err(old) = self.lut.try_insert(*name, (span, id)) {
-            Err(Error::DuplicateClassName(*name, old.entry.get().0, span))
-        } else {
-            if let Some(lang) = class.attr
-                .iter()
-                .find(|a| &**a.name == "lang")
-                .and_then(|a| a.args.as_ref())
-            {
-                if lang.iter().find(|a| &**a.name == "not").is_some() {
-                    self.lang.not = Some(id);
-                }
-                if lang.iter().find(|a| &**a.name == "neg").is_some() {
-                    self.lang.neg = Some(id);
-                }
-                if lang.iter().find(|a| &**a.name == "eq").is_some() {
-                    self.lang.eq = Some(id);
-                }
+        if let Some(lang) = class.attr
+            .iter()
+            .find(|a| &**a.name == "lang")
+            .and_then(|a| a.args.as_ref())
+        {
+            if lang.iter().find(|a| &**a.name == "not").is_some() {
+                self.lang.not = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "neg").is_some() {
+                self.lang.neg = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "eq").is_some() {
+                self.lang.eq = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "monad").is_some() {
+                self.lang.monad = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "ord").is_some() {
+                self.lang.ord = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "debug").is_some() {
+                self.lang.debug = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "show").is_some() {
+                self.lang.show = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "default").is_some() {
+                self.lang.default = Some(id);
             }
-
-            self.classes.push(class);
-            Ok(id)
         }
+
+        self.classes.push(class);
+        id
     }
 
     pub fn check_lang_items(&self) -> Vec<Error> {
@@ -112,10 +148,26 @@ impl Classes {
         if self.lang.not.is_none() { errors.push(Error::MissingLangItem("not")); }
         if self.lang.neg.is_none() { errors.push(Error::MissingLangItem("neg")); }
         if self.lang.eq.is_none() { errors.push(Error::MissingLangItem("eq")); }
+        if self.lang.monad.is_none() { errors.push(Error::MissingLangItem("monad")); }
 
         errors
     }
 
+    /// Find the class that declares the given field, searching the class itself and then its superclasses.
+    pub fn field_class(&self, class: ClassId, field: Ident) -> Option<ClassId> {
+        if self.get(class).field(field).is_some() {
+            Some(class)
+        } else {
+            self.get(class).obligations
+                .as_ref()
+                .expect("Class obligations must be known here")
+                .iter()
+                .find_map(|obl| match &**obl {
+                    Obligation::MemberOf(class) => self.field_class(*class, field),
+                })
+        }
+    }
+
     pub fn define_obligations(&mut self, id: ClassId, obligations: Vec<SrcNode<Obligation>>) {
         self.classes[id.0].obligations = Some(obligations);
     }
@@ -128,10 +180,23 @@ impl Classes {
         self.classes[id.0].fields = Some(fields);
     }
 
+    pub fn define_defaults(&mut self, id: ClassId, defaults: HashMap<Ident, TyExpr>) {
+        self.classes[id.0].defaults = Some(defaults);
+    }
+
     pub fn get_member(&self, id: MemberId) -> &Member {
         &self.members[id.0]
     }
 
+    /// Find the class that a member was declared for.
+    pub fn member_class(&self, member: MemberId) -> ClassId {
+        self.member_lut
+            .iter()
+            .find(|(_, members)| members.contains(&member))
+            .map(|(class, _)| *class)
+            .expect("Member must belong to a class")
+    }
+
     // TODO: Pre-insert member here so we can do inference inside members themselves
     pub fn declare_member(&mut self, class: ClassId, member: Member) -> MemberId {
         let id = MemberId(self.members.len());
@@ -149,7 +214,52 @@ impl Classes {
         self.members[id.0].fields = Some(fields);
     }
 
-    pub fn lookup_member(&self, hir: &Context, ctx: &ConContext, ty: ConTyId, class: ClassId) -> Option<MemberId> {
+    /// Check that no two members of the same class could ever apply to the same type.
+    ///
+    /// Members are checked when they are declared, so that overlapping members are reported rather than being
+    /// discovered when the program is lowered. Conflicts that involve an orphan member (see [`Classes::is_orphan`]) are
+    /// reported as such, since moving the orphan is usually the fix.
+    pub fn check_coherence(&self, tys: &Types, datas: &Datas) -> Vec<Error> {
+        let mut errors = Vec::new();
+        // Classes are visited in order so that errors are reported in a consistent order
+        let mut classes = self.member_lut.iter().collect::<Vec<_>>();
+        classes.sort_by_key(|(class, _)| **class);
+        for (class, members) in classes {
+            for (i, a) in members.iter().enumerate() {
+                for b in &members[..i] {
+                    let (a, b) = (*a, *b);
+                    let (a_member, b_member) = (self.get_member(a), self.get_member(b));
+                    // Generic types are shared between a member's type and its class parameters
+                    let mut gens = HashMap::new();
+                    if overlaps(tys, (0, a_member.member), (1, b_member.member), &mut gens) && a_member.params
+                        .iter()
+                        .zip(b_member.params.iter())
+                        .all(|(a_param, b_param)| overlaps(tys, (0, *a_param), (1, *b_param), &mut gens))
+                    {
+                        errors.push(if self.is_orphan(tys, datas, *class, a) || self.is_orphan(tys, datas, *class, b) {
+                            Error::OrphanMembers(*class, b, a)
+                        } else {
+                            Error::OverlappingMembers(*class, b, a)
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Determine whether a member is declared in a module that defines neither its class nor the data type at the head of
+    /// its type. Such members are easily written without knowledge of the members that they conflict with.
+    pub fn is_orphan(&self, tys: &Types, datas: &Datas, class: ClassId, member: MemberId) -> bool {
+        let member_src = tys.get_span(self.get_member(member).member).src();
+        let head_src = match tys.get(self.get_member(member).member) {
+            Ty::Data(data, _) => Some(datas.get_data_span(data).src()),
+            _ => None,
+        };
+        self.get(class).name.span().src() != member_src && head_src != Some(member_src)
+    }
+
+    pub fn lookup_member(&self, hir: &Context, ctx: &ConContext, ty: ConTyId, params: &[ConTyId], class: ClassId) -> Option<MemberId> {
         // Returns true if member covers ty
         fn covers(hir: &Context, ctx: &ConContext, member: TyId, ty: ConTyId) -> bool {
             match (hir.tys.get(member), ctx.get_ty(ty)) {
@@ -171,51 +281,42 @@ impl Classes {
                     .into_iter()
                     .zip(y.1.iter())
                     .all(|(x, y)| covers(hir, ctx, x, *y)),
+                (Ty::Data(x, xs), ConTy::DataCon(y, ys)) if x == *y && xs.len() == ys.len() => xs
+                    .into_iter()
+                    .zip(ys.iter())
+                    .all(|(x, y)| covers(hir, ctx, x, *y)),
+                (Ty::ListCon, ConTy::ListCon) => true,
                 _ => false,
             }
         }
 
         self.member_lut
             .get(&class)
-            .and_then(|xs| {
-                let candidates = xs
-                    .iter()
-                    .filter(|m| covers(hir, ctx, self.get_member(**m).member, ty))
-                    .collect::<Vec<_>>();
-
-                assert!(
-                    candidates.len() <= 1,
-                    "Multiple member candidates detected during lowering <{:?} as {:?}>.\n\
-                    This means that incoherence has occurred!\n\
-                    Candidate members:\n{}",
-                    ctx.get_ty(ty),
-                    **self.get(class).name,
-                    candidates
+            // Overlapping members are rejected by `check_coherence`, so there is at most one candidate
+            .and_then(|xs| xs
+                .iter()
+                .find(|m| {
+                    let member = self.get_member(**m);
+                    covers(hir, ctx, member.member, ty) && member.params
                         .iter()
-                        .map(|c| {
-                            let c = self.get_member(**c);
-                            let gen_scope = hir.tys.get_gen_scope(c.gen_scope);
-                            format!(
-                                "- {}member {} of {} (in {})\n",
-                                if gen_scope.len() == 0 {
-                                    String::new()
-                                } else {
-                                    format!("for {} ", (0..gen_scope.len())
-                                        .map(|idx| format!("{}", *gen_scope.get(idx).name))
-                                        .collect::<Vec<_>>()
-                                        .join(", "))
-                                },
-                                hir.tys.display(&hir.datas, &hir.effects, c.member),
-                                **self.get(class).name,
-                                gen_scope.span.src(),
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                        .join("")
-                );
-
-                candidates.first().copied().copied()
+                        .zip(params)
+                        .all(|(member_param, param)| covers(hir, ctx, *member_param, *param))
+                })
+                .copied())
+    }
+
+    /// Find the members of a class that could provide its items for a type, which may still be generic.
+    pub fn candidates<'a>(&'a self, tys: &'a Types, ty: TyId, params: &'a [TyId], class: ClassId) -> impl Iterator<Item = MemberId> + 'a {
+        self.members_of(class)
+            .filter(move |(_, member)| {
+                // Generic types in the member are unrelated to those in the type
+                let mut gens = HashMap::new();
+                overlaps(tys, (0, ty), (1, member.member), &mut gens) && params
+                    .iter()
+                    .zip(member.params.iter())
+                    .all(|(param, member_param)| overlaps(tys, (0, *param), (1, *member_param), &mut gens))
             })
+            .map(|(id, _)| id)
     }
 
     pub fn members_of(&self, class: ClassId) -> impl Iterator<Item = (MemberId, &Member)> {
@@ -228,6 +329,61 @@ impl Classes {
     }
 }
 
+// Determine whether two member types (each tagged with a side, since generic types in different members are unrelated)
+// could be made equal by some choice of their generic types
+fn overlaps(tys: &Types, x: (usize, TyId), y: (usize, TyId), gens: &mut HashMap<(usize, usize), (usize, TyId)>) -> bool {
+    match (tys.get(x.1), tys.get(y.1)) {
+        (Ty::Gen(a, _), Ty::Gen(b, _)) if x.0 == y.0 && a == b => true,
+        (Ty::Gen(a, _), _) => match gens.get(&(x.0, a)).copied() {
+            Some(x) => overlaps(tys, x, y, gens),
+            // A generic type cannot be made equal to a type that contains it
+            None if occurs(tys, (x.0, a), y, gens) => false,
+            None => {
+                gens.insert((x.0, a), y);
+                true
+            },
+        },
+        (_, Ty::Gen(_, _)) => overlaps(tys, y, x, gens),
+        (Ty::Error(_), _) | (_, Ty::Error(_)) => true,
+        (Ty::Prim(a), Ty::Prim(b)) => a == b,
+        (Ty::List(a), Ty::List(b)) => overlaps(tys, (x.0, a), (y.0, b), gens),
+        (Ty::Tuple(xs), Ty::Tuple(ys)) => xs.len() == ys.len() && xs
+            .into_iter()
+            .zip(ys)
+            .all(|(a, b)| overlaps(tys, (x.0, a), (y.0, b), gens)),
+        (Ty::Record(xs), Ty::Record(ys)) => xs.len() == ys.len() && xs
+            .into_iter()
+            .zip(ys)
+            .all(|((a_name, a), (b_name, b))| a_name == b_name && overlaps(tys, (x.0, a), (y.0, b), gens)),
+        (Ty::Func(a_i, a_o), Ty::Func(b_i, b_o)) => overlaps(tys, (x.0, a_i), (y.0, b_i), gens)
+            && overlaps(tys, (x.0, a_o), (y.0, b_o), gens),
+        (Ty::Data(a, xs), Ty::Data(b, ys)) => a == b && xs.len() == ys.len() && xs
+            .into_iter()
+            .zip(ys)
+            .all(|(a, b)| overlaps(tys, (x.0, a), (y.0, b), gens)),
+        // Effects and projections can't be compared structurally, so assume the worst
+        (Ty::Effect(_, _), Ty::Effect(_, _)) => true,
+        (Ty::Assoc(_, _, _), _) | (_, Ty::Assoc(_, _, _)) => true,
+        (Ty::ListCon, Ty::ListCon) => true,
+        // Applied type constructors are opaque, so assume the worst
+        (Ty::App(_, _), _) | (_, Ty::App(_, _)) => true,
+        _ => false,
+    }
+}
+
+// Determine whether a generic type appears within a type, following the generic types that have already been chosen
+fn occurs(tys: &Types, gen: (usize, usize), ty: (usize, TyId), gens: &HashMap<(usize, usize), (usize, TyId)>) -> bool {
+    match tys.get(ty.1) {
+        Ty::Gen(a, _) if (ty.0, a) == gen => true,
+        Ty::Gen(a, _) => gens.get(&(ty.0, a)).map_or(false, |ty| occurs(tys, gen, *ty, gens)),
+        Ty::Error(_) | Ty::Prim(_) | Ty::SelfType | Ty::ListCon | Ty::Effect(_, _) => false,
+        Ty::List(a) | Ty::Assoc(a, _, _) => occurs(tys, gen, (ty.0, a), gens),
+        Ty::Tuple(xs) | Ty::Data(_, xs) => xs.into_iter().any(|a| occurs(tys, gen, (ty.0, a), gens)),
+        Ty::Record(xs) => xs.into_iter().any(|(_, a)| occurs(tys, gen, (ty.0, a), gens)),
+        Ty::Func(a, b) | Ty::App(a, b) => occurs(tys, gen, (ty.0, a), gens) || occurs(tys, gen, (ty.0, b), gens),
+    }
+}
+
 pub enum MemberItem {
     Value {
         name: SrcNode<Ident>,
@@ -243,6 +399,8 @@ pub struct Member {
     pub gen_scope: GenScopeId,
     pub attr: Vec<SrcNode<ast::Attr>>,
     pub member: TyId,
+    /// The types given for the class's type parameters, in the member's generic scope.
+    pub params: Vec<TyId>,
     pub assoc: Option<HashMap<Ident, TyId>>,
     pub fields: Option<HashMap<Ident, TyExpr>>,
 }
@@ -263,3 +421,69 @@ impl Member {
             .get(&field)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tao_syntax::parse_module;
+    use std::ops::Range;
+
+    // Analyse a module, returning the labels of each overlap error, from the conflicting member to the previous one
+    fn overlaps(code: &str) -> Vec<Vec<(String, Range<usize>)>> {
+        let module = match parse_module(code, SrcId::from_path("main.tao")) {
+            (Some(module), errors) if errors.is_empty() => module,
+            (_, errors) => panic!("Failed to parse: {:?}", errors),
+        };
+        let (ctx, errors) = Context::from_module(&module, &[]);
+        errors
+            .into_iter()
+            .filter(|e| matches!(e, Error::OverlappingMembers(_, _, _)))
+            .map(|e| e.diagnostic(&ctx).labels
+                .into_iter()
+                .map(|(span, msg)| (msg, span.range()))
+                .collect())
+            .collect()
+    }
+
+    // The span of the type in the `n`th declaration of a member with that type
+    fn member(code: &str, ty: &str, n: usize) -> Range<usize> {
+        let start = code.match_indices(&format!("member {} of", ty)).nth(n).unwrap().0 + "member ".len();
+        start..start + ty.len()
+    }
+
+    const CLASS: &str = "class Size =\n\t=> size : Self -> Nat\n";
+
+    #[test]
+    fn overlapping_concrete_members() {
+        let code = format!("{}member Nat of Size =\n\t=> size = fn x => x\nmember Nat of Size =\n\t=> size = fn _ => 0", CLASS);
+
+        assert_eq!(overlaps(&code), [[
+            ("Conflicting member".to_string(), member(&code, "Nat", 1)),
+            ("Previous member".to_string(), member(&code, "Nat", 0)),
+        ]]);
+    }
+
+    #[test]
+    fn blanket_member_overlaps_concrete_member() {
+        let code = format!("{}member Nat of Size =\n\t=> size = fn x => x\nfor A member A of Size =\n\t=> size = fn _ => 0", CLASS);
+
+        assert_eq!(overlaps(&code), [[
+            ("Conflicting member".to_string(), member(&code, "A", 0)),
+            ("Previous member".to_string(), member(&code, "Nat", 0)),
+        ]]);
+    }
+
+    #[test]
+    fn distinct_members_do_not_overlap() {
+        let code = format!("{}member Nat of Size =\n\t=> size = fn x => x\nfor A member [A] of Size =\n\t=> size = fn _ => 0", CLASS);
+
+        assert!(overlaps(&code).is_empty());
+    }
+
+    #[test]
+    fn generic_types_do_not_overlap_types_containing_themselves() {
+        let code = format!("{}for A member (A, A) of Size =\n\t=> size = fn _ => 0\nfor B member (B, [B]) of Size =\n\t=> size = fn _ => 1", CLASS);
+
+        assert!(overlaps(&code).is_empty());
+    }
+}
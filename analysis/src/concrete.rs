@@ -11,7 +11,11 @@ pub enum ConTy {
     Record(BTreeMap<Ident, ConTyId>),
     Func(ConTyId, ConTyId),
     Data(ConDataId),
-    Effect(ConEffectId, ConTyId),
+    /// An effect object that may perform any of a set of effects (ordered by declaration).
+    Effect(Vec<ConEffectId>, ConTyId),
+    // Type constructors only exist as the types of class members, so they never have values
+    DataCon(DataId, Vec<ConTyId>),
+    ListCon,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -30,14 +34,26 @@ pub struct ConEffect {
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ConProc {
     Def(DefId, Vec<ConTyId>),
-    Field(ConTyId, MemberId, Ident),
+    // (self type, class parameters, generic types of the field, member, field)
+    Field(ConTyId, Vec<ConTyId>, Vec<ConTyId>, MemberId, Ident),
+    /// An entry point of type `IO A`, unwrapped into the `@ -> (@, A)` function that runs it.
+    RunIo(DefId),
 }
 
 impl fmt::Display for ConProc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ConProc::Def(def, params) => write!(f, "{}<{}>", def.0, params.iter().map(|ty| ty.0.to_string()).collect::<Vec<_>>().join(", ")),
-            ConProc::Field(ty, member, field) => write!(f, "<{} in {}>.{}", ty.0, member.0, field),
+            ConProc::Field(ty, params, args, member, field) => write!(
+                f,
+                "<{} in {}<{}>>.{}<{}>",
+                ty.0,
+                member.0,
+                params.iter().map(|ty| ty.0.to_string()).collect::<Vec<_>>().join(", "),
+                field,
+                args.iter().map(|ty| ty.0.to_string()).collect::<Vec<_>>().join(", "),
+            ),
+            ConProc::RunIo(def) => write!(f, "run_io({}<>)", def.0),
         }
     }
 }
@@ -51,6 +67,8 @@ pub type ConEffectId = Intern<(EffectDeclId, Vec<ConTyId>)>;
 pub struct TyInsts<'a> {
     self_ty: Option<ConTyId>,
     gen: &'a [ConTyId],
+    // The generic types of a class item, which are nested within the generic scope of the class
+    item: Option<(GenScopeId, &'a [ConTyId])>,
 }
 
 pub struct ConData {
@@ -94,9 +112,12 @@ impl ConContext {
             .collect::<Vec<_>>();
         // If no entry point attribute exists, use 'main'
         if entries.is_empty() {
-            entries.extend(hir.defs
-                .lookup(Ident::new("main"))
-                .map(|id| (id, hir.defs.get(id))));
+            entries.extend(hir.modules
+                .get(hir.modules.root())
+                .items
+                .defs
+                .get(&Ident::new("main"))
+                .map(|(_, id)| (*id, hir.defs.get(*id))));
         }
 
         let mut entries = entries.into_iter();
@@ -107,7 +128,14 @@ impl ConContext {
 
             let gen_scope = hir.tys.get_gen_scope(main.gen_scope);
             if gen_scope.len() == 0 {
-                let main_def = Intern::new(ConProc::Def(id, Vec::new()));
+                let is_io = main.body
+                    .as_ref()
+                    .map_or(false, |body| matches!(hir.tys.get(body.meta().1), Ty::Data(data, _) if Some(data) == hir.datas.lang.io));
+                let main_def = if is_io {
+                    Intern::new(ConProc::RunIo(id))
+                } else {
+                    Intern::new(ConProc::Def(id, Vec::new()))
+                };
                 this.lower_proc(hir, main_def);
                 this.entry = Some(main_def);
             } else {
@@ -172,22 +200,31 @@ impl ConContext {
                 .into_iter()
                 .zip(y.1.iter())
                 .for_each(|(x, y)| self.derive_links(hir, x, *y, link_gen)),
+            (Ty::Data(_, xs), ConTy::DataCon(_, ys)) => xs
+                .into_iter()
+                .zip(ys.iter())
+                .for_each(|(x, y)| self.derive_links(hir, x, *y, link_gen)),
+            (Ty::ListCon, ConTy::ListCon) => {},
             (Ty::Effect(x, x_out), ConTy::Effect(y, y_out)) => {
-                self.derive_links_effect(hir, x, *y, link_gen);
+                self.derive_links_effect(hir, x, y, link_gen);
                 self.derive_links(hir, x_out, *y_out, link_gen);
             },
             (x, y) => todo!("{:?}", (x, y)),
         }
     }
 
-    fn derive_links_effect(&self, hir: &Context, member: EffectId, eff: ConEffectId, link_gen: &mut impl FnMut(usize, ConTyId)) {
+    fn derive_links_effect(&self, hir: &Context, member: EffectId, effs: &[ConEffectId], link_gen: &mut impl FnMut(usize, ConTyId)) {
         // TODO: link gen for effects when polymorphic effects are added
-        match (hir.tys.get_effect(member), self.get_effect(eff)) {
-            (Effect::Known(_, xs), _) => xs
+        match hir.tys.get_effect(member) {
+            Effect::Known(xs) => xs
                 .into_iter()
-                .zip(eff.1.iter())
-                .for_each(|(x, y)| self.derive_links(hir, x, *y, link_gen)),
-            (x, y) => todo!("{:?}", (x, y)),
+                .for_each(|(decl, xs)| if let Some(eff) = effs.iter().find(|eff| eff.0 == decl) {
+                    xs
+                        .into_iter()
+                        .zip(eff.1.iter())
+                        .for_each(|(x, y)| self.derive_links(hir, x, *y, link_gen))
+                }),
+            Effect::Error => panic!("Error effect should not exist during concretization"),
         }
     }
 
@@ -209,6 +246,7 @@ impl ConContext {
                     .map(|(name, ty)| (**name, self.lower_ty(hir, *ty, &TyInsts {
                         self_ty: None,
                         gen: &args,
+                        item: None,
                     })))
                     .collect(),
             };
@@ -244,14 +282,31 @@ impl ConContext {
                     .into_iter()
                     .map(|arg| self.lower_ty(hir, arg, ty_insts))
                     .collect::<Vec<_>>();
-                ConTy::Data(self.lower_data(hir, data, &args))
+                self.lower_data_con(hir, data, args)
+            },
+            Ty::App(head, param) => {
+                let param = self.lower_ty(hir, param, ty_insts);
+                let head = self.lower_ty(hir, head, ty_insts);
+                match self.get_ty(head).clone() {
+                    ConTy::DataCon(data, mut args) => {
+                        args.push(param);
+                        self.lower_data_con(hir, data, args)
+                    },
+                    ConTy::ListCon => ConTy::List(param),
+                    _ => panic!("Only type constructors may be applied to type parameters"),
+                }
+            },
+            Ty::ListCon => ConTy::ListCon,
+            Ty::Gen(idx, scope) => return match ty_insts.item {
+                Some((item_scope, item)) if item_scope == scope => item[idx],
+                _ => ty_insts.gen[idx],
             },
-            Ty::Gen(idx, _) => return ty_insts.gen[idx],
             Ty::SelfType => return ty_insts.self_ty.expect("Self type required during concretization but none was provided"),
             Ty::Assoc(ty, class, assoc) => {
                 let self_ty = self.lower_ty(hir, ty, ty_insts);
                 let member = hir.classes
-                    .lookup_member(hir, self, self_ty, class)
+                    // Classes with associated types do not have type parameters
+                    .lookup_member(hir, self, self_ty, &[], class)
                     .map(|m| hir.classes.get_member(m))
                     .expect("Could not select member candidate");
                 let member_gen_scope = hir.tys.get_gen_scope(member.gen_scope);
@@ -268,24 +323,27 @@ impl ConContext {
                 return self.lower_ty(hir, assoc, &TyInsts {
                     self_ty: Some(self_ty),
                     gen: &gen,
+                    item: None,
                 });
             },
-            Ty::Effect(eff, out) => match hir.tys.get_effect(eff) {
-                Effect::Error => panic!("Concretizable effect cannot be an error"),
-                Effect::Known(decl, args) => {
-                    let args = args
-                        .into_iter()
-                        .map(|arg| self.lower_ty(hir, arg, ty_insts))
-                        .collect::<Vec<_>>();
-                    let out = self.lower_ty(hir, out, ty_insts);
-                    ConTy::Effect(Intern::new((decl, args.to_vec())), out)
-                },
+            Ty::Effect(eff, out) => {
+                let effs = self.lower_effect_set(hir, eff, ty_insts);
+                ConTy::Effect(effs, self.lower_ty(hir, out, ty_insts))
             },
         };
 
         self.insert_ty(cty)
     }
 
+    // Data types that are not given all of their parameters are type constructors
+    fn lower_data_con(&mut self, hir: &Context, data: DataId, args: Vec<ConTyId>) -> ConTy {
+        if args.len() < hir.tys.get_gen_scope(hir.datas.data_gen_scope(data)).len() {
+            ConTy::DataCon(data, args)
+        } else {
+            ConTy::Data(self.lower_data(hir, data, &args))
+        }
+    }
+
     // Returns (record_ty, field_ty, number_of_indirections)
     pub fn follow_field_access(&self, hir: &Context, mut ty: ConTyId, field: Ident) -> Option<(ConTyId, ConTyId, usize)> {
         let mut already_seen = Vec::new();
@@ -328,25 +386,67 @@ impl ConContext {
                         .body
                         .as_ref()
                         .unwrap(),
-                    &TyInsts { self_ty: None, gen },
+                    &TyInsts { self_ty: None, gen, item: None },
                 ),
-                ConProc::Field(self_ty, member_id, field) => {
+                ConProc::Field(self_ty, params, item_args, member_id, field) => if let Some(val) = hir.classes
+                    .get_member(*member_id)
+                    .field(*field)
+                {
                     let member = hir.classes.get_member(*member_id);
                     let member_gen_scope = hir.tys.get_gen_scope(member.gen_scope);
 
                     let mut links = HashMap::new();
                     self.derive_links(hir, member.member, *self_ty, &mut |gen_idx, ty| { links.insert(gen_idx, ty); });
+                    for (member_param, param) in member.params.iter().zip(params) {
+                        self.derive_links(hir, *member_param, *param, &mut |gen_idx, ty| { links.insert(gen_idx, ty); });
+                    }
                     let gen = (0..member_gen_scope.len())
                         .map(|idx| *links.get(&idx).expect("Generic type not mentioned in member"))
                         .collect::<Vec<_>>();
+                    let item_gen_scope = hir.classes
+                        .get(hir.classes.member_class(*member_id))
+                        .field_gen_scope(*field)
+                        .expect("Member field must exist in class");
+                    self.lower_expr(hir, val, &TyInsts {
+                        self_ty: Some(*self_ty),
+                        gen: &gen,
+                        item: Some((item_gen_scope, item_args)),
+                    })
+                } else {
+                    // The member does not provide the field, so the class's default is used instead. Defaults are
+                    // written in terms of the class's own type parameters.
+                    let class = hir.classes.get(hir.classes.member_class(*member_id));
+                    let item_gen_scope = class.field_gen_scope(*field).expect("Member field must exist in class");
                     self.lower_expr(
                         hir,
-                        member
-                            .field(*field)
-                            .unwrap(),
-                        &TyInsts { self_ty: Some(*self_ty), gen: &gen },
+                        class
+                            .default(*field)
+                            .expect("Member field has no default"),
+                        &TyInsts { self_ty: Some(*self_ty), gen: params, item: Some((item_gen_scope, item_args)) },
                     )
                 },
+                ConProc::RunIo(def) => {
+                    let io = Intern::new(ConProc::Def(*def, Vec::new()));
+                    self.lower_proc(hir, io);
+                    let io_ty = *self.get_proc(io).meta();
+                    let ConTy::Data(io_data) = self.get_ty(io_ty) else { unreachable!() };
+                    let io_data = *io_data;
+                    let (cons, run_ty) = self.get_data(io_data).cons[0];
+
+                    // match main in IO run => run
+                    let span = hir.defs.get(*def).name.span();
+                    let run = SrcNode::new(Ident::new("run"), span);
+                    let inner = ConNode::new(hir::Binding::wildcard(run.clone()), run_ty);
+                    let binding = ConNode::new(
+                        hir::Binding::from_pat(SrcNode::new(hir::Pat::Decons(io_data, cons, inner), span)),
+                        io_ty,
+                    );
+                    ConNode::new(hir::Expr::Match(
+                        false,
+                        ConNode::new(hir::Expr::Global(io), io_ty),
+                        vec![(binding, ConNode::new(hir::Expr::Local(*run), run_ty))],
+                    ), run_ty)
+                },
             };
             self.procs.insert(proc, Some(body));
         }
@@ -455,11 +555,20 @@ impl ConContext {
             },
             hir::Expr::ClassAccess(ty, class, field) => {
                 let self_ty = self.lower_ty(hir, ty.1, ty_insts);
+                let (class, params, item_args) = class.as_ref().expect("Uninferred class during concretization");
+                let params = params
+                    .iter()
+                    .map(|param| self.lower_ty(hir, *param, ty_insts))
+                    .collect::<Vec<_>>();
+                let item_args = item_args
+                    .iter()
+                    .map(|arg| self.lower_ty(hir, *arg, ty_insts))
+                    .collect::<Vec<_>>();
                 let member_id = hir.classes
-                    .lookup_member(hir, self, self_ty, class.expect("Uninferred class during concretization"))
+                    .lookup_member(hir, self, self_ty, &params, *class)
                     .expect("Could not select member candidate");
 
-                let id = Intern::new(ConProc::Field(self_ty, member_id, **field));
+                let id = Intern::new(ConProc::Field(self_ty, params, item_args, member_id, **field));
                 self.lower_proc(hir, id);
                 hir::Expr::Global(id)
             },
@@ -472,36 +581,60 @@ impl ConContext {
                 .map(|(name, field)| (name.clone(), self.lower_expr(hir, field, ty_insts)))
                 .collect()),
             hir::Expr::Basin(eff, inner) => hir::Expr::Basin(
-                self.lower_effect(hir, *eff, ty_insts),
+                self.lower_effect_set(hir, *eff, ty_insts),
                 self.lower_expr(hir, inner, ty_insts),
             ),
             hir::Expr::Suspend(eff, inner) => hir::Expr::Suspend(
                 self.lower_effect(hir, *eff, ty_insts),
                 self.lower_expr(hir, inner, ty_insts),
             ),
-            hir::Expr::Handle { expr, eff, send, recv } => hir::Expr::Handle {
+            hir::Expr::Handle { expr, eff, send, recv, resumable } => hir::Expr::Handle {
                 expr: self.lower_expr(hir, expr, ty_insts),
                 eff: self.lower_effect(hir, *eff, ty_insts),
                 send: ConNode::new(**send, self.lower_ty(hir, send.meta().1, ty_insts)),
                 recv: self.lower_expr(hir, recv, ty_insts),
+                resumable: *resumable,
             },
         };
 
         ConNode::new(expr, self.lower_ty(hir, ty_expr.meta().1, ty_insts))
     }
 
+    // Effects that are suspended or handled are always single effects, not sets
     pub fn lower_effect(&mut self, hir: &Context, eff: EffectId, ty_insts: &TyInsts) -> ConEffectId {
-        let (decl, args) = match hir.tys.get_effect(eff) {
+        match self.lower_effect_set(hir, eff, ty_insts).as_slice() {
+            [eff] => *eff,
+            effs => panic!("Expected a single effect, found {} effects", effs.len()),
+        }
+    }
+
+    pub fn lower_effect_set(&mut self, hir: &Context, eff: EffectId, ty_insts: &TyInsts) -> Vec<ConEffectId> {
+        match hir.tys.get_effect(eff) {
             Effect::Error => panic!("Error effect should not exist during concretization"),
-            Effect::Known(decl, args) => (decl, args
-                .into_iter()
-                .map(|arg| self.lower_ty(hir, arg, ty_insts))
-                .collect::<Vec<_>>()),
-        };
+            Effect::Known(effs) => {
+                let mut effs = effs
+                    .into_iter()
+                    .map(|(decl, args)| {
+                        let args = args
+                            .into_iter()
+                            .map(|arg| self.lower_ty(hir, arg, ty_insts))
+                            .collect::<Vec<_>>();
+                        self.lower_effect_inst(hir, decl, args)
+                    })
+                    .collect::<Vec<_>>();
+                // Sets are interned, so equal sets must be lowered to the same representation
+                effs.sort_by_key(|eff| eff.0);
+                effs.dedup();
+                effs
+            },
+        }
+    }
+
+    fn lower_effect_inst(&mut self, hir: &Context, decl: EffectDeclId, args: Vec<ConTyId>) -> ConEffectId {
         let id = Intern::new((decl, args.clone()));
         if !self.effects.contains_key(&id) {
             let decl = hir.effects.get_decl(decl);
-            let ty_insts = TyInsts { self_ty: None, gen: &args };
+            let ty_insts = TyInsts { self_ty: None, gen: &args, item: None };
             let eff = ConEffect {
                 send: self.lower_ty(hir, decl.send.unwrap(), &ty_insts),
                 recv: self.lower_ty(hir, decl.recv.unwrap(), &ty_insts),
@@ -562,10 +695,23 @@ impl<'a> fmt::Display for ConTyDisplay<'a> {
                 .iter()
                 .map(|param| format!(" {}", self.with_ty(*param, true)))
                 .collect::<String>()),
-            ConTy::Effect(eff_id, out) => write!(f, "{}{} ~ {}", *self.effects.get_decl(eff_id.0).name, eff_id.1
+            ConTy::Effect(effs, out) => write!(f, "{} ~ {}", effs
+                .iter()
+                .map(|eff| format!("{}{}", *self.effects.get_decl(eff.0).name, eff.1
+                    .iter()
+                    .map(|param| format!(" {}", self.with_ty(*param, true)))
+                    .collect::<String>()))
+                .collect::<Vec<_>>()
+                .join(" + "), self.with_ty(out, true)),
+            ConTy::DataCon(data, args) if self.lhs_exposed && args.len() > 0 => write!(f, "({}{})", *self.datas.get_data(data).name, args
+                .iter()
+                .map(|param| format!(" {}", self.with_ty(*param, true)))
+                .collect::<String>()),
+            ConTy::DataCon(data, args) => write!(f, "{}{}", *self.datas.get_data(data).name, args
                 .iter()
                 .map(|param| format!(" {}", self.with_ty(*param, true)))
-                .collect::<String>(), self.with_ty(out, true)),
+                .collect::<String>()),
+            ConTy::ListCon => write!(f, "[]"),
         }
     }
 }
@@ -1,6 +1,7 @@
 pub use super::*;
 
 pub struct Context {
+    pub modules: Modules,
     pub classes: Classes,
     pub datas: Datas,
     pub tys: Types,
@@ -11,148 +12,251 @@ pub struct Context {
 }
 
 impl Context {
-    pub fn from_module(module: &SrcNode<ast::Module>) -> (Self, Vec<Error>) {
+    /// Create a context from the root module of a program and the (transitive) imports that it depends on.
+    pub fn from_module(root: &SrcNode<ast::Module>, imports: &[SrcNode<ast::Module>]) -> (Self, Vec<Error>) {
         let mut this = Self {
+            modules: Modules::default(),
             classes: Classes::default(),
             datas: Datas::default(),
             tys: Types::default(),
             defs: Defs::default(),
             effects: Effects::default(),
             errors: Vec::default(),
-            root_span: module.span(),
+            root_span: root.span(),
         };
 
         let mut errors = Vec::new();
 
+        // The root module is always declared first
+        let modules = std::iter::once(root)
+            .chain(imports)
+            .map(|module| (this.modules.declare_module(module.span().src()), module))
+            .collect::<Vec<_>>();
+
         let mut classes = Vec::new();
         let mut aliases = Vec::new();
         let mut datas = Vec::new();
         let mut effects = Vec::new();
+        let mut effect_aliases = Vec::new();
         let mut members_init = Vec::new();
         let mut defs_init = Vec::new();
+        let mut item_gen_scopes = HashMap::new();
         // Declare items before declaration
-        for (attr, class) in module.classes() {
-            let (gen_scope, mut errs) = GenScope::from_ast(&class.generics);
-            if gen_scope.len() != 0 {
-                errors.push(Error::Unsupported(class.generics.span(), "type parameters on classes"));
-                continue;
+        for (module_id, module) in &modules {
+            let module_id = *module_id;
+            for (attr, vis, class) in module.classes() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &class.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                // Class items may have generic types of their own, in addition to those of the class
+                let item_scopes = class.items
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, item)| match item {
+                        ast::ClassItem::Value { generics, .. } => {
+                            let (item_gen_scope, mut errs) = GenScope::from_ast(module_id, generics);
+                            errors.append(&mut errs);
+                            Some((idx, this.tys.insert_gen_scope(item_gen_scope.with_parent(gen_scope))))
+                        },
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                match this.modules.declare(module_id, vis, &class.name, |ns| &mut ns.classes, || this.classes.declare(Class {
+                    name: class.name.clone(),
+                    obligations: None,
+                    attr: attr.to_vec(),
+                    gen_scope,
+                    kind: class.kind.as_ref().map_or(Kind::Type, |kind| Kind::from_ast(kind)),
+                    fields: None,
+                    assoc: None,
+                    defaults: None,
+                })) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateClassName(*class.name, old, class.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(class_id) => {
+                        for (idx, item_gen_scope) in item_scopes {
+                            item_gen_scopes.insert((class_id, idx), item_gen_scope);
+                        }
+                        classes.push((module_id, attr, class, class_id, gen_scope))
+                    },
+                }
             }
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            match this.classes.declare(class.name.clone(), Class {
-                name: class.name.clone(),
-                obligations: None,
-                attr: attr.to_vec(),
-                gen_scope,
-                fields: None,
-                assoc: None,
-            }) {
-                Err(err) => {
-                    errors.push(err);
-                    continue;
-                },
-                // Only mark for further processing if no errors occurred during declaration
-                Ok(class_id) => classes.push((attr, class, class_id, gen_scope)),
+            for (attr, vis, eff) in module.effects() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &eff.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                match this.modules.declare(module_id, vis, &eff.name, |ns| &mut ns.effects, || this.effects.declare(EffectDecl {
+                    name: eff.name.clone(),
+                    attr: attr.to_vec(),
+                    gen_scope,
+                    send: None,
+                    recv: None,
+                })) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateEffectDecl(*eff.name, old, eff.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(eff_id) => effects.push((module_id, attr, eff, eff_id, gen_scope)),
+                }
             }
-        }
-        for (attr, eff) in module.effects() {
-            let (gen_scope, mut errs) = GenScope::from_ast(&eff.generics);
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            match this.effects.declare(EffectDecl {
-                name: eff.name.clone(),
-                attr: attr.to_vec(),
-                gen_scope,
-                send: None,
-                recv: None,
-            }) {
-                Err(err) => {
-                    errors.push(err);
-                    continue;
-                },
-                // Only mark for further processing if no errors occurred during declaration
-                Ok(eff_id) => effects.push((attr, eff, eff_id, gen_scope)),
+            for (attr, vis, alias) in module.effect_aliases() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &alias.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                match this.modules.declare(module_id, vis, &alias.name, |ns| &mut ns.effects, || this.effects.declare_alias(EffectAlias {
+                    name: alias.name.clone(),
+                    attr: attr.to_vec(),
+                    gen_scope,
+                    effects: None,
+                })) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateEffectDecl(*alias.name, old, alias.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(alias_id) => effect_aliases.push((module_id, alias, alias_id, gen_scope)),
+                }
             }
-        }
-        for (attr, alias) in module.aliases() {
-            let (gen_scope, mut errs) = GenScope::from_ast(&alias.generics);
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            if let Err(err) = this.datas.declare_alias(*alias.name, alias.name.span(), gen_scope) {
-                errors.push(err);
-                continue;
-            } else {
-                // Only mark for further processing if no errors occurred during declaration
-                aliases.push((attr, alias));
+            for (attr, vis, alias) in module.aliases() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &alias.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                match this.modules.declare(module_id, vis, &alias.name, |ns| &mut ns.types, || this.datas.declare_alias(alias.name.span(), gen_scope)) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateTypeName(*alias.name, old, alias.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(alias_id) => aliases.push((module_id, attr, alias, alias_id)),
+                }
             }
-        }
-        for (attr, data) in module.datas() {
-            let (gen_scope, mut errs) = GenScope::from_ast(&data.generics);
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            if let Err(err) = this.datas.declare_data(data.name.clone(), gen_scope, &attr) {
-                errors.push(err);
-                continue;
-            } else {
-                // Only mark for further processing if no errors occurred during declaration
-                datas.push((attr, data));
+            for (attr, vis, data) in module.datas() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &data.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                match this.modules.declare(module_id, vis, &data.name, |ns| &mut ns.types, || this.datas.declare_data(data.name.span(), gen_scope, &attr)) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateTypeName(*data.name, old, data.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(data_id) => {
+                        // Constructors share the visibility of their data type
+                        for (cons, _) in &data.variants {
+                            if let Err(old) = this.modules.declare(module_id, vis, cons, |ns| &mut ns.cons, || (data_id, **cons)) {
+                                errors.push(Error::DuplicateConsName(**cons, old, cons.span()));
+                            }
+                        }
+                        datas.push((module_id, attr, data, data_id));
+                    },
+                }
+            }
+            for (attr, vis, def) in module.defs() {
+                let (gen_scope, mut errs) = GenScope::from_ast(module_id, &def.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                match this.modules.declare(module_id, vis, &def.name, |ns| &mut ns.defs, || this.defs.declare(Def {
+                    name: def.name.clone(),
+                    attr: attr.to_vec(),
+                    gen_scope,
+                    ty_hint: None,
+                    body: None,
+                })) {
+                    Err(old) => {
+                        errors.push(Error::DuplicateDefName(*def.name, old, def.name.span()));
+                        continue;
+                    },
+                    // Only mark for further processing if no errors occurred during declaration
+                    Ok(def_id) => defs_init.push((module_id, def, def_id, gen_scope)),
+                }
             }
         }
-        for (attr, member) in module.members() {
-            let class_id = if let Some(class_id) = this.classes.lookup(*member.class.name) {
-                class_id
-            } else {
-                errors.push(Error::NoSuchClass(member.class.name.clone()));
-                continue;
-            };
 
-            let (gen_scope, mut errs) = GenScope::from_ast(&member.generics);
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            members_init.push((attr, member, class_id, gen_scope));
+        // Now that every module's items are declared, imports can be resolved
+        let module_imports = modules
+            .iter()
+            .map(|(_, module)| module.imports.iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        errors.append(&mut this.modules.resolve_imports(&module_imports));
+
+        // Members derived with `$[derive(...)]` are declared alongside the module's own members
+        let mut derived = Vec::new();
+        for (_, module) in &modules {
+            let mut members = Vec::new();
+            for (attr, _, data) in module.datas() {
+                let (mut data_members, mut errs) = derive::derive_members(&this, attr, data);
+                errors.append(&mut errs);
+                members.append(&mut data_members);
+            }
+            derived.push(members);
         }
-        for (attr, def) in module.defs() {
-            let (gen_scope, mut errs) = GenScope::from_ast(&def.generics);
-            errors.append(&mut errs);
-            let gen_scope = this.tys.insert_gen_scope(gen_scope);
-            defs_init.push((attr, def, gen_scope));
+
+        for ((module_id, module), derived) in modules.iter().zip(&derived) {
+            let no_attrs: &[SrcNode<ast::Attr>] = &[];
+            for (attr, member) in module.members().chain(derived.iter().map(|member| (no_attrs, member))) {
+                let class_id = if let Some(class_id) = this.modules.lookup_class(*module_id, &member.class.name) {
+                    class_id
+                } else {
+                    errors.push(Error::NoSuchClass(member.class.name.clone()));
+                    continue;
+                };
+
+                let (gen_scope, mut errs) = GenScope::from_ast(*module_id, &member.generics);
+                errors.append(&mut errs);
+                let gen_scope = this.tys.insert_gen_scope(gen_scope);
+                members_init.push((*module_id, attr, member, class_id, gen_scope));
+            }
         }
 
         // Now that we have declarations for all classes and data types, we can check generic scope constraints
-        let mut gen_scope_errors = this.tys.check_gen_scopes(&this.classes);
+        let mut gen_scope_errors = this.tys.check_gen_scopes(&this.modules, &this.classes);
         this.errors.append(&mut gen_scope_errors);
 
         // Derive class obligations
-        for (attr, class, class_id, gen_scope) in &classes {
-            this.classes.define_obligations(
-                *class_id,
-                class
-                    .obligation
-                    .iter()
-                    .filter_map(|obl| {
-                        if !obl.params.is_empty() {
-                            errors.push(Error::Unsupported(obl.span(), "type parameters on classes"));
-                        }
-                        match this.classes.lookup(*obl.name) {
-                            Some(class) => Some(SrcNode::new(Obligation::MemberOf(class), obl.span())),
-                            None => {
-                                errors.push(Error::NoSuchClass(obl.name.clone()));
-                                None
-                            },
-                        }
-                    })
-                    .collect(),
-            );
+        for (module_id, attr, class, class_id, gen_scope) in &classes {
+            let obligations = class
+                .obligation
+                .iter()
+                .filter_map(|obl| {
+                    match this.modules.lookup_class(*module_id, &obl.name) {
+                        Some(class) if !obl.params.is_empty() || this.tys
+                            .get_gen_scope(this.classes.get(class).gen_scope)
+                            .len() != 0 => {
+                            errors.push(Error::Unsupported(obl.span(), "obligations on classes with type parameters"));
+                            None
+                        },
+                        Some(obl_class) if this.classes.get(obl_class).kind != this.classes.get(*class_id).kind => {
+                            errors.push(Error::KindMismatch(
+                                class.name.span(),
+                                this.classes.get(*class_id).kind.clone(),
+                                obl.span(),
+                                this.classes.get(obl_class).kind.clone(),
+                            ));
+                            None
+                        },
+                        Some(class) => Some(SrcNode::new(Obligation::MemberOf(class), obl.span())),
+                        None => {
+                            errors.push(Error::NoSuchClass(obl.name.clone()));
+                            None
+                        },
+                    }
+                })
+                .collect();
+            this.classes.define_obligations(*class_id, obligations);
         }
 
         // Alias definition must go before members and defs because they might have type hints that make use of type
         // aliases
-        for (attr, alias) in aliases {
-            let gen_scope = this.datas.name_gen_scope(*alias.name);
+        for (module_id, attr, alias, alias_id) in aliases {
+            let gen_scope = this.datas.alias_gen_scope(alias_id);
 
             let mut infer = Infer::new(&mut this, Some(gen_scope));
 
-            let ty = alias.ty.to_hir(&mut infer, &Scope::Empty);
+            let ty = alias.ty.to_hir(&mut infer, &Scope::Module(module_id));
 
             let (mut checked, mut errs) = infer.into_checked();
             errors.append(&mut errs);
@@ -160,9 +264,7 @@ impl Context {
             let ty = checked.reify(ty.meta().1);
 
             this.datas.define_alias(
-                this.datas
-                    .lookup_alias(*alias.name)
-                    .expect("Alias must be pre-declared before definition"),
+                alias_id,
                 Alias {
                     name: *alias.name,
                     attr: attr.to_vec(),
@@ -172,11 +274,11 @@ impl Context {
             );
         }
 
-        for (attr, eff, eff_id, gen_scope) in effects {
+        for (module_id, attr, eff, eff_id, gen_scope) in effects {
             let mut infer = Infer::new(&mut this, Some(gen_scope));
 
-            let send = eff.send.to_hir(&mut infer, &Scope::Empty);
-            let recv = eff.recv.to_hir(&mut infer, &Scope::Empty);
+            let send = eff.send.to_hir(&mut infer, &Scope::Module(module_id));
+            let recv = eff.recv.to_hir(&mut infer, &Scope::Module(module_id));
 
             let (mut checked, mut errs) = infer.into_checked();
             errors.append(&mut errs);
@@ -187,13 +289,38 @@ impl Context {
             this.effects.define_send_recv(eff_id, send, recv);
         }
 
+        // Effect aliases are defined in order, so an alias may only refer to those that come before it
+        for (module_id, alias, alias_id, gen_scope) in effect_aliases {
+            let mut infer = Infer::new(&mut this, Some(gen_scope));
+
+            let effs = lower_effect_set(&alias.effects, &mut infer, &Scope::Module(module_id));
+
+            let (mut checked, mut errs) = infer.into_checked();
+            errors.append(&mut errs);
+
+            if let Ok(effs) = effs {
+                let effs = effs
+                    .into_iter()
+                    .map(|(decl, args)| (decl, args
+                        .into_iter()
+                        .map(|arg| checked.reify(arg))
+                        .collect()))
+                    .collect();
+                this.effects.define_alias_effects(alias_id, effs);
+            }
+        }
+
         // Class associated types
-        for (attr, class, class_id, gen_scope) in &classes {
+        for (module_id, attr, class, class_id, gen_scope) in &classes {
             let mut existing_tys = HashMap::new();
+            let has_params = this.tys.get_gen_scope(*gen_scope).len() != 0;
             let assoc = class.items
                 .iter()
                 .filter_map(|item| match item {
                     ast::ClassItem::Type { name, obligations } => {
+                        if has_params {
+                            errors.push(Error::Unsupported(name.span(), "associated types on classes with type parameters"));
+                        }
                         if !obligations.is_empty() {
                             errors.push(Error::Unsupported(obligations.span(), "obligations on associated types"));
                         }
@@ -211,17 +338,93 @@ impl Context {
                 .collect::<Vec<_>>();
             this.classes.define_assoc(*class_id, assoc);
         }
+        // Where clauses are lowered after associated types are declared, since they often constrain associated types
+        let where_clauses = defs_init
+            .iter()
+            .map(|(module_id, def, _, gen_scope)| (*module_id, &def.generics, *gen_scope))
+            .chain(members_init
+                .iter()
+                .map(|(module_id, _, member, _, gen_scope)| (*module_id, &member.generics, *gen_scope)))
+            .chain(datas
+                .iter()
+                .map(|(module_id, _, data, data_id)| (*module_id, &data.generics, this.datas.data_gen_scope(*data_id))))
+            .chain(classes
+                .iter()
+                .flat_map(|(module_id, _, class, class_id, _)| class.items
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, item)| match item {
+                        ast::ClassItem::Value { generics, .. } => Some((*module_id, generics, item_gen_scopes[&(*class_id, idx)])),
+                        _ => None,
+                    })))
+            .filter(|(_, generics, _)| !generics.preds.is_empty())
+            .collect::<Vec<_>>();
+        for (module_id, generics, gen_scope) in where_clauses {
+            let mut infer = Infer::new(&mut this, Some(gen_scope));
+
+            let preds = generics.preds
+                .iter()
+                .map(|pred| {
+                    // The kind of the type is checked against the kind of each class below
+                    let ty = pred.ty.to_hir_of_kind(&mut infer, &Scope::Module(module_id), None);
+                    let ty_kind = infer.kind_of(ty.meta().1);
+                    let obligations = pred.obligations
+                        .iter()
+                        .filter_map(|obl| match infer.ctx().modules.lookup_class(module_id, &obl.name) {
+                            Some(class) if !obl.params.is_empty() || infer.ctx().tys
+                                .get_gen_scope(infer.ctx().classes.get(class).gen_scope)
+                                .len() != 0 => {
+                                errors.push(Error::Unsupported(obl.span(), "obligations on classes with type parameters"));
+                                None
+                            },
+                            Some(class) if ty_kind.as_ref().map_or(false, |kind| kind != &infer.ctx().classes.get(class).kind) => {
+                                errors.push(Error::KindMismatch(
+                                    pred.ty.span(),
+                                    ty_kind.clone().unwrap(),
+                                    obl.span(),
+                                    infer.ctx().classes.get(class).kind.clone(),
+                                ));
+                                None
+                            },
+                            Some(class) => Some(SrcNode::new(Obligation::MemberOf(class), obl.span())),
+                            None => {
+                                errors.push(Error::NoSuchClass(obl.name.clone()));
+                                None
+                            },
+                        })
+                        .collect::<Vec<_>>();
+                    (ty, obligations)
+                })
+                .collect::<Vec<_>>();
+
+            let (mut checked, mut errs) = infer.into_checked();
+            errors.append(&mut errs);
+
+            let preds = preds
+                .into_iter()
+                .flat_map(|(ty, obligations)| {
+                    let ty = checked.reify(ty.meta().1);
+                    obligations
+                        .into_iter()
+                        .map(move |obl| (ty, obl))
+                })
+                .collect();
+            this.tys.define_where_preds(gen_scope, preds);
+        }
+
         // Class fields
-        for (attr, class, class_id, gen_scope) in &classes {
+        for (module_id, attr, class, class_id, gen_scope) in &classes {
             let mut existing_fields = HashMap::new();
             let fields = class.items
                 .iter()
-                .filter_map(|item| match item {
-                    ast::ClassItem::Value { name, ty } => {
-                        let mut infer = Infer::new(&mut this, Some(*gen_scope))
+                .enumerate()
+                .filter_map(|(idx, item)| match item {
+                    ast::ClassItem::Value { name, ty, .. } => {
+                        let item_gen_scope = item_gen_scopes[&(*class_id, idx)];
+                        let mut infer = Infer::new(&mut this, Some(item_gen_scope))
                             .with_unknown_self(class.name.span(), vec![*class_id]);
 
-                        let ty = ty.to_hir(&mut infer, &Scope::Empty);
+                        let ty = ty.to_hir(&mut infer, &Scope::Module(*module_id));
 
                         let (mut checked, mut errs) = infer.into_checked();
                         errors.append(&mut errs);
@@ -234,6 +437,7 @@ impl Context {
                             existing_fields.insert(**name, name.span());
                             Some(ClassItem::Value {
                                 name: name.clone(),
+                                gen_scope: item_gen_scope,
                                 ty: SrcNode::new(checked.reify(ty.meta().1), ty.meta().0),
                             })
                         }
@@ -245,10 +449,10 @@ impl Context {
         }
 
         let mut members = Vec::new();
-        for (attr, member, class_id, gen_scope) in members_init {
+        for (module_id, attr, member, class_id, gen_scope) in members_init {
             let mut infer = Infer::new(&mut this, Some(gen_scope));
 
-            let member_ty = member.member.to_hir(&mut infer, &Scope::Empty);
+            let (member_ty, params) = lower::member_to_hir(&mut infer, &Scope::Module(module_id), member, class_id);
             // for obl in infer.ctx().classes.get(class_id).obligations.clone().expect("Obligations must be known") {
             //     match obl.inner() {
             //         Obligation::MemberOf(class) => infer.make_impl(member_ty.meta().1, *class, obl.span(), Vec::new()),
@@ -259,24 +463,54 @@ impl Context {
             errors.append(&mut errs);
 
             let member_ty = checked.reify(member_ty.meta().1);
+            let params = params
+                .into_iter()
+                .map(|param| checked.reify(param.meta().1))
+                .collect::<Vec<_>>();
+
+            let class_gen_scope = this.tys.get_gen_scope(this.classes.get(class_id).gen_scope);
+            if params.len() != class_gen_scope.len() {
+                errors.push(Error::WrongNumberOfGenerics(
+                    member.class.span(),
+                    params.len(),
+                    if class_gen_scope.len() == 0 {
+                        this.classes.get(class_id).name.span()
+                    } else {
+                        class_gen_scope.span
+                    },
+                    class_gen_scope.len(),
+                ));
+                continue;
+            }
 
             let member_id = this.classes.declare_member(class_id, Member {
                 gen_scope,
                 attr: attr.to_vec(),
                 member: member_ty,
+                params,
                 fields: None,
                 assoc: None,
             });
-            members.push((member, class_id, member_id, gen_scope));
+            members.push((module_id, member, class_id, member_id, gen_scope));
         }
+        errors.append(&mut this.classes.check_coherence(&this.tys, &this.datas));
         // Member obligations
-        for (member, class_id, member_id, gen_scope) in &members {
+        for (module_id, member, class_id, member_id, gen_scope) in &members {
             let mut infer = Infer::new(&mut this, Some(*gen_scope));
 
-            let member_ty = member.member.to_hir(&mut infer, &Scope::Empty);
+            let (member_ty, params) = lower::member_to_hir(&mut infer, &Scope::Module(*module_id), member, *class_id);
             for obl in infer.ctx().classes.get(*class_id).obligations.clone().expect("Obligations must be known") {
                 match obl.inner() {
-                    Obligation::MemberOf(class) => infer.make_impl(member_ty.meta().1, *class, obl.span(), Vec::new(), member_ty.meta().0),
+                    Obligation::MemberOf(class) => infer.make_impl(member_ty.meta().1, *class, Vec::new(), obl.span(), Vec::new(), member_ty.meta().0),
+                }
+            }
+            // The class parameters must fulfil the obligations of the class's generic types
+            let class_gen_scope = infer.ctx().classes.get(*class_id).gen_scope;
+            for (idx, param) in params.into_iter().enumerate() {
+                for obl in infer.ctx().tys.get_gen_scope(class_gen_scope).get(idx).obligations().to_vec() {
+                    match obl.inner() {
+                        Obligation::MemberOf(class) => infer.make_impl(param.meta().1, *class, Vec::new(), obl.span(), Vec::new(), param.meta().0),
+                    }
                 }
             }
 
@@ -284,7 +518,7 @@ impl Context {
             errors.append(&mut errs);
         }
         // Member associated types
-        for (member, class_id, member_id, gen_scope) in &members {
+        for (module_id, member, class_id, member_id, gen_scope) in &members {
             let assoc = member.items
                 .iter()
                 .filter_map(|item| {
@@ -299,7 +533,7 @@ impl Context {
                             errors.push(Error::NoSuchClassItem(name.clone(), class.name.clone()));
                             None
                         } else {
-                            let ty = ty.to_hir(&mut infer, &Scope::Empty);
+                            let ty = ty.to_hir(&mut infer, &Scope::Module(*module_id));
 
                             let (mut checked, mut errs) = infer.into_checked();
                             errors.append(&mut errs);
@@ -338,50 +572,33 @@ impl Context {
 
             this.classes.define_member_assoc(*member_id, *class_id, assoc);
         }
-        let mut defs = Vec::new();
-        for (attr, def, gen_scope) in defs_init {
+        for (module_id, def, def_id, gen_scope) in &defs_init {
             // If the type hint is fully specified, check it
-            let ty_hint = if def.ty_hint.is_fully_specified() {
-                let mut infer = Infer::new(&mut this, Some(gen_scope));
-                let ty_hint = def.ty_hint.to_hir(&mut infer, &Scope::Empty);
+            if def.ty_hint.is_fully_specified() {
+                let mut infer = Infer::new(&mut this, Some(*gen_scope));
+                let ty_hint = def.ty_hint.to_hir(&mut infer, &Scope::Module(*module_id));
 
                 let (mut checked, mut errs) = infer.into_checked();
                 errors.append(&mut errs);
 
-                Some(checked.reify(ty_hint.meta().1))
-            } else {
-                None
-            };
-
-            if let Err(err) = this.defs.declare(Def {
-                name: def.name.clone(),
-                attr: attr.to_vec(),
-                gen_scope,
-                ty_hint,
-                body: None,
-            }) {
-                errors.push(err);
-                continue;
-            } else {
-                // Only mark for further processing if no errors occurred during declaration
-                defs.push((attr, def));
+                let ty_hint = checked.reify(ty_hint.meta().1);
+                this.defs.define_ty_hint(*def_id, ty_hint);
             }
         }
 
         // Check for lang items
         this.errors.append(&mut this.classes.check_lang_items());
         this.errors.append(&mut this.datas.check_lang_items());
-        this.errors.append(&mut this.defs.check_lang_items());
 
         // Define datas
-        for (attr, data) in datas {
-            let gen_scope = this.datas.name_gen_scope(*data.name);
+        for (module_id, attr, data, data_id) in datas {
+            let gen_scope = this.datas.data_gen_scope(data_id);
 
             let mut infer = Infer::new(&mut this, Some(gen_scope));
             let variants = data.variants
                 .iter()
                 .map(|(name, ty)| {
-                    let ty = ty.to_hir(&mut infer, &Scope::Empty);
+                    let ty = ty.to_hir(&mut infer, &Scope::Module(module_id));
                     (name.clone(), ty)
                 })
                 .collect::<Vec<_>>();
@@ -394,23 +611,41 @@ impl Context {
                 .map(|(name, ty)| (name, checked.reify(ty.meta().1)))
                 .collect();
 
-            if let Err(mut errs) = this.datas.define_data(
-                this.datas
-                    .lookup_data(*data.name)
-                    .expect("Data must be pre-declared before definition"),
-                data.name.span(),
-                Data {
-                    name: data.name.clone(),
-                    attr: attr.to_vec(),
-                    gen_scope,
-                    cons,
-                },
-            ) {
-                errors.append(&mut errs);
+            this.datas.define_data(data_id, Data {
+                name: data.name.clone(),
+                attr: attr.to_vec(),
+                gen_scope,
+                cons,
+            });
+        }
+        this.errors.append(&mut this.datas.check_lang_shapes(&this.tys));
+        // Class defaults
+        for (module_id, _, class, class_id, gen_scope) in &classes {
+            let mut defaults = HashMap::new();
+            for (idx, item) in class.items.iter().enumerate() {
+                match item {
+                    // Duplicate items have already been reported
+                    ast::ClassItem::Value { name, ty, default: Some(default), .. } if !defaults.contains_key(&**name) => {
+                        let mut infer = Infer::new(&mut this, Some(item_gen_scopes[&(*class_id, idx)]))
+                            .with_unknown_self(class.name.span(), vec![*class_id]);
+
+                        let ty = ty.to_hir(&mut infer, &Scope::Module(*module_id));
+                        let default = default.to_hir(&mut infer, &Scope::Module(*module_id));
+                        infer.make_flow(default.meta().1, ty.meta().1, EqInfo::new(name.span(), format!("Type of default item must match class")));
+
+                        let (mut checked, mut errs) = infer.into_checked();
+                        errors.append(&mut errs);
+
+                        defaults.insert(**name, default.reify(&mut checked));
+                    },
+                    _ => {},
+                }
             }
+            this.classes.define_defaults(*class_id, defaults);
         }
+
         // Member fields
-        for (member, class_id, member_id, gen_scope) in &members {
+        for (module_id, member, class_id, member_id, gen_scope) in &members {
             let fields = member.items
                 .iter()
                 .filter_map(|item| {
@@ -425,14 +660,27 @@ impl Context {
                             errors.push(Error::NoSuchClassItem(name.clone(), class.name.clone()));
                             None
                         } else {
-                            let val = val.to_hir(&mut infer, &Scope::Empty);
+                            let val = val.to_hir(&mut infer, &Scope::Module(*module_id));
                             let class = infer.ctx().classes.get(*class_id);
                             if let Some(field_ty) = class.field(**name).cloned() {
-                                let self_ty = member.member.to_hir(&mut infer, &Scope::Empty).meta().1;
+                                let field_gen_scope = class.field_gen_scope(**name).unwrap();
+                                let (self_ty, params) = lower::member_to_hir(&mut infer, &Scope::Module(*module_id), member, *class_id);
+                                let self_ty = self_ty.meta().1;
+                                let params = params
+                                    .into_iter()
+                                    .map(|param| param.meta().1)
+                                    .collect::<Vec<_>>();
+                                // The field's own generic types remain generic within the member
+                                let item_gens = (0..infer.ctx().tys.get_gen_scope(field_gen_scope).len())
+                                    .map(|idx| {
+                                        let span = infer.ctx().tys.get_gen_scope(field_gen_scope).get(idx).name.span();
+                                        infer.insert(span, TyInfo::Gen(idx, field_gen_scope, span))
+                                    })
+                                    .collect::<Vec<_>>();
                                 let val_ty = infer.instantiate(
                                     *field_ty,
                                     None,//Some(field_ty.span()),
-                                    &|_, _, _| panic!("Generics not yet supported on classes"),
+                                    &|idx, scope, _| if scope == field_gen_scope { item_gens[idx] } else { params[idx] },
                                     Some(self_ty),
                                 );
                                 infer.make_flow(val.meta().1, val_ty, EqInfo::new(name.span(), format!("Type of member item must match class")));
@@ -467,7 +715,8 @@ impl Context {
 
             for field in class.fields.as_ref().expect("Class fields must be known here") {
                 if let ClassItem::Value { name, .. } = field {
-                    if !fields.contains_key(name) {
+                    // Items with a default implementation may be omitted
+                    if !fields.contains_key(name) && class.default(**name).is_none() {
                         errors.push(Error::MissingClassItem(member.member.span(), class.name.clone(), name.clone()));
                     }
                 }
@@ -475,15 +724,14 @@ impl Context {
 
             this.classes.define_member_fields(*member_id, *class_id, fields);
         }
-        for (attr, def) in defs {
-            let id = this.defs
-                .lookup(*def.name)
-                .expect("Def must be pre-declared before definition");
-            let gen_scope = this.defs.get(id).gen_scope;
-
+        // Defs without a type hint take their type from their body, so the bodies of imported defs must be checked
+        // before those of the modules that use them
+        let module_order = this.modules.dependency_order().to_vec();
+        defs_init.sort_by_key(|(module_id, ..)| module_order.iter().position(|m| m == module_id));
+        for (module_id, def, id, gen_scope) in defs_init {
             let mut infer = Infer::new(&mut this, Some(gen_scope));
 
-            let ty_hint = def.ty_hint.to_hir(&mut infer, &Scope::Empty);
+            let ty_hint = def.ty_hint.to_hir(&mut infer, &Scope::Module(module_id));
 
             let gen_tys = (0..infer.ctx().tys.get_gen_scope(gen_scope).len())
                 .map(|i| {
@@ -492,7 +740,7 @@ impl Context {
                 })
                 .collect();
 
-            let body = def.body.to_hir(&mut infer, &Scope::Recursive(def.name.clone(), ty_hint.meta().1, id, gen_tys));
+            let body = def.body.to_hir(&mut infer, &Scope::Recursive(&Scope::Module(module_id), def.name.clone(), ty_hint.meta().1, id, gen_tys));
             infer.make_flow(body.meta().1, ty_hint.meta().1, EqInfo::default());
 
             let (mut checked, mut errs) = infer.into_checked();
@@ -505,6 +753,8 @@ impl Context {
             this.defs.define_body(id, body);
         }
 
+        errors.append(&mut check_termination(&this));
+
         errors.append(&mut this.errors);
 
         (this, errors)
@@ -23,45 +23,32 @@ pub struct Alias {
 #[derive(Default)]
 pub struct Lang {
     pub go: Option<DataId>,
+    pub io: Option<DataId>,
+    // Produced by integer division and remainder, which have no result when the divisor is zero
+    pub maybe: Option<DataId>,
+    // Produced by derived members of `Ord`. Programs that derive nothing need not define it.
+    pub ordering: Option<DataId>,
 }
 
 #[derive(Default)]
 pub struct Datas {
-    // TODO: Don't use `Result`
-    name_lut: HashMap<Ident, (Span, Result<DataId, AliasId>, GenScopeId)>,
-    cons_lut: HashMap<Ident, (Span, DataId)>,
-    alias_lut: HashMap<Ident, Alias>,
-    datas: Vec<(Span, Option<Data>)>,
-    aliases: Vec<(Span, Option<Alias>)>,
+    datas: Vec<(Span, GenScopeId, Option<Data>)>,
+    aliases: Vec<(Span, GenScopeId, Option<Alias>)>,
     pub lang: Lang,
 }
 
 impl Datas {
-    pub fn name_gen_scope(&self, name: Ident) -> GenScopeId {
-        self.name_lut[&name].2
+    pub fn data_gen_scope(&self, data: DataId) -> GenScopeId {
+        self.datas[data.0].1
     }
 
-    pub fn lookup_data(&self, name: Ident) -> Option<DataId> {
-        self.name_lut
-            .get(&name)
-            .and_then(|data| data.1.as_ref().ok())
-            .copied()
-    }
-
-    pub fn lookup_alias(&self, name: Ident) -> Option<AliasId> {
-        self.name_lut
-            .get(&name)
-            .and_then(|data| data.1.as_ref().err())
-            .copied()
-    }
-
-    pub fn lookup_cons(&self, name: Ident) -> Option<DataId> {
-        self.cons_lut.get(&name).map(|(_, id)| *id)
+    pub fn alias_gen_scope(&self, alias: AliasId) -> GenScopeId {
+        self.aliases[alias.0].1
     }
 
     pub fn get_data(&self, data: DataId) -> &Data {
         self.datas[data.0]
-            .1
+            .2
             .as_ref()
             .expect("Declared data accessed before being defined")
     }
@@ -72,7 +59,7 @@ impl Datas {
 
     pub fn get_alias(&self, alias: AliasId) -> Option<&Alias> {
         self.aliases[alias.0]
-            .1
+            .2
             .as_ref()
     }
 
@@ -80,60 +67,116 @@ impl Datas {
         self.aliases[alias.0].0
     }
 
-    pub fn declare_data(&mut self, name: SrcNode<Ident>, gen_scope: GenScopeId, attr: &[SrcNode<ast::Attr>]) -> Result<DataId, Error> {
+    pub fn declare_data(&mut self, span: Span, gen_scope: GenScopeId, attr: &[SrcNode<ast::Attr>]) -> DataId {
         let id = DataId(self.datas.len());
-        if let Err(old) = self.name_lut.try_insert(*name, (name.span(), Ok(id), gen_scope)) {
-            Err(Error::DuplicateTypeName(*name, old.entry.get().0, name.span()))
-        } else {
-            if let Some(lang) = attr
-                .iter()
-                .find(|a| &**a.name == "lang")
-                .and_then(|a| a.args.as_ref())
-            {
-                if lang.iter().find(|a| &**a.name == "go").is_some() {
-                    self.lang.go = Some(id);
-                }
+        if let Some(lang) = attr
+            .iter()
+            .find(|a| &**a.name == "lang")
+            .and_then(|a| a.args.as_ref())
+        {
+            if lang.iter().find(|a| &**a.name == "go").is_some() {
+                self.lang.go = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "io").is_some() {
+                self.lang.io = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "maybe").is_some() {
+                self.lang.maybe = Some(id);
+            }
+            if lang.iter().find(|a| &**a.name == "ordering").is_some() {
+                self.lang.ordering = Some(id);
             }
-
-            self.datas.push((name.span(), None));
-            Ok(id)
         }
+
+        self.datas.push((span, gen_scope, None));
+        id
     }
 
     pub fn check_lang_items(&self) -> Vec<Error> {
         let mut errors = Vec::new();
 
         if self.lang.go.is_none() { errors.push(Error::MissingLangItem("go")); }
+        if self.lang.io.is_none() { errors.push(Error::MissingLangItem("io")); }
+        if self.lang.maybe.is_none() { errors.push(Error::MissingLangItem("maybe")); }
 
         errors
     }
 
-    pub fn declare_alias(&mut self, name: Ident, span: Span, gen_scope: GenScopeId) -> Result<AliasId, Error> {
-        let id = AliasId(self.aliases.len());
-        if let Err(old) = self.name_lut.try_insert(name, (span, Err(id), gen_scope)) {
-            Err(Error::DuplicateTypeName(name, old.entry.get().0, span))
-        } else {
-            self.aliases.push((span, None));
-            Ok(id)
+    /// Find the constructors of the `maybe` lang item by their shape, returning the indices of the one that holds its
+    /// generic type and the one that holds nothing (`Just` and `None`, as they are usually known).
+    pub fn maybe_cons(&self, tys: &Types) -> Option<(usize, usize)> {
+        let maybe = self.get_data(self.lang.maybe?);
+        if tys.get_gen_scope(maybe.gen_scope).len() != 1 || maybe.cons.len() != 2 {
+            return None;
         }
+        let cons = |f: &dyn Fn(Ty) -> bool| maybe.cons
+            .iter()
+            .position(|(_, ty)| f(tys.get(*ty)));
+        let just = cons(&|ty| matches!(ty, Ty::Gen(0, scope) if scope == maybe.gen_scope))?;
+        let none = cons(&|ty| matches!(ty, Ty::Tuple(fields) if fields.is_empty()))?;
+        Some((just, none))
     }
 
-    pub fn define_data(&mut self, id: DataId, span: Span, data: Data) -> Result<(), Vec<Error>> {
+    /// Check that the lang items have the shape that the compiler expects of them, once they are defined.
+    pub fn check_lang_shapes(&self, tys: &Types) -> Vec<Error> {
         let mut errors = Vec::new();
-        for (cons, _) in &data.cons {
-            if let Err(old) = self.cons_lut.try_insert(**cons, (cons.span(), id)) {
-                errors.push(Error::DuplicateConsName(**cons, old.entry.get().0, cons.span()));
+
+        if let Some(maybe) = self.lang.maybe {
+            if self.maybe_cons(tys).is_none() {
+                errors.push(Error::WrongLangItemShape("maybe", self.get_data(maybe).name.span(), "data Maybe A = | Just A \\ None"));
             }
         }
-        self.datas[id.0].1 = Some(data);
-        if errors.len() == 0 {
-            Ok(())
-        } else {
-            Err(errors)
-        }
+
+        errors
+    }
+
+    pub fn declare_alias(&mut self, span: Span, gen_scope: GenScopeId) -> AliasId {
+        let id = AliasId(self.aliases.len());
+        self.aliases.push((span, gen_scope, None));
+        id
+    }
+
+    pub fn define_data(&mut self, id: DataId, data: Data) {
+        self.datas[id.0].2 = Some(data);
     }
 
     pub fn define_alias(&mut self, id: AliasId, alias: Alias) {
-        self.aliases[id.0].1 = Some(alias);
+        self.aliases[id.0].2 = Some(alias);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tao_syntax::parse_module;
+
+    // Analyse a module, returning the lang items that do not have the expected shape
+    fn wrong_shapes(code: &str) -> Vec<&'static str> {
+        let module = match parse_module(code, SrcId::from_path("main.tao")) {
+            (Some(module), errors) if errors.is_empty() => module,
+            (_, errors) => panic!("Failed to parse: {:?}", errors),
+        };
+        let (_, errors) = Context::from_module(&module, &[]);
+        errors
+            .into_iter()
+            .filter_map(|e| match e {
+                Error::WrongLangItemShape(name, _, _) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn maybe_is_found_by_shape() {
+        let code = "$[lang(maybe)]\ndata Option A =\n\t| Nothing\n\t\\ Some A";
+
+        assert!(wrong_shapes(code).is_empty());
+    }
+
+    #[test]
+    fn maybe_with_wrong_shape() {
+        assert_eq!(wrong_shapes("$[lang(maybe)]\ndata Maybe A =\n\t| Just A\n\t| Many (A, A)\n\t\\ None"), ["maybe"]);
+        assert_eq!(wrong_shapes("$[lang(maybe)]\ndata Maybe A =\n\t| Just Nat\n\t\\ None"), ["maybe"]);
+        assert_eq!(wrong_shapes("$[lang(maybe)]\ndata Maybe A, B =\n\t| Just A\n\t\\ None"), ["maybe"]);
     }
 }
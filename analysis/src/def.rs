@@ -11,17 +11,9 @@ pub struct Def {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DefId(pub usize);
 
-#[derive(Default)]
-pub struct Lang {
-    pub io_unit: Option<DefId>,
-    pub io_bind: Option<DefId>,
-}
-
 #[derive(Default)]
 pub struct Defs {
-    lut: HashMap<Ident, (Span, DefId)>,
     defs: Vec<Def>,
-    pub lang: Lang,
 }
 
 impl Defs {
@@ -36,42 +28,14 @@ impl Defs {
         &self.defs[def.0]
     }
 
-    pub fn lookup(&self, name: Ident) -> Option<DefId> {
-        self.lut.get(&name).map(|(_, id)| *id)
-    }
-
-    pub fn declare(&mut self, def: Def) -> Result<DefId, Error> {
+    pub fn declare(&mut self, def: Def) -> DefId {
         let id = DefId(self.defs.len());
-        let name = *def.name;
-        let span = def.name.span();
-        if let Err(old) = self.lut.try_insert(name, (span, id)) {
-            Err(Error::DuplicateDefName(name, old.entry.get().0, span))
-        } else {
-            if let Some(lang) = def.attr
-                .iter()
-                .find(|a| &**a.name == "lang")
-                .and_then(|a| a.args.as_ref())
-            {
-                if lang.iter().find(|a| &**a.name == "io_unit").is_some() {
-                    self.lang.io_unit = Some(id);
-                }
-                if lang.iter().find(|a| &**a.name == "io_bind").is_some() {
-                    self.lang.io_bind = Some(id);
-                }
-            }
-
-            self.defs.push(def);
-            Ok(id)
-        }
+        self.defs.push(def);
+        id
     }
 
-    pub fn check_lang_items(&self) -> Vec<Error> {
-        let mut errors = Vec::new();
-
-        if self.lang.io_unit.is_none() { errors.push(Error::MissingLangItem("io_unit")); }
-        if self.lang.io_bind.is_none() { errors.push(Error::MissingLangItem("io_bind")); }
-
-        errors
+    pub fn define_ty_hint(&mut self, id: DefId, ty_hint: TyId) {
+        self.defs[id.0].ty_hint = Some(ty_hint);
     }
 
     pub fn define_body(&mut self, id: DefId, expr: TyExpr) {
@@ -0,0 +1,416 @@
+//! Synthesis of class members for data types marked with `$[derive(...)]`.
+//!
+//! Derived members are generated as syntax and then lowered like any other member. Each field is accessed through its
+//! own type (i.e: `<Nat>.cmp(a0, b0)`), so a field type that lacks the required member is reported at the field. The
+//! classes and constructors that derived members refer to are found by their lang items and referred to by absolute
+//! paths, so they mean the same thing wherever they are derived, whatever is in scope there.
+
+use super::*;
+use ast::{Binding, BinaryOp, ClassInst, Expr, GenericTy, Generics, Kind, Literal, MemberItem, Pat, Path, Type, WherePred};
+
+/// The classes that may be derived.
+pub const DERIVABLE: &[&str] = &["Eq", "Ord", "Debug", "Show", "Default"];
+
+/// Generate members for the classes listed in the `derive` attributes of a data type.
+pub fn derive_members(ctx: &Context, attrs: &[SrcNode<ast::Attr>], data: &ast::Data) -> (Vec<ast::Member>, Vec<Error>) {
+    let mut members = Vec::new();
+    let mut errors = Vec::new();
+    for class in attrs
+        .iter()
+        .filter(|attr| &**attr.name == "derive")
+        .filter_map(|attr| attr.args.as_ref())
+        .flatten()
+    {
+        let span = class.name.span();
+        let (lang, class_id) = match class.name.as_str() {
+            "Eq" => ("eq", ctx.classes.lang.eq),
+            "Ord" => ("ord", ctx.classes.lang.ord),
+            "Debug" => ("debug", ctx.classes.lang.debug),
+            "Show" => ("show", ctx.classes.lang.show),
+            "Default" => ("default", ctx.classes.lang.default),
+            _ => {
+                errors.push(Error::NoSuchDerive(class.name.clone()));
+                continue;
+            },
+        };
+        let Some((module, name)) = class_id.and_then(|class| ctx.modules.declaration(class, |ns| &ns.classes)) else {
+            errors.push(Error::MissingLangItem(lang));
+            continue;
+        };
+        let class_path = ctx.modules.absolute_path(module, SrcNode::new(name, span));
+
+        let item = match class.name.as_str() {
+            "Eq" => derive_eq(data, span),
+            "Ord" => match ctx.datas.lang.ordering.and_then(|data| ctx.modules.declaration(TypeItem::Data(data), |ns| &ns.types)) {
+                Some((module, _)) => derive_ord(data, |cons| ctx.modules.absolute_path(module, SrcNode::new(Ident::new(cons), span)), span),
+                None => {
+                    errors.push(Error::MissingLangItem("ordering"));
+                    continue;
+                },
+            },
+            "Debug" => derive_fmt(data, "debug", span),
+            "Show" => derive_fmt(data, "show", span),
+            "Default" => if let Some(item) = derive_default(data, span) {
+                item
+            } else {
+                errors.push(Error::CannotDerive(class.name.clone(), data.name.clone()));
+                continue;
+            },
+            _ => unreachable!(),
+        };
+        members.push(member(data, SrcNode::new(class_path, span), item));
+    }
+    (members, errors)
+}
+
+// The shape of the fields of a data variant
+enum Fields<'a> {
+    Unit,
+    Single(&'a SrcNode<Type>),
+    Tuple(&'a [SrcNode<Type>]),
+    Record(&'a [(SrcNode<Ident>, SrcNode<Type>)]),
+}
+
+impl<'a> Fields<'a> {
+    fn of(ty: &'a SrcNode<Type>) -> Self {
+        match &**ty {
+            Type::Tuple(fields) if fields.is_empty() => Fields::Unit,
+            Type::Tuple(fields) => Fields::Tuple(fields),
+            Type::Record(fields) => Fields::Record(fields),
+            _ => Fields::Single(ty),
+        }
+    }
+
+    fn tys(&self) -> Vec<&'a SrcNode<Type>> {
+        match self {
+            Fields::Unit => Vec::new(),
+            Fields::Single(ty) => vec![*ty],
+            Fields::Tuple(fields) => fields.iter().collect(),
+            Fields::Record(fields) => fields.iter().map(|(_, ty)| ty).collect(),
+        }
+    }
+
+    // A pattern that deconstructs the variant, binding each field to `{prefix}{index}`
+    fn pat(&self, cons: &SrcNode<Ident>, prefix: &str, span: Span) -> SrcNode<Binding> {
+        let inner = match self {
+            Fields::Unit => binding(Pat::Tuple(Vec::new()), None, span),
+            Fields::Single(_) => binding(Pat::Wildcard, Some(field_name(prefix, 0)), span),
+            Fields::Tuple(fields) => binding(Pat::Tuple((0..fields.len())
+                .map(|i| binding(Pat::Wildcard, Some(field_name(prefix, i)), span))
+                .collect()), None, span),
+            Fields::Record(fields) => binding(Pat::Record(fields
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.clone(), binding(Pat::Wildcard, Some(field_name(prefix, i)), span)))
+                .collect()), None, span),
+        };
+        binding(Pat::Deconstruct(SrcNode::new(Path::local(cons.clone()), span), inner), None, span)
+    }
+}
+
+fn field_name(prefix: &str, idx: usize) -> Ident {
+    Ident::new(format!("{}{}", prefix, idx))
+}
+
+fn binding(pat: Pat, name: Option<Ident>, span: Span) -> SrcNode<Binding> {
+    SrcNode::new(Binding {
+        pat: SrcNode::new(pat, span),
+        name: name.map(|name| SrcNode::new(name, span)),
+        ty: None,
+    }, span)
+}
+
+fn local(name: Ident, span: Span) -> SrcNode<Expr> {
+    SrcNode::new(Expr::Local(name), span)
+}
+
+fn cons(path: Path, inner: SrcNode<Expr>, span: Span) -> SrcNode<Expr> {
+    SrcNode::new(Expr::Cons(SrcNode::new(path, span), inner), span)
+}
+
+fn unit(span: Span) -> SrcNode<Expr> {
+    SrcNode::new(Expr::Tuple(Vec::new()), span)
+}
+
+fn string(s: &str, span: Span) -> SrcNode<Expr> {
+    SrcNode::new(Expr::Literal(Literal::Str(Intern::new(s.to_string()))), span)
+}
+
+// `<ty>.item(args...)`, spanned by the type so that missing members are reported at the field that needs them
+fn call(ty: &SrcNode<Type>, item: &str, args: Vec<SrcNode<Expr>>) -> SrcNode<Expr> {
+    let span = ty.span();
+    args
+        .into_iter()
+        .fold(
+            SrcNode::new(Expr::ClassAccess(ty.clone(), SrcNode::new(Ident::new(item), span)), span),
+            |f, arg| SrcNode::new(Expr::Apply(f, arg), span),
+        )
+}
+
+fn func(arms: Vec<(Vec<SrcNode<Binding>>, SrcNode<Expr>)>, span: Span) -> SrcNode<Expr> {
+    SrcNode::new(Expr::Func(SrcNode::new(arms
+        .into_iter()
+        .map(|(bindings, body)| (SrcNode::new(bindings, span), body))
+        .collect(), span)), span)
+}
+
+fn wildcard(span: Span) -> SrcNode<Binding> {
+    binding(Pat::Wildcard, None, span)
+}
+
+fn derive_eq(data: &ast::Data, span: Span) -> (&'static str, SrcNode<Expr>) {
+    let mut arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let fields = Fields::of(ty);
+            let body = fields
+                .tys()
+                .into_iter()
+                .enumerate()
+                // `OrdExt` also has an `eq` item, so `<ty>.eq` would be ambiguous for ordered fields: use `=` instead
+                .map(|(i, ty)| SrcNode::new(Expr::Binary(
+                    SrcNode::new(BinaryOp::Eq, ty.span()),
+                    local(field_name("a", i), ty.span()),
+                    local(field_name("b", i), ty.span()),
+                ), ty.span()))
+                .reduce(|a, b| SrcNode::new(Expr::Binary(SrcNode::new(BinaryOp::And, span), a, b), span))
+                .unwrap_or_else(|| SrcNode::new(Expr::Literal(Literal::Bool(true)), span));
+            (vec![fields.pat(name, "a", span), fields.pat(name, "b", span)], body)
+        })
+        .collect::<Vec<_>>();
+    // Values constructed with different variants are never equal
+    if data.variants.len() != 1 {
+        arms.push((vec![wildcard(span), wildcard(span)], SrcNode::new(Expr::Literal(Literal::Bool(data.variants.is_empty())), span)));
+    }
+    ("eq", func(arms, span))
+}
+
+// `ordering` makes the path to a constructor of `Ordering`
+fn derive_ord(data: &ast::Data, ordering: impl Fn(&str) -> Path, span: Span) -> (&'static str, SrcNode<Expr>) {
+    let ord = Ident::new("ord");
+    let mut arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let fields = Fields::of(ty);
+            // Fields are compared in order, and the first that differs determines the result
+            let body = fields
+                .tys()
+                .into_iter()
+                .enumerate()
+                .rev()
+                .fold(cons(ordering("Equal"), unit(span), span), |rest, (i, ty)| SrcNode::new(Expr::Match(
+                    SrcNode::new(vec![call(ty, "cmp", vec![local(field_name("a", i), span), local(field_name("b", i), span)])], span),
+                    vec![
+                        (SrcNode::new(vec![binding(Pat::Deconstruct(
+                            SrcNode::new(ordering("Equal"), span),
+                            binding(Pat::Tuple(Vec::new()), None, span),
+                        ), None, span)], span), rest),
+                        (SrcNode::new(vec![binding(Pat::Wildcard, Some(ord), span)], span), local(ord, span)),
+                    ],
+                ), span));
+            (vec![fields.pat(name, "a", span), fields.pat(name, "b", span)], body)
+        })
+        .collect::<Vec<_>>();
+    // Values constructed with different variants are ordered by the order in which the variants were declared
+    if data.variants.len() != 1 {
+        let (tag, x, y) = (Ident::new("tag"), Ident::new("x"), Ident::new("y"));
+        let nat = SrcNode::new(Type::Data(SrcNode::new(Path::local(SrcNode::new(Ident::new("Nat"), span)), span), Vec::new()), span);
+        let tags = data.variants
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (
+                vec![binding(Pat::Deconstruct(SrcNode::new(Path::local(name.clone()), span), wildcard(span)), None, span)],
+                SrcNode::new(Expr::Literal(Literal::Nat(i as u64)), span),
+            ))
+            .collect();
+        let body = SrcNode::new(Expr::Let(
+            vec![(binding(Pat::Wildcard, Some(tag), span), func(tags, span))],
+            call(&nat, "cmp", vec![
+                SrcNode::new(Expr::Apply(local(tag, span), local(x, span)), span),
+                SrcNode::new(Expr::Apply(local(tag, span), local(y, span)), span),
+            ]),
+        ), span);
+        arms.push((
+            vec![binding(Pat::Wildcard, Some(x), span), binding(Pat::Wildcard, Some(y), span)],
+            if data.variants.is_empty() { cons(ordering("Equal"), unit(span), span) } else { body },
+        ));
+    }
+    ("cmp", func(arms, span))
+}
+
+// Derive `Debug` or `Show`, which both produce a string from the fields of the value using syntax similar to that
+// with which it was constructed
+fn derive_fmt(data: &ast::Data, item: &'static str, span: Span) -> (&'static str, SrcNode<Expr>) {
+    let mut arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let fields = Fields::of(ty);
+            let field = |i: usize, ty: &SrcNode<Type>| call(ty, item, vec![local(field_name("a", i), span)]);
+            let mut parts = vec![Err(name.to_string())];
+            match &fields {
+                Fields::Unit => {},
+                // A lone field may need parentheses, as in `Just (Just 5)`
+                Fields::Single(ty) => parts.extend([
+                    Err(" ".to_string()),
+                    Ok(call(ty, if item == "debug" { "debug_arg" } else { "show_arg" }, vec![local(field_name("a", 0), span)])),
+                ]),
+                Fields::Tuple(tys) => {
+                    parts.push(Err(" (".to_string()));
+                    for (i, ty) in tys.iter().enumerate() {
+                        if i > 0 { parts.push(Err(", ".to_string())); }
+                        parts.push(Ok(field(i, ty)));
+                    }
+                    parts.push(Err(")".to_string()));
+                },
+                Fields::Record(tys) => {
+                    parts.push(Err(" {".to_string()));
+                    for (i, (field_name, ty)) in tys.iter().enumerate() {
+                        parts.push(Err(format!("{} {}: ", if i > 0 { "," } else { "" }, **field_name)));
+                        parts.push(Ok(field(i, ty)));
+                    }
+                    parts.push(Err(" }".to_string()));
+                },
+            }
+            (vec![fields.pat(name, "a", span)], join(parts, span))
+        })
+        .collect::<Vec<_>>();
+    if data.variants.is_empty() {
+        arms.push((vec![wildcard(span)], string("", span)));
+    }
+    (item, func(arms, span))
+}
+
+// Join the given parts into a single string, where `Err` parts are literal text
+fn join(parts: Vec<Result<SrcNode<Expr>, String>>, span: Span) -> SrcNode<Expr> {
+    let mut exprs = Vec::new();
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            Ok(expr) => {
+                if !text.is_empty() {
+                    exprs.push(string(&std::mem::take(&mut text), span));
+                }
+                exprs.push(expr);
+            },
+            Err(s) => text += &s,
+        }
+    }
+    if !text.is_empty() {
+        exprs.push(string(&text, span));
+    }
+    exprs
+        .into_iter()
+        .reduce(|a, b| SrcNode::new(Expr::Binary(SrcNode::new(BinaryOp::Join, span), a, b), span))
+        .unwrap_or_else(|| string("", span))
+}
+
+// The default value uses the first variant without fields or, failing that, the first variant with default fields
+fn derive_default(data: &ast::Data, span: Span) -> Option<(&'static str, SrcNode<Expr>)> {
+    let (name, ty) = data.variants
+        .iter()
+        .find(|(_, ty)| matches!(Fields::of(ty), Fields::Unit))
+        .or_else(|| data.variants.first())?;
+    let inner = match Fields::of(ty) {
+        Fields::Unit => unit(span),
+        Fields::Single(ty) => call(ty, "default", Vec::new()),
+        Fields::Tuple(tys) => SrcNode::new(Expr::Tuple(tys
+            .iter()
+            .map(|ty| call(ty, "default", Vec::new()))
+            .collect()), span),
+        Fields::Record(fields) => SrcNode::new(Expr::Record(fields
+            .iter()
+            .map(|(name, ty)| (name.clone(), call(ty, "default", Vec::new())))
+            .collect()), span),
+    };
+    Some(("default", SrcNode::new(Expr::Cons(SrcNode::new(Path::local(name.clone()), span), inner), span)))
+}
+
+// Determine whether a type refers to the given generic type
+fn mentions(ty: &Type, gen: Ident) -> bool {
+    match ty {
+        Type::Error | Type::Universe | Type::Unknown | Type::ListCon => false,
+        Type::List(item) => mentions(item, gen),
+        Type::Tuple(fields) => fields
+            .iter()
+            .any(|field| mentions(field, gen)),
+        Type::Record(fields) => fields
+            .iter()
+            .any(|(_, field)| mentions(field, gen)),
+        Type::Func(i, o) => mentions(i, gen) || mentions(o, gen),
+        Type::Data(name, args) => name.as_local() == Some(gen) || args
+            .iter()
+            .any(|arg| mentions(arg, gen)),
+        Type::Assoc(inner, _) => mentions(inner, gen),
+        Type::Effect(effs, out) => mentions(out, gen) || effs
+            .iter()
+            .any(|eff| eff.args
+                .iter()
+                .any(|arg| mentions(arg, gen))),
+    }
+}
+
+// Build a member of the class for the data type. The generic types of the data type that its fields use must also be
+// members of the class. Type constructors cannot be, so the fields that apply them must be members instead.
+fn member(data: &ast::Data, class: SrcNode<Path>, (name, val): (&'static str, SrcNode<Expr>)) -> ast::Member {
+    let span = class.span();
+    let class = SrcNode::new(ClassInst { name: class, params: Vec::new() }, span);
+    let field_tys = data.variants
+        .iter()
+        .flat_map(|(_, ty)| Fields::of(ty).tys())
+        .collect::<Vec<_>>();
+    let is_type = |gen: &GenericTy| matches!(gen.kind.as_deref(), None | Some(Kind::Type));
+    let is_used = |gen: &GenericTy| field_tys
+        .iter()
+        .any(|ty| mentions(ty, *gen.name));
+
+    let mut applied = Vec::<&SrcNode<Type>>::new();
+    for ty in &field_tys {
+        let applies_con = data.generics.tys
+            .iter()
+            .any(|gen| !is_type(gen) && mentions(ty, *gen.name));
+        if applies_con && !applied.iter().any(|other| ***other == ***ty) {
+            applied.push(*ty);
+        }
+    }
+
+    let generics = Generics {
+        tys: data.generics.tys
+            .iter()
+            .map(|gen| GenericTy {
+                name: gen.name.clone(),
+                kind: gen.kind.clone(),
+                obligations: gen.obligations
+                    .iter()
+                    .cloned()
+                    .chain((is_type(gen) && is_used(gen)).then(|| class.clone()))
+                    .collect(),
+            })
+            .collect(),
+        preds: data.generics.preds
+            .iter()
+            .map(|pred| SrcNode::new(WherePred {
+                ty: pred.ty.clone(),
+                obligations: pred.obligations.clone(),
+            }, pred.span()))
+            .chain(applied
+                .into_iter()
+                .map(|ty| SrcNode::new(WherePred {
+                    ty: ty.clone(),
+                    obligations: vec![class.clone()],
+                }, ty.span())))
+            .collect(),
+    };
+    let member = Type::Data(
+        SrcNode::new(Path::local(data.name.clone()), data.name.span()),
+        data.generics.tys
+            .iter()
+            .map(|gen| SrcNode::new(Type::Data(SrcNode::new(Path::local(gen.name.clone()), gen.name.span()), Vec::new()), gen.name.span()))
+            .collect(),
+    );
+    ast::Member {
+        generics: SrcNode::new(generics, data.generics.span()),
+        member: SrcNode::new(member, data.name.span()),
+        class,
+        items: vec![MemberItem::Value { name: SrcNode::new(Ident::new(name), span), val }],
+    }
+}
@@ -11,6 +11,16 @@ pub struct EffectDecl {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct EffectDeclId(usize);
 
+pub struct EffectAlias {
+    pub name: SrcNode<Ident>,
+    pub attr: Vec<SrcNode<ast::Attr>>,
+    pub gen_scope: GenScopeId,
+    pub effects: Option<Vec<(EffectDeclId, Vec<TyId>)>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EffectAliasId(usize);
+
 #[derive(Default)]
 pub struct Lang {
     // pub not: Option<EffectDeclId>,
@@ -18,8 +28,8 @@ pub struct Lang {
 
 #[derive(Default)]
 pub struct Effects {
-    lut: HashMap<Ident, (Span, EffectDeclId)>,
     effect_decls: Vec<EffectDecl>,
+    effect_aliases: Vec<EffectAlias>,
     pub lang: Lang,
 }
 
@@ -28,33 +38,34 @@ impl Effects {
         &self.effect_decls[eff.0]
     }
 
+    pub fn get_alias(&self, alias: EffectAliasId) -> &EffectAlias {
+        &self.effect_aliases[alias.0]
+    }
+
     // pub fn iter(&self) -> impl Iterator<Item = (EffectDeclId, &EffectDecl)> {
     //     self.effect_names.iter().enumerate().map(|(i, eff)| (EffectDeclId(i), eff))
     // }
 
-    pub fn lookup(&self, name: Ident) -> Option<EffectDeclId> {
-        self.lut.get(&name).map(|(_, id)| *id)
-    }
-
-    pub fn declare(&mut self, eff: EffectDecl) -> Result<EffectDeclId, Error> {
+    pub fn declare(&mut self, eff: EffectDecl) -> EffectDeclId {
         let id = EffectDeclId(self.effect_decls.len());
-        let span = eff.name.span();
-        if let Err(old) = self.lut.try_insert(*eff.name, (span, id)) {
-            Err(Error::DuplicateEffectDecl(*eff.name, old.entry.get().0, span))
-        } else {
-            if let Some(lang) = eff.attr
-                .iter()
-                .find(|a| &**a.name == "lang")
-                .and_then(|a| a.args.as_ref())
-            {
-                // if lang.iter().find(|a| &**a.name == "not").is_some() {
-                //     self.lang.not = Some(id);
-                // }
-            }
-
-            self.effect_decls.push(eff);
-            Ok(id)
+        if let Some(lang) = eff.attr
+            .iter()
+            .find(|a| &**a.name == "lang")
+            .and_then(|a| a.args.as_ref())
+        {
+            // if lang.iter().find(|a| &**a.name == "not").is_some() {
+            //     self.lang.not = Some(id);
+            // }
         }
+
+        self.effect_decls.push(eff);
+        id
+    }
+
+    pub fn declare_alias(&mut self, alias: EffectAlias) -> EffectAliasId {
+        let id = EffectAliasId(self.effect_aliases.len());
+        self.effect_aliases.push(alias);
+        id
     }
 
     pub fn check_lang_items(&self) -> Vec<Error> {
@@ -69,4 +80,8 @@ impl Effects {
         self.effect_decls[id.0].send = Some(send);
         self.effect_decls[id.0].recv = Some(recv);
     }
+
+    pub fn define_alias_effects(&mut self, id: EffectAliasId, effects: Vec<(EffectDeclId, Vec<TyId>)>) {
+        self.effect_aliases[id.0].effects = Some(effects);
+    }
 }
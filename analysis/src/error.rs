@@ -16,14 +16,18 @@ pub enum Error {
     InvalidUnaryOp(SrcNode<ast::UnaryOp>, TyId, Span),
     InvalidBinaryOp(SrcNode<ast::BinaryOp>, TyId, Span, TyId, Span),
     // (obligation, type, obligation_origin, generic_definition
-    TypeDoesNotFulfil(ClassId, TyId, Span, Option<Span>, Span),
-    NoSuchData(SrcNode<Ident>),
-    NoSuchCons(SrcNode<Ident>),
-    NoSuchClass(SrcNode<Ident>),
+    TypeDoesNotFulfil(ClassId, Vec<TyId>, TyId, Span, Option<Span>, Span),
+    NoSuchDef(SrcNode<ast::Path>),
+    NoSuchData(SrcNode<ast::Path>),
+    NoSuchCons(SrcNode<ast::Path>),
+    NoSuchClass(SrcNode<ast::Path>),
     NoSuchClassItem(SrcNode<Ident>, SrcNode<Ident>),
-    NoSuchEffect(SrcNode<Ident>),
+    NoSuchEffect(SrcNode<ast::Path>),
     AmbiguousClassItem(SrcNode<Ident>, Vec<ClassId>),
     MissingClassItem(Span, SrcNode<Ident>, SrcNode<Ident>),
+    NoSuchDerive(SrcNode<Ident>),
+    // Class, data
+    CannotDerive(SrcNode<Ident>, SrcNode<Ident>),
     RecursiveAlias(AliasId, TyId, Span),
     DuplicateTypeName(Ident, Span, Span),
     DuplicateDefName(Ident, Span, Span),
@@ -33,10 +37,20 @@ pub enum Error {
     DuplicateEffectDecl(Ident, Span, Span),
     DuplicateClassItem(Ident, Span, Span),
     DuplicateMemberItem(Ident, Span, Span),
+    // Class, previous member, conflicting member
+    OverlappingMembers(ClassId, MemberId, MemberId),
+    // Class, previous member, conflicting member, where at least one is an orphan
+    OrphanMembers(ClassId, MemberId, MemberId),
+    AmbiguousImport(Ident, Span, Span),
+    // Item, module path
+    NoSuchImport(SrcNode<Ident>, SrcNode<Intern<String>>),
+    PrivateImport(SrcNode<Ident>, SrcNode<Intern<String>>),
     PatternNotSupported(TyId, SrcNode<ast::BinaryOp>, TyId, Span),
     // Span, uncovered example, hidden_outer
     NotExhaustive(Span, ExamplePat, bool),
     WrongNumberOfGenerics(Span, usize, Span, usize),
+    // (type, its kind, origin of the expected kind, expected kind)
+    KindMismatch(Span, Kind, Span, Kind),
     DefTypeNotSpecified(Span, Span, Ident),
     SelfNotValidHere(Span),
     NoEntryPoint(Span),
@@ -45,12 +59,22 @@ pub enum Error {
     InvalidIntrinsic(SrcNode<Ident>),
     Unsupported(Span, &'static str),
     MissingLangItem(&'static str),
+    // (lang item, its declaration, the shape that it must have)
+    WrongLangItemShape(&'static str, Span, &'static str),
     NoBasin(Span),
+    HostNameNotLiteral(Span),
+    // (argument or result of a host intrinsic, its type)
+    HostTyNotConcrete(Span, TyId),
+    // (definition, recursive call)
+    MayNotTerminate(SrcNode<Ident>, Span),
+    // (definition, partial item, use of the partial item)
+    UsesPartial(SrcNode<Ident>, Ident, Span),
 }
 
 impl Error {
-    pub fn write<C: ariadne::Cache<SrcId>>(self, ctx: &Context, cache: C, main_src: SrcId, writer: impl Write) {
-        use ariadne::{Report, ReportKind, Label, Color, Fmt, Span, Config};
+    // The message of the error, its labels (most important first), and its notes
+    fn parts(self, ctx: &Context) -> (String, Vec<(Span, String, ariadne::Color)>, Vec<String>) {
+        use ariadne::{Color, Fmt};
 
         let display = |id| ctx.tys.display(&ctx.datas, &ctx.effects, id);
 
@@ -92,11 +116,21 @@ impl Error {
                 },
                 vec![],
             ),
-            Error::CannotInferEffect(a) => (
-                format!("Cannot infer effect"),
-                vec![(ctx.tys.get_effect_span(a), format!("Cannot be inferred"), Color::Red)],
-                vec![],
-            ),
+            Error::CannotInferEffect(a) => match ctx.tys.get_effect(a) {
+                Effect::Known(effs) if !effs.is_empty() => {
+                    let effs = ctx.tys.display_effect(&ctx.datas, &ctx.effects, a);
+                    (
+                        format!("Unhandled effects {}", (&effs).fg(Color::Red)),
+                        vec![(ctx.tys.get_effect_span(a), format!("Effects {} are performed here", effs.fg(Color::Red)), Color::Red)],
+                        vec![format!("Handle them or place this expression within a {} block", "@{ ... }".fg(Color::Blue))],
+                    )
+                },
+                _ => (
+                    format!("Cannot infer effect"),
+                    vec![(ctx.tys.get_effect_span(a), format!("Cannot be inferred"), Color::Red)],
+                    vec![],
+                ),
+            },
             Error::Recursive(a, span, part) => (
                 format!("Self-referencing type {} expands to have infinite size", display(a).fg(Color::Red)),
                 vec![
@@ -171,40 +205,51 @@ impl Error {
                     _ => vec![],
                 },
             ),
-            Error::TypeDoesNotFulfil(class, ty, obl_span, gen_span, use_span) => (
-                format!("Type {} is not a member of {}", display(ty).fg(Color::Red), (*ctx.classes.get(class).name).fg(Color::Red)),
-                {
-                    let mut labels = vec![
-                        (use_span, format!("Because it is used here"), Color::Yellow),
-                        (ctx.tys.get_span(ty), format!(
-                            "{} must be a member of {}",
-                            display(ty).fg(Color::Red),
-                            (*ctx.classes.get(class).name).fg(Color::Red),
-                        ), Color::Red),
-                        (obl_span, format!("Membership of {} is required here", (*ctx.classes.get(class).name).fg(Color::Cyan)), Color::Cyan),
-                    ];
-                    if let Some(gen_span) = gen_span {
-                        labels.push((gen_span, format!(
-                            "Consider adding a class constraint like {}",
-                            format!("{} < {}", display(ty), *ctx.classes.get(class).name).fg(Color::Blue),
-                        ), Color::Blue));
-                    }
-                    labels
-                },
-                vec![format!("Types must fulfil their class obligations")],
+            Error::TypeDoesNotFulfil(class, params, ty, obl_span, gen_span, use_span) => {
+                let class = format!("{}{}", *ctx.classes.get(class).name, params
+                    .iter()
+                    .map(|param| format!(" {}", display(*param)))
+                    .collect::<String>());
+                (
+                    format!("Type {} is not a member of {}", display(ty).fg(Color::Red), (&class).fg(Color::Red)),
+                    {
+                        let mut labels = vec![
+                            (use_span, format!("Because it is used here"), Color::Yellow),
+                            (ctx.tys.get_span(ty), format!(
+                                "{} must be a member of {}",
+                                display(ty).fg(Color::Red),
+                                (&class).fg(Color::Red),
+                            ), Color::Red),
+                            (obl_span, format!("Membership of {} is required here", (&class).fg(Color::Cyan)), Color::Cyan),
+                        ];
+                        if let Some(gen_span) = gen_span {
+                            labels.push((gen_span, format!(
+                                "Consider adding a class constraint like {}",
+                                format!("{} < {}", display(ty), class).fg(Color::Blue),
+                            ), Color::Blue));
+                        }
+                        labels
+                    },
+                    vec![format!("Types must fulfil their class obligations")],
+                )
+            },
+            Error::NoSuchDef(a) => (
+                format!("No such definition {}", (&*a).fg(Color::Red)),
+                vec![(a.span(), format!("Does not exist"), Color::Red)],
+                vec![],
             ),
             Error::NoSuchData(a) => (
-                format!("No such type {}", (*a).fg(Color::Red)),
+                format!("No such type {}", (&*a).fg(Color::Red)),
                 vec![(a.span(), format!("Does not exist"), Color::Red)],
                 vec![],
             ),
             Error::NoSuchCons(a) => (
-                format!("No such constructor {}", (*a).fg(Color::Red)),
+                format!("No such constructor {}", (&*a).fg(Color::Red)),
                 vec![(a.span(), format!("Does not exist"), Color::Red)],
                 vec![],
             ),
             Error::NoSuchClass(a) => (
-                format!("No such class {}", (*a).fg(Color::Red)),
+                format!("No such class {}", (&*a).fg(Color::Red)),
                 vec![(a.span(), format!("Does not exist"), Color::Red)],
                 vec![],
             ),
@@ -224,8 +269,24 @@ impl Error {
                 ],
                 vec![format!("Consider adding the item like {}", format!("=> {} = ...", *item).fg(Color::Blue))],
             ),
+            Error::NoSuchDerive(class) => (
+                format!("Class {} cannot be derived", (*class).fg(Color::Red)),
+                vec![(class.span(), format!("Members of this class must be written by hand"), Color::Red)],
+                vec![format!(
+                    "Only the following classes can be derived: {}",
+                    derive::DERIVABLE.iter().map(|class| class.fg(Color::Blue).to_string()).collect::<Vec<_>>().join(", "),
+                )],
+            ),
+            Error::CannotDerive(class, data) => (
+                format!("Cannot derive {} for {}", (*class).fg(Color::Red), (*data).fg(Color::Yellow)),
+                vec![
+                    (class.span(), format!("Derived here"), Color::Red),
+                    (data.span(), format!("This data type has no variants from which to construct a value"), Color::Yellow),
+                ],
+                vec![],
+            ),
             Error::NoSuchEffect(a) => (
-                format!("No such effect {}", (*a).fg(Color::Red)),
+                format!("No such effect {}", (&*a).fg(Color::Red)),
                 vec![(a.span(), format!("Does not exist"), Color::Red)],
                 vec![],
             ),
@@ -307,6 +368,71 @@ impl Error {
                 ],
                 vec![],
             ),
+            Error::OverlappingMembers(class, old, new) => {
+                let class_name = ctx.classes.get(class).name.clone();
+                let member = |member| ctx.classes.get_member(member).member;
+                (
+                    format!(
+                        "Members {} and {} of class {} overlap",
+                        display(member(old)).fg(Color::Yellow),
+                        display(member(new)).fg(Color::Red),
+                        (*class_name).fg(Color::Cyan),
+                    ),
+                    vec![
+                        (ctx.tys.get_span(member(new)), format!("Conflicting member"), Color::Red),
+                        (ctx.tys.get_span(member(old)), format!("Previous member"), Color::Yellow),
+                    ],
+                    vec![format!("A type may only be a member of a class once, but some types are covered by both members")],
+                )
+            },
+            Error::OrphanMembers(class, old, new) => {
+                let class_name = ctx.classes.get(class).name.clone();
+                let member = |member| ctx.classes.get_member(member).member;
+                let is_orphan = |member| ctx.classes.is_orphan(&ctx.tys, &ctx.datas, class, member);
+                let (orphan, other) = if is_orphan(new) { (new, old) } else { (old, new) };
+                (
+                    format!(
+                        "Orphan member {} of class {} overlaps member {}",
+                        display(member(orphan)).fg(Color::Red),
+                        (*class_name).fg(Color::Cyan),
+                        display(member(other)).fg(Color::Yellow),
+                    ),
+                    vec![
+                        (ctx.tys.get_span(member(orphan)), format!("Declared apart from both {} and its type", (*class_name).fg(Color::Cyan)), Color::Red),
+                        (ctx.tys.get_span(member(other)), format!("Overlapping member"), Color::Yellow),
+                    ],
+                    vec![format!(
+                        "Declare members in the same module as either their class or their type, so that overlapping members are found where they are written",
+                    )],
+                )
+            },
+            Error::AmbiguousImport(name, old, new) => (
+                format!("Item {} imported from multiple places", name.fg(Color::Red)),
+                vec![
+                    (old, format!("Previous import"), Color::Yellow),
+                    (new, format!("Conflicting import"), Color::Red),
+                ],
+                vec![format!(
+                    "Consider importing one of the items with a different name, like {}",
+                    format!("import \"...\" with {} as ...", name).fg(Color::Blue),
+                )],
+            ),
+            Error::NoSuchImport(item, module) => (
+                format!("Module {} has no item named {}", format!("\"{}\"", *module).fg(Color::Yellow), (*item).fg(Color::Red)),
+                vec![
+                    (item.span(), format!("Does not exist"), Color::Red),
+                    (module.span(), format!("Imported from here"), Color::Yellow),
+                ],
+                vec![],
+            ),
+            Error::PrivateImport(item, module) => (
+                format!("Item {} in module {} is private", (*item).fg(Color::Red), format!("\"{}\"", *module).fg(Color::Yellow)),
+                vec![
+                    (item.span(), format!("Item is not visible outside of its module"), Color::Red),
+                    (module.span(), format!("Imported from here"), Color::Yellow),
+                ],
+                vec![format!("Consider making the item public with {}", "pub".fg(Color::Blue))],
+            ),
             Error::PatternNotSupported(lhs, op, rhs, span) => (
                 format!("Arithmetic pattern {} {} {} is not supported", display(lhs).fg(Color::Red), (*op).fg(Color::Red), display(rhs).fg(Color::Red)),
                 vec![(span, format!("Pattern {} used here", (*op).fg(Color::Red)), Color::Red)],
@@ -335,6 +461,23 @@ impl Error {
                 ],
                 vec![],
             ),
+            Error::KindMismatch(ty, kind, expected_span, expected) => (
+                format!("Type has kind {} but kind {} was expected", kind.to_string().fg(Color::Red), expected.to_string().fg(Color::Yellow)),
+                std::iter::once((ty, format!("Has kind {}", kind.to_string().fg(Color::Red)), Color::Red))
+                    // Types are expected to have kind `*` by default, so there's not always a separate origin to point to
+                    .chain(Some((expected_span, format!("Kind {} is expected here", expected.to_string().fg(Color::Yellow)), Color::Yellow))
+                        .filter(|_| expected_span != ty))
+                    .collect(),
+                if kind.arity() > 0 {
+                    vec![format!(
+                        "Types of kind {} are type constructors that must be given {} type parameter(s) to produce a type",
+                        kind.to_string().fg(Color::Blue),
+                        kind.arity(),
+                    )]
+                } else {
+                    vec![]
+                },
+            ),
             Error::DefTypeNotSpecified(def, usage, name) => (
                 format!("Type of {} must be fully specified", name.fg(Color::Red)),
                 vec![
@@ -403,6 +546,13 @@ impl Error {
                 Vec::new(),
                 vec![format!("All lang items must be defined")],
             ),
+            Error::WrongLangItemShape(name, span, shape) => (
+                format!("Lang item {} does not have the expected shape", name.fg(Color::Yellow)),
+                vec![
+                    (span, format!("Lang item is declared here"), Color::Red),
+                ],
+                vec![format!("Lang item {} must have the same shape as {}", name.fg(Color::Yellow), shape.fg(Color::Blue))],
+            ),
             Error::NoBasin(span) => (
                 format!("Effect propagated, but no handler exists to catch it"/*, display(eff_ty).fg(Color::Yellow)*/),
                 vec![
@@ -410,8 +560,56 @@ impl Error {
                 ],
                 vec![format!("Place this expression within a {} block", "@{ ... }".fg(Color::Blue))],
             ),
+            Error::HostNameNotLiteral(span) => (
+                format!("Host intrinsic names must be string literals"),
+                vec![
+                    (span, format!("This is not a string literal"), Color::Red),
+                ],
+                vec![format!("Host intrinsics are looked up by name when the program runs")],
+            ),
+            Error::HostTyNotConcrete(span, ty) => (
+                format!("Host intrinsic cannot have generic type {}", display(ty).fg(Color::Red)),
+                vec![
+                    (span, format!("This has type {}", display(ty).fg(Color::Yellow)), Color::Red),
+                ],
+                vec![format!("Annotate the value with a type that has no generic types, as in {}", "let x : Nat = @host(...) in ...".fg(Color::Blue))],
+            ),
+            Error::MayNotTerminate(name, call) => (
+                format!("Definition {} may not terminate", (*name).fg(Color::Red)),
+                vec![
+                    (name.span(), format!("Recursive definition"), Color::Red),
+                    (call, format!("No argument to this recursion is structurally smaller than before"), Color::Yellow),
+                ],
+                vec![format!("Mark the definition with {} to allow it to recurse without limit, or loop with {}", "$[partial]".fg(Color::Blue), "go".fg(Color::Blue))],
+            ),
+            Error::UsesPartial(name, partial, span) => (
+                format!("Definition {} may not terminate", (*name).fg(Color::Red)),
+                vec![
+                    (name.span(), format!("Definition is not marked as partial"), Color::Red),
+                    (span, format!("{} is partial, so it may not terminate", partial.fg(Color::Yellow)), Color::Yellow),
+                ],
+                vec![format!("Mark the definition with {} too", "$[partial]".fg(Color::Blue))],
+            ),
         };
 
+        (msg, spans, notes)
+    }
+
+    /// Produce the message, labels, and notes of the error without rendering them.
+    pub fn diagnostic(self, ctx: &Context) -> Diagnostic {
+        let (msg, labels, notes) = self.parts(ctx);
+        Diagnostic {
+            msg,
+            labels: labels.into_iter().map(|(span, msg, _)| (span, msg)).collect(),
+            notes,
+        }
+    }
+
+    pub fn write<C: ariadne::Cache<SrcId>>(self, ctx: &Context, cache: C, main_src: SrcId, writer: impl Write) {
+        use ariadne::{Report, ReportKind, Label, Span, Config};
+
+        let (msg, spans, notes) = self.parts(ctx);
+
         let mut report = Report::build(
             ReportKind::Error,
             spans.first().map(|s| s.0.src()).unwrap_or(main_src),
@@ -320,7 +320,7 @@ impl AbstractPat {
                 }
                 None
             },
-            Ty::Gen(_, _) | Ty::Assoc(_, _, _) => {
+            Ty::Gen(_, _) | Ty::SelfType | Ty::Assoc(_, _, _) | Ty::App(_, _) => {
                 for pat in filter {
                     match pat {
                         AbstractPat::Wildcard => return None,
@@ -352,7 +352,7 @@ impl AbstractPat {
                     ExamplePat::List((0..n).map(|_| ExamplePat::Wildcard).collect())
                 })
             },
-            Ty::Func(_, _) => {
+            Ty::Func(_, _) | Ty::Effect(_, _) => {
                 for pat in filter {
                     match pat {
                         AbstractPat::Wildcard => return None,
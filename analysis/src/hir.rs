@@ -8,6 +8,7 @@ pub trait Meta {
     type Class;
     type Global;
     type Effect;
+    type EffectSet;
 }
 
 impl Meta for InferMeta {
@@ -16,14 +17,17 @@ impl Meta for InferMeta {
     type Class = ClassVar;
     type Global = (DefId, Vec<Self>);
     type Effect = EffectVar;
+    type EffectSet = EffectVar;
 }
 
 impl Meta for TyMeta {
     type Ty = TyId;
     type Data = SrcNode<DataId>;
-    type Class = Option<ClassId>; // Required because we don't have proper error classes yet
+    // (class, class parameters, generic types of the item); required because we don't have proper error classes yet
+    type Class = Option<(ClassId, Vec<TyId>, Vec<TyId>)>;
     type Global = (DefId, Vec<Self>);
     type Effect = EffectId;
+    type EffectSet = EffectId;
 }
 
 impl Meta for ConMeta {
@@ -32,6 +36,7 @@ impl Meta for ConMeta {
     type Class = !;
     type Global = ConProcId;
     type Effect = ConEffectId;
+    type EffectSet = Vec<ConEffectId>;
 }
 
 #[derive(Debug)]
@@ -114,6 +119,12 @@ pub enum Intrinsic {
     NegReal,
     EqChar,
     EqNat,
+    EqReal,
+    NatToReal,
+    IntToReal,
+    RealToInt,
+    RealToNat,
+    DisplayReal,
     Go,
     Print,
     Input,
@@ -121,6 +132,8 @@ pub enum Intrinsic {
     SkipList,
     TrimList,
     Propagate,
+    /// Call a function provided by the host that is embedding the program, by name.
+    Host(Ident),
 }
 
 #[derive(Debug)]
@@ -149,13 +162,17 @@ pub enum Expr<M: Meta> {
 
     // Blocks propagation of effects, collecting them
     // i.e: `@{ foo?; bar?; x }` gets type `foo + bar ~ X`
-    Basin(M::Effect, Node<Self, M>),
+    Basin(M::EffectSet, Node<Self, M>),
     Suspend(M::Effect, Node<Self, M>),
     Handle {
         expr: Node<Self, M>,
         eff: M::Effect,
         send: Node<Ident, M>,
         recv: Node<Self, M>,
+        /// Whether the handler is given the resumption of the handled computation (in which case `send` is a tuple of
+        /// the value sent and the resumption, and `recv` produces the result of the entire handler), rather than
+        /// always resuming it with the value that `recv` produces.
+        resumable: bool,
     },
 }
 
@@ -20,13 +20,21 @@ pub enum TyInfo {
     // An opaque associated type that *cannot* be determined due to lack of information
     Assoc(TyVar, ClassId, SrcNode<Ident>),
     Effect(EffectVar, TyVar),
+    // A type constructor applied to a type parameter. Once the type constructor is known, this is equivalent to the
+    // type that it produces (see `follow_info`).
+    App(TyVar, TyVar),
+    ListCon,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum EffectInfo {
     Unknown,
     Ref(EffectVar),
-    Known(EffectDeclId, Vec<TyVar>),
+    /// A set of effects that cannot grow through inference. No two effects in the set share a declaration.
+    Known(Vec<(EffectDeclId, Vec<TyVar>)>),
+    /// The effects that have been found to flow into a basin so far. The set may grow until constraints stop making
+    /// progress, at which point it becomes known.
+    Open(Vec<(EffectDeclId, Vec<TyVar>)>),
 }
 
 #[derive(Clone, Default, Debug)]
@@ -58,8 +66,8 @@ pub enum InferError {
     NoSuchField(TyVar, Span, SrcNode<Ident>),
     InvalidUnaryOp(SrcNode<ast::UnaryOp>, TyVar),
     InvalidBinaryOp(SrcNode<ast::BinaryOp>, TyVar, TyVar),
-    // (_, _, obligation span, _, usage span)
-    TypeDoesNotFulfil(ClassId, TyVar, Span, Option<Span>, Span),
+    // (_, class params, _, obligation span, _, usage span)
+    TypeDoesNotFulfil(ClassId, Vec<TyVar>, TyVar, Span, Option<Span>, Span),
     RecursiveAlias(AliasId, TyVar, Span),
     PatternNotSupported(TyVar, SrcNode<ast::BinaryOp>, TyVar, Span),
     AmbiguousClassItem(SrcNode<Ident>, Vec<ClassId>),
@@ -71,10 +79,13 @@ enum Constraint {
     Access(TyVar, SrcNode<Ident>, TyVar),
     Update(TyVar, SrcNode<Ident>, TyVar),
     Binary(SrcNode<ast::BinaryOp>, TyVar, TyVar, TyVar),
-    Impl(TyVar, ClassId, Span, Vec<(SrcNode<Ident>, TyVar)>, Span),
+    // (ty, class, class params, obligation span, unchecked assoc, usage span)
+    Impl(TyVar, ClassId, Vec<TyVar>, Span, Vec<(SrcNode<Ident>, TyVar)>, Span),
     ClassField(TyVar, ClassVar, SrcNode<Ident>, TyVar, Span),
     ClassAssoc(TyVar, ClassVar, SrcNode<Ident>, TyVar, Span),
     EffectSendRecv(EffectVar, TyVar, TyVar, Span),
+    // (handled expression effect, handled effect, enclosing basin, span)
+    Handle(EffectVar, EffectVar, Option<EffectVar>, Span),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -89,17 +100,13 @@ pub struct EffectVar(usize);
 pub struct Infer<'a> {
     ctx: &'a mut Context,
     gen_scope: Option<GenScopeId>,
-    // TODO: Add EffectSet to each type var, like
-    // enum EffectSet {
-    //     Unknown,
-    //     // Effect set can grow through inference
-    //     Open(Vec<EffectVar>),
-    //     // Effect set cannot grow through inference and instead just generates check constraints
-    //     Closed(Vec<EffectVar>),
-    // }
     vars: Vec<(Span, TyInfo, Result<(), ()>)>,
-    class_vars: Vec<(Span, Option<ClassId>)>,
+    // The type parameters of a class (and the generic types of the item being accessed) are only known once the class
+    // has been determined
+    class_vars: Vec<(Span, Option<ClassId>, Vec<TyVar>, Vec<TyVar>)>,
     effect_vars: Vec<(Span, EffectInfo)>,
+    // Whether open effect sets have been closed, which happens once no other constraints can make progress
+    effects_closed: bool,
     constraints: VecDeque<Constraint>,
     errors: Vec<InferError>,
     self_type: Option<TyVar>,
@@ -114,6 +121,7 @@ impl<'a> Infer<'a> {
             vars: Vec::new(),
             class_vars: Vec::new(),
             effect_vars: Vec::new(),
+            effects_closed: false,
             constraints: VecDeque::new(),
             errors: Vec::new(),
             self_type: None,
@@ -180,7 +188,8 @@ impl<'a> Infer<'a> {
                 | TyInfo::Unknown(_)
                 | TyInfo::Prim(_)
                 | TyInfo::Gen(..)
-                | TyInfo::SelfType => {},
+                | TyInfo::SelfType
+                | TyInfo::ListCon => {},
                 TyInfo::List(item) => self.set_error(item),
                 TyInfo::Tuple(fields) => fields
                     .into_iter()
@@ -202,6 +211,10 @@ impl<'a> Infer<'a> {
                     // TODO: Set error for eff
                     self.set_error(out);
                 },
+                TyInfo::App(f, param) => {
+                    self.set_error(f);
+                    self.set_error(param);
+                },
             }
         }
     }
@@ -221,13 +234,77 @@ impl<'a> Infer<'a> {
         self.vars[ty.0].0
     }
 
+    // Applications of known type constructors are normalised into the type that they produce (i.e: `F A` where
+    // `F = Maybe` is `Maybe A`)
     fn follow_info(&self, ty: TyVar) -> TyInfo {
         match &self.vars[ty.0].1 {
             TyInfo::Ref(x) => self.follow_info(*x),
+            TyInfo::App(f, param) => match self.follow_info(*f) {
+                TyInfo::Data(data, mut args) => {
+                    args.push(*param);
+                    TyInfo::Data(data, args)
+                },
+                TyInfo::ListCon => TyInfo::List(*param),
+                // Applying an erroneous type constructor produces an error
+                TyInfo::Error(reason) => TyInfo::Error(reason),
+                _ => TyInfo::App(*f, *param),
+            },
             info => info.clone(),
         }
     }
 
+    // Like `info`, but applications of known type constructors are normalised
+    fn info_normalised(&self, ty: TyVar) -> TyInfo {
+        match self.info(ty) {
+            TyInfo::App(_, _) => self.follow_info(ty),
+            info => info,
+        }
+    }
+
+    // The type constructor at the head of a chain of applications
+    fn app_head(&self, ty: TyVar) -> TyVar {
+        match self.follow_info(ty) {
+            TyInfo::App(f, _) => self.app_head(f),
+            _ => self.follow(ty),
+        }
+    }
+
+    // Returns true if the type is an application of a type constructor that is not yet known
+    fn is_unknown_app(&self, ty: TyVar) -> bool {
+        matches!(self.follow_info(ty), TyInfo::App(_, _))
+            && matches!(self.follow_info(self.app_head(ty)), TyInfo::Unknown(_))
+    }
+
+    /// Determine the kind of a type, if enough is known about it to do so.
+    pub fn kind_of(&self, ty: TyVar) -> Option<Kind> {
+        match self.follow_info(ty) {
+            TyInfo::Ref(_) => unreachable!(),
+            TyInfo::Unknown(_) | TyInfo::Error(_) => None,
+            TyInfo::Prim(_)
+            | TyInfo::List(_)
+            | TyInfo::Tuple(_)
+            | TyInfo::Record(_)
+            | TyInfo::Func(_, _)
+            | TyInfo::Assoc(_, _, _)
+            | TyInfo::Effect(_, _) => Some(Kind::Type),
+            TyInfo::Data(data, args) => {
+                let scope = self.ctx.tys.get_gen_scope(self.ctx.datas.data_gen_scope(data));
+                Some(Kind::of_params((args.len()..scope.len())
+                    .map(|idx| scope.get(idx).kind.clone())
+                    .collect()))
+            },
+            TyInfo::Gen(idx, scope, _) => Some(self.ctx.tys.get_gen_scope(scope).get(idx).kind.clone()),
+            TyInfo::SelfType => Some(self.self_obligations
+                .first()
+                .map_or(Kind::Type, |class| self.ctx.classes.get(*class).kind.clone())),
+            TyInfo::App(f, _) => match self.kind_of(f)? {
+                Kind::Func(_, o) => Some(*o),
+                Kind::Type => None,
+            },
+            TyInfo::ListCon => Some(Kind::Func(Box::new(Kind::Type), Box::new(Kind::Type))),
+        }
+    }
+
     pub fn insert(&mut self, span: Span, info: TyInfo) -> TyVar {
         let id = TyVar(self.vars.len());
         let err = if matches!(&info, TyInfo::Error(_)) { Err(()) } else { Ok(()) };
@@ -268,24 +345,29 @@ impl<'a> Infer<'a> {
                 .map(|param| self.instantiate(param, span, f, self_ty))
                 .collect()),
             Ty::Gen(index, scope) => TyInfo::Ref(f(index, scope, self.ctx)), // TODO: Check scope is valid for recursive scopes
+            Ty::App(head, param) => TyInfo::App(self.instantiate(head, span, f, self_ty), self.instantiate(param, span, f, self_ty)),
+            Ty::ListCon => TyInfo::ListCon,
             Ty::SelfType => TyInfo::Ref(self_ty.expect("Found self type during instantiation but no self type is available to substitute")),
             Ty::Assoc(inner, class_id, assoc) => {
                 let span = span.unwrap_or_else(|| self.ctx.tys.get_span(ty));
                 let inner = self.instantiate(inner, span, f, self_ty);
                 let assoc_ty = self.unknown(span);
-                self.make_impl(inner, class_id, span, vec![(assoc, assoc_ty)], span);
+                self.make_impl(inner, class_id, Vec::new(), span, vec![(assoc, assoc_ty)], span);
                 TyInfo::Ref(assoc_ty)
             },
             Ty::Effect(eff, out) => match self.ctx.tys.get_effect(eff) {
                 Effect::Error => TyInfo::Error(ErrorReason::Invalid),
-                Effect::Known(decl, args) => {
-                    let args = args
+                Effect::Known(effs) => {
+                    let effs = effs
                         .into_iter()
-                        .map(|param| self.instantiate(param, span, f, self_ty))
+                        .map(|(decl, args)| (decl, args
+                            .into_iter()
+                            .map(|param| self.instantiate(param, span, f, self_ty))
+                            .collect()))
                         .collect();
                     let eff = self.insert_effect(
                         span.unwrap_or_else(|| self.ctx.tys.get_span(ty)),
-                        EffectInfo::Known(decl, args),
+                        EffectInfo::Known(effs),
                     );
                     TyInfo::Effect(eff, self.instantiate(out, span, f, self_ty))
                 },
@@ -311,27 +393,27 @@ impl<'a> Infer<'a> {
     }
 
     // `unchecked_assoc` allows unification of type variables with an instance's associated type
-    pub fn make_impl(&mut self, ty: TyVar, class: ClassId, obl_span: Span, unchecked_assoc: Vec<(SrcNode<Ident>, TyVar)>, use_span: Span) {
-        self.constraints.push_back(Constraint::Impl(ty, class, obl_span, unchecked_assoc, use_span));
+    pub fn make_impl(&mut self, ty: TyVar, class: ClassId, params: Vec<TyVar>, obl_span: Span, unchecked_assoc: Vec<(SrcNode<Ident>, TyVar)>, use_span: Span) {
+        self.constraints.push_back(Constraint::Impl(ty, class, params, obl_span, unchecked_assoc, use_span));
     }
 
     pub fn make_class_field_known(&mut self, ty: TyVar, field_name: SrcNode<Ident>, class_id: Option<ClassId>, field_ty: TyVar, span: Span) -> ClassVar {
         let class = ClassVar(self.class_vars.len());
-        self.class_vars.push((span, class_id));
+        self.class_vars.push((span, class_id, Vec::new(), Vec::new()));
         self.constraints.push_back(Constraint::ClassField(ty, class, field_name, field_ty, span));
         class
     }
 
     pub fn make_class_field(&mut self, ty: TyVar, field_name: SrcNode<Ident>, field_ty: TyVar, span: Span) -> ClassVar {
         let class = ClassVar(self.class_vars.len());
-        self.class_vars.push((span, None));
+        self.class_vars.push((span, None, Vec::new(), Vec::new()));
         self.constraints.push_back(Constraint::ClassField(ty, class, field_name, field_ty, span));
         class
     }
 
     pub fn make_class_assoc(&mut self, ty: TyVar, assoc_name: SrcNode<Ident>, assoc_ty: TyVar, span: Span) -> ClassVar {
         let class = ClassVar(self.class_vars.len());
-        self.class_vars.push((span, None));
+        self.class_vars.push((span, None, Vec::new(), Vec::new()));
         self.constraints.push_back(Constraint::ClassAssoc(ty, class, assoc_name, assoc_ty, span));
         class
     }
@@ -352,6 +434,14 @@ impl<'a> Infer<'a> {
         }
     }
 
+    // The effect variable that `eff` refers to, possibly through several references
+    fn root_effect(&self, eff: EffectVar) -> EffectVar {
+        match &self.effect_vars[eff.0].1 {
+            EffectInfo::Ref(eff) => self.root_effect(*eff),
+            _ => eff,
+        }
+    }
+
     pub fn insert_effect(&mut self, span: Span, eff: EffectInfo) -> EffectVar {
         let id = EffectVar(self.effect_vars.len());
         self.effect_vars.push((span, eff));
@@ -366,6 +456,12 @@ impl<'a> Infer<'a> {
         self.constraints.push_back(Constraint::EffectSendRecv(eff, send, recv, span));
     }
 
+    // Handle the effect `handled` (which must be a single effect) out of the effects of the handled expression, `eff`.
+    // Any other effects that the expression performs are performed in the enclosing basin instead.
+    pub fn make_handle(&mut self, eff: EffectVar, handled: EffectVar, basin: Option<EffectVar>, span: Span) {
+        self.constraints.push_back(Constraint::Handle(eff, handled, basin, span));
+    }
+
     pub fn emit(&mut self, err: InferError) {
         self.errors.push(err);
     }
@@ -381,7 +477,8 @@ impl<'a> Infer<'a> {
                 | TyInfo::Error(_)
                 | TyInfo::Prim(_)
                 | TyInfo::SelfType
-                | TyInfo::Gen(_, _, _) => false,
+                | TyInfo::Gen(_, _, _)
+                | TyInfo::ListCon => false,
                 TyInfo::Ref(y) => x == y || self.occurs_in_inner(x, y, seen),
                 TyInfo::List(item) => x == item || self.occurs_in_inner(x, item, seen),
                 TyInfo::Func(i, o) => x == i || x == o || self.occurs_in_inner(x, i, seen) || self.occurs_in_inner(x, o, seen),
@@ -395,11 +492,13 @@ impl<'a> Infer<'a> {
                     .into_iter()
                     .any(|y| x == y || self.occurs_in_inner(x, y, seen)),
                 TyInfo::Assoc(inner, _, _) => x == inner || self.occurs_in_inner(x, inner, seen),
+                TyInfo::App(f, param) => x == f || x == param || self.occurs_in_inner(x, f, seen) || self.occurs_in_inner(x, param, seen),
                 TyInfo::Effect(eff, out) => self.occurs_in_inner(x, out, seen) || match self.follow_effect(eff) {
                     EffectInfo::Unknown => false,
                     EffectInfo::Ref(_) => unreachable!(),
-                    EffectInfo::Known(_, params) => params
+                    EffectInfo::Known(effs) | EffectInfo::Open(effs) => effs
                         .into_iter()
+                        .flat_map(|(_, params)| params)
                         .any(|y| x == y || self.occurs_in_inner(x, y, seen)),
                 },
             };
@@ -440,30 +539,84 @@ impl<'a> Infer<'a> {
         }
 
         // TODO: Allow errors that mention effects instead of types
+        // An effect set flows into another if every effect in the former is also in the latter
         fn make_flow_effect(
             infer: &mut Infer,
             (x, x_ty): (EffectVar, TyVar),
             (y, y_ty): (EffectVar, TyVar),
         ) -> Result<(), (TyVar, TyVar)> {
-            match (infer.follow_effect(x), infer.follow_effect(y)) {
-                // TODO: These shouldn't be reachable?
-                (EffectInfo::Ref(x), _) => make_flow_effect(infer, (x, x_ty), (y, y_ty)),
-                (_, EffectInfo::Ref(y)) => make_flow_effect(infer, (x, x_ty), (y, y_ty)),
-
-                (EffectInfo::Unknown, _) => Ok(infer.effect_vars[x.0].1 = EffectInfo::Ref(y)),
-                (_, EffectInfo::Unknown) => Ok(infer.effect_vars[y.0].1 = EffectInfo::Ref(x)),
-                (EffectInfo::Known(x, xs), EffectInfo::Known(y, ys)) if x == y => {
-                    // TODO: Unnecessarily conservative, variance of effect generics should be determined
-                    let co_error = make_flow_many(infer, xs.iter().copied(), ys.iter().copied()).err();
-                    let contra_error = make_flow_many(infer, ys, xs).err().map(|(a, b)| (b, a));
-                    co_error.or(contra_error).map(Err).unwrap_or(Ok(()))
+            let (x, y) = (infer.root_effect(x), infer.root_effect(y));
+            if x == y { return Ok(()) }
+            let (xs, x_open) = match (infer.effect_vars[x.0].1.clone(), infer.effect_vars[y.0].1.clone()) {
+                (EffectInfo::Ref(_), _) | (_, EffectInfo::Ref(_)) => unreachable!(),
+                (EffectInfo::Unknown, _) => return Ok(infer.effect_vars[x.0].1 = EffectInfo::Ref(y)),
+                (_, EffectInfo::Unknown) => return Ok(infer.effect_vars[y.0].1 = EffectInfo::Ref(x)),
+                (EffectInfo::Known(xs), _) => (xs, false),
+                (EffectInfo::Open(xs), _) => (xs, true),
+            };
+
+            let mut args = Vec::new();
+            let mut err = None;
+            match infer.effect_vars[y.0].1.clone() {
+                EffectInfo::Known(ys) => for (decl, xs) in xs {
+                    match ys.iter().find(|(y, _)| *y == decl) {
+                        Some((_, ys)) => args.push((xs, ys.clone())),
+                        None => err = Some((x_ty, y_ty)),
+                    }
                 },
-                (_, _) => Err((x_ty, y_ty)),
+                // Effects not yet in an open set get added to it
+                EffectInfo::Open(mut ys) => {
+                    for (decl, xs) in xs {
+                        match ys.iter().find(|(y, _)| *y == decl) {
+                            Some((_, ys)) => args.push((xs, ys.clone())),
+                            None => ys.push((decl, xs)),
+                        }
+                    }
+                    infer.effect_vars[y.0].1 = EffectInfo::Open(ys);
+                },
+                _ => unreachable!(),
+            }
+            // With nothing else to go on, a basin is assumed to perform all of the effects that it flows into
+            if x_open {
+                infer.effect_vars[x.0].1 = EffectInfo::Ref(y);
             }
+
+            for (xs, ys) in args {
+                // TODO: Unnecessarily conservative, variance of effect generics should be determined
+                let co_error = make_flow_many(infer, xs.iter().copied(), ys.iter().copied()).err();
+                let contra_error = make_flow_many(infer, ys, xs).err().map(|(a, b)| (b, a));
+                err = err.or(co_error).or(contra_error);
+            }
+            err.map(Err).unwrap_or(Ok(()))
+        }
+
+        // Type constructors are invariant
+        fn make_flow_invariant(infer: &mut Infer, x: TyVar, y: TyVar) -> Result<(), (TyVar, TyVar)> {
+            let co_error = infer.make_flow_inner(x, y).err();
+            let contra_error = infer.make_flow_inner(y, x).err().map(|(a, b)| (b, a));
+            co_error.or(contra_error).map(Err).unwrap_or(Ok(()))
+        }
+
+        // Unify an application of a type constructor with a type that is not an application, splitting the type into a
+        // type constructor and its last parameter
+        fn make_flow_app(infer: &mut Infer, (f, param): (TyVar, TyVar), other: TyVar, other_info: TyInfo) -> Result<(), ()> {
+            let span = infer.span(other);
+            let (head, last) = match other_info {
+                TyInfo::Data(data, mut args) => if let Some(last) = args.pop() {
+                    (infer.insert(span, TyInfo::Data(data, args)), last)
+                } else {
+                    return Err(());
+                },
+                TyInfo::List(item) => (infer.insert(span, TyInfo::ListCon), item),
+                _ => return Err(()),
+            };
+            let f_err = make_flow_invariant(infer, f, head).err();
+            let param_err = make_flow_invariant(infer, param, last).err();
+            f_err.or(param_err).map(|_| Err(())).unwrap_or(Ok(()))
         }
 
         if x == y { return Ok(()) } // If the vars are equal, we have no need to check flow
-        match (self.info(x), self.info(y)) {
+        match (self.info_normalised(x), self.info_normalised(y)) {
             // Follow references
             (TyInfo::Ref(x), _) => self.make_flow_inner(x, y),
             (_, TyInfo::Ref(y)) => self.make_flow_inner(x, y),
@@ -528,6 +681,14 @@ impl<'a> Infer<'a> {
                 let o_err = self.make_flow_inner(x_out, y_out).err();
                 o_err.or(eff_err).map(Err).unwrap_or(Ok(()))
             },
+            (TyInfo::App(x_f, x_param), TyInfo::App(y_f, y_param)) => {
+                let f_err = make_flow_invariant(self, x_f, y_f).err();
+                let param_err = make_flow_invariant(self, x_param, y_param).err();
+                f_err.or(param_err).map(Err).unwrap_or(Ok(()))
+            },
+            (TyInfo::App(f, param), y_info) => make_flow_app(self, (f, param), y, y_info).map_err(|()| (x, y)),
+            (x_info, TyInfo::App(f, param)) => make_flow_app(self, (f, param), x, x_info).map_err(|()| (x, y)),
+            (TyInfo::ListCon, TyInfo::ListCon) => Ok(()),
             (_, _) => Err((x, y)),
         }
     }
@@ -539,7 +700,12 @@ impl<'a> Infer<'a> {
         match self.info(ty) {
             TyInfo::Ref(x) => self.try_reinstantiate(span, x),
             TyInfo::Error(reason) => self.insert(self.span(ty), TyInfo::Error(reason)),
-            TyInfo::Unknown(_) | TyInfo::Prim(_) => ty,
+            TyInfo::Unknown(_) | TyInfo::Prim(_) | TyInfo::ListCon => ty,
+            TyInfo::App(f, param) => {
+                let f = self.try_reinstantiate(span, f);
+                let param = self.try_reinstantiate(span, param);
+                self.insert(self.span(ty), TyInfo::App(f, param))
+            },
             TyInfo::List(item) => {
                 let item = self.try_reinstantiate(span, item);
                 self.insert(self.span(ty), TyInfo::List(item))
@@ -580,14 +746,17 @@ impl<'a> Infer<'a> {
             },
             TyInfo::Effect(eff, out) => {
                 let eff = match self.follow_effect(eff) {
-                    EffectInfo::Unknown => eff,
+                    EffectInfo::Unknown | EffectInfo::Open(_) => eff,
                     EffectInfo::Ref(_) => unreachable!(), // `follow_effect` shouldn't ever return Ref
-                    EffectInfo::Known(eff, args) => {
-                        let args = args
+                    EffectInfo::Known(effs) => {
+                        let effs = effs
                             .into_iter()
-                            .map(|arg| self.try_reinstantiate(span, arg))
+                            .map(|(decl, args)| (decl, args
+                                .into_iter()
+                                .map(|arg| self.try_reinstantiate(span, arg))
+                                .collect()))
                             .collect();
-                        self.insert_effect(self.span(ty), EffectInfo::Known(eff, args))
+                        self.insert_effect(self.span(ty), EffectInfo::Known(effs))
                     },
                 };
                 let out = self.try_reinstantiate(span, out);
@@ -669,6 +838,16 @@ impl<'a> Infer<'a> {
                     self.make_flow(b, output_item, EqInfo::new(op.span(), "The types of joined lists must be equal".to_string()));
                     Some(Ok(TyInfo::List(output_item)))
                 },
+                // Integer division is total: there is no result when the divisor is zero
+                (Div | Rem, TyInfo::Prim(prim_a @ (ty::Prim::Nat | ty::Prim::Int)), TyInfo::Prim(prim_b)) if prim_a == prim_b => {
+                    if let Some(maybe) = self.ctx.datas.lang.maybe {
+                        let quotient = self.insert(self.span(output), TyInfo::Prim(prim_a));
+                        Some(Ok(TyInfo::Data(maybe, vec![quotient])))
+                    } else {
+                        // The missing lang item has already been reported
+                        Some(Ok(TyInfo::Error(ErrorReason::Invalid)))
+                    }
+                },
                 (_, TyInfo::Prim(prim_a), TyInfo::Prim(prim_b)) => {
                     use ty::Prim::*;
                     lazy_static::lazy_static! {
@@ -684,8 +863,6 @@ impl<'a> Infer<'a> {
                             ((Add, Nat, Nat), Nat),
                             ((Sub, Nat, Nat), Int),
                             ((Mul, Nat, Nat), Nat),
-                            ((Rem, Nat, Nat), Nat),
-                            ((Div, Nat, Nat), Real),
                             ((Eq, Nat, Nat), Bool),
                             ((NotEq, Nat, Nat), Bool),
                             ((Less, Nat, Nat), Bool),
@@ -697,7 +874,6 @@ impl<'a> Infer<'a> {
                             ((Add, Int, Int), Int),
                             ((Sub, Int, Int), Int),
                             ((Mul, Int, Int), Int),
-                            ((Div, Int, Int), Real),
                             ((Eq, Int, Int), Bool),
                             ((NotEq, Int, Int), Bool),
                             ((Less, Int, Int), Bool),
@@ -705,6 +881,18 @@ impl<'a> Infer<'a> {
                             ((More, Int, Int), Bool),
                             ((MoreEq, Int, Int), Bool),
 
+                            // Real
+                            ((Add, Real, Real), Real),
+                            ((Sub, Real, Real), Real),
+                            ((Mul, Real, Real), Real),
+                            ((Div, Real, Real), Real),
+                            ((Eq, Real, Real), Bool),
+                            ((NotEq, Real, Real), Bool),
+                            ((Less, Real, Real), Bool),
+                            ((LessEq, Real, Real), Bool),
+                            ((More, Real, Real), Bool),
+                            ((MoreEq, Real, Real), Bool),
+
                             // Char
                             ((Eq, Char, Char), Bool),
                             ((NotEq, Char, Char), Bool),
@@ -732,7 +920,7 @@ impl<'a> Infer<'a> {
                     let result_ty = self.insert(self.span(output), info);
                     self.make_flow(result_ty, output, self.span(output));
                 })),
-            Constraint::Impl(ty, obligation, obl_span, unchecked_assoc, use_span) => self.resolve_obligation(ty, obligation, obl_span, use_span).map(|res| match res {
+            Constraint::Impl(ty, obligation, params, obl_span, unchecked_assoc, use_span) => self.resolve_obligation(ty, obligation, &params, obl_span, use_span).map(|res| match res {
                     Ok(member) => {
                         for (assoc, assoc_ty) in unchecked_assoc {
                             match member {
@@ -772,9 +960,15 @@ impl<'a> Infer<'a> {
             Constraint::ClassField(ty, class, field, field_ty, span) => self.try_resolve_class_from_field(ty, class, field.clone(), field_ty, span),
             Constraint::ClassAssoc(ty, class, assoc, assoc_ty, span) => self.try_resolve_class_from_assoc(ty, class, assoc.clone(), assoc_ty, span),
             Constraint::EffectSendRecv(eff, send, recv, span) => match self.follow_effect(eff) {
-                EffectInfo::Unknown => None,
+                EffectInfo::Unknown | EffectInfo::Open(_) => None,
                 EffectInfo::Ref(_) => unreachable!(),
-                EffectInfo::Known(decl, args) => {
+                // It's not possible to tell which of several effects is being suspended
+                EffectInfo::Known(effs) if effs.len() != 1 => {
+                    let eff = self.insert_effect(span, EffectInfo::Known(Vec::new()));
+                    Some(Err(InferError::CannotInferEffect(eff)))
+                },
+                EffectInfo::Known(mut effs) => {
+                    let (decl, args) = effs.remove(0);
                     let send_ty = self.instantiate(
                         self.ctx.effects.get_decl(decl).send.expect("Send must be init"),
                         span,
@@ -794,6 +988,59 @@ impl<'a> Infer<'a> {
                     Some(Ok(()))
                 },
             },
+            Constraint::Handle(eff, handled, basin, span) => {
+                let (handled_decl, handled_args) = match self.follow_effect(handled) {
+                    EffectInfo::Known(mut effs) if effs.len() == 1 => effs.remove(0),
+                    _ => unreachable!("Handled effect must be a single known effect"),
+                };
+                let eff = self.root_effect(eff);
+                let effs = match self.effect_vars[eff.0].1.clone() {
+                    // Nothing else is known about the handled expression, so assume that it performs only the handled effect
+                    EffectInfo::Unknown if self.effects_closed => {
+                        self.effect_vars[eff.0].1 = EffectInfo::Ref(handled);
+                        return Some(Ok(()));
+                    },
+                    EffectInfo::Unknown => return None,
+                    EffectInfo::Ref(_) => unreachable!(),
+                    EffectInfo::Known(effs) => effs,
+                    // A basin that gets handled performs the handled effect, along with whatever it has collected
+                    EffectInfo::Open(mut effs) => {
+                        if !effs.iter().any(|(decl, _)| *decl == handled_decl) {
+                            effs.push((handled_decl, handled_args.clone()));
+                        }
+                        self.effect_vars[eff.0].1 = EffectInfo::Known(effs.clone());
+                        effs
+                    },
+                };
+
+                let mut rest = Vec::new();
+                for (decl, args) in effs {
+                    if decl == handled_decl {
+                        for (x, y) in args.into_iter().zip(handled_args.iter().copied()) {
+                            self.make_flow(x, y, span);
+                            self.make_flow(y, x, span);
+                        }
+                    } else {
+                        rest.push((decl, args));
+                    }
+                }
+
+                if rest.is_empty() {
+                    Some(Ok(()))
+                } else {
+                    // Unhandled effects are performed by the enclosing basin
+                    let rest = self.insert_effect(span, EffectInfo::Known(rest));
+                    if let Some(basin) = basin {
+                        let unit = self.insert(span, TyInfo::Tuple(Vec::new()));
+                        let rest_ty = self.insert(span, TyInfo::Effect(rest, unit));
+                        let basin_ty = self.insert(span, TyInfo::Effect(basin, unit));
+                        self.make_flow(rest_ty, basin_ty, span);
+                        Some(Ok(()))
+                    } else {
+                        Some(Err(InferError::CannotInferEffect(rest)))
+                    }
+                }
+            },
         }
     }
 
@@ -815,7 +1062,7 @@ impl<'a> Infer<'a> {
                 self.class_vars[class_var.0].1 = Some(class_id); // Can't fail
 
                 // Require an implementation to exist
-                self.make_impl(ty, class_id, span, vec![
+                self.make_impl(ty, class_id, Vec::new(), span, vec![
                     (assoc, assoc_ty),
                 ], span);
 
@@ -844,13 +1091,34 @@ impl<'a> Infer<'a> {
             },
             1 => {
                 let class_id = possible_classes.into_iter().next().unwrap();
+                // Each use of a class item gets fresh type parameters, to be determined by the member that gets selected
+                let params = (0..self.ctx.tys.get_gen_scope(self.ctx.classes.get(class_id).gen_scope).len())
+                    .map(|_| self.unknown(span))
+                    .collect::<Vec<_>>();
                 self.class_vars[class_var.0].1 = Some(class_id); // Can't fail
-                self.make_impl(ty, class_id, span, Vec::new(), span);
-                let field_ty_id = **self.ctx.classes
-                    .get(class_id)
-                    .field(*field)
-                    .unwrap();
-                let inst_field_ty = self.instantiate(field_ty_id, field.span(), &|_, _, _| panic!("Tried to substitute generic type for non-generic class"), Some(ty));
+                self.class_vars[class_var.0].2 = params.clone();
+                self.make_impl(ty, class_id, params.clone(), span, Vec::new(), span);
+                let class = self.ctx.classes.get(class_id);
+                let field_ty_id = **class.field(*field).unwrap();
+                // ...as do the item's own generic types, which must fulfil their obligations
+                let item_gen_scope = class.field_gen_scope(*field).unwrap();
+                let item_args = (0..self.ctx.tys.get_gen_scope(item_gen_scope).len())
+                    .map(|_| self.unknown(span))
+                    .collect::<Vec<_>>();
+                for (idx, arg) in item_args.iter().enumerate() {
+                    for obl in self.ctx.tys.get_gen_scope(item_gen_scope).get(idx).obligations().to_vec() {
+                        match &*obl {
+                            Obligation::MemberOf(class) => self.make_impl(*arg, *class, Vec::new(), obl.span(), Vec::new(), span),
+                        }
+                    }
+                }
+                self.class_vars[class_var.0].3 = item_args.clone();
+                let inst_field_ty = self.instantiate(
+                    field_ty_id,
+                    field.span(),
+                    &|idx, scope, _| if scope == item_gen_scope { item_args[idx] } else { params[idx] },
+                    Some(ty),
+                );
                 // TODO: Check soundness of flow relationship
                 self.make_flow(inst_field_ty, field_ty, field.span());
                 Some(Ok(()))
@@ -876,18 +1144,63 @@ impl<'a> Infer<'a> {
         implied
     }
 
+    // Extract a list of all classes that the type is a member of according to the `where` clause of the current scope
+    fn implied_obligations_for_where(&self, ty: TyVar) -> HashSet<ClassId> {
+        let mut implied = HashSet::default();
+        if let Some(gen_scope) = self.gen_scope {
+            for (pred_ty, obl) in self.ctx.tys.get_gen_scope(gen_scope).where_preds() {
+                if self.is_ty(ty, *pred_ty) {
+                    match &**obl {
+                        Obligation::MemberOf(class) => self.walk_implied_obligations(&mut implied, *class),
+                    }
+                }
+            }
+        }
+        implied
+    }
+
+    // Returns true if var is exactly ty, treating generic types as opaque
+    fn is_ty(&self, var: TyVar, ty: TyId) -> bool {
+        match (self.follow_info(var), self.ctx.tys.get(ty)) {
+            (TyInfo::Gen(x, x_scope, _), Ty::Gen(y, y_scope)) => x == y && x_scope == y_scope,
+            (TyInfo::Prim(x), Ty::Prim(y)) => x == y,
+            (TyInfo::List(x), Ty::List(y)) => self.is_ty(x, y),
+            (TyInfo::Tuple(xs), Ty::Tuple(ys)) => xs.len() == ys.len() && xs
+                .into_iter()
+                .zip(ys.into_iter())
+                .all(|(x, y)| self.is_ty(x, y)),
+            (TyInfo::Record(xs), Ty::Record(ys)) => xs.len() == ys.len() && xs
+                .into_iter()
+                .zip(ys.into_iter())
+                .all(|((x_name, x), (y_name, y))| x_name == y_name && self.is_ty(x, y)),
+            (TyInfo::Func(x_i, x_o), Ty::Func(y_i, y_o)) => self.is_ty(x_i, y_i) && self.is_ty(x_o, y_o),
+            (TyInfo::Data(x, xs), Ty::Data(y, ys)) => x == y && xs
+                .into_iter()
+                .zip(ys.into_iter())
+                .all(|(x, y)| self.is_ty(x, y)),
+            (TyInfo::Assoc(x, class_x, assoc_x), Ty::Assoc(y, class_y, assoc_y)) => {
+                class_x == class_y && *assoc_x == *assoc_y && self.is_ty(x, y)
+            },
+            (TyInfo::App(x_f, x), Ty::App(y_f, y)) => self.is_ty(x_f, y_f) && self.is_ty(x, y),
+            (TyInfo::ListCon, Ty::ListCon) => true,
+            _ => false,
+        }
+    }
+
     fn find_class_candidates_from_item(&mut self, ty: TyVar, item: SrcNode<Ident>, item_ty: TyVar, assoc_ty: bool) -> Option<HashSet<ClassId>> {
-        let implied_candidates = match self.follow_info(ty) {
+        let implied_candidates: Vec<_> = match self.follow_info(ty) {
             TyInfo::Error(_) => {
                 self.set_error(ty);
                 self.set_error(item_ty);
                 return None
             }
             TyInfo::Unknown(_) => return None, // We don't know what the type is yet, so how can we possibly determine what classes it is a member of?
+            TyInfo::App(_, _) if self.is_unknown_app(ty) => return None,
             TyInfo::Gen(gen_idx, gen_scope, _) => {
                 return Some(self
                     .implied_obligations_for_gen(gen_scope, gen_idx)
                     .into_iter()
+                    .chain(self.implied_obligations_for_where(ty))
                     // Filter by class obligations that contain the given field
                     .filter(|class_id| if assoc_ty {
                         self.ctx.classes.get(*class_id).assoc_ty(*item).is_some()
@@ -896,21 +1209,30 @@ impl<'a> Infer<'a> {
                     })
                     .collect())
             },
+            // Like generic types, `Self` is opaque, so only the classes it is known to be a member of apply
             TyInfo::SelfType => {
                 let mut implied = HashSet::default();
                 for obl in &self.self_obligations {
                     self.walk_implied_obligations(&mut implied, *obl);
                 }
-                implied
+                return Some(implied
                     .into_iter()
                     .filter(|class_id| if assoc_ty {
                         self.ctx.classes.get(*class_id).assoc_ty(*item).is_some()
                     } else {
                         self.ctx.classes.get(*class_id).field(*item).is_some()
                     })
-                    .collect()
+                    .collect())
             },
-            _ => Vec::new(),
+            _ => self
+                .implied_obligations_for_where(ty)
+                .into_iter()
+                .filter(|class_id| if assoc_ty {
+                    self.ctx.classes.get(*class_id).assoc_ty(*item).is_some()
+                } else {
+                    self.ctx.classes.get(*class_id).field(*item).is_some()
+                })
+                .collect(),
         };
 
         let external_candidates = self.ctx.classes
@@ -924,7 +1246,7 @@ impl<'a> Infer<'a> {
             // Filter further by classes that have members that cover our type
             .filter(|(class_id, _)| self.ctx.classes
                 .members_of(*class_id)
-                .find(|(_, member)| Self::covers_var(self, ty, member.member))
+                .find(|(_, member)| Self::covers_var(self, ty, member.member, false))
                 .is_some())
             .map(|(class_id, _)| class_id);
 
@@ -933,36 +1255,46 @@ impl<'a> Infer<'a> {
             .collect())
     }
 
-    // Returns true if ty covers var (i.e: var is a structural subset of ty)
-    fn covers_var(&self, var: TyVar, ty: TyId) -> bool {
+    // Returns true if ty covers var (i.e: var is a structural subset of ty). If `partial` is set, types that are not yet
+    // known are assumed to be covered.
+    fn covers_var(&self, var: TyVar, ty: TyId, partial: bool) -> bool {
         match (self.follow_info(var), self.ctx.tys.get(ty)) {
             (_, Ty::Gen(_, _)) => true, // Blanket impls match everything
+            (TyInfo::Unknown(_), _) => partial,
+            (TyInfo::App(_, _), _) => partial && self.is_unknown_app(var),
             (TyInfo::Prim(x), Ty::Prim(y)) if x == y => true,
-            (TyInfo::List(x), Ty::List(y)) => self.covers_var(x, y),
+            (TyInfo::ListCon, Ty::ListCon) => true,
+            (TyInfo::List(x), Ty::List(y)) => self.covers_var(x, y, partial),
             (TyInfo::Tuple(xs), Ty::Tuple(ys)) if xs.len() == ys.len() => xs
                 .into_iter()
                 .zip(ys.into_iter())
-                .all(|(x, y)| self.covers_var(x, y)),
+                .all(|(x, y)| self.covers_var(x, y, partial)),
             (TyInfo::Record(xs), Ty::Record(ys)) if xs.len() == ys.len() => xs
                 .into_iter()
                 .zip(ys.into_iter())
-                .all(|((_, x), (_, y))| self.covers_var(x, y)),
+                .all(|((_, x), (_, y))| self.covers_var(x, y, partial)),
             (TyInfo::Func(x_i, x_o), Ty::Func(y_i, y_o)) => {
-                self.covers_var(x_i, y_i) && self.covers_var(x_o, y_o)
+                self.covers_var(x_i, y_i, partial) && self.covers_var(x_o, y_o, partial)
             },
             (TyInfo::Data(x, xs), Ty::Data(y, ys)) if x == y && xs.len() == ys.len() => xs
                 .into_iter()
                 .zip(ys.into_iter())
-                .all(|(x, y)| self.covers_var(x, y)),
+                .all(|(x, y)| self.covers_var(x, y, partial)),
             (TyInfo::Assoc(x, class_x, assoc_x), Ty::Assoc(y, class_y, assoc_y))
-                if class_x == class_y && assoc_x == assoc_y => self.covers_var(x, y),
-            (TyInfo::Effect(x, x_out), Ty::Effect(y, y_out)) => self.covers_var(x_out, y_out) &&
+                if class_x == class_y && assoc_x == assoc_y => self.covers_var(x, y, partial),
+            (TyInfo::Effect(x, x_out), Ty::Effect(y, y_out)) => self.covers_var(x_out, y_out, partial) &&
                 match (self.follow_effect(x), self.ctx.tys.get_effect(y)) {
                     (EffectInfo::Ref(_), _) => unreachable!(),
-                    (EffectInfo::Known(_, xs), Effect::Known(_, ys)) => xs
+                    // Effect sets are unordered, so match effects up by their declaration
+                    (EffectInfo::Known(xs), Effect::Known(ys)) => xs.len() == ys.len() && xs
                         .into_iter()
-                        .zip(ys.into_iter())
-                        .all(|(x, y)| self.covers_var(x, y)),
+                        .all(|(x, xs)| ys
+                            .iter()
+                            .find(|(y, _)| *y == x)
+                            .map_or(false, |(_, ys)| xs
+                                .into_iter()
+                                .zip(ys.iter())
+                                .all(|(x, y)| self.covers_var(x, *y, partial)))),
                     (_, _) => false,
                 },
             _ => false,
@@ -998,10 +1330,14 @@ impl<'a> Infer<'a> {
             (Ty::Effect(x, x_out), TyInfo::Effect(y, y_out)) => {
                 match (self.ctx.tys.get_effect(x), self.follow_effect(y)) {
                     (_, EffectInfo::Ref(_)) => unreachable!(),
-                    (Effect::Known(_, xs), EffectInfo::Known(_, ys)) => xs
+                    (Effect::Known(xs), EffectInfo::Known(ys) | EffectInfo::Open(ys)) => xs
                         .into_iter()
-                        .zip(ys.into_iter())
-                        .for_each(|(x, y)| self.derive_links(x, y, link_gen)),
+                        .for_each(|(x, xs)| if let Some((_, ys)) = ys.iter().find(|(y, _)| *y == x) {
+                            xs
+                                .into_iter()
+                                .zip(ys.iter().copied())
+                                .for_each(|(x, y)| self.derive_links(x, y, link_gen))
+                        }),
                     (_, _) => {},
                 }
                 self.derive_links(x_out, y_out, link_gen);
@@ -1024,14 +1360,19 @@ impl<'a> Infer<'a> {
     /// Resolve a class obligation for a type, returning the ID of the type's membership. If no member can be provided
     /// (because, for example, the membership is implied by a generic bound) then `Err(false)` is returned instead. If
     /// resolution failed due to an existing error, `Err(true)` is returned.
-    fn resolve_obligation(&mut self, ty: TyVar, obligation: ClassId, obl_span: Span, use_span: Span) -> Option<Result<Result<MemberId, bool>, InferError>> {
+    ///
+    /// The type parameters of the class are considered when selecting a member. Parameters that are not yet known are
+    /// inferred from the member, provided that only one member could apply.
+    fn resolve_obligation(&mut self, ty: TyVar, obligation: ClassId, params: &[TyVar], obl_span: Span, use_span: Span) -> Option<Result<Result<MemberId, bool>, InferError>> {
         // TODO: Resolve possible infinite loop when resolving by having an obligation cache
         match self.follow_info(ty) {
             TyInfo::Error(_) => {
                 self.set_error(ty);
+                params.iter().for_each(|param| self.set_error(*param));
                 return Some(Ok(Err(true))); // Resolving an error type always succeeds
             },
             TyInfo::Unknown(_) => None, // No idea if it implements the trait yet
+            TyInfo::App(_, _) if self.is_unknown_app(ty) => None,
             TyInfo::Gen(gen_idx, gen_scope, _) if self
                 .implied_obligations_for_gen(gen_scope, gen_idx)
                 .into_iter()
@@ -1046,34 +1387,85 @@ impl<'a> Infer<'a> {
                 }
                 implied.contains(&obligation)
             } => {
+                // Within a class, `Self` is a member of the class for the class's own type parameters
+                let class_gen_scope = self.ctx.classes.get(obligation).gen_scope;
+                for (idx, param) in params.iter().enumerate() {
+                    let gen_span = self.ctx.tys.get_gen_scope(class_gen_scope).get(idx).name.span();
+                    let gen_ty = self.insert(gen_span, TyInfo::Gen(idx, class_gen_scope, gen_span));
+                    self.make_flow(gen_ty, *param, obl_span);
+                }
                 Some(Ok(Err(false)))
             },
+            _ if self.implied_obligations_for_where(ty).contains(&obligation) => Some(Ok(Err(false))),
             info => {
-                // Find a class member declaration that covers our type
-                let covering_member = self.ctx.classes
+                // Find class member declarations that could cover our type and the class parameters
+                let covering_members = self.ctx.classes
                     .members_of(obligation)
-                    .find(|(_, member)| Self::covers_var(self, ty, member.member));
-
-                if let Some((covering_member_id, covering_member)) = covering_member {
+                    .filter(|(_, member)| Self::covers_var(self, ty, member.member, false) && params
+                        .iter()
+                        .zip(member.params.iter())
+                        .all(|(param, member_param)| Self::covers_var(self, *param, *member_param, true)))
+                    .map(|(member_id, _)| member_id)
+                    .collect::<Vec<_>>();
+
+                if let [covering_member_id] = covering_members[..] {
+                    let covering_member = self.ctx.classes.get_member(covering_member_id);
                     let scope = self.ctx.tys.get_gen_scope(covering_member.gen_scope);
+                    let (member_ty, member_params) = (covering_member.member, covering_member.params.clone());
 
                     let mut links = HashSet::new();
-                    self.derive_links(covering_member.member, ty, &mut |gen_idx, var| { links.insert((gen_idx, var)); });
+                    self.derive_links(member_ty, ty, &mut |gen_idx, var| { links.insert((gen_idx, var)); });
+                    for (member_param, param) in member_params.iter().zip(params.iter()) {
+                        self.derive_links(*member_param, *param, &mut |gen_idx, var| { links.insert((gen_idx, var)); });
+                    }
+
+                    // Generic types of the member that are not yet linked to a type (because they only appear in class
+                    // parameters that are not yet known) get fresh type variables
+                    let gens = (0..scope.len())
+                        .map(|idx| links
+                            .iter()
+                            .find(|(gen_idx, _)| *gen_idx == idx)
+                            .map(|(_, var)| *var)
+                            .unwrap_or_else(|| {
+                                let var = self.unknown(obl_span);
+                                links.insert((idx, var));
+                                var
+                            }))
+                        .collect::<Vec<_>>();
+
+                    // Infer any class parameters that are not yet known from the member
+                    for (member_param, param) in member_params.into_iter().zip(params.iter()) {
+                        let member_param = self.instantiate(member_param, obl_span, &|idx, _, _| gens[idx], None);
+                        self.make_flow(member_param, *param, obl_span);
+                    }
 
+                    let scope = self.ctx.tys.get_gen_scope(self.ctx.classes.get_member(covering_member_id).gen_scope);
+                    let mut obls = Vec::new();
                     for (gen_idx, ty) in links {
                         for obl in scope.get(gen_idx).obligations.as_ref().unwrap() {
                             match &**obl {
-                                Obligation::MemberOf(class) => {
-                                    self.constraints.push_back(Constraint::Impl(ty, *class, obl_span, Vec::new(), use_span));
-                                },
+                                Obligation::MemberOf(class) => obls.push((ty, *class)),
                             }
                         }
                     }
+                    // The `where` clause of the member must also hold for the types it was selected for
+                    for (pred_ty, obl) in scope.where_preds().to_vec() {
+                        let pred_ty = self.instantiate(pred_ty, obl_span, &|idx, _, _| gens[idx], None);
+                        match &*obl {
+                            Obligation::MemberOf(class) => obls.push((pred_ty, *class)),
+                        }
+                    }
+                    for (ty, class) in obls {
+                        self.constraints.push_back(Constraint::Impl(ty, class, Vec::new(), obl_span, Vec::new(), use_span));
+                    }
 
                     Some(Ok(Ok(covering_member_id)))
+                } else if covering_members.len() > 1 {
+                    None // Several members could apply, wait until we know more about the class parameters
                 } else {
                     Some(Err(InferError::TypeDoesNotFulfil(
                         obligation,
+                        params.to_vec(),
                         ty,
                         obl_span,
                         if let TyInfo::Gen(gen_idx, gen_scope, _) = info {
@@ -1089,29 +1481,42 @@ impl<'a> Infer<'a> {
     }
 
     fn resolve_constraints(&mut self) {
-        let mut tries = self.constraints.len();
-        while tries > 0 {
-            if let Some(c) = self.constraints.pop_front() {
-                tries -= 1;
-                match self.resolve(c.clone()) {
-                    // Constraint resolved
-                    Some(res) => {
-                        // Record any errors while resolving the constraint
-                        if let Err(e) = res {
-                            self.errors.push(e);
-                        }
-                        // A constraint being resolved resets the counter
-                        tries = self.constraints.len();
-                    },
-                    None => self.constraints.push_back(c), // Still unresolved...
+        loop {
+            let mut tries = self.constraints.len();
+            while tries > 0 {
+                if let Some(c) = self.constraints.pop_front() {
+                    tries -= 1;
+                    match self.resolve(c.clone()) {
+                        // Constraint resolved
+                        Some(res) => {
+                            // Record any errors while resolving the constraint
+                            if let Err(e) = res {
+                                self.errors.push(e);
+                            }
+                            // A constraint being resolved resets the counter
+                            tries = self.constraints.len();
+                        },
+                        None => self.constraints.push_back(c), // Still unresolved...
+                    }
+                } else {
+                    break
                 }
-            } else {
+            }
+
+            // No more effects can flow into basins, so close their effect sets and try again
+            if self.effects_closed {
                 break
+            } else {
+                self.close_effects();
             }
         }
 
         // Generate errors for all remaining constraints
         for c in std::mem::take(&mut self.constraints) {
+            // Types that have already produced an error don't need to produce more
+            if let Constraint::Impl(ty, ..) = &c {
+                if self.is_error(*ty) { continue }
+            }
             self.errors.push(match c {
                 Constraint::Access(record, field_name, _field) => {
                     InferError::NoSuchItem(record, self.span(record), field_name.clone())
@@ -1122,8 +1527,8 @@ impl<'a> Infer<'a> {
                 Constraint::Binary(op, a, b, _output) => {
                     InferError::InvalidBinaryOp(op.clone(), a, b)
                 },
-                Constraint::Impl(ty, obligation, obl_span, _, use_span) => {
-                    InferError::TypeDoesNotFulfil(obligation, ty, obl_span, None, use_span)
+                Constraint::Impl(ty, obligation, params, obl_span, _, use_span) => {
+                    InferError::TypeDoesNotFulfil(obligation, params, ty, obl_span, None, use_span)
                 },
                 Constraint::ClassField(_ty, _class, field, _field_ty, _span) => {
                     InferError::AmbiguousClassItem(field, Vec::new())
@@ -1132,10 +1537,25 @@ impl<'a> Infer<'a> {
                     InferError::AmbiguousClassItem(assoc, Vec::new())
                 },
                 Constraint::EffectSendRecv(eff, send, recv, span) => InferError::CannotInferEffect(eff),
+                Constraint::Handle(eff, _handled, _basin, _span) => InferError::CannotInferEffect(eff),
             });
         }
     }
 
+    fn close_effects(&mut self) {
+        self.effects_closed = true;
+        for (_, info) in &mut self.effect_vars {
+            if let EffectInfo::Open(effs) = info {
+                // A basin that collected no effects gives no hint as to what it performs
+                *info = if effs.is_empty() {
+                    EffectInfo::Unknown
+                } else {
+                    EffectInfo::Known(std::mem::take(effs))
+                };
+            }
+        }
+    }
+
     pub fn into_checked(mut self) -> (Checked<'a>, Vec<Error>) {
         self.resolve_constraints();
 
@@ -1180,7 +1600,13 @@ impl<'a> Infer<'a> {
                 InferError::NoSuchField(a, record_span, field) => Error::NoSuchField(checked.reify(a), record_span, field),
                 InferError::InvalidUnaryOp(op, a) => Error::InvalidUnaryOp(op, checked.reify(a), checked.infer.span(a)),
                 InferError::InvalidBinaryOp(op, a, b) => Error::InvalidBinaryOp(op, checked.reify(a), checked.infer.span(a), checked.reify(b), checked.infer.span(b)),
-                InferError::TypeDoesNotFulfil(class, ty, obl_span, gen_span, use_span) => Error::TypeDoesNotFulfil(class, checked.reify(ty), obl_span, gen_span, use_span),
+                InferError::TypeDoesNotFulfil(class, params, ty, obl_span, gen_span, use_span) => {
+                    let params = params
+                        .into_iter()
+                        .map(|param| checked.reify(param))
+                        .collect();
+                    Error::TypeDoesNotFulfil(class, params, checked.reify(ty), obl_span, gen_span, use_span)
+                },
                 InferError::RecursiveAlias(alias, a, span) => Error::RecursiveAlias(alias, checked.reify(a), span),
                 InferError::PatternNotSupported(lhs, op, rhs, span) => Error::PatternNotSupported(checked.reify(lhs), op, checked.reify(rhs), span),
                 InferError::AmbiguousClassItem(field, candidate_classes) => Error::AmbiguousClassItem(field, candidate_classes),
@@ -1205,7 +1631,7 @@ impl<'a> Checked<'a> {
         if let Some(ty) = self.ty_cache.get(&var) {
             *ty
         } else {
-            let ty = match self.infer.info(var) {
+            let ty = match self.infer.info_normalised(var) {
                 // Follow references
                 TyInfo::Ref(x) => return self.reify_inner(x),
                 // Unknown types are treated as errors from here on out
@@ -1230,6 +1656,8 @@ impl<'a> Checked<'a> {
                 TyInfo::SelfType => Ty::SelfType,
                 TyInfo::Assoc(inner, class_id, assoc) => Ty::Assoc(self.reify_inner(inner), class_id, assoc),
                 TyInfo::Effect(eff, out) => Ty::Effect(self.reify_effect(eff), self.reify_inner(out)),
+                TyInfo::App(f, param) => Ty::App(self.reify_inner(f), self.reify_inner(param)),
+                TyInfo::ListCon => Ty::ListCon,
             };
             self.infer.ctx.tys.insert(self.infer.span(var), ty)
         }
@@ -1248,10 +1676,15 @@ impl<'a> Checked<'a> {
             let eff = match self.infer.follow_effect(var) {
                 EffectInfo::Unknown => Effect::Error,
                 EffectInfo::Ref(_) => unreachable!(),
-                EffectInfo::Known(eff, args) => Effect::Known(eff, args
-                    .into_iter()
-                    .map(|arg| self.reify_inner(arg))
-                    .collect()),
+                EffectInfo::Known(effs) | EffectInfo::Open(effs) => {
+                    Effect::Known(effs
+                        .into_iter()
+                        .map(|(decl, args)| (decl, args
+                            .into_iter()
+                            .map(|arg| self.reify_inner(arg))
+                            .collect()))
+                        .collect())
+                },
             };
             self.infer.ctx.tys.insert_effect(self.infer.effect_span(var), eff)
         };
@@ -1259,7 +1692,18 @@ impl<'a> Checked<'a> {
         eff
     }
 
-    pub fn reify_class(&mut self, class: ClassVar) -> Option<ClassId> {
-        self.infer.class_vars[class.0].1
+    pub fn reify_class(&mut self, class: ClassVar) -> Option<(ClassId, Vec<TyId>, Vec<TyId>)> {
+        let (_, class_id, params, item_args) = self.infer.class_vars[class.0].clone();
+        Some((
+            class_id?,
+            params
+                .into_iter()
+                .map(|param| self.reify(param))
+                .collect(),
+            item_args
+                .into_iter()
+                .map(|arg| self.reify(arg))
+                .collect(),
+        ))
     }
 }
@@ -5,13 +5,16 @@ pub mod concrete;
 pub mod context;
 pub mod data;
 pub mod def;
+pub mod derive;
 pub mod effect;
 pub mod error;
 pub mod exhaustivity;
 pub mod infer;
 pub mod hir;
 pub mod lower;
+pub mod module;
 pub mod reify;
+pub mod termination;
 pub mod ty;
 
 pub use crate::{
@@ -20,14 +23,16 @@ pub use crate::{
     context::Context,
     data::{Datas, Data, DataId, Alias, AliasId},
     def::{Defs, Def, DefId},
-    effect::{Effects, EffectDecl, EffectDeclId},
+    effect::{Effects, EffectDecl, EffectDeclId, EffectAlias, EffectAliasId},
     error::Error,
     exhaustivity::{exhaustivity, ExamplePat},
     hir::{InferExpr, InferBinding, TyExpr, TyBinding, ConBinding, ConExpr, Intrinsic},
     infer::{Infer, Checked, TyVar, TyInfo, InferNode, InferMeta, InferError, EqInfo, ClassVar, EffectVar, EffectInfo},
-    lower::{Scope, ToHir},
+    lower::{Scope, ToHir, ToHirOfKind, lower_effect_set},
+    module::{Modules, Module, ModuleId, Namespace, TypeItem, EffectItem},
     reify::Reify,
-    ty::{Types, TyId, GenScope, GenScopeId, Prim, Ty, TyNode, TyMeta, ErrorReason, Obligation, Effect, EffectId},
+    termination::check_termination,
+    ty::{Types, TyId, GenScope, GenScopeId, Kind, Prim, Ty, TyNode, TyMeta, ErrorReason, Obligation, Effect, EffectId},
 };
 pub use tao_syntax::ast::Ident;
 
@@ -36,6 +41,7 @@ use tao_syntax::{
     Span,
     SrcNode,
     SrcId,
+    Diagnostic,
     ast,
 };
 use hashbrown::{HashMap, HashSet};
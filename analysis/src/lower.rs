@@ -13,15 +13,23 @@ fn litr_ty_info(litr: &ast::Literal, infer: &mut Infer, span: Span) -> TyInfo {
 }
 
 pub enum Scope<'a> {
-    Empty,
-    Recursive(SrcNode<Ident>, TyVar, DefId, Vec<(Span, TyVar)>),
+    Module(ModuleId),
+    Recursive(&'a Scope<'a>, SrcNode<Ident>, TyVar, DefId, Vec<(Span, TyVar)>),
     Binding(&'a Scope<'a>, SrcNode<Ident>, TyVar),
     Many(&'a Scope<'a>, &'a [(SrcNode<Ident>, TyVar)]),
     Basin(&'a Scope<'a>, EffectVar),
 }
 
 impl<'a> Scope<'a> {
-    pub fn empty() -> Self { Self::Empty }
+    pub fn module(&self) -> ModuleId {
+        match self {
+            Self::Module(module) => *module,
+            Self::Recursive(parent, _, _, _, _) => parent.module(),
+            Self::Binding(parent, _, _) => parent.module(),
+            Self::Many(parent, _) => parent.module(),
+            Self::Basin(parent, _) => parent.module(),
+        }
+    }
 
     fn with(&self, name: SrcNode<Ident>, ty: TyVar) -> Scope<'_> {
         Scope::Binding(self, name, ty)
@@ -38,11 +46,11 @@ impl<'a> Scope<'a> {
     // bool = is_local
     fn find(&self, infer: &mut Infer, span: Span, name: &Ident) -> Option<(TyVar, Option<(DefId, Vec<(Span, TyVar)>)>)> {
         match self {
-            Self::Empty => None,
-            Self::Recursive(def, ty, def_id, tys) => if &**def == name {
+            Self::Module(_) => None,
+            Self::Recursive(parent, def, ty, def_id, tys) => if &**def == name {
                 Some((infer.try_reinstantiate(span, *ty), Some((*def_id, tys.clone()))))
             } else {
-                None
+                parent.find(infer, span, name)
             },
             Self::Binding(_, local, ty) if &**local == name => Some((*ty, None)),
             Self::Binding(parent, _, _) => parent.find(infer, span, name),
@@ -57,8 +65,8 @@ impl<'a> Scope<'a> {
 
     fn last_basin(&self) -> Option<EffectVar> {
         match self {
-            Self::Empty => None,
-            Self::Recursive(_, _, _, _) => None,
+            Self::Module(_) => None,
+            Self::Recursive(_, _, _, _, _) => None,
             Self::Binding(parent, _, _) => parent.last_basin(),
             Self::Many(parent, _) => parent.last_basin(),
             Self::Basin(_, eff) => Some(*eff),
@@ -109,22 +117,121 @@ fn enforce_generic_obligations(
                 }
             }
         }
+        let where_preds = gen_scope.where_preds().to_vec();
         for (idx, class, span) in obls {
-            infer.make_impl(params[idx], class, use_span, Vec::new(), span);
+            infer.make_impl(params[idx], class, Vec::new(), use_span, Vec::new(), span);
+        }
+        // Enforce obligations from the `where` clause
+        for (ty, obl) in where_preds {
+            let ty = infer.instantiate(ty, use_span, &|idx, _, _| params[idx], None);
+            match &*obl {
+                Obligation::MemberOf(class) => infer.make_impl(ty, *class, Vec::new(), use_span, Vec::new(), obl.span()),
+            }
         }
         Ok(())
     }
 }
 
+/// Lower a set of effects, expanding effect aliases into the effects that they stand for. An effect that appears more
+/// than once must be given the same generic arguments each time.
+pub fn lower_effect_set(effs: &[SrcNode<ast::EffectInst>], infer: &mut Infer, scope: &Scope) -> Result<Vec<(EffectDeclId, Vec<TyVar>)>, ()> {
+    let mut set: Vec<(EffectDeclId, Vec<TyVar>)> = Vec::new();
+    let mut res = Ok(());
+    for eff in effs {
+        let args = eff.args
+            .iter()
+            .map(|arg| arg.to_hir(infer, scope).meta().1)
+            .collect::<Vec<_>>();
+
+        let insts = if let Some(eff_id) = infer.ctx().modules.lookup_effect(scope.module(), &eff.name) {
+            let decl = infer.ctx().effects.get_decl(eff_id);
+            let (eff_gen_scope, eff_span) = (decl.gen_scope, decl.name.span());
+            match enforce_generic_obligations(infer, eff_gen_scope, &args, eff.span(), eff_span) {
+                Ok(()) => vec![(eff_id, args)],
+                Err(()) => { res = Err(()); continue },
+            }
+        } else if let Some(alias_id) = infer.ctx().modules.lookup_effect_alias(scope.module(), &eff.name) {
+            let alias = infer.ctx().effects.get_alias(alias_id);
+            let (alias_gen_scope, alias_span) = (alias.gen_scope, alias.name.span());
+            let Some(alias_effs) = alias.effects.clone() else {
+                infer.ctx_mut().emit(Error::Unsupported(eff.name.span(), "effect aliases that refer to later effect aliases"));
+                res = Err(());
+                continue;
+            };
+            match enforce_generic_obligations(infer, alias_gen_scope, &args, eff.span(), alias_span) {
+                Ok(()) => alias_effs
+                    .into_iter()
+                    .map(|(decl, decl_args)| (decl, decl_args
+                        .into_iter()
+                        .map(|arg| infer.instantiate(arg, eff.span(), &|idx, _, _| args[idx], None))
+                        .collect()))
+                    .collect(),
+                Err(()) => { res = Err(()); continue },
+            }
+        } else {
+            infer.ctx_mut().emit(Error::NoSuchEffect(eff.name.clone()));
+            res = Err(());
+            continue;
+        };
+
+        for (decl, args) in insts {
+            if let Some((_, existing)) = set.iter().find(|(existing, _)| *existing == decl) {
+                for (x, y) in existing.clone().into_iter().zip(args) {
+                    infer.make_flow(x, y, EqInfo::from(eff.span()));
+                    infer.make_flow(y, x, EqInfo::from(eff.span()));
+                }
+            } else {
+                set.push((decl, args));
+            }
+        }
+    }
+    res.map(|()| set)
+}
+
+/// Lower the type and class parameters of a member, which must have the kinds that the class expects.
+pub fn member_to_hir(infer: &mut Infer, scope: &Scope, member: &ast::Member, class: ClassId) -> (InferNode<()>, Vec<InferNode<()>>) {
+    let class = infer.ctx().classes.get(class);
+    let (class_kind, class_span, class_gen_scope) = (class.kind.clone(), class.name.span(), class.gen_scope);
+    let member_ty = member.member.to_hir_of_kind(infer, scope, Some((class_kind, class_span)));
+    let params = member.class.params
+        .iter()
+        .enumerate()
+        .map(|(idx, param)| {
+            let gen_scope = infer.ctx().tys.get_gen_scope(class_gen_scope);
+            let kind = if idx < gen_scope.len() {
+                Some((gen_scope.get(idx).kind.clone(), gen_scope.get(idx).name.span()))
+            } else {
+                // Reported elsewhere
+                None
+            };
+            param.to_hir_of_kind(infer, scope, kind)
+        })
+        .collect();
+    (member_ty, params)
+}
+
 impl ToHir for ast::Type {
     type Output = ();
 
     fn to_hir(self: &SrcNode<Self>, infer: &mut Infer, scope: &Scope) -> InferNode<()> {
+        self.to_hir_of_kind(infer, scope, Some((Kind::Type, self.span())))
+    }
+}
+
+pub trait ToHirOfKind: Sized {
+    /// Lower a type that must have the given kind, along with the origin of that expectation. If no kind is given, the
+    /// type may have any kind.
+    fn to_hir_of_kind(self: &SrcNode<Self>, infer: &mut Infer, scope: &Scope, kind: Option<(Kind, Span)>) -> InferNode<()>;
+}
+
+impl ToHirOfKind for ast::Type {
+    fn to_hir_of_kind(self: &SrcNode<Self>, infer: &mut Infer, scope: &Scope, kind: Option<(Kind, Span)>) -> InferNode<()> {
         let info = match &**self {
             ast::Type::Error => TyInfo::Error(ErrorReason::Unknown),
             ast::Type::Unknown => TyInfo::Unknown(None),
             ast::Type::Universe => TyInfo::Prim(Prim::Universe),
             ast::Type::List(item) => TyInfo::List(item.to_hir(infer, scope).meta().1),
+            ast::Type::ListCon => TyInfo::ListCon,
             ast::Type::Tuple(items) => TyInfo::Tuple(items
                 .iter()
                 .map(|item| item.to_hir(infer, scope).meta().1)
@@ -134,30 +241,62 @@ impl ToHir for ast::Type {
                 .map(|(name, field)| (**name, field.to_hir(infer, scope).meta().1))
                 .collect()),
             ast::Type::Func(i, o) => TyInfo::Func(i.to_hir(infer, scope).meta().1, o.to_hir(infer, scope).meta().1),
-            ast::Type::Data(name, params) => match (name.as_str(), params.len()) {
-                ("Self", 0) => if let Some(var) = infer.self_type() {
-                    TyInfo::Ref(var)
-                } else {
-                    infer.ctx_mut().emit(Error::SelfNotValidHere(name.span()));
-                    TyInfo::Error(ErrorReason::Invalid)
-                },
-                ("Nat", 0) => TyInfo::Prim(Prim::Nat),
-                ("Int", 0) => TyInfo::Prim(Prim::Int),
-                ("Real", 0) => TyInfo::Prim(Prim::Real),
-                ("Bool", 0) => TyInfo::Prim(Prim::Bool),
-                ("Char", 0) => TyInfo::Prim(Prim::Char),
+            ast::Type::Data(name, params) => match (name.as_local().as_ref().map(|name| name.as_str()), params.len()) {
+                (Some("Nat"), 0) => TyInfo::Prim(Prim::Nat),
+                (Some("Int"), 0) => TyInfo::Prim(Prim::Int),
+                (Some("Real"), 0) => TyInfo::Prim(Prim::Real),
+                (Some("Bool"), 0) => TyInfo::Prim(Prim::Bool),
+                (Some("Char"), 0) => TyInfo::Prim(Prim::Char),
                 _ => {
-                    let params = params
-                        .iter()
-                        .map(|param| param.to_hir(infer, scope).meta().1)
-                        .collect::<Vec<_>>();
-
-                    if let Some((scope, (gen_idx, gen_ty))) = infer
-                        .gen_scope()
-                        .and_then(|scope| Some((scope, infer.ctx().tys.get_gen_scope(scope).find(**name)?)))
+                    // `Self` and generic types might be type constructors, in which case they get applied to their
+                    // parameters
+                    let head = if name.as_local().map_or(false, |name| name.as_str() == "Self") {
+                        if let Some(var) = infer.self_type() {
+                            Some(Ok((var, name.span())))
+                        } else {
+                            infer.ctx_mut().emit(Error::SelfNotValidHere(name.span()));
+                            Some(Err(()))
+                        }
+                    } else if let Some((gen_scope, gen_idx, gen_span)) = name
+                        .as_local()
+                        .zip(infer.gen_scope())
+                        .and_then(|(name, scope)| infer.ctx().tys.find_gen(scope, name))
+                        .map(|(scope, idx, gen_ty)| (scope, idx, gen_ty.name.span()))
                     {
-                        TyInfo::Gen(gen_idx, scope, gen_ty.name.span())
-                    } else if let Some(alias_id) = infer.ctx().datas.lookup_alias(**name) {
+                        Some(Ok((infer.insert(name.span(), TyInfo::Gen(gen_idx, gen_scope, gen_span)), gen_span)))
+                    } else {
+                        None
+                    };
+
+                    if let Some(head) = head {
+                        match head {
+                            Ok((head, head_span)) => {
+                                let mut ty = head;
+                                let mut head_kind = infer.kind_of(head);
+                                let mut info = None;
+                                for param in params {
+                                    let param = match head_kind {
+                                        Some(Kind::Func(i, o)) => {
+                                            head_kind = Some(*o);
+                                            param.to_hir_of_kind(infer, scope, Some((*i, head_span)))
+                                        },
+                                        // Too many parameters were given, so the type constructor has the wrong kind
+                                        Some(Kind::Type) => {
+                                            let found = infer.kind_of(head).unwrap_or(Kind::Type);
+                                            let expected = Kind::of_params(params.iter().map(|_| Kind::Type).collect());
+                                            infer.ctx_mut().emit(Error::KindMismatch(name.span(), found, name.span(), expected));
+                                            info = Some(TyInfo::Error(ErrorReason::Invalid));
+                                            break;
+                                        },
+                                        None => param.to_hir_of_kind(infer, scope, None),
+                                    };
+                                    ty = infer.insert(self.span(), TyInfo::App(ty, param.meta().1));
+                                }
+                                info.unwrap_or(TyInfo::Ref(ty))
+                            },
+                            Err(()) => TyInfo::Error(ErrorReason::Invalid),
+                        }
+                    } else if let Some(alias_id) = infer.ctx().modules.lookup_alias(scope.module(), name) {
                         if let Some(alias) = infer.ctx().datas.get_alias(alias_id) {
                             let alias_gen_scope = infer.ctx().tys.get_gen_scope(alias.gen_scope);
                             if alias_gen_scope.len() != params.len() {
@@ -171,6 +310,15 @@ impl ToHir for ast::Type {
                                 TyInfo::Error(ErrorReason::Unknown)
                             } else {
                                 let (alias_ty, alias_gen_scope) = (alias.ty, alias.gen_scope);
+                                let params = params
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(idx, param)| {
+                                        let gen_ty = infer.ctx().tys.get_gen_scope(alias_gen_scope).get(idx);
+                                        let gen_kind = (gen_ty.kind.clone(), gen_ty.name.span());
+                                        param.to_hir_of_kind(infer, scope, Some(gen_kind)).meta().1
+                                    })
+                                    .collect::<Vec<_>>();
                                 let get_gen = |index, scope, ctx: &Context| {
                                     params[index]
                                 };
@@ -187,16 +335,56 @@ impl ToHir for ast::Type {
                             infer.emit(InferError::RecursiveAlias(alias_id, err_ty, name.span()));
                             TyInfo::Ref(err_ty)
                         }
-                    } else if let Some(data) = infer.ctx().datas.lookup_data(**name) {
-                        match enforce_generic_obligations(
-                            infer,
-                            infer.ctx().datas.name_gen_scope(**name),
-                            &params,
-                            self.span(),
-                            infer.ctx().datas.get_data_span(data),
-                        ) {
-                            Ok(()) => TyInfo::Data(data, params),
-                            Err(()) => TyInfo::Error(ErrorReason::Unknown),
+                    } else if let Some(data) = infer.ctx().modules.lookup_data(scope.module(), name) {
+                        let data_gen_scope = infer.ctx().datas.data_gen_scope(data);
+                        let gen_count = infer.ctx().tys.get_gen_scope(data_gen_scope).len();
+                        let params = params
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, param)| if idx < gen_count {
+                                let gen_ty = infer.ctx().tys.get_gen_scope(data_gen_scope).get(idx);
+                                let gen_kind = (gen_ty.kind.clone(), gen_ty.name.span());
+                                param.to_hir_of_kind(infer, scope, Some(gen_kind)).meta().1
+                            } else {
+                                param.to_hir_of_kind(infer, scope, None).meta().1
+                            })
+                            .collect::<Vec<_>>();
+
+                        // A data type that is not given all of its parameters is a type constructor, which is only
+                        // permitted where something other than a type is expected
+                        if params.len() < gen_count && !matches!(kind, Some((Kind::Type, _))) {
+                            let gen_scope = infer.ctx().tys.get_gen_scope(data_gen_scope);
+                            if (params.len()..gen_count).any(|idx| !gen_scope.get(idx).obligations().is_empty())
+                                || !gen_scope.where_preds().is_empty()
+                            {
+                                infer.ctx_mut().emit(Error::Unsupported(self.span(), "partial application of data types with obligations"));
+                                TyInfo::Error(ErrorReason::Invalid)
+                            } else {
+                                let obls = (0..params.len())
+                                    .flat_map(|idx| gen_scope
+                                        .get(idx)
+                                        .obligations()
+                                        .iter()
+                                        .map(move |obl| (idx, obl.clone())))
+                                    .collect::<Vec<_>>();
+                                for (idx, obl) in obls {
+                                    match &*obl {
+                                        Obligation::MemberOf(class) => infer.make_impl(params[idx], *class, Vec::new(), self.span(), Vec::new(), obl.span()),
+                                    }
+                                }
+                                TyInfo::Data(data, params)
+                            }
+                        } else {
+                            match enforce_generic_obligations(
+                                infer,
+                                data_gen_scope,
+                                &params,
+                                self.span(),
+                                infer.ctx().datas.get_data_span(data),
+                            ) {
+                                Ok(()) => TyInfo::Data(data, params),
+                                Err(()) => TyInfo::Error(ErrorReason::Unknown),
+                            }
                         }
                     } else {
                         infer.ctx_mut().emit(Error::NoSuchData(name.clone()));
@@ -210,39 +398,30 @@ impl ToHir for ast::Type {
                 infer.make_class_assoc(inner.meta().1, assoc.clone(), assoc_ty, self.span());
                 TyInfo::Ref(assoc_ty)
             },
-            ast::Type::Effect(name, params, out) => {
-                let params = params
-                    .iter()
-                    .map(|param| param.to_hir(infer, scope).meta().1)
-                    .collect::<Vec<_>>();
-
+            ast::Type::Effect(effs, out) => {
+                let effs = lower_effect_set(effs, infer, scope);
                 let out = out.to_hir(infer, scope).meta().1;
 
-                if let Some(eff_id) = infer.ctx().effects.lookup(**name) {
-                    let eff = infer.ctx().effects.get_decl(eff_id);
-                    let eff_gen_scope = eff.gen_scope;
-                    let eff_span = eff.name.span();
-                    match enforce_generic_obligations(
-                        infer,
-                        eff_gen_scope,
-                        &params,
-                        self.span(),
-                        eff_span,
-                    ) {
-                        Ok(()) => {
-                            let eff = infer.insert_effect(self.span(), EffectInfo::Known(eff_id, params));
-                            TyInfo::Effect(eff, out)
-                        },
-                        Err(()) => TyInfo::Error(ErrorReason::Unknown),
-                    }
-                } else {
-                    infer.ctx_mut().emit(Error::NoSuchEffect(name.clone()));
-                    TyInfo::Error(ErrorReason::Invalid)
+                match effs {
+                    Ok(effs) => {
+                        let eff = infer.insert_effect(self.span(), EffectInfo::Known(effs));
+                        TyInfo::Effect(eff, out)
+                    },
+                    Err(()) => TyInfo::Error(ErrorReason::Invalid),
                 }
             },
         };
 
-        InferNode::new((), (self.span(), infer.insert(self.span(), info)))
+        let ty = infer.insert(self.span(), info);
+        if let (Some((expected, expected_span)), Some(found)) = (kind, infer.kind_of(ty)) {
+            if found != expected {
+                infer.ctx_mut().emit(Error::KindMismatch(self.span(), found, expected_span, expected));
+                let err = infer.insert(self.span(), TyInfo::Error(ErrorReason::Invalid));
+                return InferNode::new((), (self.span(), err));
+            }
+        }
+
+        InferNode::new((), (self.span(), ty))
     }
 }
 
@@ -343,7 +522,7 @@ impl ToHir for ast::Binding {
                 });
                 (TyInfo::List(item_ty), hir::Pat::ListFront(items, tail))
             },
-            ast::Pat::Deconstruct(name, inner) => if let Some(data) = infer.ctx().datas.lookup_cons(**name) {
+            ast::Pat::Deconstruct(name, inner) => if let Some((data, cons_name)) = infer.ctx().modules.lookup_cons(scope.module(), name) {
                 let gen_scope_id = infer.ctx().datas.get_data(data).gen_scope;
                 let gen_scope = infer.ctx().tys.get_gen_scope(gen_scope_id);
                 let generics_count = gen_scope.len();
@@ -371,7 +550,7 @@ impl ToHir for ast::Binding {
                     .get_data(data)
                     .cons
                     .iter()
-                    .find(|(cons, _)| **cons == **name)
+                    .find(|(cons, _)| **cons == cons_name)
                     .unwrap()
                     .1;
 
@@ -392,7 +571,7 @@ impl ToHir for ast::Binding {
                 let inner = inner.to_hir(infer, scope);
                 infer.make_flow(inner_ty, inner.meta().1, self.span());
 
-                (TyInfo::Data(data, generic_tys), hir::Pat::Decons(SrcNode::new(data, self.span()), **name, inner))
+                (TyInfo::Data(data, generic_tys), hir::Pat::Decons(SrcNode::new(data, self.span()), cons_name, inner))
             } else {
                 infer.ctx_mut().emit(Error::NoSuchCons(name.clone()));
                 // TODO: Don't use a hard, preserve inner expression
@@ -433,10 +612,18 @@ fn instantiate_def(def_id: DefId, span: Span, infer: &mut Infer, span_override:
             .to_vec()
         {
             match &*obl {
-                Obligation::MemberOf(class) => infer.make_impl(*ty, *class, obl.span(), Vec::new(), inst_span),
+                Obligation::MemberOf(class) => infer.make_impl(*ty, *class, Vec::new(), obl.span(), Vec::new(), inst_span),
             }
         }
     }
+    // Enforce obligations from the `where` clause
+    let where_preds = infer.ctx().tys.get_gen_scope(infer.ctx().defs.get(def_id).gen_scope).where_preds().to_vec();
+    for (ty, obl) in where_preds {
+        let ty = infer.instantiate(ty, inst_span, &|idx, _, _| generic_tys[idx].1, None);
+        match &*obl {
+            Obligation::MemberOf(class) => infer.make_impl(ty, *class, Vec::new(), obl.span(), Vec::new(), inst_span),
+        }
+    }
 
     // Recreate type in context
     let def = infer.ctx().defs.get(def_id);
@@ -477,12 +664,36 @@ impl ToHir for ast::Expr {
                 (TyInfo::Ref(infer.insert(span, ty_info)), hir::Expr::Literal(*litr))
             },
             ast::Expr::LangDef(def) => {
-                let def = match def {
-                    ast::LangDef::IoUnit => infer.ctx().defs.lang.io_unit.unwrap(),
-                    ast::LangDef::IoBind => infer.ctx().defs.lang.io_bind.unwrap(),
+                // `do` blocks and `return` are sugar for the items of the monad class, whichever type they're used with
+                let monad = infer.ctx().classes.lang.monad;
+                let field = SrcNode::new(Ident::new(match def {
+                    ast::LangDef::Pure => "pure",
+                    ast::LangDef::Bind => "bind",
+                }), self.span());
+                let class = monad.and_then(|monad| infer.ctx().classes.field_class(monad, *field));
+
+                // The shape of the item's type is known up front so that non-monadic types are caught where they're
+                // used, rather than during class resolution
+                let monad_ty = infer.unknown(self.span());
+                let a = infer.unknown(self.span());
+                let monad_a = infer.insert(self.span(), TyInfo::App(monad_ty, a));
+                let field_ty = match def {
+                    ast::LangDef::Pure => infer.insert(self.span(), TyInfo::Func(a, monad_a)),
+                    ast::LangDef::Bind => {
+                        let b = infer.unknown(self.span());
+                        let monad_b = infer.insert(self.span(), TyInfo::App(monad_ty, b));
+                        let then = infer.insert(self.span(), TyInfo::Func(a, monad_b));
+                        let then_ty = infer.insert(self.span(), TyInfo::Func(then, monad_b));
+                        infer.insert(self.span(), TyInfo::Func(monad_a, then_ty))
+                    },
                 };
+                let class = infer.make_class_field_known(monad_ty, field.clone(), class, field_ty, self.span());
+                // `pure` belongs to a superclass, but `return` is only permitted for monads
+                if let (ast::LangDef::Pure, Some(monad)) = (def, monad) {
+                    infer.make_impl(monad_ty, monad, Vec::new(), self.span(), Vec::new(), self.span());
+                }
 
-                instantiate_def(def, self.span(), infer, Some(self.span()), self.span())
+                (TyInfo::Ref(field_ty), hir::Expr::ClassAccess((self.span(), monad_ty), class, field))
             },
             ast::Expr::Local(local) => {
                 if let Some((ty, rec)) = scope.find(infer, self.span(), &local) {
@@ -491,13 +702,19 @@ impl ToHir for ast::Expr {
                     } else {
                         (TyInfo::Ref(ty), hir::Expr::Local(*local))
                     }
-                } else if let Some(def_id) = infer.ctx().defs.lookup(*local) {
+                } else if let Some(def_id) = infer.ctx().modules.lookup_def(scope.module(), &ast::Path::local(SrcNode::new(*local, self.span()))) {
                     instantiate_def(def_id, self.span(), infer, None, self.span())
                 } else {
                     infer.ctx_mut().emit(Error::NoSuchLocal(SrcNode::new(*local, self.span())));
                     (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
                 }
             },
+            ast::Expr::Path(path) => if let Some(def_id) = infer.ctx().modules.lookup_def(scope.module(), path) {
+                instantiate_def(def_id, self.span(), infer, None, self.span())
+            } else {
+                infer.ctx_mut().emit(Error::NoSuchDef(SrcNode::new(path.clone(), self.span())));
+                (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
+            },
             ast::Expr::Tuple(items) => {
                 let items = items
                     .iter()
@@ -745,18 +962,23 @@ impl ToHir for ast::Expr {
                     (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
                 }
             },
-            ast::Expr::Apply(f, param) => {
-                let f = f.to_hir(infer, scope);
+            ast::Expr::Apply(f_ast, param) => {
+                let f = f_ast.to_hir(infer, scope);
                 let param = param.to_hir(infer, scope);
                 let input_ty = infer.unknown(param.meta().0);
                 let output_ty = infer.unknown(self.span());
                 let func = infer.insert(f.meta().0, TyInfo::Func(input_ty, output_ty));
                 infer.make_flow(f.meta().1, func, EqInfo::new(self.span(), format!("Only functions are callable")));
-                infer.make_flow(param.meta().1, input_ty, EqInfo::new(param.meta().0, format!("Functions may only be called with compatible arguments")));
+                let reason = if matches!(&**f_ast, ast::Expr::LangDef(ast::LangDef::Bind)) {
+                    format!("Values bound within `do` blocks must have a type that is a member of Monad")
+                } else {
+                    format!("Functions may only be called with compatible arguments")
+                };
+                infer.make_flow(param.meta().1, input_ty, EqInfo::new(param.meta().0, reason));
 
                 (TyInfo::Ref(output_ty), hir::Expr::Apply(f, param))
             },
-            ast::Expr::Cons(name, inner) => if let Some(data) = infer.ctx().datas.lookup_cons(**name) {
+            ast::Expr::Cons(name, inner) => if let Some((data, cons_name)) = infer.ctx().modules.lookup_cons(scope.module(), name) {
                 let gen_scope = infer.ctx().tys.get_gen_scope(infer.ctx().datas.get_data(data).gen_scope);
                 let generics_count = gen_scope.len();
                 let generic_tys = (0..generics_count)
@@ -783,7 +1005,7 @@ impl ToHir for ast::Expr {
                     .get_data(data)
                     .cons
                     .iter()
-                    .find(|(cons, _)| **cons == **name)
+                    .find(|(cons, _)| **cons == cons_name)
                     .unwrap()
                     .1;
 
@@ -804,20 +1026,21 @@ impl ToHir for ast::Expr {
                 let inner = inner.to_hir(infer, scope);
                 infer.make_flow(inner.meta().1, inner_ty, self.span());
 
-                (TyInfo::Data(data, generic_tys), hir::Expr::Cons(SrcNode::new(data, name.span()), **name, inner))
+                (TyInfo::Data(data, generic_tys), hir::Expr::Cons(SrcNode::new(data, name.span()), cons_name, inner))
             } else {
                 infer.ctx_mut().emit(Error::NoSuchCons(name.clone()));
                 // TODO: Don't use a hard, preserve inner expression
                 (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
             },
             ast::Expr::ClassAccess(ty, field) => {
-                let ty = ty.to_hir(infer, scope);
+                // The type may be a type constructor, such as in `Maybe.map`
+                let ty = ty.to_hir_of_kind(infer, scope, None);
                 let field_ty = infer.unknown(field.span());
                 let class = infer.make_class_field(ty.meta().1, field.clone(), field_ty, ty.meta().0);
                 (TyInfo::Ref(field_ty), hir::Expr::ClassAccess(*ty.meta(), class, field.clone()))
             },
-            ast::Expr::Intrinsic(name, args) => {
-                let mut args = args
+            ast::Expr::Intrinsic(name, intrinsic_args) => {
+                let mut args = intrinsic_args
                     .iter()
                     .map(|arg| arg.to_hir(infer, scope))
                     .collect::<Vec<_>>();
@@ -865,6 +1088,41 @@ impl ToHir for ast::Expr {
                         infer.make_flow(args[1].meta().1, nat, EqInfo::from(name.span()));
                         (TyInfo::Prim(Prim::Bool), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::EqNat, name.span()), args))
                     },
+                    "eq_real" if args.len() == 2 => {
+                        let a = &args[0];
+                        let b = &args[1];
+                        let real = infer.insert(a.meta().0, TyInfo::Prim(Prim::Real));
+                        infer.make_flow(args[0].meta().1, real, EqInfo::from(name.span()));
+                        infer.make_flow(args[1].meta().1, real, EqInfo::from(name.span()));
+                        (TyInfo::Prim(Prim::Bool), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::EqReal, name.span()), args))
+                    },
+                    "nat_to_real" if args.len() == 1 => {
+                        let nat = infer.insert(args[0].meta().0, TyInfo::Prim(Prim::Nat));
+                        infer.make_flow(args[0].meta().1, nat, EqInfo::from(name.span()));
+                        (TyInfo::Prim(Prim::Real), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::NatToReal, name.span()), args))
+                    },
+                    "int_to_real" if args.len() == 1 => {
+                        let int = infer.insert(args[0].meta().0, TyInfo::Prim(Prim::Int));
+                        infer.make_flow(args[0].meta().1, int, EqInfo::from(name.span()));
+                        (TyInfo::Prim(Prim::Real), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::IntToReal, name.span()), args))
+                    },
+                    "real_to_int" if args.len() == 1 => {
+                        let real = infer.insert(args[0].meta().0, TyInfo::Prim(Prim::Real));
+                        infer.make_flow(args[0].meta().1, real, EqInfo::from(name.span()));
+                        (TyInfo::Prim(Prim::Int), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::RealToInt, name.span()), args))
+                    },
+                    "real_to_nat" if args.len() == 1 => {
+                        let real = infer.insert(args[0].meta().0, TyInfo::Prim(Prim::Real));
+                        infer.make_flow(args[0].meta().1, real, EqInfo::from(name.span()));
+                        (TyInfo::Prim(Prim::Nat), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::RealToNat, name.span()), args))
+                    },
+                    "display_real" if args.len() == 1 => {
+                        let real = infer.insert(args[0].meta().0, TyInfo::Prim(Prim::Real));
+                        infer.make_flow(args[0].meta().1, real, EqInfo::from(name.span()));
+                        // Produces a string
+                        let c = infer.insert(name.span(), TyInfo::Prim(Prim::Char));
+                        (TyInfo::List(c), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::DisplayReal, name.span()), args))
+                    },
                     "go" if args.len() == 2 => if let Some(go_data) = infer.ctx().datas.lang.go {
                         let c = args[1].meta().1;
                         let r = infer.unknown(self.span());
@@ -935,6 +1193,16 @@ impl ToHir for ast::Expr {
                         infer.make_effect_send_recv(eff, a.meta().1, out, self.span());
                         (TyInfo::Ref(out), hir::Expr::Suspend(eff, args.remove(0)))
                     },
+                    "host" if args.len() == 2 => if let ast::Expr::Literal(ast::Literal::Str(host_name)) = &*intrinsic_args[0] {
+                        // The result takes whatever type the program expects, since the host is trusted to produce a
+                        // value of that type (see `Vm::intrinsic`). If nothing constrains it, inference fails and the
+                        // user must annotate it. Generic types are rejected once the types are known.
+                        let out = infer.unknown(self.span());
+                        (TyInfo::Ref(out), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::Host(Ident::new(host_name.as_str())), name.span()), vec![args.remove(1)]))
+                    } else {
+                        infer.ctx_mut().emit(Error::HostNameNotLiteral(intrinsic_args[0].span()));
+                        (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error)
+                    },
                     _ => {
                         infer.ctx_mut().emit(Error::InvalidIntrinsic(name.clone()));
                         (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error)
@@ -955,7 +1223,7 @@ impl ToHir for ast::Expr {
                 (TyInfo::Ref(record.meta().1), hir::Expr::Update(record, fields))
             },
             ast::Expr::Block(init, last) => {
-                let eff = infer.unknown_effect(self.span());
+                let eff = infer.insert_effect(self.span(), EffectInfo::Open(Vec::new()));
 
                 // Collect effects into this basin
                 let scope = scope.with_basin(eff);
@@ -982,10 +1250,16 @@ impl ToHir for ast::Expr {
 
                 (TyInfo::Effect(eff, last_meta.1), hir::Expr::Basin(eff, chain))
             },
-            ast::Expr::Handle { expr, eff_name, eff_args, send, recv } => {
+            ast::Expr::Handle { expr, eff_name, eff_args, send, resume, recv } => {
                 let expr = expr.to_hir(infer, &scope);
                 let send = send.to_hir(infer, &scope);
-                let recv = recv.to_hir(infer, &scope.with_many(&send.get_binding_tys()));
+                let resume = resume.as_ref().map(|resume| resume.to_hir(infer, &scope));
+                let bindings = send
+                    .get_binding_tys()
+                    .into_iter()
+                    .chain(resume.iter().flat_map(|resume| resume.get_binding_tys()))
+                    .collect::<Vec<_>>();
+                let recv = recv.to_hir(infer, &scope.with_many(&bindings));
 
                 let eff_args = eff_args
                     .iter()
@@ -994,7 +1268,7 @@ impl ToHir for ast::Expr {
 
                 let out_ty = infer.unknown(self.span());
 
-                if let Some(eff_id) = infer.ctx().effects.lookup(**eff_name) {
+                if let Some(eff_id) = infer.ctx().modules.lookup_effect(scope.module(), eff_name) {
                     let eff = infer.ctx().effects.get_decl(eff_id);
                     let eff_gen_scope = eff.gen_scope;
                     let eff_span = eff.name.span();
@@ -1006,12 +1280,36 @@ impl ToHir for ast::Expr {
                         eff_span,
                     ) {
                         Ok(()) => {
-                            let eff = infer.insert_effect(self.span(), EffectInfo::Known(eff_id, eff_args));
-                            infer.make_effect_send_recv(eff, send.meta().1, recv.meta().1, eff_name.span());
-                            let eff_obj_ty = infer.insert(expr.meta().0, TyInfo::Effect(eff, out_ty));
+                            let eff = infer.insert_effect(self.span(), EffectInfo::Known(vec![(eff_id, eff_args)]));
+                            // The handled expression may perform other effects too, which the enclosing basin performs
+                            let expr_eff = infer.unknown_effect(expr.meta().0);
+                            let eff_obj_ty = infer.insert(expr.meta().0, TyInfo::Effect(expr_eff, out_ty));
                             infer.make_flow(expr.meta().1, eff_obj_ty, EqInfo::from(self.span()));
+                            infer.make_handle(expr_eff, eff, scope.last_basin(), self.span());
 
                             let recv_meta = *recv.meta();
+                            let (send, resumable) = if let Some(resume) = resume {
+                                // The handler produces the result of the entire expression, and is given a resumption
+                                // that continues the handled computation (with the handler still in place) and produces
+                                // the result that it eventually gives
+                                let resume_span = resume.meta().0;
+                                let recv_ty = infer.unknown(resume_span);
+                                infer.make_effect_send_recv(eff, send.meta().1, recv_ty, eff_name.span());
+                                let resume_ty = infer.insert(resume_span, TyInfo::Func(recv_ty, out_ty));
+                                infer.make_flow(resume_ty, resume.meta().1, EqInfo::from(resume_span));
+                                infer.make_flow(recv.meta().1, out_ty, EqInfo::new(recv_meta.0, format!("Handlers that take a resumption produce the result of the handled expression")));
+
+                                let send_span = send.meta().0.union(resume_span);
+                                let tuple_ty = infer.insert(send_span, TyInfo::Tuple(vec![send.meta().1, resume.meta().1]));
+                                (InferNode::new(hir::Binding {
+                                    pat: SrcNode::new(hir::Pat::Tuple(vec![send, resume]), send_span),
+                                    name: None,
+                                }, (send_span, tuple_ty)), true)
+                            } else {
+                                infer.make_effect_send_recv(eff, send.meta().1, recv.meta().1, eff_name.span());
+                                (send, false)
+                            };
+
                             (TyInfo::Ref(out_ty), hir::Expr::Handle {
                                 expr,
                                 eff,
@@ -1021,10 +1319,14 @@ impl ToHir for ast::Expr {
                                     InferNode::new(hir::Expr::Local(Ident::new("send")), *send.meta()),
                                     vec![(send, recv)],
                                 ), recv_meta),
+                                resumable,
                             })
                         },
                         Err(()) => (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error),
                     }
+                } else if infer.ctx().modules.lookup_effect_alias(scope.module(), eff_name).is_some() {
+                    infer.ctx_mut().emit(Error::Unsupported(eff_name.span(), "handling effect aliases"));
+                    (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error)
                 } else {
                     infer.ctx_mut().emit(Error::NoSuchEffect(eff_name.clone()));
                     (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error) // TODO: Can we avoid making this entire node an error?
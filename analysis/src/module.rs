@@ -0,0 +1,459 @@
+use super::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(usize);
+
+pub type Names<T> = HashMap<Ident, (Span, T)>;
+
+/// A declaration in the type namespace.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TypeItem {
+    Data(DataId),
+    Alias(AliasId),
+}
+
+/// A declaration in the effect namespace.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EffectItem {
+    Decl(EffectDeclId),
+    Alias(EffectAliasId),
+}
+
+impl From<DataId> for TypeItem {
+    fn from(data: DataId) -> Self { TypeItem::Data(data) }
+}
+
+impl From<AliasId> for TypeItem {
+    fn from(alias: AliasId) -> Self { TypeItem::Alias(alias) }
+}
+
+impl From<EffectDeclId> for EffectItem {
+    fn from(decl: EffectDeclId) -> Self { EffectItem::Decl(decl) }
+}
+
+impl From<EffectAliasId> for EffectItem {
+    fn from(alias: EffectAliasId) -> Self { EffectItem::Alias(alias) }
+}
+
+/// The items that can be referred to by name from some scope.
+#[derive(Clone, Default)]
+pub struct Namespace {
+    pub defs: Names<DefId>,
+    pub types: Names<TypeItem>,
+    /// Constructors, along with the name that their data type declares them with (imports may rename them).
+    pub cons: Names<(DataId, Ident)>,
+    pub classes: Names<ClassId>,
+    pub effects: Names<EffectItem>,
+    pub modules: Names<ModuleId>,
+}
+
+impl Namespace {
+    pub fn contains(&self, name: Ident) -> bool {
+        self.defs.contains_key(&name)
+            || self.types.contains_key(&name)
+            || self.cons.contains_key(&name)
+            || self.classes.contains_key(&name)
+            || self.effects.contains_key(&name)
+            || self.modules.contains_key(&name)
+    }
+}
+
+pub struct Module {
+    pub src: SrcId,
+    /// Items declared by the module itself.
+    pub items: Namespace,
+    /// Items brought into scope by the module's imports. Items declared by the module shadow these.
+    pub imports: Namespace,
+    /// Items that are visible to modules that import this one.
+    pub exports: Namespace,
+}
+
+#[derive(Default)]
+pub struct Modules {
+    modules: Vec<Module>,
+    /// Modules ordered such that each appears after the modules it imports (cycles are broken arbitrarily).
+    dependency_order: Vec<ModuleId>,
+}
+
+impl Modules {
+    pub fn get(&self, module: ModuleId) -> &Module {
+        &self.modules[module.0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ModuleId, &Module)> {
+        self.modules.iter().enumerate().map(|(i, module)| (ModuleId(i), module))
+    }
+
+    /// The module that the program was compiled from. This is always the first module to be declared.
+    pub fn root(&self) -> ModuleId {
+        ModuleId(0)
+    }
+
+    pub fn dependency_order(&self) -> &[ModuleId] {
+        &self.dependency_order
+    }
+
+    pub fn lookup_src(&self, src: SrcId) -> Option<ModuleId> {
+        self.iter().find(|(_, module)| module.src == src).map(|(id, _)| id)
+    }
+
+    pub fn declare_module(&mut self, src: SrcId) -> ModuleId {
+        let id = ModuleId(self.modules.len());
+        self.modules.push(Module {
+            src,
+            items: Namespace::default(),
+            imports: Namespace::default(),
+            exports: Namespace::default(),
+        });
+        id
+    }
+
+    /// Declare an item within a module, producing the span of the existing item if the name is already taken.
+    pub fn declare<T: Copy, I: Copy + Into<T>>(
+        &mut self,
+        module: ModuleId,
+        vis: ast::Visibility,
+        name: &SrcNode<Ident>,
+        ns: impl Fn(&mut Namespace) -> &mut Names<T>,
+        item: impl FnOnce() -> I,
+    ) -> Result<I, Span> {
+        let module = &mut self.modules[module.0];
+        if let Some((old, _)) = ns(&mut module.items).get(&**name) {
+            return Err(*old);
+        }
+
+        let item = item();
+        ns(&mut module.items).insert(**name, (name.span(), item.into()));
+        if vis == ast::Visibility::Public {
+            ns(&mut module.exports).insert(**name, (name.span(), item.into()));
+        }
+        Ok(item)
+    }
+
+    // The name by which absolute paths refer to a module. It is not a valid identifier, so it cannot be written in source
+    // code or clash with the names of other items.
+    fn absolute_name(module: ModuleId) -> Ident {
+        Ident::new(format!("<module {}>", module.0))
+    }
+
+    /// Make a path that refers to an item declared by the given module, whatever is in scope where the path is used and
+    /// whether or not the item is exported. Such paths cannot be written in source code: they are only generated by the
+    /// compiler (i.e: by derived members, which must refer to the same items wherever they are derived).
+    pub fn absolute_path(&self, module: ModuleId, name: SrcNode<Ident>) -> ast::Path {
+        ast::Path { base: vec![SrcNode::new(Self::absolute_name(module), name.span())], name }
+    }
+
+    /// Find the module that declares the given item, along with the name that it declares it with.
+    pub fn declaration<T: Copy + PartialEq>(&self, item: T, ns: impl Fn(&Namespace) -> &Names<T>) -> Option<(ModuleId, Ident)> {
+        self.iter().find_map(|(id, module)| ns(&module.items)
+            .iter()
+            .find(|(_, (_, other))| *other == item)
+            .map(|(name, _)| (id, *name)))
+    }
+
+    fn lookup<T: Copy>(&self, module: ModuleId, path: &ast::Path, ns: impl Fn(&Namespace) -> &Names<T>) -> Option<T> {
+        let module = self.get(module);
+        match path.base.split_first() {
+            None => ns(&module.items)
+                .get(&*path.name)
+                .or_else(|| ns(&module.imports).get(&*path.name)),
+            Some((first, rest)) => match self.iter().find(|(id, _)| Self::absolute_name(*id) == **first) {
+                Some((_, target)) if rest.is_empty() => ns(&target.items).get(&*path.name),
+                _ => {
+                    let mut exports = &self.get(module.imports.modules.get(&**first)?.1).exports;
+                    for name in rest {
+                        exports = &self.get(exports.modules.get(&**name)?.1).exports;
+                    }
+                    ns(exports).get(&*path.name)
+                },
+            },
+        }
+            .map(|(_, item)| *item)
+    }
+
+    pub fn lookup_def(&self, module: ModuleId, path: &ast::Path) -> Option<DefId> {
+        self.lookup(module, path, |ns| &ns.defs)
+    }
+
+    pub fn lookup_data(&self, module: ModuleId, path: &ast::Path) -> Option<DataId> {
+        match self.lookup(module, path, |ns| &ns.types)? {
+            TypeItem::Data(data) => Some(data),
+            TypeItem::Alias(_) => None,
+        }
+    }
+
+    pub fn lookup_alias(&self, module: ModuleId, path: &ast::Path) -> Option<AliasId> {
+        match self.lookup(module, path, |ns| &ns.types)? {
+            TypeItem::Alias(alias) => Some(alias),
+            TypeItem::Data(_) => None,
+        }
+    }
+
+    pub fn lookup_cons(&self, module: ModuleId, path: &ast::Path) -> Option<(DataId, Ident)> {
+        self.lookup(module, path, |ns| &ns.cons)
+    }
+
+    pub fn lookup_class(&self, module: ModuleId, path: &ast::Path) -> Option<ClassId> {
+        self.lookup(module, path, |ns| &ns.classes)
+    }
+
+    pub fn lookup_effect(&self, module: ModuleId, path: &ast::Path) -> Option<EffectDeclId> {
+        match self.lookup(module, path, |ns| &ns.effects)? {
+            EffectItem::Decl(decl) => Some(decl),
+            EffectItem::Alias(_) => None,
+        }
+    }
+
+    pub fn lookup_effect_alias(&self, module: ModuleId, path: &ast::Path) -> Option<EffectAliasId> {
+        match self.lookup(module, path, |ns| &ns.effects)? {
+            EffectItem::Alias(alias) => Some(alias),
+            EffectItem::Decl(_) => None,
+        }
+    }
+
+    /// Bring the items named by the imports of each module into scope. `imports` is indexed by module.
+    pub fn resolve_imports(&mut self, imports: &[Vec<&ast::Import>]) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut visited = HashSet::new();
+        for i in 0..self.modules.len() {
+            self.resolve_module_imports(ModuleId(i), imports, &mut visited, &mut errors);
+        }
+        errors
+    }
+
+    fn resolve_module_imports(
+        &mut self,
+        module: ModuleId,
+        imports: &[Vec<&ast::Import>],
+        visited: &mut HashSet<ModuleId>,
+        errors: &mut Vec<Error>,
+    ) {
+        if !visited.insert(module) {
+            return;
+        }
+
+        for import in imports.get(module.0).into_iter().flatten() {
+            // Modules that could not be found have already been reported by whoever loaded them
+            let Some(target) = self.lookup_src(SrcId::from_path(import.path.as_str())) else { continue };
+
+            // Re-exports of the imported module must be known before we can import from it. Cyclic imports only see
+            // the re-exports that have been resolved so far.
+            self.resolve_module_imports(target, imports, visited, errors);
+            let exports = self.get(target).exports.clone();
+
+            if let Some(alias) = &import.alias {
+                self.import(module, import.vis, **alias, alias.span(), target, |ns| &mut ns.modules, errors);
+            }
+
+            if import.is_glob() {
+                let span = import.path.span();
+                for (name, (_, def)) in &exports.defs { self.import(module, import.vis, *name, span, *def, |ns| &mut ns.defs, errors); }
+                for (name, (_, ty)) in &exports.types { self.import(module, import.vis, *name, span, *ty, |ns| &mut ns.types, errors); }
+                for (name, (_, cons)) in &exports.cons { self.import(module, import.vis, *name, span, *cons, |ns| &mut ns.cons, errors); }
+                for (name, (_, class)) in &exports.classes { self.import(module, import.vis, *name, span, *class, |ns| &mut ns.classes, errors); }
+                for (name, (_, eff)) in &exports.effects { self.import(module, import.vis, *name, span, *eff, |ns| &mut ns.effects, errors); }
+                for (name, (_, m)) in &exports.modules { self.import(module, import.vis, *name, span, *m, |ns| &mut ns.modules, errors); }
+            }
+
+            for (item, rename) in import.items.iter().flatten() {
+                let name = rename.as_ref().unwrap_or(item);
+                let mut found = false;
+
+                if let Some((_, def)) = exports.defs.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *def, |ns| &mut ns.defs, errors);
+                }
+                if let Some((_, ty)) = exports.types.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *ty, |ns| &mut ns.types, errors);
+                    // Importing a data type also brings its constructors into scope
+                    if let TypeItem::Data(data) = ty {
+                        for (cons_name, (_, cons)) in &exports.cons {
+                            if cons.0 == *data {
+                                self.import(module, import.vis, *cons_name, name.span(), *cons, |ns| &mut ns.cons, errors);
+                            }
+                        }
+                    }
+                }
+                if let Some((_, cons)) = exports.cons.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *cons, |ns| &mut ns.cons, errors);
+                }
+                if let Some((_, class)) = exports.classes.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *class, |ns| &mut ns.classes, errors);
+                }
+                if let Some((_, eff)) = exports.effects.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *eff, |ns| &mut ns.effects, errors);
+                }
+                if let Some((_, m)) = exports.modules.get(&**item) {
+                    found = true;
+                    self.import(module, import.vis, **name, name.span(), *m, |ns| &mut ns.modules, errors);
+                }
+
+                if !found {
+                    errors.push(if self.get(target).items.contains(**item) {
+                        Error::PrivateImport(item.clone(), import.path.clone())
+                    } else {
+                        Error::NoSuchImport(item.clone(), import.path.clone())
+                    });
+                }
+            }
+        }
+
+        self.dependency_order.push(module);
+    }
+
+    fn import<T: Copy + PartialEq>(
+        &mut self,
+        module: ModuleId,
+        vis: ast::Visibility,
+        name: Ident,
+        span: Span,
+        item: T,
+        ns: impl Fn(&mut Namespace) -> &mut Names<T>,
+        errors: &mut Vec<Error>,
+    ) {
+        let module = &mut self.modules[module.0];
+
+        // Items declared by the module shadow imported items
+        if ns(&mut module.items).contains_key(&name) {
+            return;
+        }
+
+        match ns(&mut module.imports).get(&name).copied() {
+            // The same item imported via several routes is fine
+            Some((_, old)) if old == item => {},
+            Some((old, _)) => {
+                errors.push(Error::AmbiguousImport(name, old, span));
+                return;
+            },
+            None => { ns(&mut module.imports).insert(name, (span, item)); },
+        }
+
+        if vis == ast::Visibility::Public {
+            ns(&mut module.exports).entry(name).or_insert((span, item));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tao_syntax::parse_module;
+
+    // Analyse the first module, which may import the others by their paths
+    fn analyse(modules: &[(&str, &str)]) -> (Context, Vec<Error>) {
+        let parse = |(path, code): &(&str, &str)| match parse_module(code, SrcId::from_path(path)) {
+            (Some(module), errors) if errors.is_empty() => module,
+            (_, errors) => panic!("Failed to parse {}: {:?}", path, errors),
+        };
+        let root = parse(&modules[0]);
+        let imports = modules[1..].iter().map(parse).collect::<Vec<_>>();
+        let (ctx, errors) = Context::from_module(&root, &imports);
+        // Lang items are declared by the standard library, which these modules do not import
+        let errors = errors.into_iter().filter(|e| !matches!(e, Error::MissingLangItem(_))).collect();
+        (ctx, errors)
+    }
+
+    fn path(names: &[&str]) -> ast::Path {
+        let node = |name: &&str| SrcNode::new(Ident::new(name), Span::new(SrcId::repl(), 0..0));
+        let (name, base) = names.split_last().unwrap();
+        ast::Path { base: base.iter().map(node).collect(), name: node(name) }
+    }
+
+    fn module(ctx: &Context, path: &str) -> ModuleId {
+        ctx.modules.lookup_src(SrcId::from_path(path)).unwrap()
+    }
+
+    #[test]
+    fn private_items_are_not_importable() {
+        let (ctx, errors) = analyse(&[
+            ("main.tao", "import \"a.tao\" with hidden, shown\nimport \"a.tao\" as a"),
+            ("a.tao", "def hidden : Nat = 1\npub def shown : Nat = 2"),
+        ]);
+
+        assert!(matches!(errors.as_slice(), [Error::PrivateImport(item, _)] if **item == Ident::new("hidden")));
+        let root = ctx.modules.root();
+        assert!(ctx.modules.lookup_def(root, &path(&["shown"])).is_some());
+        assert!(ctx.modules.lookup_def(root, &path(&["a", "hidden"])).is_none());
+    }
+
+    #[test]
+    fn aliased_import_with_items() {
+        let (ctx, errors) = analyse(&[
+            ("main.tao", "import \"m.tao\" as m with a, B as C\ndef x : C = Bee"),
+            ("m.tao", "pub def a : Nat = 1\npub def b : Nat = 2\npub data B =\n\t\\ Bee"),
+        ]);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (root, m) = (ctx.modules.root(), module(&ctx, "m.tao"));
+        assert_eq!(ctx.modules.lookup_def(root, &path(&["a"])), ctx.modules.lookup_def(m, &path(&["a"])));
+        assert!(ctx.modules.lookup_def(root, &path(&["b"])).is_none());
+        assert!(ctx.modules.lookup_def(root, &path(&["m", "b"])).is_some());
+        // Renamed data types keep their constructors
+        let data = ctx.modules.lookup_data(m, &path(&["B"]));
+        assert!(data.is_some());
+        assert_eq!(ctx.modules.lookup_data(root, &path(&["C"])), data);
+        assert_eq!(ctx.modules.lookup_cons(root, &path(&["Bee"])).map(|(data, _)| data), data);
+        assert!(ctx.modules.lookup_data(root, &path(&["B"])).is_none());
+    }
+
+    #[test]
+    fn renamed_constructors_resolve_to_the_original() {
+        let (ctx, errors) = analyse(&[
+            ("main.tao", "import \"m.tao\" with Bee as Buzz\ndef x : Nat = match Buzz in\n\t\\ Buzz => 1"),
+            ("m.tao", "pub data B =\n\t\\ Bee"),
+        ]);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        let data = ctx.modules.lookup_data(module(&ctx, "m.tao"), &path(&["B"])).unwrap();
+        assert_eq!(ctx.modules.lookup_cons(ctx.modules.root(), &path(&["Buzz"])), Some((data, Ident::new("Bee"))));
+        assert!(ctx.modules.lookup_cons(ctx.modules.root(), &path(&["Bee"])).is_none());
+    }
+
+    #[test]
+    fn glob_imports_are_ambiguous() {
+        let (_, errors) = analyse(&[
+            ("main.tao", "import \"a.tao\"\nimport \"b.tao\""),
+            ("a.tao", "pub def x : Nat = 1"),
+            ("b.tao", "pub def x : Nat = 2"),
+        ]);
+
+        assert!(matches!(errors.as_slice(), [Error::AmbiguousImport(name, _, _)] if *name == Ident::new("x")), "{:?}", errors);
+    }
+
+    #[test]
+    fn glob_imports_of_the_same_item_are_not_ambiguous() {
+        let (ctx, errors) = analyse(&[
+            ("main.tao", "import \"a.tao\"\nimport \"b.tao\"\ndef y : Nat = x"),
+            ("a.tao", "pub def x : Nat = 1"),
+            ("b.tao", "pub import \"a.tao\""),
+        ]);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            ctx.modules.lookup_def(ctx.modules.root(), &path(&["x"])),
+            ctx.modules.lookup_def(module(&ctx, "a.tao"), &path(&["x"])),
+        );
+    }
+
+    #[test]
+    fn qualified_access() {
+        let (ctx, errors) = analyse(&[
+            ("main.tao", "import \"b.tao\" as b\ndef y : Nat = b::a::x"),
+            ("a.tao", "pub def x : Nat = 1\ndef z : Nat = 2"),
+            ("b.tao", "pub import \"a.tao\" as a"),
+        ]);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        let root = ctx.modules.root();
+        let x = ctx.modules.lookup_def(module(&ctx, "a.tao"), &path(&["x"]));
+        assert!(x.is_some());
+        assert_eq!(ctx.modules.lookup_def(root, &path(&["b", "a", "x"])), x);
+        // Aliased imports do not bring items into scope unqualified, and private items cannot be reached
+        assert!(ctx.modules.lookup_def(root, &path(&["x"])).is_none());
+        assert!(ctx.modules.lookup_def(root, &path(&["b", "a", "z"])).is_none());
+    }
+}
@@ -99,21 +99,36 @@ impl Reify for hir::Expr<InferMeta> {
             hir::Expr::ClassAccess((ty_span, ty), class, field) => {
                 hir::Expr::ClassAccess((ty_span, infer.reify(ty)), infer.reify_class(class), field)
             },
-            hir::Expr::Intrinsic(name, args) => hir::Expr::Intrinsic(name, args
-                .into_iter()
-                .map(|arg| arg.reify(infer))
-                .collect()),
+            hir::Expr::Intrinsic(name, args) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| arg.reify(infer))
+                    .collect::<Vec<_>>();
+
+                // The host has no way to know what types it is given or must produce, so they must be known
+                if let Intrinsic::Host(_) = &*name {
+                    let out = infer.reify(ty);
+                    for (span, ty) in args.iter().map(|arg| *arg.meta()).chain(std::iter::once((span, out))) {
+                        if !infer.ctx().tys.is_concrete(ty) {
+                            infer.ctx_mut().emit(Error::HostTyNotConcrete(span, ty));
+                        }
+                    }
+                }
+
+                hir::Expr::Intrinsic(name, args)
+            },
             hir::Expr::Update(record, fields) => hir::Expr::Update(record.reify(infer), fields
                 .into_iter()
                 .map(|(name, field)| (name, field.reify(infer)))
                 .collect()),
             hir::Expr::Basin(eff, inner) => hir::Expr::Basin(infer.reify_effect(eff), inner.reify(infer)),
             hir::Expr::Suspend(eff, inner) => hir::Expr::Suspend(infer.reify_effect(eff), inner.reify(infer)),
-            hir::Expr::Handle { expr, eff, send, recv } => hir::Expr::Handle {
+            hir::Expr::Handle { expr, eff, send, recv, resumable } => hir::Expr::Handle {
                 expr: expr.reify(infer),
                 eff: infer.reify_effect(eff),
                 send: TyNode::new(*send, (send.meta().0, infer.reify(send.meta().1))),
                 recv: recv.reify(infer),
+                resumable,
             },
         };
 
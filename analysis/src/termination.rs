@@ -0,0 +1,549 @@
+//! Termination checking for recursive definitions.
+//!
+//! Every recursive call is summarised by a size-change graph that records, for each argument of the call, which
+//! parameter of the caller it is no larger than (or strictly smaller than). Arguments are only considered smaller when
+//! they are taken apart from a parameter by a pattern (the tail of a list, the inner value of a data constructor, `n`
+//! in `n + 1`, etc.), or when they add back less than such a pattern took off (`m + 1`, where `m` was bound by
+//! `m + 2`). A group of mutually recursive definitions terminates if every cycle of calls through it, once repeated,
+//! strictly decreases some parameter. See 'The Size-Change Principle for Program Termination' (Lee, Jones & Ben-Amram,
+//! 2001).
+//!
+//! The value items of class members are checked along with definitions. Until the program is monomorphised, it is not
+//! always known which member provides an item that is accessed through a class, so the access is treated as a call to
+//! every member that could provide it. To show that recursion through the members of a class terminates (as it does
+//! when the member for `(A, B)` uses the members for `A` and `B`), the types that each item is instantiated with are
+//! treated as an extra parameter: a member is instantiated with its type, of which its generic types are parts, and a
+//! definition is instantiated with its generic types.
+//!
+//! Definitions and members marked with `$[partial]` are not checked, and calls to them are not considered to be
+//! recursive. Instead, partiality spreads: anything that refers to a partial item must be marked `$[partial]` too. A
+//! member that is accessed through a class is only known to be partial if it is the only member that could provide
+//! the item, so generic code that may be instantiated with a partial member is not caught.
+
+use super::*;
+
+// Guards against pathological groups of definitions producing an unreasonable number of graphs
+const MAX_GRAPHS: usize = 4096;
+
+// The parameter that stands for the types that an item is instantiated with
+const TYPES: usize = usize::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Size {
+    NoLarger,
+    Smaller,
+}
+
+// Something that can be called recursively: a definition, or a value item of a class member
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Item {
+    Def(DefId),
+    Member(MemberId, Ident),
+}
+
+// The size of a value relative to a parameter of the definition being checked
+type Rel = Option<(usize, Size)>;
+
+fn smaller(rel: Rel) -> Rel {
+    rel.map(|(param, _)| (param, Size::Smaller))
+}
+
+struct Call {
+    callee: Item,
+    args: Vec<Rel>,
+    // The size of the types that the callee is instantiated with, relative to those of the caller
+    tys: Option<Size>,
+    span: Span,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Graph {
+    caller: Item,
+    callee: Item,
+    // (caller parameter, callee parameter) => size of the latter relative to the former
+    edges: BTreeMap<(usize, usize), Size>,
+}
+
+impl Graph {
+    fn compose(&self, other: &Self) -> Self {
+        let mut edges = BTreeMap::new();
+        for (&(i, j), &x) in &self.edges {
+            for (&(_, k), &y) in other.edges.range((j, 0)..=(j, usize::MAX)) {
+                let size = edges.entry((i, k)).or_insert(Size::NoLarger);
+                *size = (*size).max(x).max(y);
+            }
+        }
+        Self { caller: self.caller, callee: other.callee, edges }
+    }
+
+    fn decreases(&self) -> bool {
+        self.edges
+            .iter()
+            .any(|(&(i, j), &size)| i == j && size == Size::Smaller)
+    }
+}
+
+struct Walker<'a> {
+    ctx: &'a Context,
+    caller: Item,
+    recursive: &'a HashSet<Item>,
+    // Each local, its size, and (if it was bound by a pattern like `m + 2`) the size of the value it was taken from along
+    // with the amount taken off
+    locals: Vec<(Ident, Rel, Option<(Rel, u64)>)>,
+    calls: Vec<Call>,
+    // The first partial item that is referred to, if any
+    partial_use: Option<(Ident, Span)>,
+}
+
+impl<'a> Walker<'a> {
+    fn local(&self, local: &Ident) -> Option<(Rel, Option<(Rel, u64)>)> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|(name, _, _)| name == local)
+            .map(|(_, rel, taken_from)| (*rel, *taken_from))
+    }
+
+    fn rel_of(&self, expr: &TyExpr) -> Rel {
+        match &**expr {
+            hir::Expr::Local(local) => self.local(local)?.0,
+            // Fields are no larger than the value they belong to
+            hir::Expr::Access(record, _) => self.rel_of(record),
+            hir::Expr::Binary(op, x, y) if **op == ast::BinaryOp::Add => match (&**x, &**y) {
+                (hir::Expr::Local(local), hir::Expr::Literal(hir::Literal::Nat(k))) => match self.local(local)?.1 {
+                    Some((whole, n)) if *k < n => smaller(whole),
+                    Some((whole, n)) if *k == n => whole,
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The size of a type relative to the types that the caller is instantiated with
+    fn ty_rel(&self, ty: TyId) -> Option<Size> {
+        match (self.caller, self.ctx.tys.get(ty)) {
+            (Item::Member(_, _), Ty::SelfType) => Some(Size::NoLarger),
+            (Item::Member(member, _), _) => part_of(&self.ctx.tys, ty, self.ctx.classes.get_member(member).member),
+            (Item::Def(def), Ty::Gen(_, scope)) if scope == self.ctx.defs.get(def).gen_scope => Some(Size::NoLarger),
+            (Item::Def(_), _) => None,
+        }
+    }
+
+    // The recursive items that an expression might refer to, along with the size of the types that each would be
+    // instantiated with
+    fn callees(&self, expr: &TyExpr) -> Vec<(Item, Option<Size>)> {
+        let callees = match &**expr {
+            hir::Expr::Global((def, gens)) => {
+                // A definition is instantiated with all of its generic types, so they must all be no larger
+                let tys = gens
+                    .iter()
+                    .map(|(_, ty)| self.ty_rel(*ty))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|sizes| sizes.into_iter().min().unwrap_or(Size::NoLarger));
+                vec![(Item::Def(*def), tys)]
+            },
+            hir::Expr::ClassAccess((_, ty), Some((class, params, _)), field) => self.ctx.classes
+                .candidates(&self.ctx.tys, *ty, params, *class)
+                .map(|member| (Item::Member(member, **field), self.ty_rel(*ty)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        callees
+            .into_iter()
+            .filter(|(item, _)| self.recursive.contains(item))
+            .collect()
+    }
+
+    // Record a reference to an item marked `$[partial]`
+    fn check_partial(&mut self, expr: &TyExpr) {
+        let partial = match &**expr {
+            hir::Expr::Global((def, _)) => Some(self.ctx.defs.get(*def))
+                .filter(|def| is_partial(&def.attr))
+                .map(|def| *def.name),
+            hir::Expr::ClassAccess((_, ty), Some((class, params, _)), field) => {
+                let mut members = self.ctx.classes.candidates(&self.ctx.tys, *ty, params, *class);
+                match (members.next(), members.next()) {
+                    (Some(member), None) if is_partial(&self.ctx.classes.get_member(member).attr) => Some(**field),
+                    _ => None,
+                }
+            },
+            _ => None,
+        };
+        if let (Some(name), None) = (partial, self.partial_use) {
+            self.partial_use = Some((name, expr.meta().0));
+        }
+    }
+
+    fn bind(&mut self, binding: &TyBinding, rel: Rel) {
+        self.bind_taken(binding, rel, None)
+    }
+
+    // Bind a pattern whose value was taken from another by a pattern like `m + 2`
+    fn bind_taken(&mut self, binding: &TyBinding, rel: Rel, taken_from: Option<(Rel, u64)>) {
+        if let Some(name) = &binding.name {
+            self.locals.push((**name, rel, taken_from));
+        }
+        match &*binding.pat {
+            hir::Pat::Error | hir::Pat::Wildcard | hir::Pat::Literal(_) => {},
+            hir::Pat::Single(inner) => self.bind_taken(inner, rel, taken_from),
+            hir::Pat::Add(lhs, n) => self.bind_taken(lhs, if **n > 0 { smaller(rel) } else { rel }, Some((rel, **n))),
+            hir::Pat::Tuple(fields) => fields
+                .iter()
+                .for_each(|field| self.bind(field, rel)),
+            hir::Pat::Record(fields) => fields
+                .values()
+                .for_each(|field| self.bind(field, rel)),
+            hir::Pat::ListExact(items) => items
+                .iter()
+                .for_each(|item| self.bind(item, smaller(rel))),
+            hir::Pat::ListFront(items, tail) => {
+                items
+                    .iter()
+                    .for_each(|item| self.bind(item, smaller(rel)));
+                if let Some(tail) = tail {
+                    self.bind(tail, smaller(rel));
+                }
+            },
+            hir::Pat::Decons(_, _, inner) => self.bind(inner, smaller(rel)),
+        }
+    }
+
+    // Tuples constructed only to be matched against (as the parameters of multi-parameter functions are) are matched
+    // field by field
+    fn bind_expr(&mut self, binding: &TyBinding, expr: &TyExpr) {
+        match (&*binding.pat, &**expr) {
+            (hir::Pat::Tuple(fields), hir::Expr::Tuple(exprs)) if fields.len() == exprs.len() => {
+                if let Some(name) = &binding.name {
+                    self.locals.push((**name, None, None));
+                }
+                for (field, expr) in fields.iter().zip(exprs.iter()) {
+                    self.bind_expr(field, expr);
+                }
+            },
+            _ => self.bind(binding, self.rel_of(expr)),
+        }
+    }
+
+    // Walk an application of some arguments (perhaps none) to a function. If the application is the whole of the
+    // caller's body, it is also given the caller's parameters after the first `params`.
+    fn walk_call(&mut self, expr: &TyExpr, params: Option<usize>) {
+        let mut args = Vec::new();
+        let mut f = expr;
+        while let hir::Expr::Apply(g, arg) = &**f {
+            args.push(arg);
+            f = g;
+        }
+        args.reverse();
+
+        self.check_partial(f);
+        let callees = self.callees(f);
+        if callees.is_empty() && !args.is_empty() {
+            self.walk(f);
+        }
+        for (callee, tys) in callees {
+            // Any other arguments that the callee is applied to could be anything
+            let mut rels = args
+                .iter()
+                .map(|arg| self.rel_of(arg))
+                .collect::<Vec<_>>();
+            if let Some(params) = params {
+                let passed = arity(self.ctx, callee).saturating_sub(rels.len());
+                rels.extend((0..passed).map(|i| Some((params + i, Size::NoLarger))));
+            }
+            self.calls.push(Call { callee, args: rels, tys, span: expr.meta().0 });
+        }
+        args.into_iter().for_each(|arg| self.walk(arg));
+    }
+
+    fn walk(&mut self, expr: &TyExpr) {
+        match &**expr {
+            hir::Expr::Error | hir::Expr::Literal(_) | hir::Expr::Local(_) => {},
+            hir::Expr::Global(_) | hir::Expr::ClassAccess(_, _, _) | hir::Expr::Apply(_, _) => self.walk_call(expr, None),
+            hir::Expr::Tuple(fields) => fields
+                .iter()
+                .for_each(|field| self.walk(field)),
+            hir::Expr::List(items, tails) => items
+                .iter()
+                .chain(tails.iter())
+                .for_each(|item| self.walk(item)),
+            hir::Expr::Record(fields) => fields
+                .iter()
+                .for_each(|(_, field)| self.walk(field)),
+            hir::Expr::Access(record, _) => self.walk(record),
+            hir::Expr::Binary(_, x, y) => {
+                self.walk(x);
+                self.walk(y);
+            },
+            hir::Expr::Match(_, pred, arms) => {
+                self.walk(pred);
+                for (binding, body) in arms {
+                    let old_locals = self.locals.len();
+                    self.bind_expr(binding, pred);
+                    self.walk(body);
+                    self.locals.truncate(old_locals);
+                }
+            },
+            hir::Expr::Func(arg, body) => {
+                self.locals.push((**arg, None, None));
+                self.walk(body);
+                self.locals.pop();
+            },
+            hir::Expr::Cons(_, _, inner) => self.walk(inner),
+            hir::Expr::Intrinsic(_, args) => args
+                .iter()
+                .for_each(|arg| self.walk(arg)),
+            hir::Expr::Update(record, fields) => {
+                self.walk(record);
+                fields
+                    .iter()
+                    .for_each(|(_, field)| self.walk(field));
+            },
+            hir::Expr::Basin(_, inner) | hir::Expr::Suspend(_, inner) => self.walk(inner),
+            hir::Expr::Handle { expr, send, recv, .. } => {
+                self.walk(expr);
+                self.locals.push((**send, None, None));
+                self.walk(recv);
+                self.locals.pop();
+            },
+        }
+    }
+}
+
+fn same_ty(tys: &Types, x: TyId, y: TyId) -> bool {
+    let all_same = |xs: Vec<TyId>, ys: Vec<TyId>| xs.len() == ys.len() && xs
+        .into_iter()
+        .zip(ys)
+        .all(|(x, y)| same_ty(tys, x, y));
+    match (tys.get(x), tys.get(y)) {
+        (Ty::Prim(x), Ty::Prim(y)) => x == y,
+        (Ty::List(x), Ty::List(y)) => same_ty(tys, x, y),
+        (Ty::Tuple(xs), Ty::Tuple(ys)) => all_same(xs, ys),
+        (Ty::Record(xs), Ty::Record(ys)) => xs.keys().eq(ys.keys()) && all_same(xs.into_values().collect(), ys.into_values().collect()),
+        (Ty::Func(x_i, x_o), Ty::Func(y_i, y_o)) => same_ty(tys, x_i, y_i) && same_ty(tys, x_o, y_o),
+        (Ty::Data(x, xs), Ty::Data(y, ys)) => x == y && all_same(xs, ys),
+        (Ty::Gen(x, x_scope), Ty::Gen(y, y_scope)) => x == y && x_scope == y_scope,
+        (Ty::SelfType, Ty::SelfType) | (Ty::ListCon, Ty::ListCon) => true,
+        (Ty::App(x_f, x), Ty::App(y_f, y)) => same_ty(tys, x_f, y_f) && same_ty(tys, x, y),
+        // Anything else is assumed to be different, which is never unsound
+        _ => false,
+    }
+}
+
+// The size of a type relative to a type that it might be a part of
+fn part_of(tys: &Types, ty: TyId, whole: TyId) -> Option<Size> {
+    if same_ty(tys, ty, whole) {
+        return Some(Size::NoLarger);
+    }
+    let parts = match tys.get(whole) {
+        Ty::List(item) => vec![item],
+        Ty::Tuple(fields) | Ty::Data(_, fields) => fields,
+        Ty::Record(fields) => fields.into_values().collect(),
+        Ty::Func(i, o) => vec![i, o],
+        Ty::App(_, arg) => vec![arg],
+        _ => Vec::new(),
+    };
+    parts
+        .into_iter()
+        .find_map(|part| part_of(tys, ty, part))
+        .map(|_| Size::Smaller)
+}
+
+fn is_partial(attr: &[SrcNode<ast::Attr>]) -> bool {
+    attr
+        .iter()
+        .any(|attr| &**attr.name == "partial")
+}
+
+// The body of an item, along with the name to report it by
+fn item_body(ctx: &Context, item: Item) -> (SrcNode<Ident>, &TyExpr) {
+    match item {
+        Item::Def(def) => {
+            let def = ctx.defs.get(def);
+            (def.name.clone(), def.body.as_ref().unwrap())
+        },
+        Item::Member(member, field) => {
+            // Members that do not provide an item use the class's default implementation
+            let body = ctx.classes
+                .get_member(member)
+                .field(field)
+                .or_else(|| ctx.classes.get(ctx.classes.member_class(member)).default(field))
+                .unwrap();
+            (SrcNode::new(field, body.meta().0), body)
+        },
+    }
+}
+
+// The number of parameters that an item has
+fn arity(ctx: &Context, item: Item) -> usize {
+    let mut body = item_body(ctx, item).1;
+    let mut arity = 0;
+    while let hir::Expr::Func(_, inner) = &**body {
+        arity += 1;
+        body = inner;
+    }
+    arity
+}
+
+// Find the calls that an item makes to the given items, relating their arguments to its own parameters, along with the
+// first partial item that it refers to
+fn find_calls(ctx: &Context, caller: Item, recursive: &HashSet<Item>) -> (Vec<Call>, Option<(Ident, Span)>) {
+    let mut walker = Walker { ctx, caller, recursive, locals: Vec::new(), calls: Vec::new(), partial_use: None };
+    let body = item_body(ctx, caller).1;
+
+    // The parameters of the definition are the arguments of the functions that it is immediately made of
+    let mut body = body;
+    while let hir::Expr::Func(arg, inner) = &**body {
+        walker.locals.push((**arg, Some((walker.locals.len(), Size::NoLarger)), None));
+        body = inner;
+    }
+
+    match &**body {
+        // Definitions written in point-free style pass their parameters on
+        hir::Expr::Global(_) | hir::Expr::ClassAccess(_, _, _) | hir::Expr::Apply(_, _) => {
+            walker.walk_call(body, Some(walker.locals.len()));
+        },
+        _ => walker.walk(body),
+    }
+    (walker.calls, walker.partial_use)
+}
+
+// Tarjan's algorithm
+fn strongly_connected(items: &[Item], calls: &HashMap<Item, Vec<Call>>) -> Vec<Vec<Item>> {
+    struct State<'a> {
+        calls: &'a HashMap<Item, Vec<Call>>,
+        index: HashMap<Item, (usize, usize)>,
+        stack: Vec<Item>,
+        components: Vec<Vec<Item>>,
+    }
+
+    fn visit(state: &mut State, def: Item) {
+        let idx = state.index.len();
+        state.index.insert(def, (idx, idx));
+        state.stack.push(def);
+
+        for call in state.calls.get(&def).into_iter().flatten() {
+            let low = match state.index.get(&call.callee) {
+                None => {
+                    visit(state, call.callee);
+                    state.index[&call.callee].1
+                },
+                Some(&(callee_idx, _)) if state.stack.contains(&call.callee) => callee_idx,
+                Some(_) => continue,
+            };
+            let entry = state.index.get_mut(&def).unwrap();
+            entry.1 = entry.1.min(low);
+        }
+
+        let (idx, low) = state.index[&def];
+        if idx == low {
+            let pos = state.stack.iter().position(|d| *d == def).unwrap();
+            state.components.push(state.stack.split_off(pos));
+        }
+    }
+
+    let mut state = State { calls, index: HashMap::default(), stack: Vec::new(), components: Vec::new() };
+    for def in items {
+        if !state.index.contains_key(def) {
+            visit(&mut state, *def);
+        }
+    }
+    state.components
+}
+
+/// Check that every recursive definition and class member that is not marked `$[partial]` terminates, and that it
+/// refers to no item that is.
+pub fn check_termination(ctx: &Context) -> Vec<Error> {
+    let mut checked = ctx.defs
+        .iter()
+        .filter(|(_, def)| def.body.is_some() && !is_partial(&def.attr))
+        .map(|(id, _)| Item::Def(id))
+        .collect::<Vec<_>>();
+    for (class_id, class) in ctx.classes.iter() {
+        for (member_id, member) in ctx.classes.members_of(class_id) {
+            if is_partial(&member.attr) || member.fields.is_none() {
+                continue;
+            }
+            for field in class.fields.iter().flatten() {
+                if let ClassItem::Value { name, .. } = field {
+                    if member.field(**name).is_some() || class.default(**name).is_some() {
+                        checked.push(Item::Member(member_id, **name));
+                    }
+                }
+            }
+        }
+    }
+    let recursive = checked.iter().copied().collect::<HashSet<_>>();
+
+    let mut errors = Vec::new();
+    let calls = checked
+        .iter()
+        .map(|item| {
+            let (calls, partial_use) = find_calls(ctx, *item, &recursive);
+            if let Some((callee, span)) = partial_use {
+                errors.push(Error::UsesPartial(item_body(ctx, *item).0, callee, span));
+            }
+            (*item, calls)
+        })
+        .collect::<HashMap<_, _>>();
+
+    for component in strongly_connected(&checked, &calls) {
+        let is_cyclic = component.len() > 1 || calls[&component[0]]
+            .iter()
+            .any(|call| call.callee == component[0]);
+        if !is_cyclic {
+            continue;
+        }
+
+        // The size-change graphs of every sequence of calls within the component, along with the first call made
+        let mut graphs = HashMap::<Graph, Span>::default();
+        for caller in &component {
+            for call in calls[caller].iter().filter(|call| component.contains(&call.callee)) {
+                let edges = call.args
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, rel)| rel.map(|(i, size)| ((i, j), size)))
+                    .chain(call.tys.map(|size| ((TYPES, TYPES), size)))
+                    .collect();
+                graphs
+                    .entry(Graph { caller: *caller, callee: call.callee, edges })
+                    .or_insert(call.span);
+            }
+        }
+        let mut new = graphs.keys().cloned().collect::<Vec<_>>();
+        while !new.is_empty() && graphs.len() <= MAX_GRAPHS {
+            let mut next = Vec::new();
+            for g in &new {
+                let span = graphs[g];
+                let composed = graphs
+                    .keys()
+                    .filter(|h| h.caller == g.callee)
+                    .map(|h| g.compose(h))
+                    .collect::<Vec<_>>();
+                for h in composed {
+                    if !graphs.contains_key(&h) {
+                        graphs.insert(h.clone(), span);
+                        next.push(h);
+                    }
+                }
+            }
+            new = next;
+        }
+
+        let mut non_terminating = graphs
+            .iter()
+            // If closure was abandoned, some cycles could not be checked so assume the worst
+            .filter(|(g, _)| g.caller == g.callee && (graphs.len() > MAX_GRAPHS || (g.compose(g) == **g && !g.decreases())))
+            .map(|(g, span)| (g.caller, *span))
+            .collect::<Vec<_>>();
+        non_terminating.sort_by_key(|(def, span)| (*def, span.range().start));
+        non_terminating.dedup_by_key(|(def, _)| *def);
+
+        for (item, span) in non_terminating {
+            errors.push(Error::MayNotTerminate(item_body(ctx, item).0, span));
+        }
+    }
+
+    errors
+}
@@ -27,6 +27,52 @@ impl fmt::Display for Prim {
     }
 }
 
+/// The kind of a type: either a type that has values, or a type constructor that must be given type parameters to
+/// produce one.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// `*`
+    Type,
+    /// `* -> *`, `(* -> *) -> *`, etc.
+    Func(Box<Kind>, Box<Kind>),
+}
+
+impl Kind {
+    pub fn from_ast(kind: &ast::Kind) -> Self {
+        match kind {
+            // Syntax errors have already been reported
+            ast::Kind::Error | ast::Kind::Type => Kind::Type,
+            ast::Kind::Func(i, o) => Kind::Func(Box::new(Self::from_ast(i)), Box::new(Self::from_ast(o))),
+        }
+    }
+
+    /// The kind of a type constructor that accepts parameters of the given kinds.
+    pub fn of_params(params: Vec<Kind>) -> Self {
+        params
+            .into_iter()
+            .rev()
+            .fold(Kind::Type, |o, i| Kind::Func(Box::new(i), Box::new(o)))
+    }
+
+    /// The number of type parameters that a type of this kind accepts.
+    pub fn arity(&self) -> usize {
+        match self {
+            Kind::Type => 0,
+            Kind::Func(_, o) => 1 + o.arity(),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Type => write!(f, "*"),
+            Kind::Func(i, o) if matches!(&**i, Kind::Func(_, _)) => write!(f, "({}) -> {}", i, o),
+            Kind::Func(i, o) => write!(f, "{} -> {}", i, o),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ErrorReason {
     Unknown,
@@ -42,11 +88,16 @@ pub enum Ty {
     Tuple(Vec<TyId>),
     Record(BTreeMap<Ident, TyId>),
     Func(TyId, TyId),
+    // Data types may be given fewer parameters than they have generic types, leaving a type constructor
     Data(DataId, Vec<TyId>),
     Gen(usize, GenScopeId),
     SelfType,
     Assoc(TyId, ClassId, SrcNode<Ident>),
     Effect(EffectId, TyId),
+    /// A type constructor (usually a generic type or `Self`) applied to a type parameter.
+    App(TyId, TyId),
+    /// The list type constructor, `[]`.
+    ListCon,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -55,7 +106,9 @@ pub struct TyId(usize);
 #[derive(Clone, Debug)]
 pub enum Effect {
     Error,
-    Known(EffectDeclId, Vec<TyId>),
+    /// A set of effects, each with its generic arguments. No two effects in the set share a declaration, and they are
+    /// kept in order of declaration so that equal sets have the same representation.
+    Known(Vec<(EffectDeclId, Vec<TyId>)>),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -79,10 +132,28 @@ impl Types {
         id
     }
 
-    pub fn check_gen_scopes(&mut self, classes: &Classes) -> Vec<Error> {
+    pub fn define_where_preds(&mut self, scope: GenScopeId, preds: Vec<(TyId, SrcNode<Obligation>)>) {
+        self.scopes[scope.0].where_preds = preds;
+    }
+
+    /// Find a generic type by name in the given scope or in any of its parents.
+    pub fn find_gen(&self, mut scope: GenScopeId, name: Ident) -> Option<(GenScopeId, usize, &GenTy)> {
+        loop {
+            if let Some((idx, gen_ty)) = self.get_gen_scope(scope).find(name) {
+                break Some((scope, idx, gen_ty));
+            }
+            scope = self.get_gen_scope(scope).parent?;
+        }
+    }
+
+    pub fn check_gen_scopes(&mut self, modules: &Modules, classes: &Classes) -> Vec<Error> {
         let mut errors = Vec::new();
+        let class_params = classes
+            .iter()
+            .map(|(class_id, class)| (class_id, (self.scopes[class.gen_scope.0].len(), class.kind.clone())))
+            .collect::<HashMap<_, _>>();
         for scope in &mut self.scopes {
-            scope.check(classes, &mut errors);
+            scope.check(modules, &class_params, &mut errors);
         }
         assert!(self.scopes
             .iter()
@@ -124,47 +195,98 @@ impl Types {
                 .all(|(x, y)| self.is_eq(x, y)),
             (Ty::Gen(x, x_scope), Ty::Gen(y, y_scope)) => x == y && x_scope == y_scope,
             (Ty::SelfType, Ty::SelfType) => true,
+            (Ty::App(x_f, x), Ty::App(y_f, y)) => self.is_eq(x_f, y_f) && self.is_eq(x, y),
+            (Ty::ListCon, Ty::ListCon) => true,
             (Ty::Assoc(x_ty, x_class, x_name), Ty::Assoc(y_ty, y_class, y_name)) => self.is_eq(x_ty, y_ty)
                 && x_class == y_class
                 && *x_name == *y_name,
-            (Ty::Effect(x, x_out), Ty::Effect(y, y_out)) =>
-                x == y &&
+            (Ty::Effect(x, x_out), Ty::Effect(y, y_out)) => self.is_eq(x_out, y_out) &&
                 match (self.get_effect(x), self.get_effect(y)) {
                     (Effect::Error, _) => true,
                     (_, Effect::Error) => true,
-                    (Effect::Known(x, xs), Effect::Known(y, ys)) => x == y && xs.len() == ys.len() && xs
+                    // Effect sets are unordered, but no two effects in a set share a declaration
+                    (Effect::Known(xs), Effect::Known(ys)) => xs.len() == ys.len() && xs
                         .into_iter()
-                        .zip(ys)
-                        .all(|(x, y)| self.is_eq(x, y)),
+                        .all(|(x, xs)| ys
+                            .iter()
+                            .find(|(y, _)| *y == x)
+                            .map_or(false, |(_, ys)| xs.len() == ys.len() && xs
+                                .into_iter()
+                                .zip(ys)
+                                .all(|(x, y)| self.is_eq(x, *y)))),
                 },
             _ => false,
         }
     }
 
     pub fn has_inhabitants(&self, datas: &Datas, ty: TyId, gen: &mut dyn FnMut(usize) -> bool) -> bool {
+        self.has_inhabitants_inner(datas, ty, gen, &mut Vec::new())
+    }
+
+    fn has_inhabitants_inner(&self, datas: &Datas, ty: TyId, gen: &mut dyn FnMut(usize) -> bool, visiting: &mut Vec<DataId>) -> bool {
         match self.get(ty) {
             Ty::Error(_) => false,
             Ty::Prim(_) => true,
             Ty::List(_) => true, // Empty list
             Ty::Tuple(fields) => fields
                 .into_iter()
-                .all(|field| self.has_inhabitants(datas, field, gen)),
+                .all(|field| self.has_inhabitants_inner(datas, field, gen, visiting)),
             Ty::Record(fields) => fields
                 .into_iter()
-                .all(|(_, field)| self.has_inhabitants(datas, field, gen)),
+                .all(|(_, field)| self.has_inhabitants_inner(datas, field, gen, visiting)),
             Ty::Func(_, _) => true,
-            Ty::Data(data, args) => datas
-                .get_data(data)
-                .cons
-                .iter()
-                .any(|(_, ty)| {
-                    self.has_inhabitants(datas, *ty, &mut |id| self.has_inhabitants(datas, args[id], gen))
-                }),
+            // A data type that we're already inside of can only be inhabited via one of its other constructors
+            Ty::Data(data, _) if visiting.contains(&data) => false,
+            Ty::Data(data, args) => {
+                visiting.push(data);
+                let has_inhabitants = datas
+                    .get_data(data)
+                    .cons
+                    .iter()
+                    .any(|(_, ty)| {
+                        self.has_inhabitants_inner(datas, *ty, &mut |id| self.has_inhabitants(datas, args[id], gen), visiting)
+                    });
+                visiting.pop();
+                has_inhabitants
+            },
             Ty::Gen(id, _) => gen(id),
             Ty::SelfType => true,
             Ty::Assoc(_, _, _) => true,
             // An effect is always an inhabited object until propagated, even if the output type is not inhabited
             Ty::Effect(_, _) => true,
+            // Nothing is known about the type constructor, so assume the worst
+            Ty::App(_, _) => true,
+            Ty::ListCon => true,
+        }
+    }
+
+    /// Determine whether a type is fully known, such that it mentions no generic types, `Self` or associated types.
+    pub fn is_concrete(&self, ty: TyId) -> bool {
+        match self.get(ty) {
+            // Errors have already been reported
+            Ty::Error(_) => true,
+            Ty::Prim(_) => true,
+            Ty::List(item) => self.is_concrete(item),
+            Ty::Tuple(fields) => fields
+                .into_iter()
+                .all(|field| self.is_concrete(field)),
+            Ty::Record(fields) => fields
+                .into_iter()
+                .all(|(_, field)| self.is_concrete(field)),
+            Ty::Func(i, o) => self.is_concrete(i) && self.is_concrete(o),
+            Ty::Data(_, args) => args
+                .into_iter()
+                .all(|arg| self.is_concrete(arg)),
+            Ty::Gen(_, _) | Ty::SelfType | Ty::Assoc(_, _, _) | Ty::App(_, _) => false,
+            Ty::Effect(eff, out) => self.is_concrete(out) && match self.get_effect(eff) {
+                Effect::Error => true,
+                Effect::Known(effs) => effs
+                    .into_iter()
+                    .all(|(_, args)| args
+                        .into_iter()
+                        .all(|arg| self.is_concrete(arg))),
+            },
+            Ty::ListCon => true,
         }
     }
 
@@ -179,15 +301,34 @@ impl Types {
         }
     }
 
+    pub fn display_effect(&self, datas: &Datas, effects: &Effects, eff: EffectId) -> String {
+        match self.get_effect(eff) {
+            Effect::Error => "!".to_string(),
+            Effect::Known(effs) => effs
+                .iter()
+                .map(|(decl, args)| format!("{}{}", *effects.get_decl(*decl).name, args
+                    .iter()
+                    .map(|arg| format!(" {}", TyDisplay { lhs_exposed: true, ..self.display(datas, effects, *arg) }))
+                    .collect::<String>()))
+                .collect::<Vec<_>>()
+                .join(" + "),
+        }
+    }
+
     pub fn get_effect(&self, eff: EffectId) -> Effect {
         self.effects[eff.0].1.clone()
     }
 
     pub fn get_effect_span(&self, eff: EffectId) -> Span {
-        self.tys[eff.0].0
+        self.effects[eff.0].0
     }
 
-    pub fn insert_effect(&mut self, span: Span, eff: Effect) -> EffectId {
+    pub fn insert_effect(&mut self, span: Span, mut eff: Effect) -> EffectId {
+        // Effect sets are unordered, so give them a canonical order
+        if let Effect::Known(effs) = &mut eff {
+            effs.sort_by_key(|(decl, _)| *decl);
+            effs.dedup_by_key(|(decl, _)| *decl);
+        }
         let id = EffectId(self.effects.len());
         self.effects.push((span, eff));
         id
@@ -251,17 +392,31 @@ impl<'a> fmt::Display for TyDisplay<'a> {
                 .map(|param| format!(" {}", self.with_ty(*param, true)))
                 .collect::<String>()),
             Ty::Gen(index, scope) => write!(f, "{}", **self.types.get_gen_scope(scope).get(index).name),
+            Ty::App(head, param) => {
+                // Display `F A B` rather than `(F A) B`
+                let mut params = vec![param];
+                let mut head = head;
+                while let Ty::App(inner, param) = self.types.get(head) {
+                    params.push(param);
+                    head = inner;
+                }
+                let params = params
+                    .into_iter()
+                    .rev()
+                    .map(|param| format!(" {}", self.with_ty(param, true)))
+                    .collect::<String>();
+                if self.lhs_exposed {
+                    write!(f, "({}{})", self.with_ty(head, true), params)
+                } else {
+                    write!(f, "{}{}", self.with_ty(head, true), params)
+                }
+            },
+            Ty::ListCon => write!(f, "[]"),
             // TODO: Include class_id?
             Ty::Assoc(inner, _class_id, assoc) => write!(f, "{}.{}", self.with_ty(inner, true), *assoc),
             Ty::SelfType => write!(f, "Self"),
             Ty::Effect(eff, out) => {
-                let eff = match self.types.get_effect(eff) {
-                    Effect::Error => "!".to_string(),
-                    Effect::Known(decl, args) => format!("{}{}", *self.effects.get_decl(decl).name, args
-                        .iter()
-                        .map(|arg| format!(" {}", self.with_ty(*arg, true)))
-                        .collect::<String>()),
-                };
+                let eff = self.types.display_effect(self.datas, self.effects, eff);
                 if self.lhs_exposed {
                     write!(f, "({} ~ {})", eff, self.with_ty(out, true))
                 } else {
@@ -279,6 +434,7 @@ pub enum Obligation {
 
 pub struct GenTy {
     pub name: SrcNode<Ident>,
+    pub kind: Kind,
     // TODO: Don't store this here, it's silly
     pub ast_obligations: Vec<SrcNode<ast::ClassInst>>,
     pub obligations: Option<Vec<SrcNode<Obligation>>>,
@@ -297,11 +453,19 @@ pub struct GenScopeId(usize);
 
 pub struct GenScope {
     pub span: Span,
+    /// The module that the generic types are declared in, used to resolve obligations.
+    pub module: ModuleId,
+    /// The scope that this one is nested within (i.e: that of the class, for the generic types of a class item), whose
+    /// generic types are also visible.
+    pub parent: Option<GenScopeId>,
     types: Vec<GenTy>,
+    // Types mentioned by `where` clauses can only be lowered once every item has been declared, so these are empty
+    // until then
+    where_preds: Vec<(TyId, SrcNode<Obligation>)>,
 }
 
 impl GenScope {
-    pub fn from_ast(generics: &SrcNode<ast::Generics>) -> (Self, Vec<Error>) {
+    pub fn from_ast(module: ModuleId, generics: &SrcNode<ast::Generics>) -> (Self, Vec<Error>) {
         let mut existing = HashMap::new();
 
         let mut errors = Vec::new();
@@ -313,34 +477,58 @@ impl GenScope {
 
         (Self {
             span: generics.span(),
+            module,
+            parent: None,
             types: generics.tys
                 .iter()
                 .map(|gen_ty| GenTy {
                     name: gen_ty.name.clone(),
+                    kind: gen_ty.kind.as_ref().map_or(Kind::Type, |kind| Kind::from_ast(kind)),
                     ast_obligations: gen_ty.obligations.clone(),
                     obligations: None,
                 })
                 .collect(),
+            where_preds: Vec::new(),
         }, errors)
     }
 
+    pub fn with_parent(mut self, parent: GenScopeId) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
     pub fn len(&self) -> usize { self.types.len() }
 
     pub fn get(&self, index: usize) -> &GenTy {
         &self.types[index]
     }
 
+    /// The obligations imposed by the scope's `where` clause, each on a type that may mention the scope's generic types.
+    pub fn where_preds(&self) -> &[(TyId, SrcNode<Obligation>)] {
+        &self.where_preds
+    }
+
     pub fn find(&self, name: Ident) -> Option<(usize, &GenTy)> {
         self.types.iter().enumerate().find(|(_, ty)| &*ty.name == &name)
     }
 
-    fn check(&mut self, classes: &Classes, errors: &mut Vec<Error>) {
+    fn check(&mut self, modules: &Modules, class_params: &HashMap<ClassId, (usize, Kind)>, errors: &mut Vec<Error>) {
+        let module = self.module;
         for ty in &mut self.types {
             let obligations = ty
                 .ast_obligations
                 .iter()
-                .filter_map(|obl| if let Some(class) = classes.lookup(*obl.name) {
-                    Some(SrcNode::new(Obligation::MemberOf(class), obl.name.span()))
+                .filter_map(|obl| if let Some(class) = modules.lookup_class(module, &obl.name) {
+                    let (class_params, class_kind) = &class_params[&class];
+                    if !obl.params.is_empty() || *class_params != 0 {
+                        errors.push(Error::Unsupported(obl.span(), "obligations on classes with type parameters"));
+                        None
+                    } else if *class_kind != ty.kind {
+                        errors.push(Error::KindMismatch(ty.name.span(), ty.kind.clone(), obl.name.span(), class_kind.clone()));
+                        None
+                    } else {
+                        Some(SrcNode::new(Obligation::MemberOf(class), obl.name.span()))
+                    }
                 } else {
                     errors.push(Error::NoSuchClass(obl.name.clone()));
                     None
@@ -0,0 +1,132 @@
+//! Benchmarks for the bytecode VM and the JIT, using the programs in `examples/`.
+//!
+//! Only the execution of each compiled program is measured, not its compilation. Every example is measured both
+//! unoptimised and optimised with the VM, so the difference between the two shows what the optimisations (including
+//! the bytecode peephole optimiser) gain. The JIT runs the optimised program, for comparison with the VM.
+//!
+//! The mandelbrot renderer that `examples/brainfuck.tao` runs takes far too long to be measured repeatedly, so the
+//! interpreter is given a smaller program instead: a nested counting loop followed by hello world.
+//!
+//! The examples mostly print, so a program that only computes is measured too, to show the speed of the JIT relative to
+//! the VM where it matters most.
+
+#![feature(test)]
+
+extern crate test;
+
+use tao::{Options, OptMode, Backend, SrcId, compile, compile_mir, run_program};
+use tao_cranelift::Jit;
+use std::{fs, io, path::Path};
+use test::Bencher;
+
+const BRAINFUCK_PROGRAM: &str = "++++++++[>++++++++[>++++[>+<-]<-]<-]\
+    ++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+const COMPUTE_SRC: &str = "import \"lib/std.tao\"
+
+fn fib : Nat -> Nat =
+    | 0 => 0
+    | 1 => 1
+    \\ m + 2 => fib(m + 1) + fib(m)
+
+fn upto : Nat -> [Nat] =
+    | 0 => []
+    \\ n + 1 => [n] ++ upto(n)
+
+fn sum : [Nat] -> Nat =
+    | [] => 0
+    \\ [x .. xs] => x + sum(xs)
+
+$[main]
+def main = fib(20) + sum(upto(500))
+";
+
+fn read(path: &Path) -> Option<String> {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path)).ok()
+}
+
+fn example_src(name: &str) -> (String, String) {
+    let path = format!("examples/{}.tao", name);
+    let src = read(Path::new(&path)).unwrap_or_else(|| panic!("Failed to read {}", path));
+    (path, src)
+}
+
+// Swap the program that the brainfuck interpreter runs for the one being benchmarked
+fn brainfuck_src() -> (String, String) {
+    let (path, src) = example_src("brainfuck");
+    let start = src.find("return \"").expect("Failed to find brainfuck program") + "return \"".len();
+    let end = start + src[start..].find("\":parse").expect("Failed to find end of brainfuck program");
+    (path, format!("{}{}{}", &src[..start], BRAINFUCK_PROGRAM, &src[end..]))
+}
+
+fn compute_src() -> (String, String) {
+    ("compute.tao".to_string(), COMPUTE_SRC.to_string())
+}
+
+fn options(opt: OptMode, backend: Backend) -> Options {
+    Options { debug: Vec::new(), opt, backend, output: None }
+}
+
+fn run_vm(b: &mut Bencher, (path, src): (String, String), opt: OptMode) {
+    let mut errors = Vec::new();
+    let prog = compile(src, SrcId::from_path(&path), options(opt, Backend::Vm), &mut errors, |src| read(&src.to_path()))
+        .unwrap_or_else(|| panic!("Failed to compile {}:\n{}", path, String::from_utf8_lossy(&errors)));
+    b.iter(|| run_program(&prog, io::sink(), |_| None));
+}
+
+fn run_jit(b: &mut Bencher, (path, src): (String, String)) {
+    let mut errors = Vec::new();
+    let (ctx, _) = compile_mir(src, SrcId::from_path(&path), &options(OptMode::Fast, Backend::Jit), &mut errors, &mut |src| read(&src.to_path()))
+        .unwrap_or_else(|| panic!("Failed to compile {}:\n{}", path, String::from_utf8_lossy(&errors)));
+    let jit = Jit::new(&ctx).unwrap_or_else(|e| panic!("Failed to compile {}: {}", path, e));
+    b.iter(|| jit.run(|_| {}));
+}
+
+fn run_example(b: &mut Bencher, name: &str, opt: OptMode) { run_vm(b, example_src(name), opt) }
+
+fn run_brainfuck(b: &mut Bencher, opt: OptMode) { run_vm(b, brainfuck_src(), opt) }
+
+#[bench]
+fn brainfuck_opt_none(b: &mut Bencher) { run_brainfuck(b, OptMode::None) }
+
+#[bench]
+fn brainfuck_opt_fast(b: &mut Bencher) { run_brainfuck(b, OptMode::Fast) }
+
+#[bench]
+fn brainfuck_jit(b: &mut Bencher) { run_jit(b, brainfuck_src()) }
+
+#[bench]
+fn bottles_opt_none(b: &mut Bencher) { run_example(b, "99", OptMode::None) }
+
+#[bench]
+fn bottles_opt_fast(b: &mut Bencher) { run_example(b, "99", OptMode::Fast) }
+
+#[bench]
+fn bottles_jit(b: &mut Bencher) { run_jit(b, example_src("99")) }
+
+#[bench]
+fn eval_opt_none(b: &mut Bencher) { run_example(b, "eval", OptMode::None) }
+
+#[bench]
+fn eval_opt_fast(b: &mut Bencher) { run_example(b, "eval", OptMode::Fast) }
+
+#[bench]
+fn eval_jit(b: &mut Bencher) { run_jit(b, example_src("eval")) }
+
+#[bench]
+fn compute_opt_none(b: &mut Bencher) { run_vm(b, compute_src(), OptMode::None) }
+
+#[bench]
+fn compute_opt_fast(b: &mut Bencher) { run_vm(b, compute_src(), OptMode::Fast) }
+
+#[bench]
+fn compute_jit(b: &mut Bencher) { run_jit(b, compute_src()) }
+
+#[bench]
+fn effect_opt_none(b: &mut Bencher) { run_example(b, "effect", OptMode::None) }
+
+#[bench]
+fn effect_opt_fast(b: &mut Bencher) { run_example(b, "effect", OptMode::Fast) }
+
+#[bench]
+fn effect_jit(b: &mut Bencher) { run_jit(b, example_src("effect")) }
@@ -0,0 +1,39 @@
+use tao::{Options, OptMode, Backend, SrcId, run};
+use structopt::StructOpt;
+use std::{fs, path::PathBuf};
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum Command {
+    /// Compile a program to bytecode, so that it can be run later with `tao run` without the source
+    Build {
+        /// Add a debugging layer to stdout (tokens, ast, hir, mir, bytecode)
+        #[structopt(long)]
+        debug: Vec<String>,
+        /// Specify an optimisation mode (none, fast, size)
+        #[structopt(short, long, default_value = "none")]
+        opt: OptMode,
+        /// Specify the file to write the bytecode to (by default, FILE with a `.taob` extension)
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Specify the file to compile
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+fn main() {
+    match Command::from_args() {
+        Command::Build { debug, opt, output, file } => {
+            let src = fs::read_to_string(&file)
+                .expect("Failed to read file");
+            let src_id = SrcId::from_path(file);
+            let options = Options {
+                debug,
+                opt,
+                backend: Backend::Bytecode,
+                output,
+            };
+            run(src, src_id, options, std::io::stdout(), |src| fs::read_to_string(src.to_path()).ok());
+        },
+    }
+}
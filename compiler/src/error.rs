@@ -4,6 +4,8 @@ use std::io::Write;
 #[derive(Debug)]
 pub enum Error {
     CannotImport(SrcNode<Intern<String>>),
+    /// A runtime error with a known source span.
+    Runtime(RuntimeError, Span),
 }
 
 impl Error {
@@ -18,6 +20,22 @@ impl Error {
                 ],
                 vec![format!("The file {} must exist", (*path).fg(Color::Yellow))],
             ),
+            Error::Runtime(err, span) => (
+                format!("Runtime error at 0x{:03X}: {}", err.addr.0, err.kind),
+                vec![
+                    (span, format!("Occurred while executing this"), Color::Red),
+                ],
+                vec![format!(
+                    "Stack (top first):\n{}",
+                    err.stack
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .map(|(i, x)| format!("{:02} | {}", i, x))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                )],
+            ),
         };
 
         let mut report = Report::build(ReportKind::Error, spans.first().unwrap().0.src(), spans.first().unwrap().0.start())
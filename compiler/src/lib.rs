@@ -1,11 +1,15 @@
 mod error;
+mod repl;
 
 pub use tao_syntax::SrcId;
+pub use tao_middle::OptMode;
+pub use tao_vm::Program;
+pub use repl::Repl;
 
-use tao_syntax::{parse_module, ast, SrcNode, Error as SyntaxError};
+use tao_syntax::{parse_module, ast, SrcNode, Span, Error as SyntaxError};
 use tao_analysis::Context as HirContext;
-use tao_middle::{Context, OptMode};
-use tao_vm::{Program, exec};
+use tao_middle::Context;
+use tao_vm::{RuntimeError, Vm};
 use ariadne::sources;
 use structopt::StructOpt;
 use internment::Intern;
@@ -13,7 +17,9 @@ use std::{
     str::FromStr,
     io::Write,
     collections::HashMap,
+    path::PathBuf,
     fmt,
+    fs,
 };
 use error::Error;
 
@@ -25,109 +31,321 @@ pub struct Options {
     /// Specify an optimisation mode (none, fast, size)
     #[structopt(short, long, default_value = "none")]
     pub opt: OptMode,
+    /// Specify a backend to run the program with (vm, jit, object, bytecode). The jit backend does not free memory until
+    /// a program finishes, so it stops programs once they have allocated 2 GiB in total
+    #[structopt(short, long, default_value = "vm")]
+    pub backend: Backend,
+    /// Specify the file that the object and bytecode backends write to
+    #[structopt(long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
 }
 
-pub fn run<F: FnMut(SrcId) -> Option<String>>(src: String, src_id: SrcId, options: Options, mut writer: impl Write, mut get_file: F) {
-    let (mut ast, mut syntax_errors) = parse_module(&src, src_id);
-
-    // TODO: Write a proper module system you lazy git
-    fn resolve_imports<F: FnMut(SrcId) -> Option<String>>(
-        module: Option<&mut ast::Module>,
-        imported: &mut HashMap<SrcId, String>,
-        import_errors: &mut Vec<Error>,
-        syntax_errors: &mut Vec<SyntaxError>,
-        get_file: &mut F,
-    ) {
-        if let Some(module) = module {
-            let imports = std::mem::take(&mut module.imports);
-
-            for import in imports {
-                let src_id = SrcId::from_path(import.as_str());
-                match get_file(src_id) {
-                    Some(src) => {
-                        // Check for cycles
-                        if imported.insert(src_id, src.clone()).is_none() {
-                            let (mut ast, mut new_syntax_errors) = parse_module(&src, src_id);
-                            syntax_errors.append(&mut new_syntax_errors);
-
-                            resolve_imports(ast.as_deref_mut(), imported, import_errors, syntax_errors, get_file);
-
-                            if let Some(mut ast) = ast {
-                                let mut old_items = std::mem::take(&mut module.items);
-                                module.items.append(&mut ast.items);
-                                module.items.append(&mut old_items);
-                            }
-                        }
-                    },
-                    None => import_errors.push(Error::CannotImport(import.clone())),
+#[derive(Copy, Clone, Debug)]
+pub enum Backend {
+    /// Compile to bytecode and run it with the VM
+    Vm,
+    /// Compile to native code with Cranelift and run it immediately
+    Jit,
+    /// Compile to native code with Cranelift and write it to an object file
+    Object,
+    /// Compile to bytecode and write it to a file, to be run later
+    Bytecode,
+}
+
+impl FromStr for Backend {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, &'static str> {
+        match s {
+            "vm" => Ok(Backend::Vm),
+            "jit" => Ok(Backend::Jit),
+            "object" => Ok(Backend::Object),
+            "bytecode" => Ok(Backend::Bytecode),
+            _ => Err("Backend does not exist"),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Vm => write!(f, "vm"),
+            Backend::Jit => write!(f, "jit"),
+            Backend::Object => write!(f, "object"),
+            Backend::Bytecode => write!(f, "bytecode"),
+        }
+    }
+}
+
+// Load every module that the root module depends on, directly or indirectly
+fn load_imports<F: FnMut(SrcId) -> Option<String>>(
+    module: &ast::Module,
+    srcs: &mut HashMap<SrcId, String>,
+    modules: &mut Vec<SrcNode<ast::Module>>,
+    import_errors: &mut Vec<Error>,
+    syntax_errors: &mut Vec<SyntaxError>,
+    get_file: &mut F,
+) {
+    for import in &module.imports {
+        let src_id = SrcId::from_path(import.path.as_str());
+        // Modules are only loaded once, no matter how many times (or how cyclically) they're imported
+        if srcs.contains_key(&src_id) {
+            continue;
+        }
+        match get_file(src_id) {
+            Some(src) => {
+                srcs.insert(src_id, src.clone());
+                let (ast, mut new_syntax_errors) = parse_module(&src, src_id);
+                syntax_errors.append(&mut new_syntax_errors);
+
+                if let Some(ast) = ast {
+                    load_imports(&ast, srcs, modules, import_errors, syntax_errors, get_file);
+                    modules.push(ast);
                 }
-            }
+            },
+            None => import_errors.push(Error::CannotImport(import.path.clone())),
         }
     }
+}
+
+/// Parse, check, and optimise a module along with its imports, writing any errors to the writer.
+///
+/// Returns the MIR of the program, along with the source of every module that it was compiled from.
+pub fn compile_mir<F: FnMut(SrcId) -> Option<String>>(
+    src: String,
+    src_id: SrcId,
+    options: &Options,
+    writer: &mut impl Write,
+    get_file: &mut F,
+) -> Option<(Context, HashMap<SrcId, String>)> {
+    let (ast, mut syntax_errors) = parse_module(&src, src_id);
 
     // Resolve imports
-    let mut imported = HashMap::new();
+    let mut srcs = HashMap::new();
+    srcs.insert(src_id, src);
+    let mut imports = Vec::new();
     let mut import_errors = Vec::new();
-    resolve_imports(ast.as_deref_mut(), &mut imported, &mut import_errors, &mut syntax_errors, &mut get_file);
-    let mut srcs = sources(imported.into_iter().chain(std::iter::once((src_id, src))));
+    if let Some(ast) = &ast {
+        load_imports(ast, &mut srcs, &mut imports, &mut import_errors, &mut syntax_errors, get_file);
+    }
+    let src_map = srcs.clone();
+    let mut srcs = sources(srcs);
     if !import_errors.is_empty() {
         for e in import_errors {
-            e.write(&mut srcs, &mut writer);
+            e.write(&mut srcs, &mut *writer);
         }
-        return;
+        return None;
     }
 
     let mut syntax_error = false;
     for e in syntax_errors {
         syntax_error = true;
-        e.write(&mut srcs, &mut writer);
+        e.write(&mut srcs, &mut *writer);
     }
 
     if options.debug.contains(&"ast".to_string()) {
         writeln!(writer, "{:?}", ast).unwrap();
     }
 
-    if let Some(ast) = ast {
-        let (ctx, mut analysis_errors) = HirContext::from_module(&ast);
+    let ast = ast?;
+    let (ctx, analysis_errors) = HirContext::from_module(&ast, &imports);
 
-        if options.debug.contains(&"hir".to_string()) {
-            for (_, def) in ctx.defs.iter() {
-                writeln!(writer, "{} = {:?}", *def.name, def.body).unwrap();
-            }
+    if options.debug.contains(&"hir".to_string()) {
+        for (_, def) in ctx.defs.iter() {
+            writeln!(writer, "{} = {:?}", *def.name, def.body).unwrap();
+        }
+    }
+
+    if !analysis_errors.is_empty() || syntax_error {
+        for e in analysis_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut *writer);
+        }
+        return None;
+    }
+
+    let (concrete, con_errors) = ctx.concretize();
+
+    if !con_errors.is_empty() {
+        for e in con_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut *writer);
         }
+        return None;
+    }
+
+    let mut ctx = Context::from_concrete(&ctx, &concrete);
+
+    ctx.optimize(options.opt);
+
+    if options.debug.contains(&"mir".to_string()) {
+        for (id, proc) in ctx.procs.iter() {
+            writeln!(writer, "PROCEDURE {:?}\n\n{}\n", id, proc.body.print()).unwrap();
+        }
+    }
+
+    Some((ctx, src_map))
+}
 
-        if !analysis_errors.is_empty() || syntax_error {
-            for e in analysis_errors {
-                e.write(&ctx, &mut srcs, src_id, &mut writer);
+/// Compile a module and its imports to bytecode, writing any errors to the writer.
+///
+/// Returns `None` if the program could not be compiled.
+pub fn compile<F: FnMut(SrcId) -> Option<String>>(src: String, src_id: SrcId, options: Options, mut writer: impl Write, mut get_file: F) -> Option<Program> {
+    let (ctx, _) = compile_mir(src, src_id, &options, &mut writer, &mut get_file)?;
+    Some(lower(&ctx, &options, &mut writer))
+}
+
+// Lower a MIR program to bytecode, optimising it unless optimisation is disabled
+fn lower(ctx: &Context, options: &Options, mut writer: impl Write) -> Program {
+    let mut prog = Program::from_mir(ctx);
+    if !matches!(options.opt, OptMode::None) {
+        prog.optimize();
+    }
+
+    if options.debug.contains(&"bytecode".to_string()) {
+        prog.write(&mut writer);
+    }
+
+    prog
+}
+
+pub fn run<F: FnMut(SrcId) -> Option<String>>(src: String, src_id: SrcId, options: Options, mut writer: impl Write, mut get_file: F) {
+    let (ctx, src_map) = match compile_mir(src, src_id, &options, &mut writer, &mut get_file) {
+        Some(compiled) => compiled,
+        None => return,
+    };
+
+    match options.backend {
+        Backend::Vm => {
+            let prog = lower(&ctx, &options, &mut writer);
+
+            run_program(&prog, &mut writer, |src| src_map.get(&src).cloned());
+        },
+        Backend::Jit => match tao_cranelift::jit(&ctx, |s| writeln!(writer, "{}", s).unwrap()) {
+            Ok(Some(result)) => writeln!(writer, "{}", result).unwrap(),
+            Ok(None) => {},
+            Err(e) => writeln!(writer, "{}", e).unwrap(),
+        },
+        Backend::Object => {
+            let output = options.output.unwrap_or_else(|| PathBuf::from("out.o"));
+            if let Err(e) = tao_cranelift::emit_object(&ctx, &output) {
+                writeln!(writer, "{}", e).unwrap();
+            }
+        },
+        Backend::Bytecode => {
+            let prog = lower(&ctx, &options, &mut writer);
+
+            let output = options.output.unwrap_or_else(|| src_id.to_path().with_extension("taob"));
+            if let Err(e) = fs::write(&output, prog.encode()) {
+                writeln!(writer, "Failed to write {}: {}", output.display(), e).unwrap();
             }
+        },
+    }
+}
+
+/// Format the source of a module in the canonical style, writing any syntax errors to the writer.
+///
+/// Returns `None` if the module could not be formatted. As a safeguard against bugs in the formatter, formatted code that
+/// does not parse back into the same module is rejected too.
+pub fn format(src: &str, src_id: SrcId, mut writer: impl Write) -> Option<String> {
+    match tao_syntax::format(src, src_id) {
+        Ok(formatted) => if parse_module(&formatted, src_id).0 == parse_module(src, src_id).0 {
+            Some(formatted)
         } else {
-            let (concrete, mut con_errors) = ctx.concretize();
+            writeln!(writer, "Formatting {} would change its meaning, please report this as a bug", src_id).unwrap();
+            None
+        },
+        Err(errors) => {
+            let mut srcs = sources(vec![(src_id, src.to_string())]);
+            for e in errors {
+                e.write(&mut srcs, &mut writer);
+            }
+            None
+        },
+    }
+}
 
-            if !con_errors.is_empty() {
-                for e in con_errors {
-                    e.write(&ctx, &mut srcs, src_id, &mut writer);
-                }
-            } else {
-                let mut ctx = Context::from_concrete(&ctx, &concrete);
+/// Run a compiled program with the VM, writing its output and result (or any runtime error) to the writer.
+///
+/// Runtime errors are reported against the program's source code if `get_file` can provide it.
+pub fn run_program<F: FnMut(SrcId) -> Option<String>>(prog: &Program, mut writer: impl Write, mut get_file: F) {
+    let res = Vm::default()
+        .on_print(|s| writeln!(writer, "{}", s))
+        .run(prog);
 
-                ctx.optimize(options.opt);
+    match res {
+        Ok(Some(result)) => writeln!(writer, "{}", result).unwrap(),
+        Ok(None) => {},
+        Err(err) => match err.span.and_then(|span| Some((span, get_file(span.src())?))) {
+            Some((span, src)) => Error::Runtime(err, span).write(sources(vec![(span.src(), src)]), &mut writer),
+            None => write!(writer, "{}", err).unwrap(),
+        },
+    }
+}
 
-                if options.debug.contains(&"mir".to_string()) {
-                    for (id, proc) in ctx.procs.iter() {
-                        writeln!(writer, "PROCEDURE {:?}\n\n{}\n", id, proc.body.print()).unwrap();
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tao_vm::{Limits, Value};
+    use std::path::Path;
 
-                let prog = Program::from_mir(&ctx);
+    // Build a Peano number one successor at a time
+    const PEANO: &str = "import \"lib/std.tao\"
 
-                if options.debug.contains(&"bytecode".to_string()) {
-                    prog.write(&mut writer);
-                }
+data Peano =
+    | Succ Peano
+    \\ Zero
 
-                if let Some(result) = exec(&prog) {
-                    writeln!(writer, "{}", result).unwrap();
-                }
-            }
+fn to_peano : Nat -> Peano -> Peano =
+    | 0, p => p
+    \\ n + 1, p => to_peano(n, Succ p)
+";
+
+    // The standard library is imported relative to the root of the repository
+    fn run_limited(src: &str, opt: OptMode, limits: Limits) -> Option<Value> {
+        let options = Options { debug: Vec::new(), opt, backend: Backend::Vm, output: None };
+        let mut errors = Vec::new();
+        let prog = compile(src.to_string(), SrcId::from_path("main.tao"), options, &mut errors, |src| {
+            fs::read_to_string(Path::new("..").join(src.to_path())).ok()
+        })
+            .unwrap_or_else(|| panic!("Failed to compile:\n{}", String::from_utf8_lossy(&errors)));
+        Vm::default().limits(limits).run(&prog).unwrap_or_else(|err| panic!("Failed to run: {:?}", err))
+    }
+
+    #[test]
+    fn recursive_repr_runs_in_constant_space() {
+        // The loop is a tail call, so nothing grows as it runs except for the Peano number itself
+        let limits = Limits { call_depth: Some(16), stack: Some(16), locals: Some(16), ..Limits::default() };
+        let peano = |n| format!("{}\n$[main]\ndef main = to_peano({}, Zero)\n", PEANO, n);
+
+        // Once optimised, a Peano number is a single natural number, however large it is
+        let res = run_limited(&peano(100000), OptMode::Fast, limits);
+        assert!(matches!(res, Some(Value::Int(100000))), "{:?}", res);
+
+        // Without optimisation, it is a chain with a link for every successor
+        let mut value = run_limited(&peano(1000), OptMode::None, limits).unwrap();
+        let mut links = 0;
+        while let Value::Sum(_, inner) = value {
+            value = (*inner).clone();
+            links += 1;
         }
+        assert_eq!(links, 1000);
+    }
+
+    #[test]
+    fn jit_heap_limit() {
+        // Without optimisation, every successor of a Peano number is a heap object
+        let src = format!("{}\n$[main]\ndef main = to_peano(1000, Zero)\n", PEANO);
+        let options = Options { debug: Vec::new(), opt: OptMode::None, backend: Backend::Jit, output: None };
+        let mut errors = Vec::new();
+        let (ctx, _) = compile_mir(src, SrcId::from_path("main.tao"), &options, &mut errors, &mut |src| {
+            fs::read_to_string(Path::new("..").join(src.to_path())).ok()
+        })
+            .unwrap_or_else(|| panic!("Failed to compile:\n{}", String::from_utf8_lossy(&errors)));
+
+        let mut jit = tao_cranelift::Jit::new(&ctx).unwrap();
+        let res = jit.heap_limit(Some(100)).run(|_| {});
+        assert!(matches!(res, Err(tao_cranelift::Error::HeapExhausted)), "{:?}", res);
+        // Each run gets the whole of the limit, whatever happened to the runs before it
+        let res = jit.heap_limit(Some(100_000)).run(|_| {});
+        assert!(matches!(res, Ok(Some(_))), "{:?}", res);
     }
 }
@@ -1,20 +1,101 @@
-use tao::{Options, SrcId, run};
+use tao::{Options, Program, Repl, SrcId, format, run, run_program};
 use structopt::StructOpt;
-use std::{fs, path::PathBuf};
+use std::{fs, io::{self, BufRead, Write}, path::PathBuf, process};
 
 #[derive(Clone, Debug, StructOpt)]
-pub struct Args {
-    #[structopt(flatten)]
-    pub options: Options,
-    /// Specify the file to run
-    #[structopt(name = "FILE", parse(from_os_str))]
-    pub file: PathBuf,
+pub enum Command {
+    /// Run a program, either from source or from bytecode compiled by `taoc build` (a `.taob` file)
+    Run {
+        #[structopt(flatten)]
+        options: Options,
+        /// Specify the file to run
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Start an interactive session, evaluating items and expressions as they are entered
+    Repl {
+        #[structopt(flatten)]
+        options: Options,
+        /// Specify the module to import at the start of the session
+        #[structopt(long, default_value = "lib/std.tao")]
+        prelude: String,
+    },
+    /// Rewrite source files in the canonical style
+    Fmt {
+        /// Don't rewrite any files, instead list those that are not formatted and fail if there are any
+        #[structopt(long)]
+        check: bool,
+        /// Specify the files to format
+        #[structopt(name = "FILES", parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
 fn main() {
-    let args = Args::from_args();
-    let src = fs::read_to_string(&args.file)
-        .expect("Failed to read file");
-    let src_id = SrcId::from_path(args.file);
-    run(src, src_id, args.options, std::io::stdout(), |src| fs::read_to_string(src.to_path()).ok());
+    match Command::from_args() {
+        Command::Run { options, file } => if file.extension().map_or(false, |ext| ext == "taob") {
+            let bytes = fs::read(&file)
+                .expect("Failed to read file");
+            match Program::decode(&bytes) {
+                Ok(prog) => run_program(&prog, std::io::stdout(), |src| fs::read_to_string(src.to_path()).ok()),
+                Err(err) => {
+                    eprintln!("Failed to load {}: {}", file.display(), err);
+                    process::exit(1);
+                },
+            }
+        } else {
+            let src = fs::read_to_string(&file)
+                .expect("Failed to read file");
+            let src_id = SrcId::from_path(file);
+            run(src, src_id, options, std::io::stdout(), |src| fs::read_to_string(src.to_path()).ok());
+        },
+        Command::Repl { options, prelude } => {
+            let mut repl = Repl::new(options, Some(&prelude), |src| fs::read_to_string(src.to_path()).ok(), io::stdout());
+
+            // Input is read until it forms a complete item or expression, or until an empty line is entered
+            let mut input = String::new();
+            loop {
+                print!("{}", if input.is_empty() { ">> " } else { ".. " });
+                io::stdout().flush().unwrap();
+
+                let mut line = String::new();
+                if io::stdin().lock().read_line(&mut line).expect("Failed to read input") == 0 {
+                    break;
+                }
+                let blank = line.trim().is_empty();
+                input += &line;
+
+                if blank || !repl.is_incomplete(&input) {
+                    repl.eval(input.trim_end(), io::stdout());
+                    input.clear();
+                }
+            }
+        },
+        Command::Fmt { check, files } => {
+            let mut failed = false;
+            for file in files {
+                let src = fs::read_to_string(&file)
+                    .expect("Failed to read file");
+                let formatted = match format(&src, SrcId::from_path(&file), io::stderr()) {
+                    Some(formatted) => formatted,
+                    None => {
+                        failed = true;
+                        continue;
+                    },
+                };
+                if formatted == src {
+                    continue;
+                } else if check {
+                    println!("{}", file.display());
+                    failed = true;
+                } else if let Err(e) = fs::write(&file, formatted) {
+                    eprintln!("Failed to write {}: {}", file.display(), e);
+                    failed = true;
+                }
+            }
+            if failed {
+                process::exit(1);
+            }
+        },
+    }
 }
@@ -0,0 +1,247 @@
+use super::*;
+use tao_syntax::{parse_expr, parse_expr_at, ErrorKind};
+use std::collections::HashSet;
+
+/// An interactive session in which items and expressions are entered one at a time.
+///
+/// Items that are accepted are added to the session's history, which is a module (`SrcId::repl()`) that starts by
+/// importing the prelude. Each expression is evaluated in the scope of that module, and diagnostics are reported against
+/// the history with the latest input on the end.
+pub struct Repl<F> {
+    options: Options,
+    get_file: F,
+    history: String,
+    /// The source of every module that has been imported so far.
+    srcs: HashMap<SrcId, String>,
+    imports: Vec<SrcNode<ast::Module>>,
+}
+
+// Inputs that start with these are items, anything else (except for `fn` items, see `Repl::is_item`) is an expression
+const ITEM_KEYWORDS: [&str; 9] = ["import", "pub", "data", "type", "def", "class", "member", "for", "effect"];
+
+impl<F: FnMut(SrcId) -> Option<String>> Repl<F> {
+    /// Start a session, loading the given prelude module (if any) up front.
+    pub fn new(options: Options, prelude: Option<&str>, get_file: F, writer: impl Write) -> Self {
+        let mut this = Self {
+            options,
+            get_file,
+            history: String::new(),
+            srcs: HashMap::new(),
+            imports: Vec::new(),
+        };
+        if let Some(prelude) = prelude {
+            this.eval(&format!("import \"{}\"", prelude), writer);
+        }
+        this
+    }
+
+    /// Determine whether the input ends before the item or expression that it contains does, meaning that more input
+    /// should be read before evaluating it.
+    pub fn is_incomplete(&self, input: &str) -> bool {
+        let errors = if Self::is_item(input) {
+            parse_module(input, SrcId::repl()).1
+        } else {
+            parse_expr(input, SrcId::repl()).1
+        };
+        errors.iter().any(|e| matches!(e.kind(), ErrorKind::UnexpectedEnd | ErrorKind::Unclosed { before: None, .. }))
+    }
+
+    fn is_item(input: &str) -> bool {
+        let input = input.trim_start();
+        let starts_with_kw = |kw: &str| input.starts_with(kw)
+            && !input[kw.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        input.starts_with("$[")
+            || ITEM_KEYWORDS.iter().any(|kw| starts_with_kw(kw))
+            // Both functions and lambdas start with `fn`
+            || (starts_with_kw("fn") && !parse_expr(input, SrcId::repl()).1.is_empty())
+    }
+
+    /// Evaluate an item or an expression, writing any output to the writer.
+    pub fn eval(&mut self, input: &str, mut writer: impl Write) {
+        if input.trim().is_empty() {
+            return;
+        }
+
+        let src = format!("{}{}\n", self.history, input);
+        let old_imports = self.imports.len();
+        let old_srcs = self.srcs.keys().copied().collect::<HashSet<_>>();
+
+        if Self::is_item(input) {
+            if self.check(&src, None, &mut writer).is_some() {
+                self.history = src;
+            } else {
+                // Forget any modules that were imported by the rejected items
+                self.imports.truncate(old_imports);
+                self.srcs.retain(|src, _| old_srcs.contains(src));
+            }
+        } else if let Some((ctx, ty)) = self.check(&src, Some(input), &mut writer) {
+            self.run(&src, &ctx, &ty, &mut writer);
+        }
+    }
+
+    // Parse and analyse the history with the new input on the end, either as items or as an expression that becomes the
+    // entry point. Returns the context and the type of the expression if no errors occurred.
+    fn check(&mut self, src: &str, expr: Option<&str>, mut writer: impl Write) -> Option<(HirContext, String)> {
+        let (ast, mut syntax_errors) = match expr {
+            None => parse_module(src, SrcId::repl()),
+            Some(expr) => {
+                let (ast, mut syntax_errors) = parse_module(&self.history, SrcId::repl());
+                let (expr, mut expr_errors) = parse_expr_at(expr, SrcId::repl(), self.history.chars().count());
+                syntax_errors.append(&mut expr_errors);
+                (ast.zip(expr).map(|(mut ast, expr)| {
+                    // The expression becomes the entry point, so items entered earlier must not be
+                    ast.items
+                        .iter_mut()
+                        .for_each(|item| item.attrs.retain(|attr| attr.name.as_str() != "entry"));
+                    let span = expr.span();
+                    ast.items.push(ast::Item {
+                        kind: ast::ItemKind::Def(ast::Def {
+                            name: SrcNode::new(ast::Ident::new("<expr>"), span),
+                            generics: SrcNode::new(ast::Generics { tys: Vec::new(), preds: Vec::new() }, span),
+                            ty_hint: SrcNode::new(ast::Type::Unknown, span),
+                            body: expr,
+                        }),
+                        attrs: vec![SrcNode::new(ast::Attr {
+                            name: SrcNode::new(ast::Ident::new("entry"), span),
+                            args: None,
+                        }, span)],
+                        vis: ast::Visibility::Private,
+                    });
+                    ast
+                }), syntax_errors)
+            },
+        };
+
+        let mut import_errors = Vec::new();
+        if let Some(ast) = &ast {
+            load_imports(ast, &mut self.srcs, &mut self.imports, &mut import_errors, &mut syntax_errors, &mut self.get_file);
+        }
+
+        let mut srcs = self.sources(src);
+        let failed = !import_errors.is_empty() || !syntax_errors.is_empty();
+        for e in import_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        for e in syntax_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        if failed {
+            return None;
+        }
+
+        let (ctx, analysis_errors) = HirContext::from_module(&ast?, &self.imports);
+        if !analysis_errors.is_empty() {
+            for e in analysis_errors {
+                e.write(&ctx, &mut srcs, SrcId::repl(), &mut writer);
+            }
+            return None;
+        }
+
+        let ty = ctx.defs
+            .iter()
+            .find(|(_, def)| def.name.as_str() == "<expr>")
+            .and_then(|(_, def)| def.body.as_ref())
+            .map(|body| ctx.tys.display(&ctx.datas, &ctx.effects, body.meta().1).to_string())
+            .unwrap_or_default();
+
+        Some((ctx, ty))
+    }
+
+    fn run(&mut self, src: &str, ctx: &HirContext, ty: &str, mut writer: impl Write) {
+        let mut srcs = self.sources(src);
+
+        let (concrete, con_errors) = ctx.concretize();
+        if !con_errors.is_empty() {
+            for e in con_errors {
+                e.write(ctx, &mut srcs, SrcId::repl(), &mut writer);
+            }
+            return;
+        }
+
+        let mut mir = Context::from_concrete(ctx, &concrete);
+        mir.optimize(self.options.opt);
+        let prog = Program::from_mir(&mir);
+
+        let res = Vm::default()
+            .on_print(|s| writeln!(writer, "{}", s))
+            .run(&prog);
+
+        match res {
+            Ok(Some(result)) => writeln!(writer, "{} : {}", result, ty).unwrap(),
+            Ok(None) => {},
+            Err(err) => match err.span {
+                Some(span) => Error::Runtime(err, span).write(&mut srcs, &mut writer),
+                None => write!(writer, "{}", err).unwrap(),
+            },
+        }
+    }
+
+    fn sources(&self, src: &str) -> impl ariadne::Cache<SrcId> {
+        sources(self.srcs
+            .clone()
+            .into_iter()
+            .chain(std::iter::once((SrcId::repl(), src.to_string()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    // A session with the standard library in scope, which is imported relative to the root of the repository
+    fn repl() -> Repl<impl FnMut(SrcId) -> Option<String>> {
+        let options = Options { debug: Vec::new(), opt: OptMode::None, backend: Backend::Vm, output: None };
+        let get_file = |src: SrcId| fs::read_to_string(Path::new("..").join(src.to_path())).ok();
+        let mut output = Vec::new();
+        let repl = Repl::new(options, Some("lib/std.tao"), get_file, &mut output);
+        assert!(output.is_empty(), "{}", String::from_utf8_lossy(&output));
+        repl
+    }
+
+    fn eval<F: FnMut(SrcId) -> Option<String>>(repl: &mut Repl<F>, input: &str) -> String {
+        let mut output = Vec::new();
+        repl.eval(input, &mut output);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn items_stay_in_scope() {
+        let mut repl = repl();
+        assert_eq!(eval(&mut repl, "def x : Nat = 5"), "");
+        assert_eq!(eval(&mut repl, "fn double : Nat -> Nat = n => n * 2"), "");
+        assert_eq!(eval(&mut repl, "data Box = Nat"), "");
+        assert_eq!(eval(&mut repl, "double(x) + 1"), "11i : Nat\n");
+        // Items may refer to those entered before them
+        assert_eq!(eval(&mut repl, "def boxed : Box = Box(double(x))"), "");
+        assert_eq!(eval(&mut repl, "let Box n = boxed in n"), "10i : Nat\n");
+    }
+
+    #[test]
+    fn expressions_do_not_stay_in_scope() {
+        let mut repl = repl();
+        assert_eq!(eval(&mut repl, "let y = 3 in y"), "3i : Nat\n");
+        assert!(!eval(&mut repl, "y").is_empty());
+        assert_eq!(eval(&mut repl, "4"), "4i : Nat\n");
+    }
+
+    #[test]
+    fn rejected_items_are_forgotten() {
+        let mut repl = repl();
+        assert_eq!(eval(&mut repl, "def x : Nat = 5"), "");
+        assert!(!eval(&mut repl, "def y : Nat = True").is_empty());
+        // The rejected item does not break later entries, and can be declared again
+        assert_eq!(eval(&mut repl, "x"), "5i : Nat\n");
+        assert_eq!(eval(&mut repl, "def y : Nat = x + 1"), "");
+        assert_eq!(eval(&mut repl, "y"), "6i : Nat\n");
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let repl = repl();
+        assert!(repl.is_incomplete("def x : Nat ="));
+        assert!(repl.is_incomplete("(1, 2"));
+        assert!(!repl.is_incomplete("def x : Nat = 5"));
+        assert!(!repl.is_incomplete("fn x => x"));
+    }
+}
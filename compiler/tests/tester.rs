@@ -1,8 +1,11 @@
 macro_rules! test {
     ($x:ident) => {
+        test!($x, [Vm, Jit, Bytecode]);
+    };
+    ($x:ident, [$($backend:ident),*]) => {
         #[test]
         fn $x() {
-            test_configs(stringify!($x))
+            test_configs(stringify!($x), &[$(Backend::$backend),*])
         }
     };
 }
@@ -10,11 +13,38 @@ macro_rules! test {
 test!(math);
 test!(lists);
 test!(records);
+test!(classes);
+test!(derive);
+test!(hkt);
+test!(monad);
+test!(termination);
+test!(division);
+test!(reals);
+test!(recursive_repr);
+// The JIT does not yet support resumable effect handlers
+test!(effects, [Vm, Bytecode]);
+test!(tail_calls);
+test!(host);
 
-use tao::{Options, Opt, SrcId, run};
-use std::fs;
+use tao::{Options, OptMode, Backend, Program, SrcId, run, run_program};
+use std::{fs, path::Path};
 
-fn test_configs(name: &str) {
+// Strip the colours from diagnostics, along with the trailing whitespace that they pad lines with
+fn plain(s: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the rest of the escape sequence, which ends with a letter
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain.trim().lines().map(str::trim_end).collect::<Vec<_>>().join("\n")
+}
+
+fn test_configs(name: &str, backends: &[Backend]) {
     fn test_config(name: &str, options: Options) {
         let path = format!("tests/{}.tao", name);
         let src = fs::read_to_string(&path).unwrap();
@@ -36,9 +66,18 @@ fn test_configs(name: &str) {
                 State::Start | State::Output => if line.trim() == "# >>>> INPUT" || line.trim() == "# >>>> END" {
                     if let State::Output = &state {
                         let mut output = Vec::new();
-                        run(input.clone(), src_id, options.clone(), &mut output, |_| unimplemented!());
+                        let bytecode = options.output.as_ref().unwrap();
+                        let _ = fs::remove_file(bytecode);
+                        // Tests have the standard library in scope, which is imported relative to the root of the repository
+                        let src = format!("import \"lib/std.tao\"\n{}", input);
+                        run(src, src_id, options.clone(), &mut output, |src| fs::read_to_string(Path::new("..").join(src.to_path())).ok());
+                        // Run the bytecode that was just written (unless compilation failed), to check that it survives the
+                        // round trip
+                        if let (Backend::Bytecode, Ok(bytes)) = (options.backend, fs::read(bytecode)) {
+                            run_program(&Program::decode(&bytes).unwrap(), &mut output, |_| None);
+                        }
                         let output = String::from_utf8(output).unwrap();
-                        if output.trim() != expected.trim() {
+                        if plain(&output) != plain(&expected) {
                             panic!("\n\n \
                                 ========[ EXPECTED OUTPUT ]========\n\n \
                                 {}\n \
@@ -66,10 +105,15 @@ fn test_configs(name: &str) {
 
     let mut options = Options {
         debug: Vec::new(),
-        opt: Opt::None,
+        opt: OptMode::None,
+        backend: Backend::Vm,
+        output: Some(std::env::temp_dir().join(format!("tao_test_{}.taob", name))),
     };
-    options.opt = Opt::None;
-    test_config(name, options.clone());
-    options.opt = Opt::Fast;
-    test_config(name, options.clone());
+    for backend in backends {
+        options.backend = *backend;
+        options.opt = OptMode::None;
+        test_config(name, options.clone());
+        options.opt = OptMode::Fast;
+        test_config(name, options.clone());
+    }
 }
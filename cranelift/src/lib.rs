@@ -0,0 +1,168 @@
+//! A native code backend that lowers MIR to Cranelift IR.
+//!
+//! Every value is represented by a single 64-bit word. Primitives are stored inline, while tuples, lists, sums,
+//! functions, and effect objects are pointers to heap objects allocated by the runtime (see [`rt`]).
+
+pub mod lower;
+pub mod value;
+
+pub use tao_rt as rt;
+
+pub use crate::{
+    lower::Lowerer,
+    value::Value,
+};
+use tao_middle::{
+    mir,
+    Context as MirContext,
+    MirNode,
+    ProcId,
+    repr::{Repr, Prim},
+    EffectId,
+};
+use cranelift_codegen::{
+    ir::{self, types, AbiParam, InstBuilder, MemFlags, TrapCode, condcodes::{IntCC, FloatCC}},
+    isa::CallConv,
+    settings::{self, Configurable},
+    entity::EntityRef,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_module::{Module, FuncId, DataId, DataDescription, Linkage, ModuleError, default_libcall_names};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use hashbrown::HashMap;
+use std::{fmt, fs, io, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The program uses a feature that this backend cannot lower yet.
+    Unsupported(&'static str),
+    /// The program allocated more heap memory than it was allowed to (see [`Jit::heap_limit`]).
+    HeapExhausted,
+    /// Cranelift rejected the generated code.
+    Module(Box<ModuleError>),
+    Io(io::Error),
+}
+
+impl From<ModuleError> for Error {
+    fn from(err: ModuleError) -> Self { Error::Module(Box::new(err)) }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self { Error::Io(err) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unsupported(feature) => write!(f, "Cranelift backend does not support {}", feature),
+            Error::HeapExhausted => write!(f, "Heap exhausted: the JIT does not free memory until a program finishes"),
+            Error::Module(err) => write!(f, "Cranelift error: {}", err),
+            Error::Io(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+fn isa_flags() -> settings::Flags {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").unwrap();
+    flags.set("is_pic", "true").unwrap();
+    settings::Flags::new(flags)
+}
+
+/// A program that has been compiled to native code in memory.
+pub struct Jit<'a> {
+    mir: &'a MirContext,
+    /// Owns the compiled code, so it must outlive every call to `main`.
+    module: Option<JITModule>,
+    main: extern "C" fn() -> i64,
+    heap: Option<usize>,
+}
+
+/// The number of words that a program run with the JIT may allocate by default (2 GiB).
+pub const DEFAULT_HEAP_LIMIT: usize = 1 << 28;
+
+impl<'a> Jit<'a> {
+    /// Compile the program to native code in memory.
+    pub fn new(mir: &'a MirContext) -> Result<Self, Error> {
+        let mut builder = JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names())?;
+        for func in rt::funcs() {
+            builder.symbol(func.name, func.ptr);
+        }
+
+        let mut lowerer = Lowerer::new(mir, JITModule::new(builder))?;
+        let main = lowerer.lower_program(Linkage::Local)?;
+        let mut module = lowerer.finish();
+        module.finalize_definitions()?;
+
+        // SAFETY: `main` was declared by `Lowerer::lower_program` with the signature `fn() -> i64`.
+        let main = unsafe { std::mem::transmute::<*const u8, extern "C" fn() -> i64>(module.get_finalized_function(main)) };
+        Ok(Self { mir, module: Some(module), main, heap: Some(DEFAULT_HEAP_LIMIT) })
+    }
+
+    /// Limit the number of words that each run of the program may allocate, or remove the limit. Nothing is freed while
+    /// the program runs (see [`rt`]), so this bounds the memory that it uses. Defaults to [`DEFAULT_HEAP_LIMIT`].
+    pub fn heap_limit(&mut self, words: Option<usize>) -> &mut Self {
+        self.heap = words;
+        self
+    }
+
+    /// Run the program's entry point, returning the value it produced (or nothing, if the program performs IO). Lines
+    /// written by `@print` are given to `print`.
+    pub fn run(&self, mut print: impl FnMut(&str)) -> Result<Option<Value>, Error> {
+        rt::set_heap_limit(self.heap);
+        let word = rt::with_print(&mut print, || (self.main)());
+
+        let result = if rt::heap_exhausted() {
+            Err(Error::HeapExhausted)
+        } else if self.mir.entry_does_io() {
+            Ok(None)
+        } else {
+            let entry = self.mir.entry.expect("No entry point");
+            Ok(Some(Value::from_word(self.mir, self.mir.procs.get(entry).unwrap().body.meta().clone(), word)))
+        };
+        // SAFETY: the result has been decoded, so nothing refers to the heap objects that the program allocated
+        unsafe { rt::free_heap() };
+        result
+    }
+}
+
+impl<'a> Drop for Jit<'a> {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: the compiled code cannot be called once the program has been dropped
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Compile the program to native code in memory and run its entry point, returning the value it produced (or nothing,
+/// if the program performs IO). Lines written by `@print` are given to `print`.
+pub fn jit(mir: &MirContext, print: impl FnMut(&str)) -> Result<Option<Value>, Error> {
+    Jit::new(mir)?.run(print)
+}
+
+/// Compile the program to a native object file.
+///
+/// The object exports the program's entry point as `tao_main`, a C function that takes no arguments and returns the
+/// resulting value as a 64-bit word. It also exports a C `main` function that runs `tao_main` and discards its result,
+/// so linking the object against the static library of [`rt`] produces an executable (see [`rt`] for how).
+pub fn emit_object(mir: &MirContext, path: impl AsRef<Path>) -> Result<(), Error> {
+    let isa = cranelift_native::builder()
+        .map_err(Error::Unsupported)?
+        .finish(isa_flags())
+        .map_err(|err| Error::from(ModuleError::Compilation(err)))?;
+    let builder = ObjectBuilder::new(isa, "tao", default_libcall_names())?;
+
+    let mut lowerer = Lowerer::new(mir, ObjectModule::new(builder))?;
+    let main = lowerer.lower_program(Linkage::Export)?;
+    lowerer.lower_c_main(main)?;
+    let bytes = lowerer
+        .finish()
+        .finish()
+        .emit()
+        .map_err(|err| Error::from(ModuleError::Backend(err.into())))?;
+
+    fs::write(path, bytes)?;
+    Ok(())
+}
@@ -0,0 +1,819 @@
+use super::*;
+
+/// Every value is a single word.
+const WORD: ir::Type = types::I64;
+const WORD_BYTES: i32 = 8;
+
+/// The variant of the sum produced by the body of a `Go` loop that indicates another iteration is needed.
+const NEXT_VARIANT: i64 = 0;
+
+/// The trap raised when code that should be unreachable is reached.
+const TRAP_UNREACHABLE: u8 = 1;
+/// The trap raised when an effect is suspended with no handler for it.
+const TRAP_NO_HANDLER: u8 = 2;
+
+/// A function body that has been declared but not yet lowered.
+struct Lifted<'a> {
+    id: FuncId,
+    arg: Option<mir::Local>,
+    captures: Vec<mir::Local>,
+    /// The index of the first capture within the environment object.
+    env_offset: usize,
+    body: &'a MirNode<mir::Expr>,
+}
+
+pub struct Lowerer<'a, M: Module> {
+    mir: &'a MirContext,
+    module: M,
+    ctx: cranelift_codegen::Context,
+    fn_ctx: FunctionBuilderContext,
+    procs: HashMap<ProcId, FuncId>,
+    rt: HashMap<&'static str, FuncId>,
+    /// A word that native code sets once the heap has been exhausted. Every function then returns without doing anything
+    /// more, since the program has been abandoned and the results of its functions are meaningless.
+    exhausted: DataId,
+    effects: HashMap<EffectId, i64>,
+    lifted: Vec<Lifted<'a>>,
+    lifted_count: usize,
+}
+
+impl<'a, M: Module> Lowerer<'a, M> {
+    pub fn new(mir: &'a MirContext, mut module: M) -> Result<Self, Error> {
+        if module.target_config().pointer_type() != WORD {
+            return Err(Error::Unsupported("targets without 64-bit pointers"));
+        }
+
+        let mut rt = HashMap::new();
+        for func in rt::funcs() {
+            let mut sig = module.make_signature();
+            sig.params.extend(std::iter::repeat_n(AbiParam::new(WORD), func.params));
+            if func.returns {
+                sig.returns.push(AbiParam::new(WORD));
+            }
+            rt.insert(func.name, module.declare_function(func.name, Linkage::Import, &sig)?);
+        }
+
+        let exhausted = module.declare_data("tao_exhausted", Linkage::Local, true, false)?;
+        let mut data = DataDescription::new();
+        data.define_zeroinit(WORD_BYTES as usize);
+        module.define_data(exhausted, &data)?;
+
+        Ok(Self {
+            mir,
+            ctx: module.make_context(),
+            module,
+            fn_ctx: FunctionBuilderContext::new(),
+            procs: HashMap::new(),
+            rt,
+            exhausted,
+            effects: HashMap::new(),
+            lifted: Vec::new(),
+            lifted_count: 0,
+        })
+    }
+
+    pub fn finish(self) -> M { self.module }
+
+    fn proc_sig(&self) -> ir::Signature {
+        let mut sig = self.module.make_signature();
+        sig.call_conv = CallConv::Tail;
+        sig.returns.push(AbiParam::new(WORD));
+        sig
+    }
+
+    /// The signature of `tao_main`, which is called by the host and so uses the platform's calling convention.
+    fn main_sig(&self) -> ir::Signature {
+        let mut sig = self.module.make_signature();
+        sig.returns.push(AbiParam::new(WORD));
+        sig
+    }
+
+    /// Lower every reachable procedure, along with a `tao_main` function that runs the entry point.
+    pub fn lower_program(&mut self, main_linkage: Linkage) -> Result<FuncId, Error> {
+        let mir = self.mir;
+        let entry = mir.entry.expect("No entry point");
+        let reachable = mir.reachable_procs();
+        for (i, proc) in reachable.iter().enumerate() {
+            let id = self.module.declare_function(&format!("tao_proc_{}", i), Linkage::Local, &self.proc_sig())?;
+            self.procs.insert(*proc, id);
+        }
+
+        for proc in reachable {
+            self.lower_func(self.procs[&proc], None, Vec::new(), 0, &mir.procs.get(proc).unwrap().body)?;
+            while let Some(lifted) = self.lifted.pop() {
+                self.lower_func(lifted.id, lifted.arg, lifted.captures, lifted.env_offset, lifted.body)?;
+            }
+        }
+
+        let main = self.module.declare_function("tao_main", main_linkage, &self.main_sig())?;
+        self.ctx.func.signature = self.main_sig();
+        {
+            let exhausted = self.module.declare_data_in_func(self.exhausted, &mut self.ctx.func);
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.fn_ctx);
+            let block = builder.create_block();
+            builder.switch_to_block(block);
+
+            // The program may have been run before, and abandoned
+            let exhausted = builder.ins().global_value(WORD, exhausted);
+            let zero = builder.ins().iconst(WORD, 0);
+            builder.ins().store(MemFlags::trusted(), zero, exhausted, 0);
+
+            let entry = self.module.declare_func_in_func(self.procs[&entry], builder.func);
+            let call = builder.ins().call(entry, &[]);
+            let mut result = builder.inst_results(call)[0];
+            // IO programs are functions that need to be applied to the initial universe
+            if self.mir.entry_does_io() {
+                // An abandoned program produces no function to apply
+                let apply = builder.create_block();
+                let abandon = builder.create_block();
+                let flag = builder.ins().load(WORD, MemFlags::trusted(), exhausted, 0);
+                builder.ins().brif(flag, abandon, &[], apply, &[]);
+
+                builder.switch_to_block(abandon);
+                builder.ins().return_(&[zero]);
+
+                builder.switch_to_block(apply);
+                let sig = builder.import_signature(lifted_sig(&self.module));
+                let f_addr = builder.ins().load(WORD, MemFlags::trusted(), result, 0);
+                let call = builder.ins().call_indirect(sig, f_addr, &[result, zero]);
+                result = builder.inst_results(call)[0];
+            }
+            builder.ins().return_(&[result]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        self.module.define_function(main, &mut self.ctx)?;
+        self.module.clear_context(&mut self.ctx);
+
+        Ok(main)
+    }
+
+    /// Lower a C `main` function that runs `tao_main`, so that an object file can be linked into an executable.
+    pub fn lower_c_main(&mut self, tao_main: FuncId) -> Result<(), Error> {
+        let mut sig = self.module.make_signature();
+        sig.returns.push(AbiParam::new(types::I32));
+        let main = self.module.declare_function("main", Linkage::Export, &sig)?;
+        self.ctx.func.signature = sig;
+        {
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.fn_ctx);
+            let block = builder.create_block();
+            builder.switch_to_block(block);
+
+            let tao_main = self.module.declare_func_in_func(tao_main, builder.func);
+            builder.ins().call(tao_main, &[]);
+            let status = builder.ins().iconst(types::I32, 0);
+            builder.ins().return_(&[status]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        self.module.define_function(main, &mut self.ctx)?;
+        self.module.clear_context(&mut self.ctx);
+
+        Ok(())
+    }
+
+    fn lower_func(
+        &mut self,
+        id: FuncId,
+        arg: Option<mir::Local>,
+        captures: Vec<mir::Local>,
+        env_offset: usize,
+        body: &'a MirNode<mir::Expr>,
+    ) -> Result<(), Error> {
+        // Procedures take no parameters, lifted functions take their environment and an argument
+        let is_proc = self.procs.values().any(|proc| *proc == id);
+        self.ctx.func.signature = if is_proc { self.proc_sig() } else { lifted_sig(&self.module) };
+
+        let exhausted = self.module.declare_data_in_func(self.exhausted, &mut self.ctx.func);
+        let mut func = FuncLowerer {
+            mir: self.mir,
+            builder: FunctionBuilder::new(&mut self.ctx.func, &mut self.fn_ctx),
+            module: &mut self.module,
+            procs: &self.procs,
+            rt: &self.rt,
+            exhausted,
+            abandon: None,
+            effects: &mut self.effects,
+            lifted: &mut self.lifted,
+            lifted_count: &mut self.lifted_count,
+            locals: HashMap::new(),
+            vars: 0,
+        };
+
+        let block = func.builder.create_block();
+        func.builder.append_block_params_for_function_params(block);
+        func.builder.switch_to_block(block);
+
+        if !is_proc {
+            let env = func.builder.block_params(block)[0];
+            let arg_val = func.builder.block_params(block)[1];
+            if let Some(arg) = arg {
+                func.bind(arg, arg_val);
+            }
+            for (i, capture) in captures.into_iter().enumerate() {
+                let val = func.load(env, env_offset + i);
+                func.bind(capture, val);
+            }
+        }
+
+        let result = func.lower_expr(body)?;
+        func.builder.ins().return_(&[result]);
+
+        if let Some(abandon) = func.abandon {
+            func.builder.switch_to_block(abandon);
+            let exhausted = func.builder.ins().global_value(WORD, func.exhausted);
+            let one = func.imm(1);
+            func.store(exhausted, 0, one);
+            let nothing = func.imm(0);
+            func.builder.ins().return_(&[nothing]);
+        }
+
+        func.builder.seal_all_blocks();
+        func.builder.finalize();
+
+        self.module.define_function(id, &mut self.ctx)?;
+        self.module.clear_context(&mut self.ctx);
+        Ok(())
+    }
+}
+
+/// The signature of functions and effect bodies, which take their environment object and an argument. Like procedures,
+/// they use the tail calling convention so that applications in tail position can reuse the frame of the caller.
+fn lifted_sig(module: &impl Module) -> ir::Signature {
+    let mut sig = module.make_signature();
+    sig.call_conv = CallConv::Tail;
+    sig.params.push(AbiParam::new(WORD));
+    sig.params.push(AbiParam::new(WORD));
+    sig.returns.push(AbiParam::new(WORD));
+    sig
+}
+
+struct FuncLowerer<'a, 'b, M: Module> {
+    mir: &'a MirContext,
+    builder: FunctionBuilder<'b>,
+    module: &'b mut M,
+    procs: &'b HashMap<ProcId, FuncId>,
+    rt: &'b HashMap<&'static str, FuncId>,
+    exhausted: ir::GlobalValue,
+    /// The block that abandons the program once the heap has been exhausted, if any code branches to it.
+    abandon: Option<ir::Block>,
+    effects: &'b mut HashMap<EffectId, i64>,
+    lifted: &'b mut Vec<Lifted<'a>>,
+    lifted_count: &'b mut usize,
+    locals: HashMap<usize, Variable>,
+    vars: usize,
+}
+
+impl<'a, 'b, M: Module> FuncLowerer<'a, 'b, M> {
+    fn bind(&mut self, local: mir::Local, val: ir::Value) {
+        let var = Variable::new(self.vars);
+        self.vars += 1;
+        self.builder.declare_var(var, WORD);
+        self.builder.def_var(var, val);
+        self.locals.insert(local.0, var);
+    }
+
+    fn local(&mut self, local: mir::Local) -> ir::Value {
+        let var = *self.locals
+            .get(&local.0)
+            .unwrap_or_else(|| panic!("Tried to find local ${}, but it was not found", local.0));
+        self.builder.use_var(var)
+    }
+
+    fn load(&mut self, obj: ir::Value, idx: usize) -> ir::Value {
+        self.builder.ins().load(WORD, MemFlags::trusted(), obj, idx as i32 * WORD_BYTES)
+    }
+
+    fn store(&mut self, obj: ir::Value, idx: usize, val: ir::Value) {
+        self.builder.ins().store(MemFlags::trusted(), val, obj, idx as i32 * WORD_BYTES);
+    }
+
+    fn imm(&mut self, x: i64) -> ir::Value {
+        self.builder.ins().iconst(WORD, x)
+    }
+
+    fn call_rt(&mut self, name: &str, args: &[ir::Value]) -> Option<ir::Value> {
+        let func = self.module.declare_func_in_func(self.rt[name], self.builder.func);
+        let call = self.builder.ins().call(func, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    /// Call a runtime function that produces a heap object, abandoning the program if the heap has been exhausted.
+    fn call_rt_alloc(&mut self, name: &str, args: &[ir::Value]) -> ir::Value {
+        let obj = self.call_rt(name, args).unwrap();
+        let abandon = self.abandon_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(obj, next, &[], abandon, &[]);
+        self.builder.switch_to_block(next);
+        obj
+    }
+
+    fn abandon_block(&mut self) -> ir::Block {
+        match self.abandon {
+            Some(abandon) => abandon,
+            None => {
+                let abandon = self.builder.create_block();
+                self.abandon = Some(abandon);
+                abandon
+            },
+        }
+    }
+
+    /// Abandon the program if the heap was exhausted by a function that was just called, since its result is meaningless.
+    fn check_exhausted(&mut self) {
+        let exhausted = self.builder.ins().global_value(WORD, self.exhausted);
+        let flag = self.load(exhausted, 0);
+        let abandon = self.abandon_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(flag, abandon, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn alloc(&mut self, words: &[ir::Value]) -> ir::Value {
+        let len = self.imm(words.len() as i64);
+        let obj = self.call_rt_alloc("tao_rt_alloc", &[len]);
+        for (i, word) in words.iter().enumerate() {
+            self.store(obj, i, *word);
+        }
+        obj
+    }
+
+    fn make_list(&mut self, items: &[ir::Value]) -> ir::Value {
+        let len = self.imm(items.len() as i64);
+        self.alloc(&std::iter::once(len).chain(items.iter().copied()).collect::<Vec<_>>())
+    }
+
+    fn bool_to_word(&mut self, cond: ir::Value) -> ir::Value {
+        self.builder.ins().uextend(WORD, cond)
+    }
+
+    // Division by zero (and signed division by -1, which overflows for the smallest integer) would trap, so the divisor
+    // is replaced with 1 in those cases and the result is corrected afterwards
+    fn div_rem(&mut self, x: ir::Value, y: ir::Value, signed: bool, rem: bool) -> ir::Value {
+        let one = self.imm(1);
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, y, 0);
+        let is_neg_one = self.builder.ins().icmp_imm(IntCC::Equal, y, -1);
+        let is_special = if signed { self.builder.ins().bor(is_zero, is_neg_one) } else { is_zero };
+        let divisor = self.builder.ins().select(is_special, one, y);
+        match (signed, rem) {
+            // `x % 1` is 0, which is correct for -1 too
+            (_, true) => {
+                let r = if signed { self.builder.ins().srem(x, divisor) } else { self.builder.ins().urem(x, divisor) };
+                self.builder.ins().select(is_zero, x, r)
+            },
+            (_, false) => {
+                let zero = self.imm(0);
+                let q = if signed {
+                    let q = self.builder.ins().sdiv(x, divisor);
+                    let neg = self.builder.ins().ineg(x);
+                    self.builder.ins().select(is_neg_one, neg, q)
+                } else {
+                    self.builder.ins().udiv(x, divisor)
+                };
+                self.builder.ins().select(is_zero, zero, q)
+            },
+        }
+    }
+
+    fn real(&mut self, x: ir::Value) -> ir::Value {
+        self.builder.ins().bitcast(types::F64, MemFlags::new(), x)
+    }
+
+    fn effect_id(&mut self, eff: EffectId) -> ir::Value {
+        let next = self.effects.len() as i64;
+        let id = *self.effects.entry(eff).or_insert(next);
+        self.imm(id)
+    }
+
+    /// Continue lowering in a fresh block, if `cond` is true. Otherwise, branch to `fail`.
+    fn check(&mut self, cond: ir::Value, fail: ir::Block) {
+        let next = self.builder.create_block();
+        self.builder.ins().brif(cond, next, &[], fail, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    /// Trap, leaving the builder in an unreachable block that the caller may continue to lower into.
+    fn trap(&mut self) -> ir::Value {
+        self.builder.ins().trap(TrapCode::unwrap_user(TRAP_UNREACHABLE));
+        let unreachable = self.builder.create_block();
+        self.builder.switch_to_block(unreachable);
+        self.imm(0)
+    }
+
+    fn lower_litr(&mut self, litr: &mir::Literal) -> ir::Value {
+        match litr {
+            mir::Literal::Never => self.imm(0), // Evaluating a `Never` is UB anyway, so who cares what it generates?
+            mir::Literal::Unknown(x) => *x,
+            mir::Literal::Nat(x) => self.imm(*x as i64),
+            mir::Literal::Int(x) => self.imm(*x),
+            mir::Literal::Real(x) => {
+                let x = self.builder.ins().f64const(*x);
+                self.builder.ins().bitcast(WORD, MemFlags::new(), x)
+            },
+            mir::Literal::Char(c) => self.imm(*c as i64),
+            mir::Literal::Bool(x) => self.imm(*x as i64),
+            mir::Literal::Tuple(fields) => {
+                let fields = fields.iter().map(|field| self.lower_litr(field)).collect::<Vec<_>>();
+                self.alloc(&fields)
+            },
+            mir::Literal::List(items) => {
+                let items = items.iter().map(|item| self.lower_litr(item)).collect::<Vec<_>>();
+                self.make_list(&items)
+            },
+            mir::Literal::Sum(variant, inner) => {
+                let variant = self.imm(*variant as i64);
+                let inner = self.lower_litr(inner);
+                self.alloc(&[variant, inner])
+            },
+            mir::Literal::Data(_, inner) => self.lower_litr(inner),
+        }
+    }
+
+    /// Bind the locals of a pattern. If `fail` is provided, the value is first tested against the pattern and control
+    /// branches to `fail` if it does not match.
+    fn lower_binding(&mut self, binding: &MirNode<mir::Binding>, val: ir::Value, fail: Option<ir::Block>) -> Result<(), Error> {
+        if let Some(name) = binding.name {
+            self.bind(name, val);
+        }
+
+        // Only visit sub-patterns that bind locals or (if we're testing) that might not match
+        let needs = |binding: &MirNode<mir::Binding>| binding.binds() || (fail.is_some() && binding.is_refutable());
+
+        match &binding.pat {
+            mir::Pat::Wildcard => {},
+            mir::Pat::Literal(litr) => if let Some(fail) = fail {
+                let expected = match litr {
+                    mir::Literal::Tuple(fields) if fields.is_empty() => return Ok(()),
+                    mir::Literal::Nat(x) => *x as i64,
+                    mir::Literal::Int(x) => *x,
+                    mir::Literal::Real(x) => x.to_bits() as i64,
+                    mir::Literal::Char(c) => *c as i64,
+                    mir::Literal::Bool(x) => *x as i64,
+                    _ => return Err(Error::Unsupported("non-primitive literal patterns")),
+                };
+                let cond = self.builder.ins().icmp_imm(IntCC::Equal, val, expected);
+                self.check(cond, fail);
+            },
+            mir::Pat::Single(inner) => self.lower_binding(inner, val, fail)?,
+            mir::Pat::Add(lhs, rhs) => {
+                if let Some(fail) = fail {
+                    let cond = self.builder.ins().icmp_imm(IntCC::SignedGreaterThanOrEqual, val, *rhs as i64);
+                    self.check(cond, fail);
+                }
+                if needs(lhs) {
+                    let lhs_val = self.builder.ins().iadd_imm(val, -(*rhs as i64));
+                    self.lower_binding(lhs, lhs_val, fail)?;
+                }
+            },
+            mir::Pat::Tuple(fields) => for (i, field) in fields.iter().enumerate() {
+                if needs(field) {
+                    let field_val = self.load(val, i);
+                    self.lower_binding(field, field_val, fail)?;
+                }
+            },
+            mir::Pat::ListExact(items) | mir::Pat::ListFront(items, _) => {
+                let is_exact = matches!(&binding.pat, mir::Pat::ListExact(_));
+                if let Some(fail) = fail {
+                    let len = self.load(val, 0);
+                    let cc = if is_exact { IntCC::Equal } else { IntCC::SignedGreaterThanOrEqual };
+                    let cond = self.builder.ins().icmp_imm(cc, len, items.len() as i64);
+                    self.check(cond, fail);
+                }
+                for (i, item) in items.iter().enumerate() {
+                    if needs(item) {
+                        let item_val = self.load(val, 1 + i);
+                        self.lower_binding(item, item_val, fail)?;
+                    }
+                }
+                if let mir::Pat::ListFront(_, Some(tail)) = &binding.pat {
+                    if needs(tail) {
+                        let skip = self.imm(items.len() as i64);
+                        let tail_val = self.call_rt_alloc("tao_rt_list_skip", &[val, skip]);
+                        self.lower_binding(tail, tail_val, fail)?;
+                    }
+                }
+            },
+            mir::Pat::Variant(variant, inner) => {
+                if let Some(fail) = fail {
+                    let tag = self.load(val, 0);
+                    let cond = self.builder.ins().icmp_imm(IntCC::Equal, tag, *variant as i64);
+                    self.check(cond, fail);
+                }
+                if needs(inner) {
+                    let inner_val = self.load(val, 1);
+                    self.lower_binding(inner, inner_val, fail)?;
+                }
+            },
+            mir::Pat::Data(_, inner) => self.lower_binding(inner, val, fail)?,
+        }
+
+        Ok(())
+    }
+
+    /// Declare a function for `body` that gets lowered later, returning its address and the locals that it captures.
+    fn lift(&mut self, arg: Option<mir::Local>, env_offset: usize, body: &'a MirNode<mir::Expr>) -> Result<(ir::Value, Vec<ir::Value>), Error> {
+        let mut captures = Vec::new();
+        for capture in body.required_locals(arg) {
+            if !captures.contains(&capture) {
+                captures.push(capture);
+            }
+        }
+
+        let id = self.module.declare_function(
+            &format!("tao_lifted_{}", *self.lifted_count),
+            Linkage::Local,
+            &lifted_sig(&*self.module),
+        )?;
+        *self.lifted_count += 1;
+
+        let func = self.module.declare_func_in_func(id, self.builder.func);
+        let f_addr = self.builder.ins().func_addr(WORD, func);
+        let capture_vals = captures.iter().map(|capture| self.local(*capture)).collect();
+
+        self.lifted.push(Lifted { id, arg, captures, env_offset, body });
+
+        Ok((f_addr, capture_vals))
+    }
+
+    /// Call the function or effect body that `obj` points to, passing `obj` as its environment.
+    fn call_obj(&mut self, obj: ir::Value, arg: ir::Value) -> ir::Value {
+        let sig = self.builder.import_signature(lifted_sig(&*self.module));
+        let f_addr = self.load(obj, 0);
+        let call = self.builder.ins().call_indirect(sig, f_addr, &[obj, arg]);
+        let result = self.builder.inst_results(call)[0];
+        self.check_exhausted();
+        result
+    }
+
+    /// Call the function that `obj` points to in place of the current function, leaving the builder in an unreachable
+    /// block that the caller may continue to lower into.
+    fn tail_call_obj(&mut self, obj: ir::Value, arg: ir::Value) -> ir::Value {
+        let sig = self.builder.import_signature(lifted_sig(&*self.module));
+        let f_addr = self.load(obj, 0);
+        self.builder.ins().return_call_indirect(sig, f_addr, &[obj, arg]);
+        let unreachable = self.builder.create_block();
+        self.builder.switch_to_block(unreachable);
+        self.imm(0)
+    }
+
+    /// Run an effect object with its captured handlers in scope.
+    fn propagate(&mut self, eff: ir::Value) -> ir::Value {
+        let old_len = self.call_rt("tao_rt_handlers_len", &[]).unwrap();
+        let snapshot = self.load(eff, 1);
+        self.call_rt("tao_rt_handlers_extend", &[snapshot]);
+        let nothing = self.imm(0);
+        let result = self.call_obj(eff, nothing);
+        self.call_rt("tao_rt_handlers_truncate", &[old_len]);
+        result
+    }
+
+    fn tuple_width(&self, repr: &Repr) -> Result<usize, Error> {
+        match repr {
+            Repr::Tuple(fields) => Ok(fields.len()),
+            Repr::Data(data) => self.tuple_width(&self.mir.reprs.get(*data).repr),
+            _ => Err(Error::Unsupported("updating fields of non-tuple values")),
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &'a MirNode<mir::Expr>) -> Result<ir::Value, Error> {
+        Ok(match &**expr {
+            mir::Expr::Undefined => self.trap(),
+            mir::Expr::Literal(litr) => self.lower_litr(litr),
+            mir::Expr::Local(local) => self.local(*local),
+            mir::Expr::Global(proc, _) => {
+                let func = self.module.declare_func_in_func(self.procs[proc], self.builder.func);
+                let call = self.builder.ins().call(func, &[]);
+                let result = self.builder.inst_results(call)[0];
+                self.check_exhausted();
+                result
+            },
+            mir::Expr::Intrinsic(intrinsic, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.lower_expr(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                use mir::Intrinsic;
+                match intrinsic {
+                    Intrinsic::Debug => args.first().copied().unwrap_or_else(|| self.imm(0)),
+                    Intrinsic::MakeList(_) => self.make_list(&args),
+                    Intrinsic::NotBool => self.builder.ins().bxor_imm(args[0], 1),
+                    Intrinsic::NegNat | Intrinsic::NegInt => self.builder.ins().ineg(args[0]),
+                    Intrinsic::NegReal => {
+                        let x = self.real(args[0]);
+                        let neg = self.builder.ins().fneg(x);
+                        self.builder.ins().bitcast(WORD, MemFlags::new(), neg)
+                    },
+                    Intrinsic::AddNat | Intrinsic::AddInt => self.builder.ins().iadd(args[0], args[1]),
+                    Intrinsic::SubNat | Intrinsic::SubInt => self.builder.ins().isub(args[0], args[1]),
+                    Intrinsic::MulNat | Intrinsic::MulInt => self.builder.ins().imul(args[0], args[1]),
+                    Intrinsic::DivNat => self.div_rem(args[0], args[1], false, false),
+                    Intrinsic::DivInt => self.div_rem(args[0], args[1], true, false),
+                    Intrinsic::RemNat => self.div_rem(args[0], args[1], false, true),
+                    Intrinsic::RemInt => self.div_rem(args[0], args[1], true, true),
+                    Intrinsic::AddReal | Intrinsic::SubReal | Intrinsic::MulReal | Intrinsic::DivReal => {
+                        let (x, y) = (self.real(args[0]), self.real(args[1]));
+                        let out = match intrinsic {
+                            Intrinsic::AddReal => self.builder.ins().fadd(x, y),
+                            Intrinsic::SubReal => self.builder.ins().fsub(x, y),
+                            Intrinsic::MulReal => self.builder.ins().fmul(x, y),
+                            _ => self.builder.ins().fdiv(x, y),
+                        };
+                        self.builder.ins().bitcast(WORD, MemFlags::new(), out)
+                    },
+                    Intrinsic::EqReal | Intrinsic::NotEqReal
+                    | Intrinsic::LessReal | Intrinsic::MoreReal
+                    | Intrinsic::LessEqReal | Intrinsic::MoreEqReal => {
+                        let cc = match intrinsic {
+                            Intrinsic::EqReal => FloatCC::Equal,
+                            Intrinsic::NotEqReal => FloatCC::NotEqual,
+                            Intrinsic::LessReal => FloatCC::LessThan,
+                            Intrinsic::MoreReal => FloatCC::GreaterThan,
+                            Intrinsic::LessEqReal => FloatCC::LessThanOrEqual,
+                            _ => FloatCC::GreaterThanOrEqual,
+                        };
+                        let (x, y) = (self.real(args[0]), self.real(args[1]));
+                        let cond = self.builder.ins().fcmp(cc, x, y);
+                        self.bool_to_word(cond)
+                    },
+                    Intrinsic::NatToReal | Intrinsic::IntToReal => {
+                        let x = self.builder.ins().fcvt_from_sint(types::F64, args[0]);
+                        self.builder.ins().bitcast(WORD, MemFlags::new(), x)
+                    },
+                    Intrinsic::RealToInt | Intrinsic::RealToNat => {
+                        let x = self.real(args[0]);
+                        let x = self.builder.ins().fcvt_to_sint_sat(WORD, x);
+                        if let Intrinsic::RealToNat = intrinsic {
+                            let zero = self.imm(0);
+                            self.builder.ins().smax(x, zero)
+                        } else {
+                            x
+                        }
+                    },
+                    Intrinsic::DisplayReal => self.call_rt_alloc("tao_rt_real_display", &args),
+                    Intrinsic::EqNat | Intrinsic::EqInt | Intrinsic::EqChar
+                    | Intrinsic::NotEqNat | Intrinsic::NotEqInt | Intrinsic::NotEqChar
+                    | Intrinsic::LessNat | Intrinsic::LessInt
+                    | Intrinsic::MoreNat | Intrinsic::MoreInt
+                    | Intrinsic::LessEqNat | Intrinsic::LessEqInt
+                    | Intrinsic::MoreEqNat | Intrinsic::MoreEqInt => {
+                        let cc = match intrinsic {
+                            Intrinsic::EqNat | Intrinsic::EqInt | Intrinsic::EqChar => IntCC::Equal,
+                            Intrinsic::NotEqNat | Intrinsic::NotEqInt | Intrinsic::NotEqChar => IntCC::NotEqual,
+                            Intrinsic::LessNat | Intrinsic::LessInt => IntCC::SignedLessThan,
+                            Intrinsic::MoreNat | Intrinsic::MoreInt => IntCC::SignedGreaterThan,
+                            Intrinsic::LessEqNat | Intrinsic::LessEqInt => IntCC::SignedLessThanOrEqual,
+                            _ => IntCC::SignedGreaterThanOrEqual,
+                        };
+                        let cond = self.builder.ins().icmp(cc, args[0], args[1]);
+                        self.bool_to_word(cond)
+                    },
+                    Intrinsic::Join(_) => self.call_rt_alloc("tao_rt_list_join", &args),
+                    Intrinsic::AndBool => self.builder.ins().band(args[0], args[1]),
+                    Intrinsic::Print => self.call_rt("tao_rt_print", &args).unwrap(),
+                    Intrinsic::Input => self.call_rt_alloc("tao_rt_input", &args),
+                    Intrinsic::UpdateField(idx) => {
+                        let width = self.tuple_width(expr.meta())?;
+                        let fields = (0..width)
+                            .map(|i| if i == *idx { args[1] } else { self.load(args[0], i) })
+                            .collect::<Vec<_>>();
+                        self.alloc(&fields)
+                    },
+                    Intrinsic::LenList => self.load(args[0], 0),
+                    Intrinsic::SkipList => self.call_rt_alloc("tao_rt_list_skip", &args),
+                    Intrinsic::TrimList => self.call_rt_alloc("tao_rt_list_trim", &args),
+                    Intrinsic::Suspend(eff) => {
+                        let eff = self.effect_id(*eff);
+                        let handler = self.call_rt("tao_rt_handlers_find", &[eff]).unwrap();
+                        // Native code has no host to fall back to for effects that the program does not handle
+                        self.builder.ins().trapz(handler, TrapCode::unwrap_user(TRAP_NO_HANDLER));
+                        self.call_obj(handler, args[0])
+                    },
+                    Intrinsic::Propagate => self.propagate(args[0]),
+                    Intrinsic::Host(_) => return Err(Error::Unsupported("host intrinsics")),
+                }
+            },
+            mir::Expr::Match(pred, arms) => {
+                let pred = self.lower_expr(pred)?;
+
+                let end = self.builder.create_block();
+                let result = self.builder.append_block_param(end, WORD);
+
+                for (i, (binding, body)) in arms.iter().enumerate() {
+                    let is_last = i + 1 == arms.len();
+
+                    // Skip pattern match if pattern is irrefutable or it's the last pattern
+                    let fail = if binding.is_refutable() && !is_last {
+                        Some(self.builder.create_block())
+                    } else {
+                        None
+                    };
+
+                    self.lower_binding(binding, pred, fail)?;
+                    let val = self.lower_expr(body)?;
+                    self.builder.ins().jump(end, &[val]);
+
+                    match fail {
+                        Some(fail) => self.builder.switch_to_block(fail),
+                        // Later arms can never be reached
+                        None => break,
+                    }
+                }
+
+                if arms.is_empty() {
+                    self.trap();
+                    let nothing = self.imm(0);
+                    self.builder.ins().jump(end, &[nothing]);
+                }
+
+                self.builder.switch_to_block(end);
+                result
+            },
+            mir::Expr::Func(arg, body) => {
+                let (f_addr, captures) = self.lift(Some(**arg), 1, body)?;
+                self.alloc(&std::iter::once(f_addr).chain(captures).collect::<Vec<_>>())
+            },
+            mir::Expr::Apply(f, arg, flags) => {
+                let f = self.lower_expr(f)?;
+                let arg = self.lower_expr(arg)?;
+                if flags.get().is_tail {
+                    self.tail_call_obj(f, arg)
+                } else {
+                    self.call_obj(f, arg)
+                }
+            },
+            mir::Expr::Go(next, body, init) => {
+                let init = self.lower_expr(init)?;
+
+                let luup = self.builder.create_block();
+                let next_val = self.builder.append_block_param(luup, WORD);
+                self.builder.ins().jump(luup, &[init]);
+
+                self.builder.switch_to_block(luup);
+                self.bind(**next, next_val);
+                let sum = self.lower_expr(body)?;
+
+                let done = self.builder.create_block();
+                let result = self.builder.append_block_param(done, WORD);
+
+                let variant = self.load(sum, 0);
+                let inner = self.load(sum, 1);
+                let is_next = self.builder.ins().icmp_imm(IntCC::Equal, variant, NEXT_VARIANT);
+                self.builder.ins().brif(is_next, luup, &[inner], done, &[inner]);
+
+                self.builder.switch_to_block(done);
+                result
+            },
+            mir::Expr::Tuple(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| self.lower_expr(field))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.alloc(&fields)
+            },
+            mir::Expr::Access(tuple, field) => {
+                let tuple = self.lower_expr(tuple)?;
+                self.load(tuple, *field)
+            },
+            mir::Expr::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.lower_expr(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.make_list(&items)
+            },
+            mir::Expr::Variant(variant, inner) => {
+                let variant = self.imm(*variant as i64);
+                let inner = self.lower_expr(inner)?;
+                self.alloc(&[variant, inner])
+            },
+            mir::Expr::AccessVariant(inner, _) => {
+                let inner = self.lower_expr(inner)?;
+                self.load(inner, 1)
+            },
+            mir::Expr::Data(_, inner) => self.lower_expr(inner)?,
+            mir::Expr::AccessData(inner, _) => self.lower_expr(inner)?,
+            mir::Expr::Basin(_, inner) => {
+                let snapshot = self.call_rt_alloc("tao_rt_handlers_snapshot", &[]);
+                let (f_addr, captures) = self.lift(None, 2, inner)?;
+                self.alloc(&[f_addr, snapshot].into_iter().chain(captures).collect::<Vec<_>>())
+            },
+            mir::Expr::Handle { resumable: true, .. } => return Err(Error::Unsupported("resumable effect handlers")),
+            mir::Expr::Handle { expr, eff, send, recv, .. } => {
+                let (h_addr, captures) = self.lift(Some(**send), 1, recv)?;
+                let handler = self.alloc(&std::iter::once(h_addr).chain(captures).collect::<Vec<_>>());
+
+                let old_len = self.call_rt("tao_rt_handlers_len", &[]).unwrap();
+                let eff = self.effect_id(*eff);
+                self.call_rt("tao_rt_handlers_push", &[eff, handler]);
+
+                let expr = self.lower_expr(expr)?;
+                let result = self.propagate(expr);
+
+                self.call_rt("tao_rt_handlers_truncate", &[old_len]);
+                result
+            },
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use super::*;
+
+/// A value produced by native code, decoded using its representation.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    Real(f64),
+    Char(char),
+    Bool(bool),
+    List(Vec<Self>),
+    Func(usize),
+    Sum(usize, Box<Self>),
+    Universe(u64),
+    Effect(usize),
+}
+
+impl Value {
+    pub fn from_word(mir: &MirContext, repr: Repr, word: i64) -> Self {
+        // SAFETY: native code only produces words that are valid for the representation of the value
+        let load = |idx: usize| unsafe { *(word as *const i64).add(idx) };
+        match repr {
+            Repr::Prim(Prim::Nat | Prim::Int) => Value::Int(word),
+            Repr::Prim(Prim::Real) => Value::Real(f64::from_bits(word as u64)),
+            Repr::Prim(Prim::Char) => Value::Char(char::from_u32(word as u32).unwrap_or('?')),
+            Repr::Prim(Prim::Bool) => Value::Bool(word != 0),
+            Repr::Prim(Prim::Universe) => Value::Universe(word as u64),
+            Repr::List(item) => Value::List(unsafe { rt::list(word) }
+                .iter()
+                .map(|x| Self::from_word(mir, (*item).clone(), *x))
+                .collect()),
+            Repr::Tuple(fields) => Value::List(fields
+                .into_iter()
+                .enumerate()
+                .map(|(i, field)| Self::from_word(mir, field, load(i)))
+                .collect()),
+            Repr::Sum(mut variants) => {
+                let variant = load(0) as usize;
+                Value::Sum(variant, Box::new(Self::from_word(mir, variants.swap_remove(variant), load(1))))
+            },
+            Repr::Data(data) => Self::from_word(mir, mir.reprs.get(data).repr.clone(), word),
+            Repr::Func(_, _) => Value::Func(load(0) as usize),
+            Repr::Effect(_, _) => Value::Effect(load(0) as usize),
+        }
+    }
+}
+
+// Kept consistent with the VM's formatting, so that both backends produce identical output
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(x) => write!(f, "{}i", x),
+            Value::Real(x) => write!(f, "{}f", x),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::List(items) => match items.first() {
+                Some(Value::Char(_)) => items
+                    .iter()
+                    .try_for_each(|c| write!(f, "{}", c)),
+                _ => write!(f, "[{}]", items
+                    .iter()
+                    .map(|x| format!("{}", x))
+                    .collect::<Vec<_>>()
+                    .join(", ")),
+            },
+            Value::Func(addr) => write!(f, "Function(addr = 0x{:03X})", addr),
+            Value::Sum(variant, inner) => write!(f, "#{} {}", variant, inner),
+            Value::Universe(x) => write!(f, "Universe({})", x),
+            Value::Effect(addr) => write!(f, "Effect(addr = 0x{:03X})", addr),
+        }
+    }
+}
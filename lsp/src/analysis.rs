@@ -0,0 +1,412 @@
+use super::*;
+use tao_analysis::{hir, Context, TyExpr, TyBinding, TyId, Effect, EffectId, Namespace, ClassItem, TypeItem, EffectItem};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Something in a document that the cursor can be placed over.
+struct Node {
+    span: Span,
+    /// The type of the node, if it is an expression or binding.
+    ty: Option<TyId>,
+    /// The span of the item that the node refers to, if any.
+    target: Option<Span>,
+}
+
+/// A local binding, along with the span of the code in which it is in scope.
+struct Local {
+    name: Ident,
+    ty: TyId,
+    scope: Span,
+}
+
+/// The result of analysing a document and the modules that it imports.
+pub struct Analysis {
+    pub src: SrcId,
+    /// The source of the document and of every module that it imports.
+    pub srcs: HashMap<SrcId, String>,
+    pub diagnostics: Vec<Diagnostic>,
+    ctx: Option<Context>,
+    nodes: Vec<Node>,
+    locals: Vec<Local>,
+}
+
+impl Analysis {
+    /// Analyse a document. Imported modules are loaded with `get_file`.
+    pub fn new(src: SrcId, code: String, mut get_file: impl FnMut(SrcId) -> Option<String>) -> Self {
+        let mut this = Self {
+            src,
+            srcs: HashMap::new(),
+            diagnostics: Vec::new(),
+            ctx: None,
+            nodes: Vec::new(),
+            locals: Vec::new(),
+        };
+
+        let (ast, syntax_errors) = parse_module(&code, src);
+        this.srcs.insert(src, code);
+        this.diagnostics.extend(syntax_errors.into_iter().map(|e| e.diagnostic()));
+
+        let Some(ast) = ast else { return this };
+
+        let mut imports = Vec::new();
+        this.load_imports(&ast, &mut imports, &mut get_file);
+
+        // The analyser is not yet robust against every malformed program, and a panic should not take the server down
+        // with it
+        let ctx = match panic::catch_unwind(AssertUnwindSafe(|| {
+            let (ctx, errors) = Context::from_module(&ast, &imports);
+            let errors = errors
+                .into_iter()
+                .map(|e| e.diagnostic(&ctx))
+                .collect::<Vec<_>>();
+            (ctx, errors)
+        })) {
+            Ok((ctx, errors)) => {
+                this.diagnostics.extend(errors);
+                ctx
+            },
+            Err(_) => {
+                this.diagnostics.push(Diagnostic {
+                    msg: "The compiler crashed while analysing this module".to_string(),
+                    labels: vec![(ast.span(), "While analysing this module".to_string())],
+                    notes: Vec::new(),
+                });
+                return this;
+            },
+        };
+
+        for (_, def) in ctx.defs.iter() {
+            if def.name.span().src() != src {
+                continue;
+            }
+            if let Some(body) = &def.body {
+                this.nodes.push(Node { span: def.name.span(), ty: Some(body.meta().1), target: None });
+                this.visit_expr(&ctx, body);
+            }
+        }
+        this.ctx = Some(ctx);
+
+        this
+    }
+
+    // Load every module that the document depends on, directly or indirectly
+    fn load_imports(
+        &mut self,
+        module: &ast::Module,
+        modules: &mut Vec<SrcNode<ast::Module>>,
+        get_file: &mut impl FnMut(SrcId) -> Option<String>,
+    ) {
+        for import in &module.imports {
+            let src_id = SrcId::from_path(import.path.as_str());
+            if self.srcs.contains_key(&src_id) {
+                continue;
+            }
+            match get_file(src_id) {
+                Some(src) => {
+                    let (ast, syntax_errors) = parse_module(&src, src_id);
+                    self.srcs.insert(src_id, src);
+                    self.diagnostics.extend(syntax_errors.into_iter().map(|e| e.diagnostic()));
+
+                    if let Some(ast) = ast {
+                        self.load_imports(&ast, modules, get_file);
+                        modules.push(ast);
+                    }
+                },
+                None => self.diagnostics.push(Diagnostic {
+                    msg: format!("Cannot import module {}", *import.path),
+                    labels: vec![(import.path.span(), "Module could not be found".to_string())],
+                    notes: Vec::new(),
+                }),
+            }
+        }
+    }
+
+    fn visit_binding(&mut self, ctx: &Context, binding: &TyBinding, scope: Span) {
+        let (span, ty) = *binding.meta();
+        self.nodes.push(Node { span, ty: Some(ty), target: None });
+        if let Some(name) = &binding.name {
+            self.locals.push(Local { name: **name, ty, scope });
+        }
+        match &*binding.pat {
+            hir::Pat::Error | hir::Pat::Wildcard | hir::Pat::Literal(_) => {},
+            hir::Pat::Single(inner) | hir::Pat::Add(inner, _) => self.visit_binding(ctx, inner, scope),
+            hir::Pat::Tuple(items) | hir::Pat::ListExact(items) => items
+                .iter()
+                .for_each(|item| self.visit_binding(ctx, item, scope)),
+            hir::Pat::Record(fields) => fields
+                .values()
+                .for_each(|field| self.visit_binding(ctx, field, scope)),
+            hir::Pat::ListFront(items, tail) => {
+                items
+                    .iter()
+                    .for_each(|item| self.visit_binding(ctx, item, scope));
+                if let Some(tail) = tail { self.visit_binding(ctx, tail, scope); }
+            },
+            hir::Pat::Decons(data, cons, inner) => {
+                self.nodes.push(Node { span: data.span(), ty: None, target: cons_span(ctx, **data, *cons) });
+                self.visit_binding(ctx, inner, scope);
+            },
+        }
+    }
+
+    fn visit_expr(&mut self, ctx: &Context, expr: &TyExpr) {
+        let (span, ty) = *expr.meta();
+        let target = match &**expr {
+            hir::Expr::Global((def, _)) => Some(ctx.defs.get(*def).name.span()),
+            hir::Expr::ClassAccess(_, Some((class, _, _)), field) => ctx.classes
+                .get(*class)
+                .fields
+                .iter()
+                .flatten()
+                .find_map(|item| match item {
+                    ClassItem::Value { name, .. } if **name == **field => Some(name.span()),
+                    _ => None,
+                }),
+            hir::Expr::Suspend(eff, _) => effect_span(ctx, *eff),
+            _ => None,
+        };
+        self.nodes.push(Node { span, ty: Some(ty), target });
+
+        match &**expr {
+            hir::Expr::Error
+            | hir::Expr::Literal(_)
+            | hir::Expr::Local(_)
+            | hir::Expr::Global(_)
+            | hir::Expr::ClassAccess(_, _, _) => {},
+            hir::Expr::Tuple(items) => items
+                .iter()
+                .for_each(|item| self.visit_expr(ctx, item)),
+            hir::Expr::List(items, tails) => items
+                .iter()
+                .chain(tails)
+                .for_each(|item| self.visit_expr(ctx, item)),
+            hir::Expr::Record(fields) => fields
+                .iter()
+                .for_each(|(_, field)| self.visit_expr(ctx, field)),
+            hir::Expr::Access(record, _) => self.visit_expr(ctx, record),
+            hir::Expr::Binary(_, x, y) | hir::Expr::Apply(x, y) => {
+                self.visit_expr(ctx, x);
+                self.visit_expr(ctx, y);
+            },
+            hir::Expr::Match(_, pred, arms) => {
+                self.visit_expr(ctx, pred);
+                for (binding, body) in arms {
+                    self.visit_binding(ctx, binding, body.meta().0);
+                    self.visit_expr(ctx, body);
+                }
+            },
+            hir::Expr::Func(arg, body) => {
+                let (arg_span, arg_ty) = *arg.meta();
+                self.nodes.push(Node { span: arg_span, ty: Some(arg_ty), target: None });
+                self.locals.push(Local { name: **arg, ty: arg_ty, scope: body.meta().0 });
+                self.visit_expr(ctx, body);
+            },
+            hir::Expr::Cons(data, cons, inner) => {
+                self.nodes.push(Node { span: data.span(), ty: None, target: cons_span(ctx, **data, *cons) });
+                self.visit_expr(ctx, inner);
+            },
+            hir::Expr::Intrinsic(_, args) => args
+                .iter()
+                .for_each(|arg| self.visit_expr(ctx, arg)),
+            hir::Expr::Update(record, fields) => {
+                self.visit_expr(ctx, record);
+                fields
+                    .iter()
+                    .for_each(|(_, field)| self.visit_expr(ctx, field));
+            },
+            hir::Expr::Basin(_, inner) | hir::Expr::Suspend(_, inner) => self.visit_expr(ctx, inner),
+            hir::Expr::Handle { expr, eff, send, recv, .. } => {
+                self.visit_expr(ctx, expr);
+                let (send_span, send_ty) = *send.meta();
+                self.nodes.push(Node { span: send_span, ty: Some(send_ty), target: effect_span(ctx, *eff) });
+                self.locals.push(Local { name: **send, ty: send_ty, scope: recv.meta().0 });
+                self.visit_expr(ctx, recv);
+            },
+        }
+    }
+
+    // The innermost node at the given offset that satisfies the predicate
+    fn node_at(&self, offset: usize, f: impl Fn(&Node) -> bool) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .filter(|node| node.span.src() == self.src && node.span.range().contains(&offset) && f(node))
+            .min_by_key(|node| node.span.range().len())
+    }
+
+    /// The type of the innermost expression or binding at the given offset, and the span of that expression.
+    pub fn hover(&self, offset: usize) -> Option<(String, Span)> {
+        let ctx = self.ctx.as_ref()?;
+        let node = self.node_at(offset, |node| node.ty.is_some())?;
+        Some((ctx.tys.display(&ctx.datas, &ctx.effects, node.ty?).to_string(), node.span))
+    }
+
+    /// The span at which the item referred to at the given offset is defined.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let ctx = self.ctx.as_ref()?;
+        if let Some(target) = self.node_at(offset, |node| node.target.is_some()).and_then(|node| node.target) {
+            return Some(target);
+        }
+
+        // Items that are not referred to by expressions (such as types in type hints) are found by name instead
+        let name = Ident::new(word_at(&self.srcs[&self.src], offset)?.to_string());
+        let module = ctx.modules.get(ctx.modules.lookup_src(self.src)?);
+        [&module.items, &module.imports]
+            .into_iter()
+            .find_map(|ns| lookup_name(ctx, ns, name))
+    }
+
+    /// Every name that is in scope at the given offset, along with what kind of item it refers to and its type.
+    pub fn completions(&self, offset: usize) -> Vec<(Ident, CompletionItemKind, Option<String>)> {
+        let Some(ctx) = &self.ctx else { return Vec::new() };
+        let display = |ty| Some(ctx.tys.display(&ctx.datas, &ctx.effects, ty).to_string());
+
+        let mut names = self.locals
+            .iter()
+            .filter(|local| local.scope.src() == self.src && local.scope.range().contains(&offset))
+            .map(|local| (local.name, CompletionItemKind::VARIABLE, display(local.ty)))
+            .collect::<Vec<_>>();
+
+        if let Some(module) = ctx.modules.lookup_src(self.src) {
+            let module = ctx.modules.get(module);
+            for ns in [&module.items, &module.imports] {
+                names.extend(ns.defs.iter().map(|(name, (_, def))| {
+                    let ty = ctx.defs.get(*def).body.as_ref().and_then(|body| display(body.meta().1));
+                    (*name, CompletionItemKind::FUNCTION, ty)
+                }));
+                names.extend(ns.types.keys().map(|name| (*name, CompletionItemKind::STRUCT, None)));
+                names.extend(ns.cons.keys().map(|name| (*name, CompletionItemKind::ENUM_MEMBER, None)));
+                names.extend(ns.classes.keys().map(|name| (*name, CompletionItemKind::INTERFACE, None)));
+                names.extend(ns.effects.keys().map(|name| (*name, CompletionItemKind::EVENT, None)));
+                names.extend(ns.modules.keys().map(|name| (*name, CompletionItemKind::MODULE, None)));
+            }
+        }
+
+        // Inner locals shadow outer locals, and locals and module items shadow imports
+        let mut seen = HashSet::new();
+        names.retain(|(name, _, _)| seen.insert(*name));
+        names
+    }
+}
+
+fn cons_span(ctx: &Context, data: tao_analysis::DataId, cons: Ident) -> Option<Span> {
+    ctx.datas
+        .get_data(data)
+        .cons
+        .iter()
+        .find(|(name, _)| **name == cons)
+        .map(|(name, _)| name.span())
+}
+
+fn effect_span(ctx: &Context, eff: EffectId) -> Option<Span> {
+    match ctx.tys.get_effect(eff) {
+        // Suspended and handled effects are always single effects
+        Effect::Known(effs) => effs.first().map(|(decl, _)| ctx.effects.get_decl(*decl).name.span()),
+        Effect::Error => None,
+    }
+}
+
+// Find where the item with the given name in the namespace is defined
+fn lookup_name(ctx: &Context, ns: &Namespace, name: Ident) -> Option<Span> {
+    ns.defs.get(&name).map(|(_, def)| ctx.defs.get(*def).name.span())
+        .or_else(|| ns.types.get(&name).map(|(_, ty)| match ty {
+            TypeItem::Data(data) => ctx.datas.get_data(*data).name.span(),
+            TypeItem::Alias(alias) => ctx.datas.get_alias_span(*alias),
+        }))
+        .or_else(|| ns.cons.get(&name).and_then(|(_, (data, cons))| cons_span(ctx, *data, *cons)))
+        .or_else(|| ns.classes.get(&name).map(|(_, class)| ctx.classes.get(*class).name.span()))
+        .or_else(|| ns.effects.get(&name).map(|(_, eff)| match eff {
+            EffectItem::Decl(decl) => ctx.effects.get_decl(*decl).name.span(),
+            EffectItem::Alias(alias) => ctx.effects.get_alias(*alias).name.span(),
+        }))
+}
+
+// The identifier at the given character offset, if any
+fn word_at(src: &str, offset: usize) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let chars = src.char_indices().collect::<Vec<_>>();
+    if !chars.get(offset).is_some_and(|(_, c)| is_ident(*c)) {
+        return None;
+    }
+    let start = (0..offset).rev().take_while(|i| is_ident(chars[*i].1)).last().unwrap_or(offset);
+    let end = (offset..chars.len()).find(|i| !is_ident(chars[*i].1)).unwrap_or(chars.len());
+    Some(&src[chars[start].0..chars.get(end).map_or(src.len(), |(i, _)| *i)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, ops::Range, path::Path};
+
+    const CODE: &str = "import \"lib/std.tao\"
+
+data Box = Nat
+
+def five : Nat = 5
+
+fn double : Nat -> Nat = k => k * 2
+
+def boxed : Box = Box(double(five))
+";
+
+    // The standard library is imported relative to the root of the repository
+    fn analyse() -> Analysis {
+        let analysis = Analysis::new(SrcId::from_path("main.tao"), CODE.to_string(), |src| {
+            fs::read_to_string(Path::new("..").join(src.to_path())).ok()
+        });
+        assert!(analysis.diagnostics.is_empty(), "{:?}", analysis.diagnostics.iter().map(|d| &d.msg).collect::<Vec<_>>());
+        analysis
+    }
+
+    // The range of the `n`th occurrence of some code in the module
+    fn find(code: &str, n: usize) -> Range<usize> {
+        let start = CODE.match_indices(code).nth(n).unwrap().0;
+        start..start + code.len()
+    }
+
+    #[test]
+    fn hover() {
+        let analysis = analyse();
+        let hover = |code, n| analysis.hover(find(code, n).start).map(|(ty, span)| (ty, span.range()));
+
+        assert_eq!(hover("five)", 0), Some(("Nat".to_string(), find("five", 1))));
+        assert_eq!(hover("k *", 0), Some(("Nat".to_string(), find("k", 1))));
+        assert_eq!(hover("double", 0), Some(("Nat -> Nat".to_string(), find("double", 0))));
+        assert_eq!(hover("boxed", 0), Some(("Box".to_string(), find("boxed", 0))));
+        assert_eq!(hover("data", 0), None);
+    }
+
+    #[test]
+    fn definition() {
+        let analysis = analyse();
+        let definition = |code, n| analysis.definition(find(code, n).start).map(|span| span.range());
+
+        assert_eq!(definition("five)", 0), Some(find("five", 0)));
+        assert_eq!(definition("double(", 0), Some(find("double", 0)));
+        // Types in type hints and constructors both lead to the data type
+        assert_eq!(definition("Box =", 1), Some(find("Box", 0)));
+        assert_eq!(definition("Box(", 0), Some(find("Box", 0)));
+        assert_eq!(definition("5", 0), None);
+    }
+
+    #[test]
+    fn completion() {
+        let analysis = analyse();
+        let completions = |offset| analysis
+            .completions(offset)
+            .into_iter()
+            .map(|(name, kind, ty)| (name.to_string(), (kind, ty)))
+            .collect::<HashMap<_, _>>();
+
+        let in_body = completions(find("k *", 0).start);
+        assert_eq!(in_body.get("k"), Some(&(CompletionItemKind::VARIABLE, Some("Nat".to_string()))));
+        assert_eq!(in_body.get("five"), Some(&(CompletionItemKind::FUNCTION, Some("Nat".to_string()))));
+        assert_eq!(in_body.get("double"), Some(&(CompletionItemKind::FUNCTION, Some("Nat -> Nat".to_string()))));
+        assert_eq!(in_body.get("Box").map(|(kind, _)| *kind), Some(CompletionItemKind::STRUCT));
+        // Items from the standard library are in scope too
+        assert!(in_body.contains_key("Maybe"));
+
+        // Locals are only in scope within their bodies
+        let outside = completions(find("boxed", 0).start);
+        assert!(!outside.contains_key("k"));
+        assert!(outside.contains_key("double"));
+    }
+}
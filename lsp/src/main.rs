@@ -0,0 +1,321 @@
+//! A language server for Tao.
+//!
+//! The server speaks the Language Server Protocol over stdio. Documents are reanalysed in full whenever they change, and
+//! the results of the latest analysis are used to answer hover, go-to-definition, and completion requests.
+
+mod analysis;
+
+use crate::analysis::Analysis;
+use tao_syntax::{parse_module, ast, SrcId, SrcNode, Span, Diagnostic};
+use tao_analysis::Ident;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{self, Notification as _},
+    request::{self, Request as _},
+    CompletionItem,
+    CompletionItemKind,
+    CompletionOptions,
+    DiagnosticRelatedInformation,
+    DiagnosticSeverity,
+    Hover,
+    HoverContents,
+    HoverProviderCapability,
+    InitializeParams,
+    Location,
+    MarkupContent,
+    MarkupKind,
+    OneOf,
+    Position,
+    PublishDiagnosticsParams,
+    Range,
+    ServerCapabilities,
+    TextDocumentSyncCapability,
+    TextDocumentSyncKind,
+    Url,
+};
+use serde::de::DeserializeOwned;
+use hashbrown::{HashMap, HashSet};
+use std::{
+    error::Error,
+    fs,
+    path::PathBuf,
+};
+
+struct Server {
+    connection: Connection,
+    /// The directory that import paths are relative to.
+    root: PathBuf,
+    /// The latest text of each open document, which takes priority over what is on disk.
+    docs: HashMap<SrcId, String>,
+    analyses: HashMap<SrcId, Analysis>,
+}
+
+impl Server {
+    fn src_id(&self, uri: &Url) -> SrcId {
+        let path = uri.to_file_path().unwrap_or_else(|_| PathBuf::from(uri.path()));
+        SrcId::from_path(path.strip_prefix(&self.root).unwrap_or(&path))
+    }
+
+    fn uri(&self, src: SrcId) -> Option<Url> {
+        Url::from_file_path(self.root.join(src.to_path())).ok()
+    }
+
+    fn analyse(&mut self, src: SrcId) -> Result<(), Box<dyn Error>> {
+        let Some(code) = self.docs.get(&src).cloned() else { return Ok(()) };
+        let (docs, root) = (&self.docs, &self.root);
+        let analysis = Analysis::new(src, code, |src| docs
+            .get(&src)
+            .cloned()
+            .or_else(|| fs::read_to_string(root.join(src.to_path())).ok()));
+
+        // Only diagnostics within the document are published for it, but they may refer to other modules
+        let diagnostics = analysis.diagnostics
+            .iter()
+            .filter_map(|diag| self.to_lsp_diagnostic(&analysis, diag))
+            .collect();
+        if let Some(uri) = self.uri(src) {
+            self.notify::<notification::PublishDiagnostics>(PublishDiagnosticsParams::new(uri, diagnostics, None))?;
+        }
+
+        self.analyses.insert(src, analysis);
+        Ok(())
+    }
+
+    fn to_lsp_diagnostic(&self, analysis: &Analysis, diag: &Diagnostic) -> Option<lsp_types::Diagnostic> {
+        let (span, label) = diag.labels.iter().find(|(span, _)| span.src() == analysis.src)?;
+        let related = diag.labels
+            .iter()
+            .filter(|(other, _)| other != span)
+            .filter_map(|(span, msg)| Some(DiagnosticRelatedInformation {
+                location: Location::new(self.uri(span.src())?, to_range(analysis.srcs.get(&span.src())?, *span)),
+                message: msg.clone(),
+            }))
+            .collect();
+        let msg = std::iter::once(diag.msg.clone())
+            .chain((label != &diag.msg).then(|| label.clone()))
+            .chain(diag.notes.iter().map(|note| format!("Note: {}", note)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(lsp_types::Diagnostic::new(
+            to_range(&analysis.srcs[&analysis.src], *span),
+            Some(DiagnosticSeverity::ERROR),
+            None,
+            Some("tao".to_string()),
+            msg,
+            Some(related),
+            None,
+        ))
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) -> Result<(), Box<dyn Error>> {
+        self.connection.sender.send(Message::Notification(Notification::new(N::METHOD.to_string(), params)))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<(), Box<dyn Error>> {
+        match not.method.as_str() {
+            notification::DidOpenTextDocument::METHOD => {
+                let params = parse_params::<lsp_types::DidOpenTextDocumentParams>(not.params)?;
+                let src = self.src_id(&params.text_document.uri);
+                self.docs.insert(src, params.text_document.text);
+                self.reanalyse(src)
+            },
+            notification::DidChangeTextDocument::METHOD => {
+                let params = parse_params::<lsp_types::DidChangeTextDocumentParams>(not.params)?;
+                let src = self.src_id(&params.text_document.uri);
+                // Documents are synchronised in full, so the last change contains the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.docs.insert(src, change.text);
+                }
+                self.reanalyse(src)
+            },
+            notification::DidCloseTextDocument::METHOD => {
+                let params = parse_params::<lsp_types::DidCloseTextDocumentParams>(not.params)?;
+                let src = self.src_id(&params.text_document.uri);
+                self.docs.remove(&src);
+                self.analyses.remove(&src);
+                self.notify::<notification::PublishDiagnostics>(PublishDiagnosticsParams::new(params.text_document.uri, Vec::new(), None))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // Analyse a document that has changed, along with any open documents that depend on it
+    fn reanalyse(&mut self, changed: SrcId) -> Result<(), Box<dyn Error>> {
+        let dependents = self.analyses
+            .iter()
+            .filter(|(src, analysis)| **src != changed && analysis.srcs.contains_key(&changed))
+            .map(|(src, _)| *src)
+            .collect::<HashSet<_>>();
+        self.analyse(changed)?;
+        for src in dependents {
+            self.analyse(src)?;
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, req: Request) -> Result<(), Box<dyn Error>> {
+        let response = match self.answer(&req.method, req.params) {
+            Ok(Some(result)) => Response::new_ok(req.id, result),
+            Ok(None) => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request {}", req.method),
+            ),
+            // A request that cannot be answered (usually because its parameters are malformed) is refused, rather than
+            // bringing down the server
+            Err(err) => {
+                eprintln!("Failed to handle request {}: {}", req.method, err);
+                Response::new_err(req.id, ErrorCode::InvalidParams as i32, err.to_string())
+            },
+        };
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    // Produce the result of a request, or nothing if the request is not supported
+    fn answer(&self, method: &str, params: serde_json::Value) -> Result<Option<serde_json::Value>, Box<dyn Error>> {
+        let result = match method {
+            request::HoverRequest::METHOD => {
+                let params = parse_params::<lsp_types::HoverParams>(params)?.text_document_position_params;
+                serde_json::to_value(self.at_position(&params.text_document.uri, params.position, |analysis, src, offset| {
+                    let (ty, span) = analysis.hover(offset)?;
+                    Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: format!("```\n{}\n```", ty),
+                        }),
+                        range: Some(to_range(src, span)),
+                    })
+                }))?
+            },
+            request::GotoDefinition::METHOD => {
+                let params = parse_params::<lsp_types::GotoDefinitionParams>(params)?.text_document_position_params;
+                serde_json::to_value(self.at_position(&params.text_document.uri, params.position, |analysis, _, offset| {
+                    let span = analysis.definition(offset)?;
+                    let range = to_range(analysis.srcs.get(&span.src())?, span);
+                    Some(lsp_types::GotoDefinitionResponse::Scalar(Location::new(self.uri(span.src())?, range)))
+                }))?
+            },
+            request::Completion::METHOD => {
+                let params = parse_params::<lsp_types::CompletionParams>(params)?.text_document_position;
+                serde_json::to_value(self.at_position(&params.text_document.uri, params.position, |analysis, _, offset| {
+                    let items = analysis
+                        .completions(offset)
+                        .into_iter()
+                        .map(|(name, kind, ty)| CompletionItem {
+                            label: name.to_string(),
+                            kind: Some(kind),
+                            detail: ty,
+                            ..CompletionItem::default()
+                        })
+                        .collect::<Vec<_>>();
+                    Some(lsp_types::CompletionResponse::Array(items))
+                }))?
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    // Answer a request about a position in a document using the latest analysis of that document
+    fn at_position<R>(&self, uri: &Url, pos: Position, f: impl FnOnce(&Analysis, &str, usize) -> Option<R>) -> Option<R> {
+        let analysis = self.analyses.get(&self.src_id(uri))?;
+        let src = &analysis.srcs[&analysis.src];
+        f(analysis, src, to_offset(src, pos))
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Ok(msg) = self.connection.receiver.recv() {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.handle_request(req)?;
+                },
+                // Notifications have no response, so failures to handle them can only be logged
+                Message::Notification(not) => {
+                    let method = not.method.clone();
+                    if let Err(err) = self.handle_notification(not) {
+                        eprintln!("Failed to handle notification {}: {}", method, err);
+                    }
+                },
+                Message::Response(_) => {},
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_params<P: DeserializeOwned>(params: serde_json::Value) -> Result<P, Box<dyn Error>> {
+    Ok(serde_json::from_value(params)?)
+}
+
+// Spans are measured in characters, but LSP positions are measured in lines and UTF-16 code units within those lines
+
+fn to_position(src: &str, offset: usize) -> Position {
+    let (mut line, mut col) = (0, 0);
+    for c in src.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            col = 0;
+        } else {
+            col += c.len_utf16() as u32;
+        }
+    }
+    Position::new(line, col)
+}
+
+fn to_range(src: &str, span: Span) -> Range {
+    Range::new(to_position(src, span.range().start), to_position(src, span.range().end))
+}
+
+fn to_offset(src: &str, pos: Position) -> usize {
+    let (mut line, mut col) = (0, 0);
+    for (i, c) in src.chars().enumerate() {
+        if line == pos.line && (col >= pos.character || c == '\n') {
+            return i;
+        }
+        if c == '\n' {
+            line += 1;
+            col = 0;
+        } else {
+            col += c.len_utf16() as u32;
+        }
+    }
+    src.chars().count()
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Diagnostics are shown by the editor, so the terminal colours that ariadne would use must not end up in them
+    yansi::Paint::disable();
+
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..ServerCapabilities::default()
+    })?;
+    let params = serde_json::from_value::<InitializeParams>(connection.initialize(capabilities)?)?;
+
+    // Imports are relative to the root of the workspace, just as they are relative to the working directory of the
+    // compiler
+    let root = params.root_uri
+        .and_then(|uri| uri.to_file_path().ok())
+        .map_or_else(std::env::current_dir, Ok)?;
+
+    Server {
+        connection,
+        root,
+        docs: HashMap::new(),
+        analyses: HashMap::new(),
+    }.run()?;
+
+    io_threads.join()?;
+    Ok(())
+}
@@ -6,6 +6,8 @@ pub struct Context {
     pub reprs: Reprs,
     pub procs: Procs,
     pub entry: Option<ProcId>,
+    /// The names of the effects that the program suspends or handles, so that backends can let the host handle them.
+    pub effect_names: HashMap<EffectId, Ident>,
 }
 
 impl Context {
@@ -14,6 +16,7 @@ impl Context {
             reprs: Reprs::default(),
             procs: Procs::default(),
             entry: None,
+            effect_names: HashMap::default(),
         };
 
         this.entry = Some(this.lower_proc(hir, con, con.entry_proc()));
@@ -23,6 +26,7 @@ impl Context {
 
     pub fn optimize(&mut self, opt_mode: OptMode) {
         if matches!(opt_mode, OptMode::None) {
+            opt::mark_tail_calls(self);
             return;
         }
 
@@ -39,15 +43,36 @@ impl Context {
             println!("\n======\n");
         }
 
+        opt::ChangeRecursiveRepr::default().run(self, debug);
+
         for _ in 0..3 {
-            opt::FlattenSingleField::default().run(self, debug);
             opt::ConstFold {
                 inline: !matches!(opt_mode, OptMode::Size),
             }
                 .run(self, debug);
+            opt::Unbox::default().run(self, debug);
             opt::RemoveUnusedBindings::default().run(self, debug);
             opt::RemoveDeadProc::default().run(self, debug);
         }
+
+        opt::mark_tail_calls(self);
+    }
+
+    /// Whether the entry point is an IO function of type `Universe -> (Universe, ())` that needs to be applied to the
+    /// initial universe to run the program.
+    pub fn entry_does_io(&self) -> bool {
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Repr::Func(i, o) = self.procs.get(entry).unwrap().body.meta() {
+            if let (Repr::Prim(Prim::Universe), Repr::Tuple(xs)) = (&**i, &**o) {
+                if let [Repr::Prim(Prim::Universe), Repr::Tuple(xs)] = &xs[..] {
+                    return xs.len() == 0;
+                }
+            }
+        }
+        false
     }
 
     fn reachable_procs_from(&self, proc: ProcId, globals: &mut BTreeSet<ProcId>) {
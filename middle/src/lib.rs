@@ -17,11 +17,10 @@ pub use crate::{
     context::{Context, OptMode},
 };
 pub use tao_analysis::Ident;
+pub use tao_syntax::{Span, SrcId};
 
 use tao_syntax::{
     Node,
-    Span,
-    SrcId,
     SrcNode,
     ast,
 };
@@ -13,8 +13,19 @@ impl Context {
         // Instantiate proc if not already done
         if !self.procs.is_declared(id) {
             self.procs.declare(id);
+            let span = match &*proc {
+                ConProc::Def(def, _) => hir.defs.get(*def).body.as_ref().map(|body| body.meta().0),
+                ConProc::Field(_, _, _, member, field) => hir.classes
+                    .get_member(*member)
+                    .fields
+                    .as_ref()
+                    .and_then(|fields| fields.get(field))
+                    .map(|field| field.meta().0),
+                ConProc::RunIo(def) => hir.defs.get(*def).body.as_ref().map(|body| body.meta().0),
+            };
             let proc = Proc {
                 body: self.lower_expr(hir, con, con.get_proc(proc), &mut Vec::new()),
+                span,
             };
             self.procs.define(id, proc);
         }
@@ -90,7 +101,8 @@ impl Context {
                 fields.sort_by_key(|(name, _)| name.as_ref());
                 Repr::Tuple(fields.into_iter().map(|(_, ty)| ty).collect())
             },
-            ConTy::Effect(eff, out) => Repr::Effect(*eff, Box::new(self.lower_ty(hir, con, *out))),
+            ConTy::Effect(effs, out) => Repr::Effect(effs.clone(), Box::new(self.lower_ty(hir, con, *out))),
+            ConTy::DataCon(_, _) | ConTy::ListCon => unreachable!("Type constructors have no values"),
         }
     }
 
@@ -154,6 +166,77 @@ impl Context {
         MirNode::new(binding, self.lower_ty(hir, con, *con_binding.meta()))
     }
 
+    // Integer division and remainder produce a `Maybe`, which is `None` when the divisor is zero
+    fn lower_int_division(
+        &mut self,
+        hir: &HirContext,
+        con: &ConContext,
+        op: ast::BinaryOp,
+        x: &ConExpr,
+        y: &ConExpr,
+        output: ConTyId,
+        stack: &mut Vec<(Ident, Local)>,
+    ) -> mir::Expr {
+        let maybe = match con.get_ty(output) {
+            ConTy::Data(maybe) => *maybe,
+            _ => unreachable!(),
+        };
+        let (just, none) = hir.datas
+            .maybe_cons(&hir.tys)
+            .expect("Shape of maybe lang item should have been checked during analysis");
+
+        let (intrinsic, zero) = match (op, con.get_ty(*x.meta())) {
+            (ast::BinaryOp::Div, ConTy::Prim(ty::Prim::Nat)) => (mir::Intrinsic::DivNat, mir::Literal::Nat(0)),
+            (ast::BinaryOp::Div, ConTy::Prim(ty::Prim::Int)) => (mir::Intrinsic::DivInt, mir::Literal::Int(0)),
+            (ast::BinaryOp::Rem, ConTy::Prim(ty::Prim::Nat)) => (mir::Intrinsic::RemNat, mir::Literal::Nat(0)),
+            (ast::BinaryOp::Rem, ConTy::Prim(ty::Prim::Int)) => (mir::Intrinsic::RemInt, mir::Literal::Int(0)),
+            op => panic!("Invalid division in HIR: {:?}", op),
+        };
+
+        self.lower_data(hir, con, maybe);
+        let sum_repr = self.reprs.get(maybe).repr.clone();
+        let maybe_repr = self.lower_ty(hir, con, output);
+        let num_repr = self.lower_ty(hir, con, *x.meta());
+        let operands_repr = Repr::Tuple(vec![num_repr.clone(), num_repr.clone()]);
+        let operands = MirNode::new(mir::Expr::Tuple(vec![
+            self.lower_expr(hir, con, x, stack),
+            self.lower_expr(hir, con, y, stack),
+        ]), operands_repr.clone());
+
+        let none_expr = MirNode::new(mir::Expr::Data(maybe, MirNode::new(mir::Expr::Variant(
+            none,
+            MirNode::new(mir::Expr::Literal(mir::Literal::Tuple(Vec::new())), Repr::Tuple(Vec::new())),
+        ), sum_repr.clone())), maybe_repr.clone());
+        let by_zero = mir::Binding {
+            pat: mir::Pat::Tuple(vec![
+                MirNode::new(mir::Binding::wildcard(None), num_repr.clone()),
+                MirNode::new(mir::Binding { pat: mir::Pat::Literal(zero), name: None }, num_repr.clone()),
+            ]),
+            name: None,
+        };
+
+        let (x, y) = (Local::new(), Local::new());
+        let just_expr = MirNode::new(mir::Expr::Data(maybe, MirNode::new(mir::Expr::Variant(
+            just,
+            MirNode::new(mir::Expr::Intrinsic(intrinsic, vec![
+                MirNode::new(mir::Expr::Local(x), num_repr.clone()),
+                MirNode::new(mir::Expr::Local(y), num_repr.clone()),
+            ]), num_repr.clone()),
+        ), sum_repr)), maybe_repr);
+        let otherwise = mir::Binding {
+            pat: mir::Pat::Tuple(vec![
+                MirNode::new(mir::Binding::wildcard(x), num_repr.clone()),
+                MirNode::new(mir::Binding::wildcard(y), num_repr),
+            ]),
+            name: None,
+        };
+
+        mir::Expr::Match(operands, vec![
+            (MirNode::new(by_zero, operands_repr.clone()), none_expr),
+            (MirNode::new(otherwise, operands_repr), just_expr),
+        ])
+    }
+
     pub fn lower_expr(&mut self, hir: &HirContext, con: &ConContext, con_expr: &ConExpr, stack: &mut Vec<(Ident, Local)>) -> mir::MirNode<mir::Expr> {
         let expr = match &**con_expr {
             hir::Expr::Error => unreachable!(),
@@ -165,6 +248,8 @@ impl Context {
                 .expect("No such local")
                 .1),
             hir::Expr::Global(proc) => mir::Expr::Global(self.lower_proc(hir, con, *proc), Default::default()),
+            hir::Expr::Binary(op, x, y) if matches!(**op, ast::BinaryOp::Div | ast::BinaryOp::Rem)
+                && matches!(con.get_ty(*con_expr.meta()), ConTy::Data(_)) => self.lower_int_division(hir, con, **op, x, y, *con_expr.meta(), stack),
             hir::Expr::Binary(op, x, y) => {
                 use ast::BinaryOp::*;
                 use ty::Prim::*;
@@ -176,6 +261,16 @@ impl Context {
                     (Sub, Prim(Int), Prim(Int)) => mir::Intrinsic::SubInt,
                     (Mul, Prim(Nat), Prim(Nat)) => mir::Intrinsic::MulNat,
                     (Mul, Prim(Int), Prim(Int)) => mir::Intrinsic::MulInt,
+                    (Add, Prim(Real), Prim(Real)) => mir::Intrinsic::AddReal,
+                    (Sub, Prim(Real), Prim(Real)) => mir::Intrinsic::SubReal,
+                    (Mul, Prim(Real), Prim(Real)) => mir::Intrinsic::MulReal,
+                    (Div, Prim(Real), Prim(Real)) => mir::Intrinsic::DivReal,
+                    (Eq, Prim(Real), Prim(Real)) => mir::Intrinsic::EqReal,
+                    (NotEq, Prim(Real), Prim(Real)) => mir::Intrinsic::NotEqReal,
+                    (Less, Prim(Real), Prim(Real)) => mir::Intrinsic::LessReal,
+                    (More, Prim(Real), Prim(Real)) => mir::Intrinsic::MoreReal,
+                    (LessEq, Prim(Real), Prim(Real)) => mir::Intrinsic::LessEqReal,
+                    (MoreEq, Prim(Real), Prim(Real)) => mir::Intrinsic::MoreEqReal,
                     (Eq, Prim(Nat), Prim(Nat)) => mir::Intrinsic::EqNat,
                     (Eq, Prim(Int), Prim(Int)) => mir::Intrinsic::EqInt,
                     (NotEq, Prim(Nat), Prim(Nat)) => mir::Intrinsic::NotEqNat,
@@ -243,7 +338,7 @@ impl Context {
 
                 mir::Expr::Func(MirNode::new(arg_local, self.lower_ty(hir, con, *arg.meta())), body)
             },
-            hir::Expr::Apply(f, arg) => mir::Expr::Apply(self.lower_expr(hir, con, f, stack), self.lower_expr(hir, con, arg, stack)),
+            hir::Expr::Apply(f, arg) => mir::Expr::Apply(self.lower_expr(hir, con, f, stack), self.lower_expr(hir, con, arg, stack), Default::default()),
             hir::Expr::Cons(data, variant, inner) => {
                 let variant = hir.datas
                     .get_data(data.0)
@@ -313,6 +408,15 @@ impl Context {
                         self.lower_expr(hir, con, &args[0], stack),
                         self.lower_expr(hir, con, &args[1], stack),
                     ]),
+                    hir::Intrinsic::EqReal => mir::Expr::Intrinsic(mir::Intrinsic::EqReal, vec![
+                        self.lower_expr(hir, con, &args[0], stack),
+                        self.lower_expr(hir, con, &args[1], stack),
+                    ]),
+                    hir::Intrinsic::NatToReal => mir::Expr::Intrinsic(mir::Intrinsic::NatToReal, vec![self.lower_expr(hir, con, &args[0], stack)]),
+                    hir::Intrinsic::IntToReal => mir::Expr::Intrinsic(mir::Intrinsic::IntToReal, vec![self.lower_expr(hir, con, &args[0], stack)]),
+                    hir::Intrinsic::RealToInt => mir::Expr::Intrinsic(mir::Intrinsic::RealToInt, vec![self.lower_expr(hir, con, &args[0], stack)]),
+                    hir::Intrinsic::RealToNat => mir::Expr::Intrinsic(mir::Intrinsic::RealToNat, vec![self.lower_expr(hir, con, &args[0], stack)]),
+                    hir::Intrinsic::DisplayReal => mir::Expr::Intrinsic(mir::Intrinsic::DisplayReal, vec![self.lower_expr(hir, con, &args[0], stack)]),
                     hir::Intrinsic::Go => {
                         let next_local = Local::new();
                         let func = self.lower_expr(hir, con, &args[0], stack);
@@ -320,7 +424,7 @@ impl Context {
                         let output_repr = if let Repr::Func(_, o) = func.meta() { (**o).clone() } else { unreachable!() };
                         mir::Expr::Go(
                             MirNode::new(next_local, next.meta().clone()),
-                            MirNode::new(mir::Expr::Apply(func, MirNode::new(mir::Expr::Local(next_local), next.meta().clone())), output_repr),
+                            MirNode::new(mir::Expr::Apply(func, MirNode::new(mir::Expr::Local(next_local), next.meta().clone()), Default::default()), output_repr),
                             next,
                         )
                     },
@@ -342,6 +446,9 @@ impl Context {
                         self.lower_expr(hir, con, &args[0], stack),
                         self.lower_expr(hir, con, &args[1], stack),
                     ]),
+                    hir::Intrinsic::Host(name) => mir::Expr::Intrinsic(mir::Intrinsic::Host(*name), vec![
+                        self.lower_expr(hir, con, &args[0], stack),
+                    ]),
                     hir::Intrinsic::Propagate => mir::Expr::Intrinsic(mir::Intrinsic::Propagate, vec![
                         self.lower_expr(hir, con, &args[0], stack),
                     ]),
@@ -391,8 +498,9 @@ impl Context {
 
                 mir_record.into_inner()
             },
-            hir::Expr::Basin(eff, inner) => mir::Expr::Basin(*eff, self.lower_expr(hir, con, inner, stack)),
-            hir::Expr::Handle { expr, eff, send, recv } => {
+            hir::Expr::Basin(effs, inner) => mir::Expr::Basin(effs.clone(), self.lower_expr(hir, con, inner, stack)),
+            hir::Expr::Handle { expr, eff, send, recv, resumable } => {
+                self.effect_names.insert(*eff, *hir.effects.get_decl(eff.0).name);
                 let send_local = Local::new();;
                 mir::Expr::Handle {
                     expr: self.lower_expr(hir, con, expr, stack),
@@ -404,11 +512,15 @@ impl Context {
                         stack.pop();
                         recv
                     },
+                    resumable: *resumable,
                 }
             },
-            hir::Expr::Suspend(eff, inner) => mir::Expr::Intrinsic(mir::Intrinsic::Suspend(*eff), vec![
-                self.lower_expr(hir, con, inner, stack),
-            ]),
+            hir::Expr::Suspend(eff, inner) => {
+                self.effect_names.insert(*eff, *hir.effects.get_decl(eff.0).name);
+                mir::Expr::Intrinsic(mir::Intrinsic::Suspend(*eff), vec![
+                    self.lower_expr(hir, con, inner, stack),
+                ])
+            },
         };
 
         MirNode::new(expr, self.lower_ty(hir, con, *con_expr.meta()))
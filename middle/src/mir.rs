@@ -154,6 +154,17 @@ pub enum Intrinsic {
     SubInt,
     MulNat,
     MulInt,
+    /// Programs only divide by a divisor that they have checked is non-zero (`x / y` produces `None` otherwise), but
+    /// dividing by zero still produces zero so that the intrinsic itself cannot fail.
+    DivNat,
+    DivInt,
+    /// As with division, the remainder of dividing by zero is never observed by programs. It is the dividend.
+    RemNat,
+    RemInt,
+    AddReal,
+    SubReal,
+    MulReal,
+    DivReal,
     EqNat,
     EqInt,
     EqChar,
@@ -168,6 +179,18 @@ pub enum Intrinsic {
     LessEqInt,
     MoreEqNat,
     MoreEqInt,
+    EqReal,
+    NotEqReal,
+    LessReal,
+    MoreReal,
+    LessEqReal,
+    MoreEqReal,
+    /// Conversions from reals round towards zero, saturating at the bounds of the target type (NaN becomes zero).
+    NatToReal,
+    IntToReal,
+    RealToInt,
+    RealToNat,
+    DisplayReal,
     Join(Repr),
     AndBool,
     Print,
@@ -178,6 +201,7 @@ pub enum Intrinsic {
     TrimList,
     Suspend(EffectId),
     Propagate,
+    Host(Ident),
 }
 
 #[derive(Clone, Debug)]
@@ -331,6 +355,14 @@ impl Default for GlobalFlags {
     }
 }
 
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ApplyFlags {
+    /// Determines whether an application is in tail position, meaning that its value is the result of the enclosing
+    /// procedure, function, effect body, or handler. This is only known once optimisation is finished, so it is set by
+    /// [`crate::opt::mark_tail_calls`].
+    pub is_tail: bool,
+}
+
 #[derive(Clone, Debug)]
 pub enum Expr {
     /// SAFETY: this node must never be evaluated at run-time
@@ -346,7 +378,7 @@ pub enum Expr {
     Match(MirNode<Self>, Vec<(MirNode<Binding>, MirNode<Self>)>),
 
     Func(MirNode<Local>, MirNode<Self>),
-    Apply(MirNode<Self>, MirNode<Self>),
+    Apply(MirNode<Self>, MirNode<Self>, Cell<ApplyFlags>),
 
     // Tail recursion
     Go(MirNode<Local>, MirNode<Self>, MirNode<Self>),
@@ -360,12 +392,14 @@ pub enum Expr {
     Data(ConDataId, MirNode<Self>),
     AccessData(MirNode<Self>, ConDataId),
 
-    Basin(EffectId, MirNode<Self>),
+    Basin(Vec<EffectId>, MirNode<Self>),
     Handle {
         expr: MirNode<Self>,
         eff: EffectId,
         send: MirNode<Local>,
         recv: MirNode<Self>,
+        /// Whether the handler is given the resumption of the handled computation along with the value sent.
+        resumable: bool,
     },
 }
 
@@ -384,6 +418,23 @@ impl Expr {
         self.for_children(|expr| expr.required_globals_inner(globals));
     }
 
+    /// Determine whether evaluating this expression might perform an effect, in which case it cannot be removed,
+    /// duplicated, or moved elsewhere without changing the meaning of the program.
+    pub fn may_suspend(&self) -> bool {
+        match self {
+            Expr::Intrinsic(Intrinsic::Suspend(_) | Intrinsic::Propagate, _) => true,
+            // Functions and effect objects are only evaluated when applied or propagated
+            Expr::Func(_, _) | Expr::Basin(_, _) => false,
+            // Effects of the handled expression that are not handled get performed by the handler expression itself
+            Expr::Handle { expr, eff, .. } if matches!(expr.meta(), Repr::Effect(effs, _) if effs.iter().any(|e| e != eff)) => true,
+            _ => {
+                let mut may_suspend = false;
+                self.for_children(|expr| may_suspend |= expr.may_suspend());
+                may_suspend
+            },
+        }
+    }
+
     pub fn refresh_locals(&mut self) {
         let required = self.required_locals(None);
         // debug_assert_eq!(required.len(), 0, "Cannot refresh locals for an expression\n\n{}\n\nthat captures (required = {:?})", self.print(), required);
@@ -424,7 +475,7 @@ impl Expr {
                 stack.pop();
                 **next = new_init;
             },
-            Expr::Handle { expr, eff, send, recv } => {
+            Expr::Handle { expr, eff, send, recv, .. } => {
                 expr.refresh_locals_inner(stack);
 
                 let new_send = Local::new();
@@ -472,7 +523,7 @@ impl Expr {
                 body.required_locals_inner(stack, required);
                 stack.pop();
             },
-            Expr::Apply(f, arg) => {
+            Expr::Apply(f, arg, _) => {
                 f.required_locals_inner(stack, required);
                 arg.required_locals_inner(stack, required);
             },
@@ -488,7 +539,7 @@ impl Expr {
             Expr::Data(_, inner) => inner.required_locals_inner(stack, required),
             Expr::AccessData(inner, _) => inner.required_locals_inner(stack, required),
             Expr::Basin(_, inner) => inner.required_locals_inner(stack, required),
-            Expr::Handle { expr, eff, send, recv } => {
+            Expr::Handle { expr, eff, send, recv, .. } => {
                 expr.required_locals_inner(stack, required);
                 stack.push(**send);
                 recv.required_locals_inner(stack, required);
@@ -550,20 +601,28 @@ impl Expr {
                     Expr::Literal(c) => write!(f, "{}", c),
                     Expr::Func(arg, body) => write!(f, "fn ${} =>\n{}", arg.0, DisplayExpr(body, self.1 + 1, true)),
                     Expr::Go(next, body, init) => write!(f, "go(${} => {}, {})", next.0, DisplayExpr(body, self.1, false), DisplayExpr(init, self.1, false)),
-                    Expr::Apply(func, arg) => write!(f, "({})({})", DisplayExpr(func, self.1, false), DisplayExpr(arg, self.1, false)),
+                    Expr::Apply(func, arg, _) => write!(f, "({})({})", DisplayExpr(func, self.1, false), DisplayExpr(arg, self.1, false)),
                     Expr::Variant(variant, inner) => write!(f, "#{} {}", variant, DisplayExpr(inner, self.1, false)),
                     Expr::Tuple(fields) => write!(f, "({})", fields.iter().map(|f| format!("{},", DisplayExpr(f, self.1 + 1, false))).collect::<Vec<_>>().join(" ")),
                     Expr::List(items) => write!(f, "[{}]", items.iter().map(|i| format!("{}", DisplayExpr(i, self.1 + 1, false))).collect::<Vec<_>>().join(", ")),
                     Expr::Intrinsic(NotBool, args) => write!(f, "!{}", DisplayExpr(&args[0], self.1, false)),
                     Expr::Intrinsic(NegNat | NegInt | NegReal, args) => write!(f, "-{}", DisplayExpr(&args[0], self.1, false)),
-                    Expr::Intrinsic(EqChar | EqNat | EqInt, args) => write!(f, "{} = {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(AddNat | AddInt, args) => write!(f, "{} + {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(SubNat | SubInt, args) => write!(f, "{} - {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(MulNat | MulInt, args) => write!(f, "{} * {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(LessNat, args) => write!(f, "{} < {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(MoreNat, args) => write!(f, "{} > {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(MoreEqNat, args) => write!(f, "{} >= {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
-                    Expr::Intrinsic(LessEqNat, args) => write!(f, "{} <= {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(EqChar | EqNat | EqInt | EqReal, args) => write!(f, "{} = {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(NotEqReal, args) => write!(f, "{} != {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(AddNat | AddInt | AddReal, args) => write!(f, "{} + {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(SubNat | SubInt | SubReal, args) => write!(f, "{} - {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(MulNat | MulInt | MulReal, args) => write!(f, "{} * {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(DivNat | DivInt | DivReal, args) => write!(f, "{} / {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(RemNat | RemInt, args) => write!(f, "{} % {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(LessNat | LessReal, args) => write!(f, "{} < {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(MoreNat | MoreReal, args) => write!(f, "{} > {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(MoreEqNat | MoreEqReal, args) => write!(f, "{} >= {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(LessEqNat | LessEqReal, args) => write!(f, "{} <= {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(NatToReal, args) => write!(f, "@nat_to_real({})", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Intrinsic(IntToReal, args) => write!(f, "@int_to_real({})", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Intrinsic(RealToInt, args) => write!(f, "@real_to_int({})", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Intrinsic(RealToNat, args) => write!(f, "@real_to_nat({})", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Intrinsic(DisplayReal, args) => write!(f, "@display_real({})", DisplayExpr(&args[0], self.1, false)),
                     Expr::Intrinsic(Join(_), args) => write!(f, "{} ++ {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
                     Expr::Intrinsic(AndBool, args) => write!(f, "{} and {}", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
                     Expr::Intrinsic(Print, args) => write!(f, "@print({}, {})", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
@@ -572,6 +631,7 @@ impl Expr {
                     Expr::Intrinsic(LenList, args) => write!(f, "@len_list({})", DisplayExpr(&args[0], self.1, false)),
                     Expr::Intrinsic(SkipList, args) => write!(f, "@skip_list({}, {})", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
                     Expr::Intrinsic(TrimList, args) => write!(f, "@trim_list({}, {})", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
+                    Expr::Intrinsic(Host(name), args) => write!(f, "@host(\"{}\", {})", name, DisplayExpr(&args[0], self.1, false)),
                     Expr::Match(pred, arms) if arms.len() == 1 => {
                         let (arm, body) = &arms[0];
                         write!(f, "let {} = {} in\n{}", DisplayBinding(arm, self.1 + 1), DisplayExpr(pred, self.1, false), DisplayExpr(body, self.1 + 1, true))
@@ -591,6 +651,16 @@ impl Expr {
                     Expr::AccessVariant(inner, variant) => write!(f, "({}).#{}", DisplayExpr(inner, self.1, false), variant),
                     Expr::Data(data, inner) => write!(f, "{:?} {}", data.0, DisplayExpr(inner, self.1, false)),
                     Expr::AccessData(inner, data) => write!(f, "{}.#{:?}", DisplayExpr(inner, self.1, false), data.0),
+                    Expr::Intrinsic(Suspend(_), args) => write!(f, "@suspend({})", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Intrinsic(Propagate, args) => write!(f, "{}?", DisplayExpr(&args[0], self.1, false)),
+                    Expr::Basin(_, inner) => write!(f, "@{{\n{}\n{}}}", DisplayExpr(inner, self.1 + 1, true), "    ".repeat(self.1)),
+                    Expr::Handle { expr, send, recv, .. } => write!(
+                        f,
+                        "{} handle ${} =>\n{}",
+                        DisplayExpr(expr, self.1, false),
+                        send.0,
+                        DisplayExpr(recv, self.1 + 1, true),
+                    ),
                     // _ => write!(f, "<TODO>"),
                     expr => todo!("{:?}", expr),
                 }
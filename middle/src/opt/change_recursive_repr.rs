@@ -0,0 +1,383 @@
+use super::*;
+
+/// Changes the repr of recursive data types that have the same shape as a built-in type into that built-in type.
+///
+/// - `data Nat = Succ Nat | Zero` becomes `Prim(Nat)`, with `Succ n` as `n + 1` and `Zero` as `0`
+/// - `data List A = Cons (A, List A) | Nil` becomes `List(A)`, with `Cons (x, xs)` as `[x] ++ xs` and `Nil` as `[]`
+///
+/// This allows values of such types to be represented in constant space rather than as a chain of allocations.
+#[derive(Default)]
+pub struct ChangeRecursiveRepr;
+
+#[derive(Clone, Debug)]
+enum Shape {
+    Nat { zero: usize },
+    List { nil: usize, item: Repr },
+}
+
+impl Shape {
+    fn of(id: ConDataId, data: &Data) -> Option<Self> {
+        if !data.is_recursive {
+            return None;
+        }
+        let variants = match &data.repr {
+            Repr::Sum(variants) if variants.len() == 2 => variants,
+            _ => return None,
+        };
+        let empty = variants.iter().position(|v| matches!(v, Repr::Tuple(fields) if fields.is_empty()))?;
+        match &variants[1 - empty] {
+            Repr::Data(inner) if *inner == id => Some(Shape::Nat { zero: empty }),
+            Repr::Tuple(fields) => match &fields[..] {
+                [item, Repr::Data(tail)] if *tail == id => Some(Shape::List { nil: empty, item: item.clone() }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn empty(&self) -> usize {
+        match self {
+            Shape::Nat { zero } => *zero,
+            Shape::List { nil, .. } => *nil,
+        }
+    }
+}
+
+// Determine whether a repr refers to the given data type, either directly or via other data types
+fn mentions(reprs: &Reprs, repr: &Repr, data: ConDataId, visited: &mut Vec<ConDataId>) -> bool {
+    match repr {
+        Repr::Prim(_) => false,
+        Repr::List(item) => mentions(reprs, item, data, visited),
+        Repr::Tuple(xs) | Repr::Sum(xs) => xs
+            .iter()
+            .any(|x| mentions(reprs, x, data, visited)),
+        Repr::Data(id) if *id == data => true,
+        Repr::Data(id) => if visited.contains(id) {
+            false
+        } else {
+            visited.push(*id);
+            mentions(reprs, &reprs.get(*id).repr, data, visited)
+        },
+        Repr::Func(i, o) => mentions(reprs, i, data, visited) || mentions(reprs, o, data, visited),
+        Repr::Effect(_, out) => mentions(reprs, out, data, visited),
+    }
+}
+
+struct Changes {
+    shapes: HashMap<ConDataId, Shape>,
+}
+
+impl Changes {
+    fn repr(&self, repr: &mut Repr) {
+        match repr {
+            Repr::Prim(_) => {},
+            Repr::List(item) => self.repr(item),
+            Repr::Tuple(xs) | Repr::Sum(xs) => xs
+                .iter_mut()
+                .for_each(|x| self.repr(x)),
+            Repr::Data(id) => match self.shapes.get(id) {
+                Some(Shape::Nat { .. }) => *repr = Repr::Prim(Prim::Nat),
+                Some(Shape::List { item, .. }) => {
+                    let mut item = item.clone();
+                    self.repr(&mut item);
+                    *repr = Repr::List(Box::new(item));
+                },
+                None => {},
+            },
+            Repr::Func(i, o) => {
+                self.repr(i);
+                self.repr(o);
+            },
+            Repr::Effect(_, out) => self.repr(out),
+        }
+    }
+
+    // Remove data types from consideration if they appear in a way that the pass does not know how to change
+    fn check_binding(&mut self, binding: &Binding) {
+        if let Pat::Data(data, inner) = &binding.pat {
+            if let Some(shape) = self.shapes.get(data) {
+                let can_change = match &inner.pat {
+                    Pat::Variant(variant, inner) => if *variant == shape.empty() {
+                        !inner.binds()
+                    } else {
+                        match shape {
+                            Shape::Nat { .. } => true,
+                            Shape::List { .. } => inner.name.is_none() && match &inner.pat {
+                                Pat::Wildcard => true,
+                                Pat::Tuple(fields) => fields.len() == 2,
+                                _ => false,
+                            },
+                        }
+                    },
+                    _ => false,
+                } && inner.name.is_none();
+                if !can_change {
+                    self.shapes.remove(data);
+                }
+            }
+        }
+        binding.for_children(|binding| self.check_binding(binding));
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Data(data, inner) if !matches!(&**inner, Expr::Variant(_, _)) => { self.shapes.remove(data); },
+            Expr::AccessData(_, data) => { self.shapes.remove(data); },
+            Expr::Literal(_) => self.check_literal(expr),
+            Expr::Match(_, arms) => arms
+                .iter()
+                .for_each(|(arm, _)| self.check_binding(arm)),
+            _ => {},
+        }
+        expr.for_children(|expr| self.check_expr(expr));
+    }
+
+    fn check_literal(&mut self, expr: &Expr) {
+        fn visit(litr: &Literal, datas: &mut Vec<ConDataId>) {
+            match litr {
+                Literal::Tuple(xs) | Literal::List(xs) => xs
+                    .iter()
+                    .for_each(|x| visit(x, datas)),
+                Literal::Sum(_, inner) => visit(inner, datas),
+                Literal::Data(data, inner) => {
+                    datas.push(*data);
+                    visit(inner, datas);
+                },
+                _ => {},
+            }
+        }
+
+        if let Expr::Literal(litr) = expr {
+            let mut datas = Vec::new();
+            visit(litr, &mut datas);
+            datas
+                .into_iter()
+                .for_each(|data| { self.shapes.remove(&data); });
+        }
+    }
+
+    fn change_binding(&self, binding: &mut MirNode<Binding>) {
+        binding.for_children_mut(|binding| self.change_binding(binding));
+        self.repr(binding.meta_mut());
+
+        if let Pat::Data(data, inner) = &mut binding.pat {
+            if let Some(shape) = self.shapes.get(data) {
+                let (variant, inner) = match &mut inner.pat {
+                    Pat::Variant(variant, inner) => (*variant, inner),
+                    _ => unreachable!(),
+                };
+                binding.pat = match (shape, variant == shape.empty()) {
+                    (Shape::Nat { .. }, true) => Pat::Literal(Literal::Nat(0)),
+                    (Shape::Nat { .. }, false) => Pat::Add(inner.clone(), 1),
+                    (Shape::List { .. }, true) => Pat::ListExact(Vec::new()),
+                    (Shape::List { .. }, false) => match &inner.pat {
+                        Pat::Tuple(fields) => Pat::ListFront(vec![fields[0].clone()], Some(fields[1].clone())),
+                        _ => {
+                            let item = match binding.meta() {
+                                Repr::List(item) => (**item).clone(),
+                                _ => unreachable!(),
+                            };
+                            Pat::ListFront(vec![MirNode::new(Binding::wildcard(None), item)], None)
+                        },
+                    },
+                };
+            }
+        }
+    }
+
+    fn change_expr(&self, expr: &mut MirNode<Expr>) {
+        expr.for_children_mut(|expr| self.change_expr(expr));
+        match &mut **expr {
+            Expr::Match(_, arms) => arms
+                .iter_mut()
+                .for_each(|(arm, _)| self.change_binding(arm)),
+            Expr::Func(arg, _) | Expr::Go(arg, _, _) => self.repr(arg.meta_mut()),
+            Expr::Handle { send, .. } => self.repr(send.meta_mut()),
+            Expr::Intrinsic(Intrinsic::MakeList(repr) | Intrinsic::Join(repr), _) => self.repr(repr),
+            _ => {},
+        }
+        self.repr(expr.meta_mut());
+
+        if let Expr::Data(data, inner) = &mut **expr {
+            if let Some(shape) = self.shapes.get(data) {
+                let (variant, inner) = match &mut **inner {
+                    Expr::Variant(variant, inner) => (*variant, inner.clone()),
+                    _ => unreachable!(),
+                };
+                **expr = match (shape, variant == shape.empty()) {
+                    (Shape::Nat { .. }, true) => Expr::Literal(Literal::Nat(0)),
+                    (Shape::Nat { .. }, false) => Expr::Intrinsic(Intrinsic::AddNat, vec![
+                        inner,
+                        MirNode::new(Expr::Literal(Literal::Nat(1)), Repr::Prim(Prim::Nat)),
+                    ]),
+                    (Shape::List { .. }, true) => Expr::List(Vec::new()),
+                    (Shape::List { .. }, false) => {
+                        let list_repr = expr.meta().clone();
+                        let item_repr = match &list_repr {
+                            Repr::List(item) => (**item).clone(),
+                            _ => unreachable!(),
+                        };
+                        let (item, tail) = (Local::new(), Local::new());
+                        let binding = Binding {
+                            pat: Pat::Tuple(vec![
+                                MirNode::new(Binding::wildcard(item), item_repr.clone()),
+                                MirNode::new(Binding::wildcard(tail), list_repr.clone()),
+                            ]),
+                            name: None,
+                        };
+                        let pair_repr = inner.meta().clone();
+                        let join = Expr::Intrinsic(Intrinsic::Join(item_repr.clone()), vec![
+                            MirNode::new(Expr::List(vec![MirNode::new(Expr::Local(item), item_repr)]), list_repr.clone()),
+                            MirNode::new(Expr::Local(tail), list_repr.clone()),
+                        ]);
+                        Expr::Match(inner, vec![(MirNode::new(binding, pair_repr), MirNode::new(join, list_repr))])
+                    },
+                };
+            }
+        }
+    }
+}
+
+impl Pass for ChangeRecursiveRepr {
+    fn apply(&mut self, ctx: &mut Context) {
+        let shapes = ctx.reprs.datas
+            .iter()
+            .filter_map(|(id, data)| Some((*id, Shape::of(*id, data.as_ref()?)?)))
+            // Types like `data Tree = Branch ([Tree], Tree) | Leaf` cannot be turned into finite reprs
+            .filter(|(id, shape)| match shape {
+                Shape::Nat { .. } => true,
+                Shape::List { item, .. } => !mentions(&ctx.reprs, item, *id, &mut Vec::new()),
+            })
+            .collect();
+        let mut changes = Changes { shapes };
+
+        for (_, proc) in ctx.procs.iter() {
+            changes.check_expr(&proc.body);
+        }
+        if changes.shapes.is_empty() {
+            return;
+        }
+
+        for (_, proc) in ctx.procs.iter_mut() {
+            changes.change_expr(&mut proc.body);
+        }
+        for data in ctx.reprs.iter_mut() {
+            changes.repr(&mut data.repr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tao_syntax::parse_module;
+    use tao_analysis::{Context as HirContext, Error as HirError};
+
+    // The standard library is not imported, so the module has no need for the operators that it would provide
+    const CODE: &str = "
+data Peano =
+    | Succ Peano
+    \\ Zero
+
+data List A =
+    | Cons (A, List A)
+    \\ Nil
+
+fn double : Peano -> Peano =
+    | Zero => Zero
+    \\ Succ n => Succ (Succ double(n))
+
+fn map A, B : (A -> B) -> List A -> List B =
+    | _, Nil => Nil
+    \\ f, Cons (x, xs) => Cons (f(x), map(f, xs))
+
+$[main]
+def main = (double(Succ Zero), map(fn x => Succ x, Cons (Zero, Nil)))
+";
+
+    fn lower(code: &str) -> Context {
+        let module = match parse_module(code, SrcId::from_path("main.tao")) {
+            (Some(module), errors) if errors.is_empty() => module,
+            (_, errors) => panic!("Failed to parse: {:?}", errors),
+        };
+        let (hir, errors) = HirContext::from_module(&module, &[]);
+        let errors = errors.into_iter().filter(|e| !matches!(e, HirError::MissingLangItem(_))).collect::<Vec<_>>();
+        assert!(errors.is_empty(), "Failed to analyse module: {:?}", errors);
+        let (concrete, errors) = hir.concretize();
+        assert_eq!(errors.len(), 0, "Failed to concretize module");
+        Context::from_concrete(&hir, &concrete)
+    }
+
+    // Every repr that appears in an expression, including those of the bindings within it
+    fn reprs(expr: &MirNode<Expr>, out: &mut Vec<Repr>) {
+        fn binding_reprs(binding: &MirNode<Binding>, out: &mut Vec<Repr>) {
+            out.push(binding.meta().clone());
+            binding.for_children(|binding| binding_reprs(binding, out));
+        }
+
+        out.push(expr.meta().clone());
+        match &**expr {
+            Expr::Match(_, arms) => arms
+                .iter()
+                .for_each(|(arm, _)| binding_reprs(arm, out)),
+            Expr::Func(arg, _) | Expr::Go(arg, _, _) => out.push(arg.meta().clone()),
+            _ => {},
+        }
+        expr.for_children(|expr| reprs(expr, out));
+    }
+
+    fn mentions_data(repr: &Repr) -> bool {
+        match repr {
+            Repr::Prim(_) => false,
+            Repr::List(item) => mentions_data(item),
+            Repr::Tuple(xs) | Repr::Sum(xs) => xs.iter().any(mentions_data),
+            Repr::Data(_) => true,
+            Repr::Func(i, o) => mentions_data(i) || mentions_data(o),
+            Repr::Effect(_, out) => mentions_data(out),
+        }
+    }
+
+    #[test]
+    fn recursive_types_become_builtin_types() {
+        let mut ctx = lower(CODE);
+        let before = ctx.procs.iter().flat_map(|(_, proc)| {
+            let mut out = Vec::new();
+            reprs(&proc.body, &mut out);
+            out
+        }).collect::<Vec<_>>();
+        assert!(before.iter().any(mentions_data), "The module should use data types before the pass runs");
+
+        ChangeRecursiveRepr.apply(&mut ctx);
+
+        let mut after = Vec::new();
+        for (_, proc) in ctx.procs.iter() {
+            reprs(&proc.body, &mut after);
+        }
+        assert!(!after.iter().any(mentions_data), "{:?}", after.iter().filter(|r| mentions_data(r)).collect::<Vec<_>>());
+        assert!(after.iter().any(|r| matches!(r, Repr::Prim(Prim::Nat))));
+        assert!(after.iter().any(|r| matches!(r, Repr::List(item) if matches!(&**item, Repr::Prim(Prim::Nat)))));
+
+        let entry = ctx.procs.get(ctx.entry.unwrap()).unwrap();
+        assert!(matches!(
+            entry.body.meta(),
+            Repr::Tuple(fields) if matches!(&fields[..], [Repr::Prim(Prim::Nat), Repr::List(item)] if matches!(&**item, Repr::Prim(Prim::Nat))),
+        ), "{:?}", entry.body.meta());
+    }
+
+    #[test]
+    fn other_recursive_types_are_left_alone() {
+        // A tree has two recursive fields, so it has no built-in equivalent
+        let mut ctx = lower("
+data Tree =
+    | Branch (Tree, Tree)
+    \\ Leaf
+
+$[main]
+def main = Branch (Leaf, Leaf)
+");
+        ChangeRecursiveRepr.apply(&mut ctx);
+
+        let entry = ctx.procs.get(ctx.entry.unwrap()).unwrap();
+        assert!(matches!(entry.body.meta(), Repr::Data(_)), "{:?}", entry.body.meta());
+    }
+}
@@ -168,7 +168,7 @@ impl ConstFold {
                 .iter_mut()
                 .map(|item| self.eval(ctx, item, stack))
                 .collect()),
-            Expr::Apply(f, arg) => {
+            Expr::Apply(f, arg, _) => {
                 self.eval(ctx, f, stack);
                 self.eval(ctx, arg, stack);
 
@@ -220,10 +220,24 @@ impl ConstFold {
                     Partial::Unknown(None)
                 }
             },
-            e => todo!("{:?}", e),
+            Expr::Basin(_, inner) => {
+                self.eval(ctx, inner, stack);
+                Partial::Unknown(None)
+            },
+            Expr::Handle { expr, send, recv, .. } => {
+                self.eval(ctx, expr, stack);
+
+                stack.push((**send, Partial::Unknown(Some(**send))));
+                self.eval(ctx, recv, stack);
+                stack.pop();
+
+                Partial::Unknown(None)
+            },
         };
 
         let partial = match partial {
+            // Expressions that might perform an effect must be kept, even if we know what they evaluate to
+            partial if expr.may_suspend() => partial,
             // If the partial output of this expression is a local found in the enclosing expression, just refer to it
             // directly.
             Partial::Unknown(local) if local
@@ -269,19 +283,42 @@ impl Intrinsic {
             Intrinsic::AddNat => op!(Nat(x), Nat(y) => Nat(x + y)),
             Intrinsic::SubNat => op!(Nat(x), Nat(y) => Int(*x as i64 - *y as i64)),
             Intrinsic::MulNat => op!(Nat(x), Nat(y) => Nat(x * y)),
+            Intrinsic::DivNat => op!(Nat(x), Nat(y) => Nat(x.checked_div(*y).unwrap_or(0))),
+            Intrinsic::RemNat => op!(Nat(x), Nat(y) => Nat(x.checked_rem(*y).unwrap_or(*x))),
             Intrinsic::LessNat => op!(Nat(x), Nat(y) => Bool(x < y)),
             Intrinsic::MoreNat => op!(Nat(x), Nat(y) => Bool(x > y)),
             Intrinsic::MoreEqNat => op!(Nat(x), Nat(y) => Bool(x >= y)),
-            Intrinsic::AddInt => op!(Int(x), Int(y) => Int(x + y)),
-            Intrinsic::SubInt => op!(Int(x), Int(y) => Int(x - y)),
-            Intrinsic::MulInt => op!(Int(x), Int(y) => Int(x * y)),
+            Intrinsic::AddInt => op!(Int(x), Int(y) => Int(x.wrapping_add(*y))),
+            Intrinsic::SubInt => op!(Int(x), Int(y) => Int(x.wrapping_sub(*y))),
+            Intrinsic::MulInt => op!(Int(x), Int(y) => Int(x.wrapping_mul(*y))),
+            Intrinsic::DivInt => op!(Int(x), Int(y) => Int(if *y == 0 { 0 } else { x.wrapping_div(*y) })),
+            Intrinsic::RemInt => op!(Int(x), Int(y) => Int(if *y == 0 { *x } else { x.wrapping_rem(*y) })),
             Intrinsic::EqNat => op!(Nat(x), Nat(y) => Bool(x == y)),
             Intrinsic::EqChar => op!(Char(x), Char(y) => Bool(x == y)),
+            Intrinsic::NegReal => op!(Real(x) => Real(-x)),
+            Intrinsic::AddReal => op!(Real(x), Real(y) => Real(x + y)),
+            Intrinsic::SubReal => op!(Real(x), Real(y) => Real(x - y)),
+            Intrinsic::MulReal => op!(Real(x), Real(y) => Real(x * y)),
+            Intrinsic::DivReal => op!(Real(x), Real(y) => Real(x / y)),
+            Intrinsic::EqReal => op!(Real(x), Real(y) => Bool(x == y)),
+            Intrinsic::NotEqReal => op!(Real(x), Real(y) => Bool(x != y)),
+            Intrinsic::LessReal => op!(Real(x), Real(y) => Bool(x < y)),
+            Intrinsic::MoreReal => op!(Real(x), Real(y) => Bool(x > y)),
+            Intrinsic::LessEqReal => op!(Real(x), Real(y) => Bool(x <= y)),
+            Intrinsic::MoreEqReal => op!(Real(x), Real(y) => Bool(x >= y)),
+            Intrinsic::NatToReal => op!(Nat(x) => Real(*x as f64)),
+            Intrinsic::IntToReal => op!(Int(x) => Real(*x as f64)),
+            Intrinsic::RealToInt => op!(Real(x) => Int(*x as i64)),
+            Intrinsic::RealToNat => op!(Real(x) => Nat((*x as i64).max(0) as u64)),
+            Intrinsic::DisplayReal => op!(Real(x) => List(format!("{:?}", x).chars().map(Char).collect())),
             Intrinsic::EqNat => op!(Nat(x), Nat(y) => Bool(x == y)),
             Intrinsic::Join(_) => op!(List(xs), List(ys) => List(xs.iter().chain(ys).cloned().collect())),
             Intrinsic::AndBool => op!(Bool(x), Bool(y) => Bool(*x && *y)),
             Intrinsic::Print => Partial::Unknown(None),
             Intrinsic::Input => Partial::Unknown(None),
+            Intrinsic::Host(_) => Partial::Unknown(None),
+            Intrinsic::Suspend(_) => Partial::Unknown(None),
+            Intrinsic::Propagate => Partial::Unknown(None),
             Intrinsic::UpdateField(idx) => Partial::Unknown(None), // TODO
             Intrinsic::LenList => op!(List(xs) => Nat(xs.len() as u64)),
             Intrinsic::SkipList => op!(List(xs), Nat(i) => List(xs.clone().split_off((*i as usize).min(xs.len())))),
@@ -1,16 +1,18 @@
 use super::*;
 use std::any::{Any, type_name};
 
+mod change_recursive_repr;
 mod const_fold;
-mod flatten_single_field;
 mod remove_dead_proc;
 mod remove_unused_bindings;
+mod unbox;
 
 pub use {
+    change_recursive_repr::ChangeRecursiveRepr,
     const_fold::ConstFold,
-    flatten_single_field::FlattenSingleField,
     remove_dead_proc::RemoveDeadProc,
     remove_unused_bindings::RemoveUnusedBindings,
+    unbox::Unbox,
 };
 
 pub trait Pass: Any {
@@ -221,7 +223,7 @@ impl Expr {
                 f(body);
                 f(init);
             },
-            Expr::Apply(func, arg) => {
+            Expr::Apply(func, arg, _) => {
                 f(func);
                 f(arg);
             },
@@ -231,7 +233,7 @@ impl Expr {
             Expr::Data(_, inner) => f(inner),
             Expr::AccessData(inner, _) => f(inner),
             Expr::Basin(_, inner) => f(inner),
-            Expr::Handle { expr, eff, send, recv } => {
+            Expr::Handle { expr, eff, send, recv, .. } => {
                 f(expr);
                 f(recv);
             },
@@ -261,7 +263,7 @@ impl Expr {
                 f(body);
                 f(init);
             },
-            Expr::Apply(func, arg) => {
+            Expr::Apply(func, arg, _) => {
                 f(func);
                 f(arg);
             },
@@ -271,7 +273,7 @@ impl Expr {
             Expr::Data(_, inner) => f(inner),
             Expr::AccessData(inner, _) => f(inner),
             Expr::Basin(_, inner) => f(inner),
-            Expr::Handle { expr, eff, send, recv } => {
+            Expr::Handle { expr, eff, send, recv, .. } => {
                 f(expr);
                 f(recv);
             },
@@ -294,7 +296,7 @@ impl Expr {
                     body.inline_local(name, local_expr);
                 }
             },
-            Expr::Handle { expr, eff, send, recv } => {
+            Expr::Handle { expr, eff, send, recv, .. } => {
                 expr.inline_local(name, local_expr);
                 if **send != name {
                     recv.inline_local(name, local_expr);
@@ -343,7 +345,7 @@ impl Expr {
                 body.visit_inner(order, repr, binding, expr);
                 init.visit_inner(order, repr, binding, expr);
             },
-            Expr::Apply(f, arg) => {
+            Expr::Apply(f, arg, _) => {
                 f.visit_inner(order, repr, binding, expr);
                 arg.visit_inner(order, repr, binding, expr);
             },
@@ -385,6 +387,37 @@ pub fn prepare(ctx: &mut Context) {
     }
 }
 
+/// Mark the applications that are in tail position, so that backends can reuse the frame of the caller for them. This
+/// must happen after every other pass, since inlining moves applications in and out of tail position.
+pub fn mark_tail_calls(ctx: &Context) {
+    fn mark(expr: &Expr, tail: bool) {
+        match expr {
+            Expr::Apply(f, arg, flags) => {
+                flags.set(mir::ApplyFlags { is_tail: tail });
+                mark(f, false);
+                mark(arg, false);
+            },
+            Expr::Match(pred, arms) => {
+                mark(pred, false);
+                for (_, body) in arms {
+                    mark(body, tail);
+                }
+            },
+            // The bodies of functions, effects, and handlers produce the result of a call
+            Expr::Func(_, body) | Expr::Basin(_, body) => mark(body, true),
+            Expr::Handle { expr, recv, .. } => {
+                mark(expr, false);
+                mark(recv, true);
+            },
+            _ => expr.for_children(|expr| mark(expr, false)),
+        }
+    }
+
+    for (_, proc) in ctx.procs.iter() {
+        mark(&proc.body, true);
+    }
+}
+
 /// Check the self-consistency of the MIR
 pub fn check(ctx: &Context) {
     fn check_binding(ctx: &Context, binding: &Binding, repr: &Repr, stack: &mut Vec<(Local, Repr)>) {
@@ -427,7 +460,7 @@ pub fn check(ctx: &Context) {
             (Expr::Go(_, body, init), _) => {
                 // TODO: Validate return body and return type
             },
-            (Expr::Apply(f, arg), _) => {
+            (Expr::Apply(f, arg, _), _) => {
                 assert!(matches!(f.meta(), Repr::Func(_, _)));
             },
             (Expr::Tuple(a), Repr::Tuple(b)) if a.len() == b.len() => {
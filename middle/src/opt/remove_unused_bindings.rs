@@ -75,8 +75,12 @@ impl Pass for RemoveUnusedBindings {
                             stack.truncate(old_stack);
                         });
 
-                    // Flatten matches with a single arm where the arm does not bind
-                    if arms.len() == 1 && !arms.first().unwrap().0.binds() {
+                    // Flatten matches with a single arm where the arm does not bind (unless the predicate performs an
+                    // effect, which must be preserved). The predicate was already visited above: visiting it again
+                    // would double-count its uses and take exponential time in the depth of nested predicates.
+                    if pred.may_suspend() {
+                        // Nothing to flatten
+                    } else if arms.len() == 1 && !arms.first().unwrap().0.binds() {
                         *expr = arms.remove(0).1.into_inner();
                     } else if arms.get(0).map_or(false, |(b, _)| matches!(&b.pat, Pat::Wildcard)) {
                         let (arm, mut body) = arms.remove(0);
@@ -84,9 +88,6 @@ impl Pass for RemoveUnusedBindings {
                             body.inline_local(name, pred);
                         }
                         *expr = body.into_inner();
-                    } else {
-                        // Visit predicate last to avoid visiting it again if the match was removed
-                        visit(mir, pred, stack, proc_stack);
                     }
                 },
                 Expr::Func(arg, body) => {
@@ -100,6 +101,12 @@ impl Pass for RemoveUnusedBindings {
                     visit(mir, body, stack, proc_stack);
                     stack.pop();
                 },
+                Expr::Handle { expr, send, recv, .. } => {
+                    visit(mir, expr, stack, proc_stack);
+                    stack.push((**send, 0));
+                    visit(mir, recv, stack, proc_stack);
+                    stack.pop();
+                },
                 _ => expr.for_children_mut(|expr| visit(mir, expr, stack, proc_stack)),
             }
         }
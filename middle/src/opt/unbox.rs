@@ -0,0 +1,269 @@
+use super::*;
+
+/// Removes boxing that the program can never observe.
+///
+/// - Variants with no fields become literals, allowing backends to represent them as plain tags
+/// - Tuples that are built only to be immediately destructured by a match (as with the parameters of a function) get
+///   flattened into their parent tuple or, if they have only one field, replaced by that field
+///
+/// Data types with a single constructor are not yet unboxed: doing so means changing the repr of the type everywhere it
+/// appears, as [`ChangeRecursiveRepr`] does for recursive types.
+#[derive(Default)]
+pub struct Unbox;
+
+fn is_unit(repr: &Repr) -> bool {
+    matches!(repr, Repr::Tuple(fields) if fields.is_empty())
+}
+
+// Determine whether a pattern destructures a tuple with the given number of fields without binding the tuple itself
+fn is_transparent(binding: &Binding, len: usize) -> bool {
+    binding.name.is_none() && match &binding.pat {
+        Pat::Wildcard => true,
+        Pat::Tuple(fields) => fields.len() == len,
+        _ => false,
+    }
+}
+
+// Split a transparent tuple pattern into the patterns for each of its fields
+fn field_patterns(binding: MirNode<Binding>) -> Vec<MirNode<Binding>> {
+    let reprs = match binding.meta() {
+        Repr::Tuple(reprs) => reprs.clone(),
+        _ => unreachable!(),
+    };
+    match binding.into_inner().pat {
+        Pat::Tuple(fields) => fields,
+        _ => reprs
+            .into_iter()
+            .map(|repr| MirNode::new(Binding::wildcard(None), repr))
+            .collect(),
+    }
+}
+
+impl Unbox {
+    fn unbox_binding(&self, binding: &mut MirNode<Binding>) {
+        binding.for_children_mut(|binding| self.unbox_binding(binding));
+
+        if let Pat::Variant(_, inner) = &mut binding.pat {
+            if is_unit(inner.meta()) && !inner.binds() {
+                inner.pat = Pat::Wildcard;
+            }
+        }
+    }
+
+    // Returns `true` if the predicate could be flattened further
+    fn flatten_match(&self, pred: &mut MirNode<Expr>, arms: &mut Vec<(MirNode<Binding>, MirNode<Expr>)>) -> bool {
+        let fields = match &mut **pred {
+            Expr::Tuple(fields) => fields,
+            _ => return false,
+        };
+        if !arms.iter().all(|(arm, _)| is_transparent(arm, fields.len())) {
+            return false;
+        }
+
+        if fields.len() == 1 {
+            *pred = fields.remove(0);
+            for (arm, _) in arms.iter_mut() {
+                *arm = field_patterns(std::mem::replace(arm, MirNode::new(Binding::wildcard(None), Repr::Tuple(Vec::new()))))
+                    .remove(0);
+            }
+            return true;
+        }
+
+        let nested = fields
+            .iter()
+            .enumerate()
+            .find(|(idx, field)| match &***field {
+                Expr::Tuple(inner) => arms
+                    .iter()
+                    .all(|(arm, _)| match &arm.pat {
+                        Pat::Tuple(arm_fields) => is_transparent(&arm_fields[*idx], inner.len()),
+                        _ => true,
+                    }),
+                _ => false,
+            })
+            .map(|(idx, _)| idx);
+
+        if let Some(idx) = nested {
+            let inner = match fields.remove(idx).into_inner() {
+                Expr::Tuple(inner) => inner,
+                _ => unreachable!(),
+            };
+            let len = fields.len() + inner.len();
+            fields.splice(idx..idx, inner);
+            let repr = Repr::Tuple(fields
+                .iter()
+                .map(|field| field.meta().clone())
+                .collect());
+            *pred.meta_mut() = repr.clone();
+
+            for (arm, _) in arms.iter_mut() {
+                let mut arm_fields = field_patterns(std::mem::replace(arm, MirNode::new(Binding::wildcard(None), Repr::Tuple(Vec::new()))));
+                let inner = field_patterns(arm_fields.remove(idx));
+                arm_fields.splice(idx..idx, inner);
+                debug_assert_eq!(arm_fields.len(), len);
+                *arm = MirNode::new(Binding { pat: Pat::Tuple(arm_fields), name: None }, repr.clone());
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unbox_expr(&self, expr: &mut MirNode<Expr>) {
+        expr.for_children_mut(|expr| self.unbox_expr(expr));
+
+        match &mut **expr {
+            Expr::Variant(variant, inner) => match &**inner {
+                Expr::Tuple(fields) if fields.is_empty() => {
+                    **expr = Expr::Literal(Literal::Sum(*variant, Box::new(Literal::Tuple(Vec::new()))));
+                },
+                Expr::Literal(Literal::Tuple(fields)) if fields.is_empty() => {
+                    **expr = Expr::Literal(Literal::Sum(*variant, Box::new(Literal::Tuple(Vec::new()))));
+                },
+                _ => {},
+            },
+            Expr::Match(pred, arms) => {
+                arms
+                    .iter_mut()
+                    .for_each(|(arm, _)| self.unbox_binding(arm));
+                while self.flatten_match(pred, arms) {}
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Pass for Unbox {
+    fn apply(&mut self, ctx: &mut Context) {
+        for (_, proc) in ctx.procs.iter_mut() {
+            self.unbox_expr(&mut proc.body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nat() -> Repr { Repr::Prim(Prim::Nat) }
+
+    fn local(local: Local) -> MirNode<Expr> { MirNode::new(Expr::Local(local), nat()) }
+
+    fn tuple(fields: Vec<MirNode<Expr>>) -> MirNode<Expr> {
+        let repr = Repr::Tuple(fields.iter().map(|field| field.meta().clone()).collect());
+        MirNode::new(Expr::Tuple(fields), repr)
+    }
+
+    fn bind(name: Option<Local>, repr: Repr) -> MirNode<Binding> { MirNode::new(Binding::wildcard(name), repr) }
+
+    fn tuple_pat(fields: Vec<MirNode<Binding>>) -> MirNode<Binding> {
+        let repr = Repr::Tuple(fields.iter().map(|field| field.meta().clone()).collect());
+        MirNode::new(Binding { pat: Pat::Tuple(fields), name: None }, repr)
+    }
+
+    // The names bound by the fields of a tuple pattern, each of which must be a wildcard
+    fn field_names(arm: &MirNode<Binding>) -> Vec<Option<Local>> {
+        match &arm.pat {
+            Pat::Tuple(fields) => fields
+                .iter()
+                .map(|field| {
+                    assert!(matches!(field.pat, Pat::Wildcard), "{:?}", field.inner());
+                    field.name
+                })
+                .collect(),
+            pat => panic!("Expected a tuple pattern, found {:?}", pat),
+        }
+    }
+
+    fn unbox_match(pred: MirNode<Expr>, arms: Vec<MirNode<Binding>>) -> (MirNode<Expr>, Vec<MirNode<Binding>>) {
+        let mut expr = MirNode::new(Expr::Match(pred, arms
+            .into_iter()
+            .map(|arm| (arm, MirNode::new(Expr::Literal(Literal::Nat(0)), nat())))
+            .collect()), nat());
+        Unbox.unbox_expr(&mut expr);
+        match expr.into_inner() {
+            Expr::Match(pred, arms) => (pred, arms.into_iter().map(|(arm, _)| arm).collect()),
+            expr => panic!("Expected a match, found {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn fieldless_variants_become_literals() {
+        let sum = Repr::Sum(vec![nat(), Repr::Tuple(Vec::new())]);
+
+        let mut expr = MirNode::new(Expr::Variant(1, tuple(Vec::new())), sum.clone());
+        Unbox.unbox_expr(&mut expr);
+        assert!(matches!(
+            &**expr,
+            Expr::Literal(Literal::Sum(1, inner)) if **inner == Literal::Tuple(Vec::new()),
+        ), "{:?}", expr.inner());
+
+        // Variants with fields keep their fields
+        let mut expr = MirNode::new(Expr::Variant(0, local(Local::new())), sum);
+        Unbox.unbox_expr(&mut expr);
+        assert!(matches!(&**expr, Expr::Variant(0, _)), "{:?}", expr.inner());
+    }
+
+    #[test]
+    fn single_field_predicates_become_their_field() {
+        let (x, y) = (Local::new(), Local::new());
+        let (pred, arms) = unbox_match(tuple(vec![local(x)]), vec![tuple_pat(vec![bind(Some(y), nat())])]);
+
+        assert!(matches!(&**pred, Expr::Local(local) if *local == x), "{:?}", pred.inner());
+        assert_eq!(*pred.meta(), nat());
+        assert!(matches!(arms[0].pat, Pat::Wildcard) && arms[0].name == Some(y), "{:?}", arms[0].inner());
+        assert_eq!(*arms[0].meta(), nat());
+    }
+
+    #[test]
+    fn nested_predicates_are_flattened() {
+        let (a, b, c) = (Local::new(), Local::new(), Local::new());
+        let (p, q) = (Local::new(), Local::new());
+        let pair = Repr::Tuple(vec![nat(), nat()]);
+        let (pred, arms) = unbox_match(
+            tuple(vec![local(a), tuple(vec![local(b), local(c)])]),
+            vec![
+                tuple_pat(vec![bind(Some(p), nat()), tuple_pat(vec![bind(Some(q), nat()), bind(None, nat())])]),
+                tuple_pat(vec![bind(None, nat()), bind(None, pair.clone())]),
+                bind(None, Repr::Tuple(vec![nat(), pair])),
+            ],
+        );
+
+        let flat = Repr::Tuple(vec![nat(), nat(), nat()]);
+        match &**pred {
+            Expr::Tuple(fields) => assert!(
+                fields.len() == 3 && fields
+                    .iter()
+                    .zip([a, b, c].iter())
+                    .all(|(field, local)| matches!(&**field, Expr::Local(l) if l == local)),
+                "{:?}", fields,
+            ),
+            pred => panic!("Expected a tuple, found {:?}", pred),
+        }
+        assert_eq!(*pred.meta(), flat);
+        assert_eq!(field_names(&arms[0]), vec![Some(p), Some(q), None]);
+        assert_eq!(field_names(&arms[1]), vec![None, None, None]);
+        assert_eq!(field_names(&arms[2]), vec![None, None, None]);
+        assert!(arms.iter().all(|arm| *arm.meta() == flat));
+    }
+
+    #[test]
+    fn bound_tuples_are_not_flattened() {
+        // The second arm binds the inner tuple, so it must still exist
+        let (a, b, c) = (Local::new(), Local::new(), Local::new());
+        let (p, t) = (Local::new(), Local::new());
+        let pair = Repr::Tuple(vec![nat(), nat()]);
+        let (pred, arms) = unbox_match(
+            tuple(vec![local(a), tuple(vec![local(b), local(c)])]),
+            vec![
+                tuple_pat(vec![bind(None, nat()), tuple_pat(vec![bind(None, nat()), bind(None, nat())])]),
+                tuple_pat(vec![bind(Some(p), nat()), bind(Some(t), pair.clone())]),
+            ],
+        );
+
+        assert_eq!(*pred.meta(), Repr::Tuple(vec![nat(), pair.clone()]));
+        assert!(matches!(&**pred, Expr::Tuple(fields) if matches!(&*fields[1], Expr::Tuple(_))), "{:?}", pred.inner());
+        assert_eq!(field_names(&arms[1]), vec![Some(p), Some(t)]);
+        assert!(arms.iter().all(|arm| *arm.meta() == Repr::Tuple(vec![nat(), pair.clone()])));
+    }
+}
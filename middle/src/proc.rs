@@ -4,6 +4,8 @@ pub type ProcId = ConProcId;
 
 pub struct Proc {
     pub body: mir::MirNode<mir::Expr>,
+    /// The source code that the procedure was generated from, if any.
+    pub span: Option<Span>,
 }
 
 #[derive(Default)]
@@ -20,7 +20,7 @@ pub enum Repr {
     Sum(Vec<Repr>),
     Data(ConDataId),
     Func(Box<Repr>, Box<Repr>),
-    Effect(EffectId, Box<Repr>),
+    Effect(Vec<EffectId>, Box<Repr>),
 }
 
 pub struct Data {
@@ -63,16 +63,27 @@ impl Reprs {
     }
 
     pub fn has_inhabitants(&self, repr: &Repr) -> bool {
+        self.has_inhabitants_inner(repr, &mut Vec::new())
+    }
+
+    fn has_inhabitants_inner(&self, repr: &Repr, visiting: &mut Vec<ConDataId>) -> bool {
         match repr {
             Repr::Prim(_) => true,
             Repr::List(_) => true, // Empty list
             Repr::Tuple(xs) => xs
                 .iter()
-                .all(|x| self.has_inhabitants(x)),
+                .all(|x| self.has_inhabitants_inner(x, visiting)),
             Repr::Sum(variants) => variants
                 .iter()
-                .any(|v| self.has_inhabitants(v)),
-            Repr::Data(data) => self.has_inhabitants(&self.get(*data).repr),
+                .any(|v| self.has_inhabitants_inner(v, visiting)),
+            // A data type that we're already inside of can only be inhabited via one of its other variants
+            Repr::Data(data) if visiting.contains(data) => false,
+            Repr::Data(data) => {
+                visiting.push(*data);
+                let has_inhabitants = self.has_inhabitants_inner(&self.get(*data).repr, visiting);
+                visiting.pop();
+                has_inhabitants
+            },
             Repr::Func(_, _) => true,
             Repr::Effect(_, _) => true, // Effect objects always have inhabitants
         }
@@ -0,0 +1,335 @@
+//! The runtime that native code compiled by the Cranelift backend calls into.
+//!
+//! The JIT links against this crate directly. Object files written by the Cranelift backend must be linked against its
+//! static library to become executables:
+//!
+//! ```sh
+//! cargo build --release -p tao_rt
+//! tao run --backend object --output hello.o examples/hello.tao
+//! cc hello.o target/release/libtao_rt.a -lpthread -ldl -lm -o hello
+//! ```
+//!
+//! Heap objects are arrays of words. Lists start with their length, followed by their items. Tuples are their fields,
+//! sums are a variant index followed by the inner value, functions are a code pointer followed by their captures,
+//! and effect objects are a code pointer, a handler snapshot, and then their captures.
+//!
+//! There is no garbage collector yet. Instead, heap objects are bump-allocated from chunks belonging to the thread that
+//! allocated them, so allocating is little more than moving a pointer. Nothing is freed while a program runs, so its
+//! memory use grows with the total size of everything it allocates rather than with the size of its live data. The JIT
+//! resets the heap once it has read back the result of the program (see [`free_heap`]), keeping the largest chunk for
+//! the next run. Native programs linked against the runtime free their chunks when their thread exits.
+//!
+//! Since long-running programs would otherwise grow without bound, the total size of everything that a program allocates
+//! may be limited (see [`set_heap_limit`]). Once the limit is reached, every allocation fails by producing a null
+//! pointer. Native code then abandons the program by returning from each function in turn, and the host can find out
+//! why with [`heap_exhausted`].
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{stdin, stdout, Write},
+};
+
+type Word = i64;
+/// A callback for the lines written by `@print`, with its lifetime erased.
+type PrintFn = *mut dyn FnMut(&str);
+
+/// The number of words in the first chunk of the heap. Each chunk after it is twice as large as the one before.
+const MIN_CHUNK_WORDS: usize = 1 << 16;
+
+thread_local! {
+    /// The next free word in the current chunk of the heap, and the end of that chunk.
+    static NEXT: Cell<(*mut Word, *mut Word)> = const { Cell::new((std::ptr::null_mut(), std::ptr::null_mut())) };
+    /// Every chunk of the heap, the current chunk last. Chunks are only touched when the current chunk is full.
+    static CHUNKS: RefCell<Vec<Box<[Word]>>> = const { RefCell::new(Vec::new()) };
+    /// The number of words that may still be allocated, if the heap is limited.
+    static HEAP_LEFT: Cell<Option<usize>> = const { Cell::new(None) };
+    /// Whether an allocation has failed because the heap limit was reached.
+    static EXHAUSTED: Cell<bool> = const { Cell::new(false) };
+    /// The effect handlers that are currently in scope, innermost last.
+    static HANDLERS: RefCell<Vec<(Word, Word)>> = const { RefCell::new(Vec::new()) };
+    /// Where the lines written by `@print` go, if not to the standard output.
+    static PRINT: Cell<Option<PrintFn>> = const { Cell::new(None) };
+}
+
+/// A function that native code may call.
+pub struct RtFunc {
+    pub name: &'static str,
+    /// The number of words that the function takes.
+    pub params: usize,
+    /// Whether the function returns a word.
+    pub returns: bool,
+    /// The address of the function, for linking JIT-compiled code.
+    pub ptr: *const u8,
+}
+
+macro_rules! rt_funcs {
+    (@returns) => { false };
+    (@returns $ret:ty) => { true };
+    ($($name:ident($params:literal) $(-> $ret:ty)?),* $(,)?) => {
+        /// Every runtime function.
+        pub fn funcs() -> Vec<RtFunc> {
+            vec![$(RtFunc {
+                name: stringify!($name),
+                params: $params,
+                returns: rt_funcs!(@returns $($ret)?),
+                ptr: $name as *const u8,
+            }),*]
+        }
+    };
+}
+
+rt_funcs! {
+    tao_rt_alloc(1) -> Word,
+    tao_rt_list_join(2) -> Word,
+    tao_rt_list_skip(2) -> Word,
+    tao_rt_list_trim(2) -> Word,
+    tao_rt_print(2) -> Word,
+    tao_rt_input(1) -> Word,
+    tao_rt_real_display(1) -> Word,
+    tao_rt_handlers_len(0) -> Word,
+    tao_rt_handlers_truncate(1),
+    tao_rt_handlers_push(2),
+    tao_rt_handlers_snapshot(0) -> Word,
+    tao_rt_handlers_extend(1),
+    tao_rt_handlers_find(1) -> Word,
+}
+
+/// Run `f`, passing the lines that it writes with `@print` to `print` rather than the standard output.
+pub fn with_print<R>(print: &mut dyn FnMut(&str), f: impl FnOnce() -> R) -> R {
+    // Restores the previous callback, even if `f` panics
+    struct Restore(Option<PrintFn>);
+    impl Drop for Restore {
+        fn drop(&mut self) { PRINT.with(|p| p.set(self.0)) }
+    }
+
+    // SAFETY: the callback is only called while `f` runs, and is removed before `print` goes out of scope
+    let print = unsafe { std::mem::transmute::<*mut (dyn FnMut(&str) + '_), PrintFn>(print) };
+    let _restore = Restore(PRINT.with(|p| p.replace(Some(print))));
+    f()
+}
+
+/// Limit the number of words that the current thread may allocate from now on, or remove the limit.
+pub fn set_heap_limit(words: Option<usize>) {
+    HEAP_LEFT.with(|left| left.set(words));
+}
+
+/// Whether an allocation has failed since the heap was last freed, because the heap limit was reached.
+pub fn heap_exhausted() -> bool {
+    EXHAUSTED.with(|exhausted| exhausted.get())
+}
+
+/// Free every heap object allocated by the current thread, keeping the largest chunk of the heap for reuse.
+///
+/// # Safety
+///
+/// No word that points to a heap object may be used afterwards.
+pub unsafe fn free_heap() {
+    CHUNKS.with(|chunks| {
+        let mut chunks = chunks.borrow_mut();
+        // The last chunk is the largest, so it is kept to avoid allocating it again on the next run
+        let last = chunks.pop();
+        chunks.clear();
+        chunks.extend(last);
+        let range = chunks.last_mut().map_or(std::ptr::null_mut()..std::ptr::null_mut(), |chunk| chunk.as_mut_ptr_range());
+        NEXT.with(|next| next.set((range.start, range.end)));
+    });
+    HANDLERS.with(|handlers| handlers.borrow_mut().clear());
+    EXHAUSTED.with(|exhausted| exhausted.set(false));
+}
+
+// Allocate space for a heap object. Its words are left for the caller to initialise. Produces a null pointer once the
+// heap limit has been reached.
+fn alloc(words: usize) -> *mut Word {
+    // Failure is sticky, so that a program never continues with some of its allocations missing
+    let within_limit = !heap_exhausted() && HEAP_LEFT.with(|left| match left.get() {
+        Some(n) if n < words => false,
+        Some(n) => { left.set(Some(n - words)); true },
+        None => true,
+    });
+    if !within_limit {
+        EXHAUSTED.with(|exhausted| exhausted.set(true));
+        return std::ptr::null_mut();
+    }
+
+    NEXT.with(|next| {
+        let (ptr, end) = next.get();
+        // Even empty objects need a valid pointer, so the first chunk is allocated when the heap has none
+        if !ptr.is_null() && words <= (end as usize - ptr as usize) / std::mem::size_of::<Word>() {
+            // SAFETY: there are at least `words` words left in the current chunk
+            next.set((unsafe { ptr.add(words) }, end));
+            ptr
+        } else {
+            CHUNKS.with(|chunks| {
+                let mut chunks = chunks.borrow_mut();
+                let len = chunks.last().map_or(MIN_CHUNK_WORDS, |chunk| chunk.len() * 2).max(words);
+                // Moving the chunk into the list does not move its contents, so pointers into it remain valid
+                chunks.push(vec![0; len].into_boxed_slice());
+                let ptr = chunks.last_mut().unwrap().as_mut_ptr();
+                // SAFETY: the chunk is at least `words` long
+                next.set((unsafe { ptr.add(words) }, unsafe { ptr.add(len) }));
+                ptr
+            })
+        }
+    })
+}
+
+fn alloc_from(words: &[Word]) -> Word {
+    let ptr = alloc(words.len());
+    if ptr.is_null() {
+        return 0;
+    }
+    // SAFETY: `alloc` produced space for exactly this many words
+    unsafe { std::ptr::copy_nonoverlapping(words.as_ptr(), ptr, words.len()) };
+    ptr as Word
+}
+
+/// View a list object as a slice of its items.
+///
+/// # Safety
+///
+/// `xs` must point to a list object.
+pub unsafe fn list<'a>(xs: Word) -> &'a [Word] {
+    let ptr = xs as *const Word;
+    std::slice::from_raw_parts(ptr.add(1), *ptr as usize)
+}
+
+// Make a list of the items in each part, one after another
+fn make_list(parts: &[&[Word]]) -> Word {
+    let len = parts.iter().map(|part| part.len()).sum::<usize>();
+    let ptr = alloc(len + 1);
+    if ptr.is_null() {
+        return 0;
+    }
+    // SAFETY: `alloc` produced space for the length followed by every item
+    unsafe {
+        *ptr = len as Word;
+        parts.iter().fold(ptr.add(1), |ptr, part| {
+            std::ptr::copy_nonoverlapping(part.as_ptr(), ptr, part.len());
+            ptr.add(part.len())
+        });
+    }
+    ptr as Word
+}
+
+fn make_str(s: &str) -> Word {
+    make_list(&[&s.chars().map(|c| c as Word).collect::<Vec<_>>()])
+}
+
+/// Allocate a heap object of the given number of words, which the caller must initialise. Produces 0 once the heap
+/// limit has been reached, as do the other functions that produce heap objects.
+#[no_mangle]
+pub extern "C" fn tao_rt_alloc(words: Word) -> Word {
+    alloc(words as usize) as Word
+}
+
+/// Join two lists.
+///
+/// # Safety
+///
+/// `xs` and `ys` must point to list objects.
+#[no_mangle]
+pub unsafe extern "C" fn tao_rt_list_join(xs: Word, ys: Word) -> Word {
+    make_list(&[list(xs), list(ys)])
+}
+
+/// The items of a list after the first `n`.
+///
+/// # Safety
+///
+/// `xs` must point to a list object.
+#[no_mangle]
+pub unsafe extern "C" fn tao_rt_list_skip(xs: Word, n: Word) -> Word {
+    let xs = list(xs);
+    make_list(&[&xs[(n as usize).min(xs.len())..]])
+}
+
+/// The first `n` items of a list.
+///
+/// # Safety
+///
+/// `xs` must point to a list object.
+#[no_mangle]
+pub unsafe extern "C" fn tao_rt_list_trim(xs: Word, n: Word) -> Word {
+    let xs = list(xs);
+    make_list(&[&xs[..(n as usize).min(xs.len())]])
+}
+
+/// Print a string, producing the next universe.
+///
+/// # Safety
+///
+/// `s` must point to a list object of characters.
+#[no_mangle]
+pub unsafe extern "C" fn tao_rt_print(universe: Word, s: Word) -> Word {
+    let s = list(s).iter().map(|c| char::from_u32(*c as u32).unwrap_or('?')).collect::<String>();
+    match PRINT.with(|p| p.get()) {
+        // SAFETY: `with_print` guarantees that the callback is alive while it is installed
+        Some(print) => (*print)(&s),
+        // Nobody is listening for IO errors, so they're ignored
+        None => { let _ = writeln!(stdout(), "{}", s); },
+    }
+    universe + 1
+}
+
+#[no_mangle]
+pub extern "C" fn tao_rt_input(universe: Word) -> Word {
+    let mut s = String::new();
+    print!("> ");
+    let _ = stdout().flush();
+    let _ = stdin().read_line(&mut s);
+
+    alloc_from(&[universe + 1, make_str(s.trim_end())])
+}
+
+/// Display a real in the same way as the VM does.
+#[no_mangle]
+pub extern "C" fn tao_rt_real_display(x: Word) -> Word {
+    make_str(&format!("{:?}", f64::from_bits(x as u64)))
+}
+
+#[no_mangle]
+pub extern "C" fn tao_rt_handlers_len() -> Word {
+    HANDLERS.with(|handlers| handlers.borrow().len() as Word)
+}
+
+#[no_mangle]
+pub extern "C" fn tao_rt_handlers_truncate(len: Word) {
+    HANDLERS.with(|handlers| handlers.borrow_mut().truncate(len as usize))
+}
+
+#[no_mangle]
+pub extern "C" fn tao_rt_handlers_push(eff: Word, handler: Word) {
+    HANDLERS.with(|handlers| handlers.borrow_mut().push((eff, handler)))
+}
+
+/// Capture the handlers currently in scope so that an effect object can restore them when it is propagated. The
+/// snapshot is a list of effect and handler pairs.
+#[no_mangle]
+pub extern "C" fn tao_rt_handlers_snapshot() -> Word {
+    let handlers = HANDLERS.with(|handlers| handlers.borrow().clone());
+    make_list(&[&handlers.into_iter().flat_map(|(eff, handler)| [eff, handler]).collect::<Vec<_>>()])
+}
+
+/// Bring the handlers in a snapshot back into scope.
+///
+/// # Safety
+///
+/// `snapshot` must have been produced by [`tao_rt_handlers_snapshot`].
+#[no_mangle]
+pub unsafe extern "C" fn tao_rt_handlers_extend(snapshot: Word) {
+    let snapshot = list(snapshot);
+    HANDLERS.with(|handlers| handlers
+        .borrow_mut()
+        .extend(snapshot.chunks_exact(2).map(|pair| (pair[0], pair[1]))))
+}
+
+/// Find the innermost handler for the effect, or produce 0 if there is none.
+#[no_mangle]
+pub extern "C" fn tao_rt_handlers_find(eff: Word) -> Word {
+    HANDLERS.with(|handlers| handlers
+        .borrow()
+        .iter()
+        .rev()
+        .find(|(e, _)| *e == eff)
+        .map_or(0, |(_, handler)| *handler))
+}
@@ -24,18 +24,33 @@ impl Deref for Ident {
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
-// #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-// pub enum PathBase {
-//     Root,
-//     Parent,
-//     This,
-// }
-
-// pub struct Item {
-//     pub name: SrcNode<Ident>,
-//     pub base: SrcNode<PathBase>,
-//     pub path: Vec<SrcNode<Ident>>,
-// }
+/// A path to an item, optionally qualified by the modules it may be found through (i.e: `list::map`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub base: Vec<SrcNode<Ident>>,
+    pub name: SrcNode<Ident>,
+}
+
+impl Path {
+    /// Create an unqualified path that refers to an item in the current scope.
+    pub fn local(name: SrcNode<Ident>) -> Self {
+        Self { base: Vec::new(), name }
+    }
+
+    /// If the path is unqualified, get the name it refers to.
+    pub fn as_local(&self) -> Option<Ident> {
+        if self.base.is_empty() { Some(*self.name) } else { None }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for module in &self.base {
+            write!(f, "{}::", **module)?;
+        }
+        write!(f, "{}", *self.name)
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum UnaryOp {
@@ -123,19 +138,21 @@ pub enum Type {
     Universe,
     Unknown,
     List(SrcNode<Self>),
+    /// The list type constructor, `[]`, which has kind `* -> *`.
+    ListCon,
     Tuple(Vec<SrcNode<Self>>),
     Record(Vec<(SrcNode<Ident>, SrcNode<Self>)>),
     Func(SrcNode<Self>, SrcNode<Self>),
-    // TODO: Replace name with `Item` when ready
-    Data(SrcNode<Ident>, Vec<SrcNode<Self>>),
+    Data(SrcNode<Path>, Vec<SrcNode<Self>>),
     Assoc(SrcNode<Self>, SrcNode<Ident>),
-    Effect(SrcNode<Ident>, Vec<SrcNode<Self>>, SrcNode<Self>),
+    /// An effect object that may perform any of a set of effects, producing a value, as in `print + input ~ Str`.
+    Effect(Vec<SrcNode<EffectInst>>, SrcNode<Self>),
 }
 
 impl Type {
     pub fn is_fully_specified(&self) -> bool {
         match self {
-            Self::Error | Self::Universe => true,
+            Self::Error | Self::Universe | Self::ListCon => true,
             Self::Unknown => false,
             Self::List(item) => item.is_fully_specified(),
             Self::Tuple(fields) => fields
@@ -149,16 +166,35 @@ impl Type {
                 .iter()
                 .all(|arg| arg.is_fully_specified()),
             Self::Assoc(inner, _) => inner.is_fully_specified(),
-            Self::Effect(_, args, out) => args
+            Self::Effect(effs, out) => effs
                 .iter()
-                .all(|arg| arg.is_fully_specified()) && out.is_fully_specified(),
+                .all(|eff| eff.args
+                    .iter()
+                    .all(|arg| arg.is_fully_specified())) && out.is_fully_specified(),
         }
     }
 }
 
+/// An effect and its generic arguments, as in `yield Nat`. The name may also refer to an effect alias.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectInst {
+    pub name: SrcNode<Path>,
+    pub args: Vec<SrcNode<Type>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    // Generated only by parser errors.
+    Error,
+    /// The kind of types, `*`.
+    Type,
+    /// The kind of type constructors, like `* -> *`.
+    Func(SrcNode<Self>, SrcNode<Self>),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClassInst {
-    pub name: SrcNode<Ident>,
+    pub name: SrcNode<Path>,
     pub params: Vec<SrcNode<Type>>,
 }
 
@@ -174,8 +210,7 @@ pub enum Pat {
     Record(Vec<(SrcNode<Ident>, SrcNode<Binding>)>),
     ListExact(Vec<SrcNode<Binding>>),
     ListFront(Vec<SrcNode<Binding>>, Option<SrcNode<Binding>>),
-    // TODO: Replace name with `Item` when ready
-    Deconstruct(SrcNode<Ident>, SrcNode<Binding>),
+    Deconstruct(SrcNode<Path>, SrcNode<Binding>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -187,8 +222,10 @@ pub struct Binding {
 
 #[derive(Debug, PartialEq)]
 pub enum LangDef {
-    IoUnit,
-    IoBind,
+    // The `pure` item of the monad lang class, used by `return`
+    Pure,
+    // The `bind` item of the monad lang class, used by `do` blocks
+    Bind,
 }
 
 #[derive(Debug, PartialEq)]
@@ -196,8 +233,9 @@ pub enum Expr {
     // Generated only by parser errors.
     Error,
     Literal(Literal),
-    // TODO: replace with `Item` when scoping is added
     Local(Ident),
+    // A qualified path to a definition in another module (i.e: `list::map`)
+    Path(Path),
     LangDef(LangDef),
     Tuple(Vec<SrcNode<Self>>),
     List(Vec<SrcNode<Self>>, Vec<SrcNode<Self>>),
@@ -210,16 +248,18 @@ pub enum Expr {
     If(SrcNode<Self>, SrcNode<Self>, SrcNode<Self>),
     Func(SrcNode<Vec<(SrcNode<Vec<SrcNode<Binding>>>, SrcNode<Self>)>>),
     Apply(SrcNode<Self>, SrcNode<Self>),
-    Cons(SrcNode<Ident>, SrcNode<Self>),
+    Cons(SrcNode<Path>, SrcNode<Self>),
     ClassAccess(SrcNode<Type>, SrcNode<Ident>),
     Intrinsic(SrcNode<Ident>, Vec<SrcNode<Self>>),
     Update(SrcNode<Self>, Vec<(SrcNode<Ident>, SrcNode<Self>)>),
     Block(Vec<SrcNode<Self>>, SrcNode<Self>),
     Handle {
         expr: SrcNode<Self>,
-        eff_name: SrcNode<Ident>,
+        eff_name: SrcNode<Path>,
         eff_args: Vec<SrcNode<Type>>,
         send: SrcNode<Binding>,
+        // The resumption of the handled computation, given to handlers that decide how (and whether) to resume it
+        resume: Option<SrcNode<Binding>>,
         recv: SrcNode<Self>
     },
 }
@@ -227,12 +267,23 @@ pub enum Expr {
 #[derive(Debug, PartialEq)]
 pub struct GenericTy {
     pub name: SrcNode<Ident>,
+    /// The kind of the generic type, if it is not `*`.
+    pub kind: Option<SrcNode<Kind>>,
+    pub obligations: Vec<SrcNode<ClassInst>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WherePred {
+    pub ty: SrcNode<Type>,
     pub obligations: Vec<SrcNode<ClassInst>>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Generics {
     pub tys: Vec<GenericTy>,
+    /// Bounds from a `where` clause, which may constrain any type (such as `[A]` or `S.Item`) rather than just a
+    /// generic type.
+    pub preds: Vec<SrcNode<WherePred>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -261,7 +312,11 @@ pub struct Def {
 pub enum ClassItem {
     Value {
         name: SrcNode<Ident>,
+        /// Generic types that are specific to this item (i.e: `A` and `B` in `=> map A, B : (A -> B) -> Self A -> Self B`).
+        generics: SrcNode<Generics>,
         ty: SrcNode<Type>,
+        /// A default implementation, used by members that do not provide their own.
+        default: Option<SrcNode<Expr>>,
     },
     Type {
         name: SrcNode<Ident>,
@@ -272,6 +327,8 @@ pub enum ClassItem {
 #[derive(Debug, PartialEq)]
 pub struct Class {
     pub name: SrcNode<Ident>,
+    /// The kind of the types that may be members of the class, if it is not `*`.
+    pub kind: Option<SrcNode<Kind>>,
     pub obligation: Vec<SrcNode<ClassInst>>,
     pub generics: SrcNode<Generics>,
     pub items: Vec<ClassItem>,
@@ -305,6 +362,14 @@ pub struct Effect {
     pub recv: SrcNode<Type>,
 }
 
+/// A name for a set of effects, as in `effect io = print + input`.
+#[derive(Debug, PartialEq)]
+pub struct EffectAlias {
+    pub name: SrcNode<Ident>,
+    pub generics: SrcNode<Generics>,
+    pub effects: Vec<SrcNode<EffectInst>>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ItemKind {
     Data(Data),
@@ -313,6 +378,7 @@ pub enum ItemKind {
     Class(Class),
     Member(Member),
     Effect(Effect),
+    EffectAlias(EffectAlias),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -321,42 +387,67 @@ pub struct Attr {
     pub args: Option<Vec<SrcNode<Self>>>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// Only visible within the module that declares it.
+    Private,
+    /// Visible to any module that imports the declaring module (`pub`).
+    Public,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Item {
     pub kind: ItemKind,
     pub attrs: Vec<SrcNode<Attr>>,
+    pub vis: Visibility,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Import {
+    pub vis: Visibility,
+    pub path: SrcNode<Intern<String>>,
+    /// `import "foo.tao" as foo` makes the module's items available as `foo::bar`.
+    pub alias: Option<SrcNode<Ident>>,
+    /// `import "foo.tao" with bar, Baz as Qux` brings only the listed items into scope (optionally renamed).
+    /// If neither an alias nor a list of items is given, all public items of the module are brought into scope.
+    pub items: Option<Vec<(SrcNode<Ident>, Option<SrcNode<Ident>>)>>,
+}
+
+impl Import {
+    /// Whether this import brings every public item of the imported module into scope.
+    pub fn is_glob(&self) -> bool { self.alias.is_none() && self.items.is_none() }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Module {
-    pub imports: Vec<SrcNode<Intern<String>>>,
+    pub imports: Vec<Import>,
     pub items: Vec<Item>,
 }
 
 impl Module {
-    pub fn classes(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], &Class)> + '_ {
+    pub fn classes(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &Class)> + '_ {
         self.items
             .iter()
             .filter_map(|item| match &item.kind {
-                ItemKind::Class(class) => Some((item.attrs.as_slice(), class)),
+                ItemKind::Class(class) => Some((item.attrs.as_slice(), item.vis, class)),
                 _ => None,
             })
     }
 
-    pub fn datas(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], &Data)> + '_ {
+    pub fn datas(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &Data)> + '_ {
         self.items
             .iter()
             .filter_map(|item| match &item.kind {
-                ItemKind::Data(data) => Some((item.attrs.as_slice(), data)),
+                ItemKind::Data(data) => Some((item.attrs.as_slice(), item.vis, data)),
                 _ => None,
             })
     }
 
-    pub fn aliases(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], &Alias)> + '_ {
+    pub fn aliases(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &Alias)> + '_ {
         self.items
             .iter()
             .filter_map(|item| match &item.kind {
-                ItemKind::Alias(alias) => Some((item.attrs.as_slice(), alias)),
+                ItemKind::Alias(alias) => Some((item.attrs.as_slice(), item.vis, alias)),
                 _ => None,
             })
     }
@@ -370,20 +461,29 @@ impl Module {
             })
     }
 
-    pub fn defs(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], &Def)> + '_ {
+    pub fn defs(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &Def)> + '_ {
+        self.items
+            .iter()
+            .filter_map(|item| match &item.kind {
+                ItemKind::Def(def) => Some((item.attrs.as_slice(), item.vis, def)),
+                _ => None,
+            })
+    }
+
+    pub fn effects(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &Effect)> + '_ {
         self.items
             .iter()
             .filter_map(|item| match &item.kind {
-                ItemKind::Def(def) => Some((item.attrs.as_slice(), def)),
+                ItemKind::Effect(eff) => Some((item.attrs.as_slice(), item.vis, eff)),
                 _ => None,
             })
     }
 
-    pub fn effects(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], &Effect)> + '_ {
+    pub fn effect_aliases(&self) -> impl Iterator<Item = (&[SrcNode<Attr>], Visibility, &EffectAlias)> + '_ {
         self.items
             .iter()
             .filter_map(|item| match &item.kind {
-                ItemKind::Effect(eff) => Some((item.attrs.as_slice(), eff)),
+                ItemKind::EffectAlias(alias) => Some((item.attrs.as_slice(), item.vis, alias)),
                 _ => None,
             })
     }
@@ -34,6 +34,8 @@ impl Error {
 }
 
 impl Error {
+    pub fn kind(&self) -> &ErrorKind { &self.kind }
+
     pub fn new(kind: ErrorKind, span: Span) -> Self {
         Self {
             kind,
@@ -52,8 +54,9 @@ impl Error {
         self
     }
 
-    pub fn write<C: ariadne::Cache<SrcId>>(self, cache: C, writer: impl Write) {
-        use ariadne::{Report, ReportKind, Label, Color, Fmt};
+    // The message of the error and its labels, most important first
+    fn parts(self) -> (String, Vec<(Span, String, ariadne::Color)>) {
+        use ariadne::{Color, Fmt};
 
         let msg = format!(
             "{}{}, expected {}",
@@ -75,37 +78,60 @@ impl Error {
             },
         );
 
-        let report = Report::build(ReportKind::Error, self.span.src(), self.span.start())
+        let mut labels = vec![(
+            self.span,
+            match &self.kind {
+                ErrorKind::UnexpectedEnd => "End of input".to_string(),
+                ErrorKind::Unexpected(pat) => format!("Unexpected {}", pat.fg(Color::Red)),
+                ErrorKind::Unclosed { start, .. } => format!("Delimiter {} is never closed", start.fg(Color::Red)),
+                ErrorKind::NoEndBranch => format!("Requires a {} branch", "\\ ... => ...".fg(Color::Blue)),
+            },
+            Color::Red,
+        )];
+
+        if let ErrorKind::Unclosed { before, before_span, .. } = self.kind {
+            labels.push((
+                before_span,
+                format!("Must be closed before {}", match before {
+                    Some(before) => format!("this {}", before.fg(Color::Yellow)),
+                    None => "end of input".to_string(),
+                }),
+                Color::Yellow,
+            ));
+        }
+
+        if let Some((while_parsing, s)) = self.while_parsing {
+            labels.push((while_parsing, format!("encountered while parsing this {}", s), Color::Blue));
+        }
+
+        (msg, labels)
+    }
+
+    /// Produce the message and labels of the error without rendering them.
+    pub fn diagnostic(self) -> Diagnostic {
+        let (msg, labels) = self.parts();
+        Diagnostic {
+            msg,
+            labels: labels.into_iter().map(|(span, msg, _)| (span, msg)).collect(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn write<C: ariadne::Cache<SrcId>>(self, cache: C, writer: impl Write) {
+        use ariadne::{Report, ReportKind, Label};
+
+        let span = self.span;
+        let (msg, labels) = self.parts();
+
+        let mut report = Report::build(ReportKind::Error, span.src(), span.start())
             .with_code(3)
-            .with_message(msg)
-            .with_label(Label::new(self.span)
-                .with_message(match &self.kind {
-                    ErrorKind::UnexpectedEnd => "End of input".to_string(),
-                    ErrorKind::Unexpected(pat) => format!("Unexpected {}", pat.fg(Color::Red)),
-                    ErrorKind::Unclosed { start, .. } => format!("Delimiter {} is never closed", start.fg(Color::Red)),
-                    ErrorKind::NoEndBranch => format!("Requires a {} branch", "\\ ... => ...".fg(Color::Blue)),
-                })
-                .with_color(Color::Red));
-
-        let report = if let ErrorKind::Unclosed { before, before_span, .. } = self.kind {
-            report
-                .with_label(Label::new(before_span)
-                    .with_message(format!("Must be closed before {}", match before {
-                        Some(before) => format!("this {}", before.fg(Color::Yellow)),
-                        None => "end of input".to_string(),
-                    }))
-                .with_color(Color::Yellow))
-        } else {
-            report
-        };
-
-        let report = if let Some((while_parsing, s)) = self.while_parsing {
-            report.with_label(Label::new(while_parsing)
-                .with_message(format!("encountered while parsing this {}", s))
-                .with_color(Color::Blue))
-        } else {
-            report
-        };
+            .with_message(msg);
+
+        for (span, msg, col) in labels {
+            report = report.with_label(Label::new(span)
+                .with_message(msg)
+                .with_color(col));
+        }
 
         report
             .finish()
@@ -114,6 +140,16 @@ impl Error {
     }
 }
 
+/// An error that has been broken down into its parts, for tools that present errors in their own way (such as language
+/// servers) rather than rendering them with ariadne.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub msg: String,
+    /// Spans that the error refers to, along with a message for each. The most relevant span comes first.
+    pub labels: Vec<(Span, String)>,
+    pub notes: Vec<String>,
+}
+
 impl PartialEq for Error {
     fn eq(&self, other: &Self) -> bool {
         self.kind == other.kind
@@ -7,9 +7,10 @@ pub mod src;
 pub mod ast;
 pub mod node;
 pub mod parse;
+pub mod pretty;
 
 pub use crate::{
-    error::{Error, ErrorKind, Pattern},
+    error::{Error, ErrorKind, Pattern, Diagnostic},
     span::Span,
     node::{Node, SrcNode},
     src::SrcId,
@@ -19,10 +20,12 @@ pub use crate::{
 use std::fmt;
 use chumsky::prelude::*;
 
-fn parse<T>(parser: impl parse::Parser<T>, code: &str, src: SrcId) -> (Option<T>, Vec<Error>) {
+// `offset` is the position of the code within its source, for code that does not start at the beginning of the source.
+// Comments are not seen by the parser, and are returned separately.
+fn parse<T>(parser: impl parse::Parser<T>, code: &str, src: SrcId, offset: usize) -> (Option<T>, Vec<SrcNode<String>>, Vec<Error>) {
     let mut errors = Vec::new();
 
-    let len = code.chars().count();
+    let len = offset + code.chars().count();
     let eoi = Span::new(src, len..len);
 
     let (tokens, mut lex_errors) = token::lexer()
@@ -31,37 +34,70 @@ fn parse<T>(parser: impl parse::Parser<T>, code: &str, src: SrcId) -> (Option<T>
             code
                 .chars()
                 .enumerate()
-                .map(|(i, c)| (c, Span::new(src, i..i + 1))),
+                .map(|(i, c)| (c, Span::new(src, offset + i..offset + i + 1))),
         ));
     errors.append(&mut lex_errors);
 
     let tokens = if let Some(tokens) = tokens {
         tokens
     } else {
-        return (None, errors);
+        return (None, Vec::new(), errors);
     };
 
+    let (comments, tokens): (Vec<_>, Vec<_>) = tokens
+        .into_iter()
+        .partition(|(token, _)| matches!(token, Token::Comment(_)));
+    let comments = comments
+        .into_iter()
+        .map(|(token, span)| SrcNode::new(token.to_string(), span))
+        .collect();
+
     let (output, mut parse_errors) = parser.parse_recovery(chumsky::Stream::from_iter(eoi, tokens.into_iter()));
     errors.append(&mut parse_errors);
 
-    (output, errors)
+    (output, comments, errors)
 }
 
 pub fn parse_expr(code: &str, src: SrcId) -> (Option<SrcNode<ast::Expr>>, Vec<Error>) {
-    parse(
+    parse_expr_at(code, src, 0)
+}
+
+/// Like [`parse_expr`], but for an expression that starts `offset` characters into its source (such as the latest input
+/// to a REPL).
+pub fn parse_expr_at(code: &str, src: SrcId, offset: usize) -> (Option<SrcNode<ast::Expr>>, Vec<Error>) {
+    let (expr, _, errors) = parse(
         parse::expr_parser()
             .then_ignore(end())
             .map_with_span(SrcNode::new),
         code,
         src,
-    )
+        offset,
+    );
+    (expr, errors)
 }
 
 pub fn parse_module(code: &str, src: SrcId) -> (Option<SrcNode<ast::Module>>, Vec<Error>) {
+    let (module, _, errors) = parse_module_with_comments(code, src);
+    (module, errors)
+}
+
+/// Like [`parse_module`], but also returns the comments within the code in the order that they appear.
+pub fn parse_module_with_comments(code: &str, src: SrcId) -> (Option<SrcNode<ast::Module>>, Vec<SrcNode<String>>, Vec<Error>) {
     parse(
         parse::module_parser()
             .map_with_span(SrcNode::new),
         code,
         src,
+        0,
     )
 }
+
+/// Format the code of a module in the canonical style, preserving its comments.
+///
+/// Code with syntax errors is never formatted, the errors are returned instead.
+pub fn format(code: &str, src: SrcId) -> Result<String, Vec<Error>> {
+    match parse_module_with_comments(code, src) {
+        (Some(module), comments, errors) if errors.is_empty() => Ok(pretty::format_module(&module, &comments, code)),
+        (_, _, errors) => Err(errors),
+    }
+}
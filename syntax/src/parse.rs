@@ -24,6 +24,21 @@ pub fn type_ident_parser() -> impl Parser<ast::Ident> {
         .map_err(|e: Error| e.expected(Pattern::TypeIdent))
 }
 
+/// A word that only acts as a keyword in certain positions (i.e: `as` in imports) and is otherwise a regular identifier.
+pub fn contextual_keyword_parser(keyword: &'static str) -> impl Parser<()> {
+    just(Token::TermIdent(ast::Ident::new(keyword)))
+        .ignored()
+}
+
+pub fn path_parser(name: impl Parser<ast::Ident>) -> impl Parser<ast::Path> {
+    term_ident_parser()
+        .map_with_span(SrcNode::new)
+        .then_ignore(just(Token::Separator))
+        .repeated()
+        .then(name.map_with_span(SrcNode::new))
+        .map(|(base, name)| ast::Path { base, name })
+}
+
 pub fn bool_parser() -> impl Parser<bool> {
     select! { Token::Bool(x) => x }
         .map_err(|e: Error| e.expected(Pattern::Literal))
@@ -46,10 +61,14 @@ pub fn nested_parser<'a, T: 'a>(parser: impl Parser<T> + 'a, delimiter: Delimite
 
 pub fn type_parser() -> impl Parser<ast::Type> {
     recursive(|ty| {
-        let data = type_ident_parser() // TODO: Replace with `data_item_parser` when ready
+        let data = path_parser(type_ident_parser())
             .map_with_span(SrcNode::new)
             .map(|data_name| ast::Type::Data(data_name, Vec::new()));
 
+        let list_con = just(Token::Open(Delimiter::Brack))
+            .then(just(Token::Close(Delimiter::Brack)))
+            .to(ast::Type::ListCon);
+
         let list = nested_parser(
             ty.clone()
                 .map_with_span(SrcNode::new)
@@ -101,6 +120,7 @@ pub fn type_parser() -> impl Parser<ast::Type> {
 
         let atom = paren_ty
             .or(data)
+            .or(list_con)
             .or(list)
             .or(tuple)
             .or(record)
@@ -119,7 +139,7 @@ pub fn type_parser() -> impl Parser<ast::Type> {
                 SrcNode::new(ast::Type::Assoc(inner, assoc), span)
             });
 
-        let data = type_ident_parser() // TODO: Replace with `data_item_parser` when ready
+        let data = path_parser(type_ident_parser())
             .map_with_span(SrcNode::new)
             .then(assoc.clone().repeated().at_least(1))
             .map(|(data, params)| ast::Type::Data(data, params))
@@ -127,12 +147,10 @@ pub fn type_parser() -> impl Parser<ast::Type> {
             .or(assoc)
             .boxed();
 
-        let effect = term_ident_parser() // TODO: Replace with `term_item_parser` when ready
-            .map_with_span(SrcNode::new)
-            .then(data.clone().repeated())
+        let effect = effect_set_parser(data.clone())
             .then_ignore(just(Token::Tilde))
             .then(data.clone())
-            .map(|((eff, params), out)| ast::Type::Effect(eff, params, out))
+            .map(|(effs, out)| ast::Type::Effect(effs, out))
             .map_with_span(SrcNode::new)
             .or(data)
             .boxed();
@@ -150,8 +168,19 @@ pub fn type_parser() -> impl Parser<ast::Type> {
     })
 }
 
+// A set of effects, as in `print + yield Nat`
+pub fn effect_set_parser(arg: impl Parser<SrcNode<ast::Type>>) -> impl Parser<Vec<SrcNode<ast::EffectInst>>> {
+    path_parser(term_ident_parser())
+        .map_with_span(SrcNode::new)
+        .then(arg.repeated())
+        .map(|(name, args)| ast::EffectInst { name, args })
+        .map_with_span(SrcNode::new)
+        .separated_by(just(Token::Op(Op::Add)))
+        .at_least(1)
+}
+
 pub fn class_inst_parser() -> impl Parser<ast::ClassInst> {
-    type_ident_parser()
+    path_parser(type_ident_parser())
         .map_with_span(SrcNode::new)
         .then(type_parser()
             .map_with_span(SrcNode::new)
@@ -270,7 +299,7 @@ pub fn binding_parser() -> impl Parser<ast::Binding> {
                 .unwrap_or(ast::Pat::Error))
             .map_with_span(SrcNode::new);
 
-        let deconstruct = type_ident_parser() // TODO: Replace with `data_item_parser` when ready
+        let deconstruct = path_parser(type_ident_parser())
             .map_with_span(SrcNode::new)
             .then(binding.or_not())
             .map_with_span(|(data, inner), span| {
@@ -373,7 +402,11 @@ pub fn binding_parser() -> impl Parser<ast::Binding> {
 pub fn expr_parser() -> impl Parser<ast::Expr> {
     recursive(|expr| {
         let litr = literal_parser().map(ast::Expr::Literal);
-        let ident = term_ident_parser().map(ast::Expr::Local);
+        let ident = path_parser(term_ident_parser())
+            .map(|path| match path.as_local() {
+                Some(local) => ast::Expr::Local(local),
+                None => ast::Expr::Path(path),
+            });
 
         let paren_exp_list = nested_parser(
             expr
@@ -460,7 +493,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
         let class_access = type_parser()
             .map_with_span(SrcNode::new)
             .delimited_by(just(Token::Op(Op::Less)), just(Token::Op(Op::More)))
-            .or(type_ident_parser()
+            .or(path_parser(type_ident_parser())
                 .map_with_span(SrcNode::new)
                 .map(|ty| {
                     let ty_span = ty.span();
@@ -534,7 +567,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
                 let mut expr = match tail {
                     Some(tail) => tail,
                     None => SrcNode::new(ast::Expr::Apply(
-                        SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoUnit), span),
+                        SrcNode::new(ast::Expr::LangDef(ast::LangDef::Pure), span),
                         SrcNode::new(ast::Expr::Tuple(Vec::new()), span),
                     ), span),
                 };
@@ -550,7 +583,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
                             }, expr_span);
                             SrcNode::new(ast::Expr::Apply(
                                 SrcNode::new(ast::Expr::Apply(
-                                    SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoBind), expr.span()),
+                                    SrcNode::new(ast::Expr::LangDef(ast::LangDef::Bind), expr.span()),
                                     rhs,
                                 ), expr_span),
                                 SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), expr)], expr_span)), expr_span),
@@ -561,7 +594,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
                             let expr_span = binding.span().union(expr.span());
                             SrcNode::new(ast::Expr::Apply(
                                 SrcNode::new(ast::Expr::Apply(
-                                    SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoBind), binding_span),
+                                    SrcNode::new(ast::Expr::LangDef(ast::LangDef::Bind), binding_span),
                                     rhs,
                                 ), binding_span),
                                 SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), expr)], expr_span)), expr_span),
@@ -579,11 +612,11 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             .ignore_then(expr.clone()
                 .map_with_span(SrcNode::new))
             .map_with_span(|expr, span| ast::Expr::Apply(
-                SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoUnit), span),
+                SrcNode::new(ast::Expr::LangDef(ast::LangDef::Pure), span),
                 expr,
             ));
 
-        let cons_unit = type_ident_parser()
+        let cons_unit = path_parser(type_ident_parser())
             .map_with_span(SrcNode::new)
             .map(|cons| {
                 let span = cons.span();
@@ -803,7 +836,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             })
             .boxed();
 
-        let cons = type_ident_parser()
+        let cons = path_parser(type_ident_parser())
             .map_with_span(SrcNode::new)
             .then(expr.clone().map_with_span(SrcNode::new))
             .map(|(cons, expr)| ast::Expr::Cons(cons, expr))
@@ -812,21 +845,25 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             .boxed();
 
         let handle = cons.then(just(Token::Handle)
-                .ignore_then(term_ident_parser().map_with_span(SrcNode::new))
+                .ignore_then(path_parser(term_ident_parser()).map_with_span(SrcNode::new))
                 .then(type_parser()
                     .map_with_span(SrcNode::new)
                     .repeated())
                 .then_ignore(just(Token::With))
                 .then(binding_parser().map_with_span(SrcNode::new))
+                .then(just(Token::Comma)
+                    .ignore_then(binding_parser().map_with_span(SrcNode::new))
+                    .or_not())
                 .then_ignore(just(Token::Op(Op::RFlow)))
                 .then(expr.clone().map_with_span(SrcNode::new))
                 .or_not())
-            .map_with_span(|(expr, handle), span| if let Some((((eff_name, eff_args), send), recv)) = handle {
+            .map_with_span(|(expr, handle), span| if let Some(((((eff_name, eff_args), send), resume), recv)) = handle {
                 SrcNode::new(ast::Expr::Handle {
                     expr,
                     eff_name,
                     eff_args,
                     send,
+                    resume,
                     recv,
                 }, span)
             } else {
@@ -847,22 +884,69 @@ pub fn obligation_parser() -> impl Parser<Vec<SrcNode<ast::ClassInst>>> {
             .allow_leading())
 }
 
+pub fn kind_parser() -> impl Parser<ast::Kind> {
+    recursive(|kind| {
+        let atom = just(Token::Op(Op::Mul))
+            .to(ast::Kind::Type)
+            .or(kind.delimited_by(just(Token::Open(Delimiter::Paren)), just(Token::Close(Delimiter::Paren))))
+            .map_with_span(SrcNode::new);
+
+        atom.clone()
+            .then(just(Token::Op(Op::RArrow))
+                .ignore_then(atom)
+                .repeated())
+            .map(|(first, rest)| {
+                // Kind arrows are right-associative
+                let mut kinds = std::iter::once(first).chain(rest).collect::<Vec<_>>();
+                let mut kind = kinds.pop().unwrap();
+                while let Some(i) = kinds.pop() {
+                    let span = i.span().union(kind.span());
+                    kind = SrcNode::new(ast::Kind::Func(i, kind), span);
+                }
+                kind.into_inner()
+            })
+    })
+        .labelled("kind")
+}
+
+/// A kind annotation, such as `: * -> *`.
+pub fn kind_hint_parser() -> impl Parser<SrcNode<ast::Kind>> {
+    just(Token::Colon)
+        .ignore_then(kind_parser().map_with_span(SrcNode::new))
+}
+
 pub fn generics_parser() -> impl Parser<ast::Generics> {
     let obligations = obligation_parser();
 
     type_ident_parser()
         .map_with_span(SrcNode::new)
+        .then(kind_hint_parser().or_not())
         .then(obligations.or_not())
-        .map(|(name, obligations)| ast::GenericTy {
+        .map(|((name, kind), obligations)| ast::GenericTy {
             name,
+            kind,
             obligations: obligations.unwrap_or_else(Vec::new),
         })
         .separated_by(just(Token::Comma))
         .allow_trailing()
-        .map(|tys| ast::Generics { tys })
+        .map(|tys| ast::Generics { tys, preds: Vec::new() })
+}
+
+pub fn where_parser() -> impl Parser<Vec<SrcNode<ast::WherePred>>> {
+    just(Token::Where)
+        .ignore_then(type_parser()
+            .map_with_span(SrcNode::new)
+            .then(obligation_parser())
+            .map(|(ty, obligations)| ast::WherePred { ty, obligations })
+            .map_with_span(SrcNode::new)
+            .separated_by(just(Token::Comma))
+            .allow_trailing())
+        .or_not()
+        .map(Option::unwrap_or_default)
 }
 
-const ITEM_STARTS: [Token; 8] = [
+const ITEM_STARTS: [Token; 9] = [
+    Token::Pub,
     Token::Data,
     Token::Type,
     Token::Def,
@@ -889,13 +973,17 @@ pub fn data_parser() -> impl Parser<ast::Data> {
         .ignore_then(type_ident_parser()
             .map_with_span(SrcNode::new))
         .then(generics_parser().map_with_span(SrcNode::new))
+        .then(where_parser())
         .then(just(Token::Op(Op::Eq))
             // TODO: Don't use `Result`
             .ignore_then(type_parser().map_with_span(SrcNode::new).map(Err)
                 .or(branches(variant).map(Some).or(just(Token::Pipe).to(None)).map(Ok)))
             .or_not())
-        .map(|((name, generics), variants)| ast::Data {
-            generics,
+        .map(|(((name, mut generics), preds), variants)| ast::Data {
+            generics: {
+                generics.preds = preds;
+                generics
+            },
             variants: variants
                 .unwrap_or_else(|| Ok(Some(vec![(name.clone(), SrcNode::new(ast::Type::Tuple(Vec::new()), name.span()))])))
                 .unwrap_or_else(|ty| Some(vec![(name.clone(), ty)]))
@@ -936,11 +1024,15 @@ pub fn fn_parser() -> impl Parser<ast::Def> {
             .map_with_span(SrcNode::new))
         .then(generics_parser().map_with_span(SrcNode::new))
         .then(ty_hint_parser())
+        .then(where_parser())
         .then_ignore(just(Token::Op(Op::Eq)))
         .then(branches(branch)
             .map_with_span(|branches, span| SrcNode::new(ast::Expr::Func(SrcNode::new(branches, span)), span)))
-        .map(|(((name, generics), ty_hint), body)| ast::Def {
-            generics,
+        .map(|((((name, mut generics), ty_hint), preds), body)| ast::Def {
+            generics: {
+                generics.preds = preds;
+                generics
+            },
             ty_hint: ty_hint.unwrap_or_else(|| SrcNode::new(ast::Type::Unknown, name.span())),
             name,
             body,
@@ -953,10 +1045,14 @@ pub fn def_parser() -> impl Parser<ast::Def> {
             .map_with_span(SrcNode::new))
         .then(generics_parser().map_with_span(SrcNode::new))
         .then(ty_hint_parser())
+        .then(where_parser())
         .then_ignore(just(Token::Op(Op::Eq)))
         .then(expr_parser().map_with_span(SrcNode::new))
-        .map(|(((name, generics), ty_hint), body)| ast::Def {
-            generics,
+        .map(|((((name, mut generics), ty_hint), preds), body)| ast::Def {
+            generics: {
+                generics.preds = preds;
+                generics
+            },
             ty_hint: ty_hint.unwrap_or_else(|| SrcNode::new(ast::Type::Unknown, name.span())),
             name,
             body,
@@ -967,10 +1063,17 @@ pub fn def_parser() -> impl Parser<ast::Def> {
 pub fn class_parser() -> impl Parser<ast::Class> {
     let value = term_ident_parser()
         .map_with_span(SrcNode::new)
+        .then(generics_parser().map_with_span(SrcNode::new))
         .then(ty_hint_parser())
-        .map(|(name, ty)| ast::ClassItem::Value {
+        .then(just(Token::Op(Op::Eq))
+            .ignore_then(expr_parser()
+                .map_with_span(SrcNode::new))
+            .or_not())
+        .map(|(((name, generics), ty), default)| ast::ClassItem::Value {
             ty: ty.unwrap_or_else(|| SrcNode::new(ast::Type::Unknown, name.span())),
             name,
+            generics,
+            default,
         });
 
     let assoc_type = type_ident_parser()
@@ -989,13 +1092,15 @@ pub fn class_parser() -> impl Parser<ast::Class> {
     just(Token::Class)
         .ignore_then(type_ident_parser()
             .map_with_span(SrcNode::new))
+        .then(kind_hint_parser().or_not())
         .then(obligation_parser().or_not())
         .then(generics_parser().map_with_span(SrcNode::new))
         .then(just(Token::Op(Op::Eq))
             .ignore_then(item.repeated())
             .or_not())
-        .map(|(((name, obligation), generics), items)| ast::Class {
+        .map(|((((name, kind), obligation), generics), items)| ast::Class {
             name,
+            kind,
             obligation: obligation.unwrap_or_default(),
             generics,
             items: items.unwrap_or_default(),
@@ -1032,11 +1137,16 @@ pub fn member_parser() -> impl Parser<ast::Member> {
             .then_ignore(just(Token::Of))
             .then(class_inst_parser()
                 .map_with_span(SrcNode::new))
+            .then(where_parser())
             .then(just(Token::Op(Op::Eq))
                 .ignore_then(item.repeated())
                 .or_not()))
-        .map(|(generics, ((member, class), items))| ast::Member {
-            generics: generics.unwrap_or_else(|| SrcNode::new(ast::Generics { tys: Vec::new() }, member.span())),
+        .map(|(generics, (((member, class), preds), items))| ast::Member {
+            generics: {
+                let mut generics = generics.unwrap_or_else(|| SrcNode::new(ast::Generics { tys: Vec::new(), preds: Vec::new() }, member.span()));
+                generics.preds = preds;
+                generics
+            },
             member,
             class,
             items: items.unwrap_or_default(),
@@ -1064,8 +1174,24 @@ pub fn effect_parser() -> impl Parser<ast::Effect> {
         .boxed()
 }
 
+pub fn effect_alias_parser() -> impl Parser<ast::EffectAlias> {
+    just(Token::Effect)
+        .ignore_then(term_ident_parser().map_with_span(SrcNode::new))
+        .then(generics_parser().map_with_span(SrcNode::new))
+        .then_ignore(just(Token::Op(Op::Eq)))
+        .then(effect_set_parser(type_parser().map_with_span(SrcNode::new)))
+        .map(|((name, generics), effects)| ast::EffectAlias {
+            name,
+            generics,
+            effects,
+        })
+        .boxed()
+}
+
 pub fn item_parser() -> impl Parser<ast::Item> {
+    // Attributes may name types, as in `$[derive(Eq)]`
     let attr = recursive(|attr| term_ident_parser()
+        .or(type_ident_parser())
         .map_with_span(SrcNode::new)
         .then(nested_parser(
                 attr
@@ -1089,13 +1215,18 @@ pub fn item_parser() -> impl Parser<ast::Item> {
         .repeated()
         .flatten();
 
-    let item = def_parser().map(ast::ItemKind::Def)
+    let named_item = def_parser().map(ast::ItemKind::Def)
         .or(fn_parser().map(ast::ItemKind::Def))
         .or(data_parser().map(ast::ItemKind::Data))
         .or(alias_parser().map(ast::ItemKind::Alias))
         .or(class_parser().map(ast::ItemKind::Class))
-        .or(member_parser().map(ast::ItemKind::Member))
-        .or(effect_parser().map(ast::ItemKind::Effect));
+        .or(effect_parser().map(ast::ItemKind::Effect))
+        .or(effect_alias_parser().map(ast::ItemKind::EffectAlias));
+
+    // Members have no name, so they cannot be given a visibility
+    let item = vis_parser()
+        .then(named_item)
+        .or(member_parser().map(|member| (ast::Visibility::Private, ast::ItemKind::Member(member))));
 
     let tail = one_of::<_, _, Error>(ITEM_STARTS)
         .ignored()
@@ -1104,7 +1235,7 @@ pub fn item_parser() -> impl Parser<ast::Item> {
 
     attrs
         .then(item)
-        .map(|(attrs, kind)| ast::Item { attrs, kind })
+        .map(|(attrs, (vis, kind))| ast::Item { attrs, kind, vis })
         .map_with_span(|item, span| (item, span))
         .then(tail.rewind().map(Ok).map(Some).or_else(|e| Ok(Some(Err(e)))))
         .validate(|((item, span), mut r), _, emit| {
@@ -1115,9 +1246,43 @@ pub fn item_parser() -> impl Parser<ast::Item> {
         })
 }
 
+pub fn vis_parser() -> impl Parser<ast::Visibility> {
+    just(Token::Pub)
+        .to(ast::Visibility::Public)
+        .or_not()
+        .map(|vis| vis.unwrap_or(ast::Visibility::Private))
+}
+
+pub fn import_parser() -> impl Parser<ast::Import> {
+    let alias = contextual_keyword_parser("as")
+        .ignore_then(term_ident_parser().or(type_ident_parser()).map_with_span(SrcNode::new));
+
+    let item = term_ident_parser()
+        .or(type_ident_parser())
+        .map_with_span(SrcNode::new)
+        .then(alias.clone().or_not());
+
+    vis_parser()
+        .then_ignore(just(Token::Import))
+        .then(select! { Token::Str(path) => path }.map_with_span(SrcNode::new))
+        .then(alias.or_not())
+        .then(just(Token::With)
+            .ignore_then(item
+                .separated_by(just(Token::Comma))
+                .at_least(1)
+                .allow_trailing())
+            .or_not())
+        .map(|(((vis, path), alias), items)| ast::Import {
+            vis,
+            path,
+            alias,
+            items,
+        })
+        .boxed()
+}
+
 pub fn module_parser() -> impl Parser<ast::Module> {
-    let imports = just(Token::Import)
-        .ignore_then(select! { Token::Str(path) => path }.map_with_span(SrcNode::new))
+    let imports = import_parser()
         .repeated();
 
     imports
@@ -1189,4 +1354,38 @@ mod tests {
             "{:#?}", res,
         );
     }
+
+    #[test]
+    fn qualified_path() {
+        let code = r#"
+            list::map
+        "#;
+        let len = code.chars().count();
+
+        let span = |i| Span::new(SrcId::empty(), i..i + 1);
+
+        let tokens = token::lexer()
+            .parse(chumsky::Stream::from_iter(
+                span(len),
+                code.chars().enumerate().map(|(i, c)| (c, span(i))),
+            ))
+            .unwrap();
+
+        let res = expr_parser()
+            .then_ignore(end())
+            .parse(chumsky::Stream::from_iter(
+                span(len),
+                tokens.into_iter(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            res,
+            ast::Expr::Path(ast::Path {
+                base: vec![SrcNode::new(ast::Ident::new("list"), Span::empty())],
+                name: SrcNode::new(ast::Ident::new("map"), Span::empty()),
+            }),
+            "{:#?}", res,
+        );
+    }
 }
@@ -0,0 +1,1082 @@
+//! A pretty-printer that formats modules in a canonical style.
+//!
+//! Layout is described by a [`Doc`], in which groups are laid out on a single line where they fit and broken across
+//! lines where they do not. Several syntactic forms are desugared by the parser and must be resugared here (`do` blocks,
+//! `return`, and `x:f` application, which is distinguished from `f(x)` by the order of the spans of its parts).
+//! Comments are not part of the AST, so they are placed before the item, branch, statement, or element that follows them
+//! in the original code (or at the end of the line they ended, if they followed code).
+
+use super::*;
+use crate::ast::*;
+
+const WIDTH: usize = 100;
+const INDENT: usize = 4;
+
+enum Doc {
+    Text(String),
+    /// Printed as the given text if the enclosing group fits on one line, otherwise a line break.
+    Line(&'static str),
+    /// A line break, which prevents the enclosing groups from being laid out on one line.
+    HardLine,
+    /// Text that is only printed if the enclosing group is broken across lines (such as trailing commas).
+    IfBroken(&'static str),
+    /// A comment, which must be followed by a line break.
+    Comment(String),
+    /// Indent the lines within by one level.
+    Nest(Box<Doc>),
+    /// Lay out the contents on one line if they fit, otherwise break every `Line` within across lines.
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+impl From<&str> for Doc {
+    fn from(s: &str) -> Self { Doc::Text(s.to_string()) }
+}
+
+impl From<String> for Doc {
+    fn from(s: String) -> Self { Doc::Text(s) }
+}
+
+macro_rules! docs {
+    ($($doc:expr),* $(,)?) => { Doc::Concat(vec![$(Doc::from($doc)),*]) };
+}
+
+fn nest(doc: Doc) -> Doc { Doc::Nest(Box::new(doc)) }
+
+fn group(doc: Doc) -> Doc { Doc::Group(Box::new(doc)) }
+
+fn join(docs: Vec<Doc>, sep: impl Fn() -> Doc) -> Doc {
+    let mut joined = Vec::new();
+    for (i, doc) in docs.into_iter().enumerate() {
+        if i > 0 {
+            joined.push(sep());
+        }
+        joined.push(doc);
+    }
+    Doc::Concat(joined)
+}
+
+// Whether the doc can be laid out on one line within the given width
+fn fits(doc: &Doc, mut width: usize) -> bool {
+    let mut stack = vec![doc];
+    while let Some(doc) = stack.pop() {
+        let text: &str = match doc {
+            Doc::Text(s) => s,
+            Doc::Line(s) => s,
+            Doc::HardLine | Doc::Comment(_) => return false,
+            Doc::IfBroken(_) => continue,
+            Doc::Nest(doc) | Doc::Group(doc) => {
+                stack.push(doc);
+                continue;
+            },
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev());
+                continue;
+            },
+        };
+        if text.contains('\n') {
+            return false;
+        }
+        match width.checked_sub(text.chars().count()) {
+            Some(rest) => width = rest,
+            None => return false,
+        }
+    }
+    true
+}
+
+fn render(doc: &Doc) -> String {
+    let mut out = String::new();
+    let mut col = 0;
+    let mut stack = vec![(0, false, doc)];
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) | Doc::Comment(s) => {
+                out += s;
+                col = match s.rfind('\n') {
+                    Some(idx) => s[idx + 1..].chars().count(),
+                    None => col + s.chars().count(),
+                };
+            },
+            Doc::Line(s) if flat => {
+                out += s;
+                col += s.chars().count();
+            },
+            Doc::Line(_) | Doc::HardLine => {
+                out.truncate(out.trim_end_matches(' ').len());
+                out.push('\n');
+                out.extend(std::iter::repeat(' ').take(indent));
+                col = indent;
+            },
+            Doc::IfBroken(s) => if !flat {
+                out += s;
+                col += s.chars().count();
+            },
+            Doc::Nest(doc) => stack.push((indent + INDENT, flat, doc)),
+            Doc::Group(doc) => stack.push((indent, flat || fits(doc, WIDTH.saturating_sub(col)), doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+        }
+    }
+    out
+}
+
+/// Format a module in the canonical style.
+///
+/// `comments` are the comments within `code` (the code that the module was parsed from) in the order that they appear,
+/// as produced by [`parse_module_with_comments`].
+pub fn format_module(module: &Module, comments: &[SrcNode<String>], code: &str) -> String {
+    let mut fmt = Formatter {
+        code: code.chars().collect(),
+        comments,
+        next_comment: 0,
+    };
+    let doc = fmt.module(module);
+    let out = render(&doc);
+    let out = out.trim_end();
+    if out.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", out)
+    }
+}
+
+// Expression precedence levels, from loosest to tightest binding
+const HANDLE: u8 = 0;
+const CONS: u8 = 1;
+const WITH: u8 = 2;
+const LOGICAL: u8 = 3;
+const COMPARISON: u8 = 4;
+const JOIN: u8 = 5;
+const SUM: u8 = 6;
+const PRODUCT: u8 = 7;
+const UNARY: u8 = 8;
+const PROPAGATE: u8 = 9;
+const CHAIN: u8 = 10;
+const ATOM: u8 = 11;
+
+// Type precedence levels, from loosest to tightest binding
+const TY_FUNC: u8 = 0;
+const TY_EFFECT: u8 = 1;
+const TY_DATA: u8 = 2;
+const TY_ASSOC: u8 = 3;
+const TY_ATOM: u8 = 4;
+
+fn binary_level(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Xor => LOGICAL,
+        BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Less | BinaryOp::LessEq | BinaryOp::More | BinaryOp::MoreEq => COMPARISON,
+        BinaryOp::Join => JOIN,
+        BinaryOp::Add | BinaryOp::Sub => SUM,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => PRODUCT,
+    }
+}
+
+fn delimited(open: &str, close: &str, pad: &'static str, docs: Vec<Doc>, force_comma: bool) -> Doc {
+    if docs.is_empty() {
+        return format!("{}{}", open, close).into();
+    }
+    group(docs![
+        open,
+        nest(docs![
+            Doc::Line(pad),
+            join(docs, || docs![",", Doc::Line(" ")]),
+            if force_comma { ",".into() } else { Doc::IfBroken(",") },
+        ]),
+        Doc::Line(pad),
+        close,
+    ])
+}
+
+fn is_unit(expr: &Expr) -> bool { matches!(expr, Expr::Tuple(items) if items.is_empty()) }
+
+fn is_cons_unit(expr: &Expr) -> bool { matches!(expr, Expr::Cons(_, inner) if is_unit(inner)) }
+
+enum DoItem<'a> {
+    Stmt(&'a SrcNode<Expr>),
+    Bind(&'a SrcNode<Binding>, &'a SrcNode<Expr>),
+}
+
+impl DoItem<'_> {
+    fn start(&self) -> usize {
+        match self {
+            DoItem::Stmt(expr) => expr.span().range().start,
+            DoItem::Bind(binding, _) => binding.span().range().start,
+        }
+    }
+}
+
+// A `let binding <- rhs; rest` within a `do` block, which the parser desugars into `Bind(rhs, fn binding => rest)`
+fn do_bind(expr: &Expr) -> Option<(&SrcNode<Binding>, &SrcNode<Expr>, &SrcNode<Expr>)> {
+    if let Expr::Apply(f, then) = expr {
+        if let (Expr::Apply(bind, rhs), Expr::Func(branches)) = (&**f, &**then) {
+            if let (Expr::LangDef(LangDef::Bind), [(bindings, rest)]) = (&**bind, branches.as_slice()) {
+                if let [binding] = bindings.as_slice() {
+                    return Some((binding, rhs, rest));
+                }
+            }
+        }
+    }
+    None
+}
+
+// The statements of a `do` block and the expression that it ends with, if the expression is one
+fn do_block(expr: &SrcNode<Expr>) -> Option<(Vec<DoItem<'_>>, Option<&SrcNode<Expr>>)> {
+    let mut items = Vec::new();
+    let mut expr = expr;
+    while let Some((binding, rhs, rest)) = do_bind(expr) {
+        // Statements are bindings that ignore their value
+        items.push(if binding.name.is_none() && binding.ty.is_none() && matches!(&*binding.pat, Pat::Wildcard) {
+            DoItem::Stmt(rhs)
+        } else {
+            DoItem::Bind(binding, rhs)
+        });
+        expr = rest;
+    }
+
+    if items.is_empty() {
+        None
+    } else {
+        // Blocks that end with a statement return `()`
+        let tail = match &**expr {
+            Expr::Apply(f, arg) if matches!(&**f, Expr::LangDef(LangDef::Pure)) && is_unit(arg) => None,
+            _ => Some(expr),
+        };
+        Some((items, tail))
+    }
+}
+
+// Whether the expression is `do` or `return` syntax, rather than a function application
+fn is_sugar(expr: &Expr) -> bool {
+    do_bind(expr).is_some() || matches!(expr, Expr::Apply(f, _) if matches!(&**f, Expr::LangDef(_)))
+}
+
+// Whether the expression is an application written as `arg:f`, rather than `f(arg)`
+fn is_infix(expr: &Expr) -> bool {
+    matches!(expr, Expr::Apply(f, arg) if arg.span().range().start < f.span().range().start) && !is_sugar(expr)
+}
+
+fn is_direct(expr: &Expr) -> bool {
+    matches!(expr, Expr::Apply(..)) && !is_infix(expr) && !is_sugar(expr)
+}
+
+fn expr_level(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Handle { .. } => HANDLE,
+        Expr::Cons(_, inner) if !is_unit(inner) => CONS,
+        Expr::Update(..) => WITH,
+        Expr::Binary(op, _, _) => binary_level(**op),
+        Expr::Unary(op, _) if **op == UnaryOp::Propagate => PROPAGATE,
+        Expr::Unary(..) => UNARY,
+        Expr::Access(..) => CHAIN,
+        Expr::Apply(..) if !is_sugar(expr) => CHAIN,
+        _ => ATOM,
+    }
+}
+
+// Whether the expression ends with a subexpression that would consume any code that follows it
+fn is_open(expr: &Expr) -> bool {
+    match expr {
+        Expr::Let(..) | Expr::If(..) | Expr::Match(..) | Expr::Func(..) | Expr::Handle { .. } => true,
+        Expr::Cons(_, inner) => !is_unit(inner),
+        Expr::Apply(f, _) => matches!(&**f, Expr::LangDef(LangDef::Pure)),
+        _ => false,
+    }
+}
+
+fn literal_code(litr: &Literal) -> String {
+    fn escape(c: char) -> String {
+        match c {
+            '\\' => "\\\\".to_string(),
+            '"' => "\\\"".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            '\x08' => "\\b".to_string(),
+            '\x0C' => "\\f".to_string(),
+            c => c.to_string(),
+        }
+    }
+
+    match litr {
+        Literal::Nat(x) => x.to_string(),
+        Literal::Int(x) => format!("{}i", x),
+        Literal::Real(x) => {
+            let s = x.to_string();
+            if s.contains('.') { s } else { format!("{}.0", s) }
+        },
+        Literal::Bool(x) => if *x { "True" } else { "False" }.to_string(),
+        Literal::Char(c) => format!("'{}'", escape(*c)),
+        Literal::Str(s) => format!("\"{}\"", s.chars().map(escape).collect::<String>()),
+    }
+}
+
+fn attr(attr: &Attr) -> String {
+    match &attr.args {
+        Some(args) => format!("{}({})", *attr.name, args.iter().map(|arg| self::attr(arg)).collect::<Vec<_>>().join(", ")),
+        None => attr.name.to_string(),
+    }
+}
+
+fn ty(ty: &SrcNode<Type>, level: u8) -> String {
+    let (ty_level, s) = match &**ty {
+        Type::Error => unreachable!("modules with syntax errors are never formatted"),
+        Type::Universe => (TY_ATOM, "@".to_string()),
+        Type::Unknown => (TY_ATOM, "?".to_string()),
+        Type::List(item) => (TY_ATOM, format!("[{}]", self::ty(item, TY_FUNC))),
+        Type::ListCon => (TY_ATOM, "[]".to_string()),
+        Type::Tuple(items) => (TY_ATOM, match items.as_slice() {
+            [item] => format!("({},)", self::ty(item, TY_FUNC)),
+            items => format!("({})", items.iter().map(|item| self::ty(item, TY_FUNC)).collect::<Vec<_>>().join(", ")),
+        }),
+        Type::Record(fields) if fields.is_empty() => (TY_ATOM, "{}".to_string()),
+        Type::Record(fields) => (TY_ATOM, format!("{{ {} }}", fields
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", **name, self::ty(ty, TY_FUNC)))
+            .collect::<Vec<_>>()
+            .join(", "))),
+        Type::Func(i, o) => (TY_FUNC, format!("{} -> {}", self::ty(i, TY_EFFECT), self::ty(o, TY_FUNC))),
+        Type::Data(path, args) if args.is_empty() => (TY_ATOM, path.to_string()),
+        Type::Data(path, args) => (TY_DATA, format!("{}{}", **path, params(args, TY_ASSOC))),
+        Type::Assoc(inner, name) => (TY_ASSOC, format!("{}.{}", self::ty(inner, TY_ASSOC), **name)),
+        Type::Effect(effs, out) => (TY_EFFECT, format!("{} ~ {}", effect_set(effs), self::ty(out, TY_DATA))),
+    };
+    if ty_level < level { format!("({})", s) } else { s }
+}
+
+// Type parameters are parsed greedily, so every parameter but the last must be an atom to not absorb those after it
+fn params(params: &[SrcNode<Type>], last_level: u8) -> String {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| format!(" {}", ty(param, if i + 1 == params.len() { last_level } else { TY_ASSOC })))
+        .collect()
+}
+
+fn class_inst(inst: &ClassInst) -> String {
+    format!("{}{}", *inst.name, params(&inst.params, TY_FUNC))
+}
+
+fn obligations(obligations: &[SrcNode<ClassInst>]) -> String {
+    obligations.iter().map(|inst| class_inst(inst)).collect::<Vec<_>>().join(" + ")
+}
+
+fn kind(kind: &Kind) -> String {
+    match kind {
+        Kind::Error => unreachable!("modules with syntax errors are never formatted"),
+        Kind::Type => "*".to_string(),
+        Kind::Func(i, o) if matches!(&**i, Kind::Func(_, _)) => format!("({}) -> {}", self::kind(i), self::kind(o)),
+        Kind::Func(i, o) => format!("{} -> {}", self::kind(i), self::kind(o)),
+    }
+}
+
+// A kind annotation, including the leading space
+fn kind_hint(kind: &Option<SrcNode<Kind>>) -> String {
+    kind.as_ref().map(|kind| format!(" : {}", self::kind(kind))).unwrap_or_default()
+}
+
+// Generics as they appear after the name of an item, including the leading space
+fn generics(generics: &Generics) -> String {
+    generics.tys
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| format!(
+            "{}{}{}{}",
+            if i == 0 { " " } else { ", " },
+            *ty.name,
+            kind_hint(&ty.kind),
+            if ty.obligations.is_empty() { String::new() } else { format!(" < {}", obligations(&ty.obligations)) },
+        ))
+        .collect()
+}
+
+// A `where` clause as it appears after the signature of an item, including the leading space
+fn where_clause(generics: &Generics) -> String {
+    if generics.preds.is_empty() {
+        String::new()
+    } else {
+        format!(" where {}", generics.preds
+            .iter()
+            .map(|pred| format!("{} < {}", ty(&pred.ty, TY_FUNC), obligations(&pred.obligations)))
+            .collect::<Vec<_>>()
+            .join(", "))
+    }
+}
+
+fn effect_set(effs: &[SrcNode<EffectInst>]) -> String {
+    effs
+        .iter()
+        .map(|eff| format!("{}{}", *eff.name, params(&eff.args, TY_DATA)))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+// A type hint as it appears after a name, including the leading space
+fn ty_hint(hint: &SrcNode<Type>) -> String {
+    match &**hint {
+        Type::Unknown => String::new(),
+        _ => format!(" : {}", ty(hint, TY_FUNC)),
+    }
+}
+
+fn item_start(item: &Item) -> usize {
+    let span = match &item.kind {
+        ItemKind::Data(data) => data.name.span(),
+        ItemKind::Alias(alias) => alias.name.span(),
+        ItemKind::Def(def) => def.name.span(),
+        ItemKind::Class(class) => class.name.span(),
+        ItemKind::Member(member) => member.generics.span(),
+        ItemKind::Effect(eff) => eff.name.span(),
+        ItemKind::EffectAlias(alias) => alias.name.span(),
+    };
+    item.attrs.first().map_or(span, |attr| attr.span()).range().start
+}
+
+struct Formatter<'a> {
+    code: Vec<char>,
+    comments: &'a [SrcNode<String>],
+    next_comment: usize,
+}
+
+impl<'a> Formatter<'a> {
+    // Whether the comment follows code on the same line
+    fn is_trailing(&self, comment: &SrcNode<String>) -> bool {
+        self.code[..comment.span().range().start]
+            .iter()
+            .rev()
+            .find(|c| **c != ' ' && **c != '\t')
+            .map_or(false, |c| *c != '\n')
+    }
+
+    // Whether the comment is followed by a blank line
+    fn is_spaced(&self, comment: &SrcNode<String>) -> bool {
+        self.code[comment.span().range().end..]
+            .iter()
+            .filter(|c| **c != ' ' && **c != '\t' && **c != '\r')
+            .take(2)
+            .filter(|c| **c == '\n')
+            .count() == 2
+    }
+
+    // Take the comments that start before the given position
+    fn take_comments(&mut self, pos: usize, trailing_only: bool) -> Vec<&'a SrcNode<String>> {
+        let comments = self.comments;
+        let mut taken = Vec::new();
+        while let Some(comment) = comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span().range().start < pos && (!trailing_only || self.is_trailing(comment)))
+        {
+            taken.push(comment);
+            self.next_comment += 1;
+        }
+        taken
+    }
+
+    // Comments that precede the code at the given position, each on its own line
+    fn leading_comments(&mut self, pos: usize) -> Doc {
+        let mut docs = Vec::new();
+        for comment in self.take_comments(pos, false) {
+            docs.push(Doc::Comment(comment.to_string()));
+            docs.push(Doc::HardLine);
+            if self.is_spaced(comment) {
+                docs.push(Doc::HardLine);
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    // Comments at the end of the current line, before the code at the given position
+    fn trailing_comments(&mut self, pos: usize) -> Doc {
+        Doc::Concat(self.take_comments(pos, true)
+            .into_iter()
+            .flat_map(|comment| [" ".into(), Doc::Comment(comment.to_string())])
+            .collect())
+    }
+
+    // Literals are kept as they were written (`0u`, escapes in strings, etc.) unless that cannot be recovered
+    fn literal(&self, litr: &Literal, span: Span) -> String {
+        let code = self.code[span.range()].iter().collect::<String>();
+        if code.starts_with(|c: char| c.is_ascii_digit() || c == '"' || c == '\'') {
+            code
+        } else {
+            literal_code(litr)
+        }
+    }
+
+    fn module(&mut self, module: &Module) -> Doc {
+        let starts = module.imports
+            .iter()
+            .map(|import| import.path.span().range().start)
+            .chain(module.items.iter().map(item_start))
+            .chain(std::iter::once(usize::MAX))
+            .collect::<Vec<_>>();
+
+        let mut docs = Vec::new();
+        for (i, import) in module.imports.iter().enumerate() {
+            docs.push(self.leading_comments(starts[i]));
+            docs.push(self.import(import));
+            docs.push(self.trailing_comments(starts[i + 1]));
+            docs.push(Doc::HardLine);
+        }
+        for (i, item) in module.items.iter().enumerate() {
+            let idx = module.imports.len() + i;
+            if idx > 0 {
+                docs.push(Doc::HardLine);
+            }
+            docs.push(self.leading_comments(starts[idx]));
+            docs.push(self.item(item));
+            docs.push(self.trailing_comments(starts[idx + 1]));
+            docs.push(Doc::HardLine);
+        }
+        if self.next_comment < self.comments.len() && !docs.is_empty() {
+            docs.push(Doc::HardLine);
+        }
+        docs.push(self.leading_comments(usize::MAX));
+
+        Doc::Concat(docs)
+    }
+
+    fn import(&mut self, import: &Import) -> Doc {
+        let mut s = String::new();
+        if import.vis == Visibility::Public {
+            s += "pub ";
+        }
+        s += "import ";
+        s += &self.literal(&Literal::Str(*import.path), import.path.span());
+        if let Some(alias) = &import.alias {
+            s += &format!(" as {}", **alias);
+        }
+        if let Some(items) = &import.items {
+            s += " with ";
+            s += &items
+                .iter()
+                .map(|(name, alias)| match alias {
+                    Some(alias) => format!("{} as {}", **name, **alias),
+                    None => name.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+        s.into()
+    }
+
+    fn item(&mut self, item: &Item) -> Doc {
+        let mut docs = Vec::new();
+        if !item.attrs.is_empty() {
+            docs.push(format!("$[{}]", item.attrs.iter().map(|a| attr(a)).collect::<Vec<_>>().join(", ")).into());
+            docs.push(Doc::HardLine);
+        }
+        if item.vis == Visibility::Public {
+            docs.push("pub ".into());
+        }
+        docs.push(match &item.kind {
+            ItemKind::Data(data) => self.data(data),
+            ItemKind::Alias(alias) => format!("type {}{} = {}", *alias.name, generics(&alias.generics), ty(&alias.ty, TY_FUNC)).into(),
+            ItemKind::Def(def) => self.def(def),
+            ItemKind::Class(class) => self.class(class),
+            ItemKind::Member(member) => self.member(member),
+            ItemKind::Effect(eff) => format!(
+                "effect {}{} = {} => {}",
+                *eff.name,
+                generics(&eff.generics),
+                ty(&eff.send, TY_FUNC),
+                ty(&eff.recv, TY_FUNC),
+            ).into(),
+            ItemKind::EffectAlias(alias) => format!(
+                "effect {}{} = {}",
+                *alias.name,
+                generics(&alias.generics),
+                effect_set(&alias.effects),
+            ).into(),
+        });
+        Doc::Concat(docs)
+    }
+
+    // Lines that each start with a prefix, as used by data variants and the items of classes and members
+    fn lines<T>(&mut self, items: &[T], start: impl Fn(&T) -> usize, mut f: impl FnMut(&mut Self, usize, &T) -> Doc) -> Doc {
+        let mut docs = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            docs.push(Doc::HardLine);
+            docs.push(self.leading_comments(start(item)));
+            docs.push(f(self, i, item));
+            if let Some(next) = items.get(i + 1) {
+                docs.push(self.trailing_comments(start(next)));
+            }
+        }
+        nest(Doc::Concat(docs))
+    }
+
+    fn data(&mut self, data: &Data) -> Doc {
+        let head = format!("data {}{}{}", *data.name, generics(&data.generics), where_clause(&data.generics));
+        match data.variants.as_slice() {
+            [] => format!("{} = |", head).into(),
+            // Data with a single variant of the same name may be written as an alias-like declaration
+            [(name, ty)] if **name == *data.name => if is_ty_unit(ty) {
+                head.into()
+            } else {
+                format!("{} = {}", head, self::ty(ty, TY_FUNC)).into()
+            },
+            variants => {
+                let lines = self.lines(variants, |(name, _)| name.span().range().start, |_, i, (name, ty)| format!(
+                    "{} {}{}",
+                    if i + 1 == variants.len() { "\\" } else { "|" },
+                    **name,
+                    if is_ty_unit(ty) { String::new() } else { format!(" {}", self::ty(ty, TY_FUNC)) },
+                ).into());
+                docs![head, " =", lines]
+            },
+        }
+    }
+
+    fn def(&mut self, def: &Def) -> Doc {
+        let sig = format!("{}{}{}{}", *def.name, generics(&def.generics), ty_hint(&def.ty_hint), where_clause(&def.generics));
+        match &*def.body {
+            // Definitions of functions are written with `fn`, which takes the branches of the function directly
+            Expr::Func(branches) if !branches.is_empty() => docs!["fn ", sig, " =", self.branches(branches)],
+            _ => docs!["def ", sig, " =", self.rhs(&def.body)],
+        }
+    }
+
+    fn class(&mut self, class: &Class) -> Doc {
+        let head = format!(
+            "class {}{}{}{}",
+            *class.name,
+            kind_hint(&class.kind),
+            if class.obligation.is_empty() { String::new() } else { format!(" < {}", obligations(&class.obligation)) },
+            generics(&class.generics),
+        );
+        if class.items.is_empty() {
+            return head.into();
+        }
+
+        let start = |item: &ClassItem| match item {
+            ClassItem::Value { name, .. } | ClassItem::Type { name, .. } => name.span().range().start,
+        };
+        let lines = self.lines(&class.items, start, |this, _, item| match item {
+            ClassItem::Value { name, generics: gens, ty, default: None } => format!("=> {}{}{}", **name, generics(gens), ty_hint(ty)).into(),
+            ClassItem::Value { name, generics: gens, ty, default: Some(default) } => docs![format!("=> {}{}{} =", **name, generics(gens), ty_hint(ty)), this.rhs(default)],
+            ClassItem::Type { name, obligations: obls } if obls.is_empty() => format!("=> {}", **name).into(),
+            ClassItem::Type { name, obligations: obls } => format!("=> {} < {}", **name, obligations(obls)).into(),
+        });
+        docs![head, " =", lines]
+    }
+
+    fn member(&mut self, member: &Member) -> Doc {
+        let head = format!(
+            "{}member {} of {}{}",
+            if member.generics.tys.is_empty() { String::new() } else { format!("for{} ", generics(&member.generics)) },
+            ty(&member.member, TY_FUNC),
+            class_inst(&member.class),
+            where_clause(&member.generics),
+        );
+        if member.items.is_empty() {
+            return head.into();
+        }
+
+        let start = |item: &MemberItem| match item {
+            MemberItem::Value { name, .. } | MemberItem::Type { name, .. } => name.span().range().start,
+        };
+        let lines = self.lines(&member.items, start, |this, _, item| match item {
+            MemberItem::Value { name, val } => docs![format!("=> {} =", **name), this.rhs(val)],
+            MemberItem::Type { name, ty } => format!("=> {} = {}", **name, self::ty(ty, TY_FUNC)).into(),
+        });
+        docs![head, " =", lines]
+    }
+
+    fn binding(&self, binding: &SrcNode<Binding>) -> String {
+        let mut s = match (&binding.name, &*binding.pat) {
+            (Some(name), Pat::Wildcard) => name.to_string(),
+            (Some(name), Pat::Single(inner)) => format!("{} ~ {}", **name, self.binding(inner)),
+            (Some(name), _) => format!("{} ~ {}", **name, self.pat(&binding.pat)),
+            (None, _) => self.pat(&binding.pat),
+        };
+        if let Some(ty) = &binding.ty {
+            s += &format!(" : {}", self::ty(ty, TY_FUNC));
+        }
+        s
+    }
+
+    fn bindings(&self, bindings: &[SrcNode<Binding>]) -> String {
+        bindings.iter().map(|binding| self.binding(binding)).collect::<Vec<_>>().join(", ")
+    }
+
+    fn pat(&self, pat: &SrcNode<Pat>) -> String {
+        match &**pat {
+            Pat::Error => unreachable!("modules with syntax errors are never formatted"),
+            Pat::Wildcard => "_".to_string(),
+            Pat::Literal(litr) => self.literal(litr, pat.span()),
+            Pat::Single(inner) => format!("({})", self.binding(inner)),
+            Pat::Binary(op, lhs, rhs) => format!("{} {} {}", self.binding(lhs), **op, self.literal(rhs, rhs.span())),
+            Pat::Tuple(items) => match items.as_slice() {
+                [item] => format!("({},)", self.binding(item)),
+                items => format!("({})", self.bindings(items)),
+            },
+            Pat::Record(fields) if fields.is_empty() => "{}".to_string(),
+            Pat::Record(fields) => format!("{{ {} }}", fields
+                .iter()
+                .map(|(field, binding)| match (&binding.name, &*binding.pat) {
+                    (Some(name), Pat::Wildcard) if **name == **field => match &binding.ty {
+                        Some(ty) => format!("{}: {}", **field, self::ty(ty, TY_FUNC)),
+                        None => field.to_string(),
+                    },
+                    _ => format!("{} ~ {}", **field, self.binding(binding)),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")),
+            Pat::ListExact(items) => format!("[{}]", self.bindings(items)),
+            Pat::ListFront(items, tail) => format!(
+                "[{}{}..{}]",
+                self.bindings(items),
+                if items.is_empty() { "" } else { " " },
+                tail.as_ref().map_or(String::new(), |tail| format!(" {}", self.binding(tail))),
+            ),
+            Pat::Deconstruct(path, inner) if inner.name.is_none() && inner.ty.is_none() && matches!(&*inner.pat, Pat::Tuple(items) if items.is_empty()) => path.to_string(),
+            Pat::Deconstruct(path, inner) => format!("{} {}", **path, self.binding(inner)),
+        }
+    }
+
+    // The code that follows `=` or `=>`. Most expressions start on the same line and break internally, but `let` reads
+    // best on a line of its own.
+    fn rhs(&mut self, expr: &SrcNode<Expr>) -> Doc {
+        if let Expr::Let(..) = &**expr {
+            group(nest(docs![Doc::Line(" "), self.expr(expr, HANDLE, true)]))
+        } else {
+            docs![" ", self.expr(expr, HANDLE, true)]
+        }
+    }
+
+    fn branch(&mut self, (bindings, body): &(SrcNode<Vec<SrcNode<Binding>>>, SrcNode<Expr>)) -> Doc {
+        docs![self.bindings(bindings), " =>", self.rhs(body)]
+    }
+
+    fn branches(&mut self, branches: &[(SrcNode<Vec<SrcNode<Binding>>>, SrcNode<Expr>)]) -> Doc {
+        match branches {
+            [] => " |".into(),
+            [branch] => docs![" ", self.branch(branch)],
+            branches => self.lines(branches, |(bindings, _)| bindings.span().range().start, |this, i, branch| docs![
+                if i + 1 == branches.len() { "\\ " } else { "| " },
+                this.branch(branch),
+            ]),
+        }
+    }
+
+    // Elements separated by commas, each preceded by the comments before it
+    fn exprs(&mut self, exprs: &[SrcNode<Expr>]) -> Vec<Doc> {
+        exprs
+            .iter()
+            .map(|expr| docs![self.leading_comments(expr.span().range().start), self.expr(expr, HANDLE, true)])
+            .collect()
+    }
+
+    fn fields(&mut self, fields: &[(SrcNode<Ident>, SrcNode<Expr>)]) -> Doc {
+        let docs = fields
+            .iter()
+            .map(|(name, val)| {
+                let comments = self.leading_comments(name.span().range().start);
+                match &**val {
+                    Expr::Local(local) if local == &**name => docs![comments, name.to_string()],
+                    _ => docs![comments, format!("{}:", **name), self.rhs(val)],
+                }
+            })
+            .collect();
+        delimited("{", "}", " ", docs, false)
+    }
+
+    fn parens(&mut self, expr: &SrcNode<Expr>) -> Doc {
+        docs!["(", self.expr(expr, HANDLE, true), ")"]
+    }
+
+    // Print an expression such that it parses at the given precedence level. If `tail` is false, code follows the
+    // expression and must not be consumed by it.
+    fn expr(&mut self, expr: &SrcNode<Expr>, level: u8, tail: bool) -> Doc {
+        if expr_level(expr) < level || (!tail && is_open(expr)) {
+            self.parens(expr)
+        } else {
+            self.expr_inner(expr, tail)
+        }
+    }
+
+    fn expr_inner(&mut self, expr: &SrcNode<Expr>, tail: bool) -> Doc {
+        match &**expr {
+            Expr::Error => unreachable!("modules with syntax errors are never formatted"),
+            Expr::Literal(litr) => self.literal(litr, expr.span()).into(),
+            Expr::Local(local) => local.to_string().into(),
+            Expr::Path(path) => path.to_string().into(),
+            Expr::LangDef(_) => unreachable!("language definitions only appear within `do` blocks and `return`"),
+            Expr::Tuple(items) => {
+                let docs = self.exprs(items);
+                delimited("(", ")", "", docs, items.len() == 1)
+            },
+            Expr::List(items, tails) => {
+                let mut docs = self.exprs(items);
+                if !tails.is_empty() {
+                    let tails = self.exprs(tails);
+                    let tails = docs![".. ", join(tails, || ", ".into())];
+                    match docs.pop() {
+                        Some(last) => docs.push(docs![last, " ", tails]),
+                        None => docs.push(tails),
+                    }
+                }
+                delimited("[", "]", "", docs, false)
+            },
+            Expr::Record(fields) => self.fields(fields),
+            // `Data.field` would be a class access
+            Expr::Access(inner, field) if is_cons_unit(inner) => docs![self.parens(inner), format!(".{}", **field)],
+            Expr::Access(inner, field) => docs![self.expr(inner, CHAIN, false), format!(".{}", **field)],
+            Expr::Unary(op, inner) => match **op {
+                UnaryOp::Propagate => docs![self.expr(inner, PROPAGATE, false), "?"],
+                op => docs![op.to_string(), self.expr(inner, UNARY, tail)],
+            },
+            Expr::Binary(op, a, b) => {
+                let level = binary_level(**op);
+                // `Data - x` would be parsed as `Data` applied to `-x`
+                let a = if **op == BinaryOp::Sub && is_cons_unit(a) { self.parens(a) } else { self.expr(a, level, false) };
+                group(docs![a, format!(" {}", **op), nest(docs![Doc::Line(" "), self.expr(b, level + 1, tail)])])
+            },
+            Expr::Let(bindings, body) => {
+                let bindings = bindings
+                    .iter()
+                    .map(|(binding, val)| docs![self.binding(binding), " =", self.rhs(val)])
+                    .collect();
+                group(docs![
+                    "let ",
+                    group(nest(join(bindings, || docs![",", Doc::Line(" ")]))),
+                    " in",
+                    Doc::Line(" "),
+                    self.expr(body, HANDLE, true),
+                ])
+            },
+            Expr::Match(inputs, branches) => {
+                let inputs = inputs.iter().map(|input| self.expr(input, HANDLE, true)).collect();
+                docs!["match ", join(inputs, || ", ".into()), " in", self.branches(branches)]
+            },
+            Expr::If(pred, a, b) => group(docs![
+                "if ",
+                self.expr(pred, HANDLE, true),
+                nest(docs![
+                    Doc::Line(" "),
+                    "then ",
+                    self.expr(a, HANDLE, true),
+                    Doc::Line(" "),
+                    "else ",
+                    self.expr(b, HANDLE, true),
+                ]),
+            ]),
+            Expr::Func(branches) => docs!["fn", self.branches(branches)],
+            Expr::Apply(..) => self.apply(expr, tail),
+            Expr::Cons(path, inner) if is_unit(inner) => path.to_string().into(),
+            Expr::Cons(path, inner) => docs![format!("{} ", **path), self.expr(inner, HANDLE, true)],
+            Expr::ClassAccess(ty, field) => match &**ty {
+                Type::Data(path, args) if args.is_empty() => format!("{}.{}", **path, **field).into(),
+                _ => format!("<{}>.{}", self::ty(ty, TY_FUNC), **field).into(),
+            },
+            Expr::Intrinsic(name, args) if args.is_empty() => format!("@{}", **name).into(),
+            Expr::Intrinsic(name, args) => {
+                let args = self.exprs(args);
+                docs![format!("@{}", **name), delimited("(", ")", "", args, false)]
+            },
+            Expr::Update(inner, fields) => docs![self.expr(inner, LOGICAL, false), " with ", self.fields(fields)],
+            Expr::Block(items, last) => {
+                let mut docs = self.exprs(items)
+                    .into_iter()
+                    .map(|item| docs![item, ";"])
+                    .collect::<Vec<_>>();
+                if !is_unit(last) {
+                    docs.push(docs![self.leading_comments(last.span().range().start), self.expr(last, HANDLE, true)]);
+                }
+                if docs.is_empty() {
+                    "@{}".into()
+                } else {
+                    group(docs![
+                        "@{",
+                        nest(docs![Doc::Line(" "), join(docs, || Doc::Line(" "))]),
+                        Doc::Line(" "),
+                        "}",
+                    ])
+                }
+            },
+            Expr::Handle { expr: inner, eff_name, eff_args, send, resume, recv } => group(docs![
+                self.expr(inner, CONS, false),
+                nest(docs![
+                    Doc::Line(" "),
+                    format!(
+                        "handle {}{} with {}{} =>",
+                        **eff_name,
+                        params(eff_args, TY_FUNC),
+                        self.binding(send),
+                        resume.as_ref().map_or(String::new(), |resume| format!(", {}", self.binding(resume))),
+                    ),
+                    self.rhs(recv),
+                ]),
+            ]),
+        }
+    }
+
+    fn apply(&mut self, expr: &SrcNode<Expr>, tail: bool) -> Doc {
+        if let Some((items, last)) = do_block(expr) {
+            return self.do_block(&items, last, expr.span());
+        }
+
+        match &**expr {
+            Expr::Apply(f, arg) if matches!(&**f, Expr::LangDef(LangDef::Pure)) => {
+                docs!["return ", self.expr(arg, HANDLE, tail)]
+            },
+            // `x:f:g`
+            _ if is_infix(expr) => {
+                let mut fs = Vec::new();
+                let mut base = expr;
+                while let Expr::Apply(f, arg) = &**base {
+                    if !is_infix(base) {
+                        break;
+                    }
+                    fs.push(f);
+                    base = arg;
+                }
+
+                let base = self.expr(base, CHAIN, false);
+                let calls = fs
+                    .into_iter()
+                    .rev()
+                    .map(|f| docs![Doc::Line(""), ":", self.infix_fn(f)])
+                    .collect();
+                group(docs![base, nest(Doc::Concat(calls))])
+            },
+            // `f(x, y)`
+            _ => {
+                let mut args = Vec::new();
+                let mut callee = expr;
+                while let Expr::Apply(f, arg) = &**callee {
+                    if !is_direct(callee) {
+                        break;
+                    }
+                    args.push(arg);
+                    callee = f;
+                }
+                args.reverse();
+
+                let callee = match &**callee {
+                    // Arguments directly after an intrinsic are its own
+                    Expr::Intrinsic(name, intrinsic_args) if intrinsic_args.is_empty() => format!("@{}()", **name).into(),
+                    // `x:f(y)` applies `f` to `y` first and `Data(x)` is a constructor
+                    _ if is_infix(callee) || is_cons_unit(callee) => self.parens(callee),
+                    _ => self.expr(callee, CHAIN, false),
+                };
+                let args = args.into_iter().map(|arg| docs![
+                    self.leading_comments(arg.span().range().start),
+                    self.expr(arg, HANDLE, true),
+                ]).collect();
+                docs![callee, delimited("(", ")", "", args, false)]
+            },
+        }
+    }
+
+    // The function in `x:f`, which must be an atom (optionally applied directly to arguments)
+    fn infix_fn(&mut self, f: &SrcNode<Expr>) -> Doc {
+        if is_direct(f) {
+            let mut callee = f;
+            while let Expr::Apply(inner, _) = &**callee {
+                if !is_direct(callee) {
+                    break;
+                }
+                callee = inner;
+            }
+            if (expr_level(callee) == ATOM && !is_open(callee)) || is_infix(callee) || is_cons_unit(callee) {
+                self.expr(f, CHAIN, false)
+            } else {
+                self.parens(f)
+            }
+        } else {
+            self.expr(f, ATOM, false)
+        }
+    }
+
+    fn do_block(&mut self, items: &[DoItem<'_>], last: Option<&SrcNode<Expr>>, span: Span) -> Doc {
+        let mut docs = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            docs.push(Doc::HardLine);
+            docs.push(self.leading_comments(item.start()));
+            docs.push(match item {
+                DoItem::Stmt(expr) => self.expr(expr, HANDLE, true),
+                DoItem::Bind(binding, rhs) => docs![format!("let {} <-", self.binding(binding)), self.rhs(rhs)],
+            });
+            docs.push(";".into());
+            let next = items
+                .get(i + 1)
+                .map(|item| item.start())
+                .or_else(|| last.map(|last| last.span().range().start))
+                .unwrap_or(span.range().end);
+            docs.push(self.trailing_comments(next));
+        }
+        if let Some(last) = last {
+            docs.push(Doc::HardLine);
+            docs.push(self.leading_comments(last.span().range().start));
+            docs.push(self.expr(last, HANDLE, true));
+        }
+        // Comments at the end of the block stay within it
+        for comment in self.take_comments(span.range().end, false) {
+            docs.push(Doc::HardLine);
+            docs.push(Doc::Comment(comment.to_string()));
+        }
+        docs!["do {", nest(Doc::Concat(docs)), Doc::HardLine, "}"]
+    }
+}
+
+fn is_ty_unit(ty: &Type) -> bool { matches!(ty, Type::Tuple(items) if items.is_empty()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        let code = r#"
+import "lib/std.tao"
+# Adds things
+fn add_all A < Add : [A] -> A =
+| [] => A.zero # Nothing to add
+\ [x .. xs] => x + xs:add_all
+
+def main = do {
+    let xs <- return [1, 2, 3];
+    print(xs:add_all:show);
+}
+"#;
+        let formatted = format(code, SrcId::empty()).unwrap();
+        assert_eq!(formatted, r#"import "lib/std.tao"
+
+# Adds things
+fn add_all A < Add : [A] -> A =
+    | [] => A.zero # Nothing to add
+    \ [x .. xs] => x + xs:add_all
+
+def main = do {
+    let xs <- return [1, 2, 3];
+    print(xs:add_all:show);
+}
+"#);
+    }
+
+    // Formatting every module in the repository must not change its meaning, and formatted code must stay the same
+    // when formatted again
+    #[test]
+    fn round_trip() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut dirs = vec![root.join("lib"), root.join("examples")];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().map_or(false, |ext| ext == "tao") {
+                    let code = std::fs::read_to_string(&path).unwrap();
+                    let (module, errors) = parse_module(&code, SrcId::empty());
+                    if !errors.is_empty() {
+                        continue;
+                    }
+                    let formatted = format(&code, SrcId::empty()).unwrap();
+                    assert_eq!(parse_module(&formatted, SrcId::empty()).0, module, "{} changed meaning", path.display());
+                    assert_eq!(format(&formatted, SrcId::empty()).unwrap(), formatted, "{} is not stable", path.display());
+                }
+            }
+        }
+    }
+}
@@ -16,6 +16,10 @@ impl Span {
         Self::new(SrcId::empty(), 0..0)
     }
 
+    pub fn new(src: SrcId, range: Range<usize>) -> Self {
+        <Self as chumsky::Span>::new(src, range)
+    }
+
     pub fn src(&self) -> SrcId { self.src }
 
     pub fn range(&self) -> Range<usize> { self.start()..self.end() }
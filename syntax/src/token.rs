@@ -82,6 +82,7 @@ pub enum Token {
     Pipe,
     EndPipe,
     Import,
+    Pub,
     Data,
     Type,
     Def,
@@ -105,6 +106,9 @@ pub enum Token {
     With,
     Effect,
     Handle,
+    Where,
+    /// A comment (including its delimiters), which the parser never sees but the formatter preserves.
+    Comment(Intern<String>),
 }
 
 impl fmt::Display for Token {
@@ -135,6 +139,7 @@ impl fmt::Display for Token {
             Token::Pipe => write!(f, "|"),
             Token::EndPipe => write!(f, "\\"),
             Token::Import => write!(f, "import"),
+            Token::Pub => write!(f, "pub"),
             Token::Data => write!(f, "data"),
             Token::Type => write!(f, "type"),
             Token::Def => write!(f, "def"),
@@ -154,10 +159,12 @@ impl fmt::Display for Token {
             Token::With => write!(f, "with"),
             Token::Effect => write!(f, "effect"),
             Token::Handle => write!(f, "handle"),
+            Token::Where => write!(f, "where"),
             Token::Tilde => write!(f, "~"),
             Token::Dollar => write!(f, "$"),
             Token::Semicolon => write!(f, ";"),
             Token::At => write!(f, "@"),
+            Token::Comment(s) => write!(f, "{}", s),
         }
     }
 }
@@ -256,6 +263,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
 
     let word = text::ident().map(|s: String| match s.as_str() {
         "import" => Token::Import,
+        "pub" => Token::Pub,
         "data" => Token::Data,
         "type" => Token::Type,
         "def" => Token::Def,
@@ -275,6 +283,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         "with" => Token::With,
         "effect" => Token::Effect,
         "handle" => Token::Handle,
+        "where" => Token::Where,
         "and" => Token::Op(Op::And),
         "or" => Token::Op(Op::Or),
         "xor" => Token::Op(Op::Xor),
@@ -288,14 +297,15 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         },
     });
 
-    let comments = just('#')
-        .then_ignore(just('(')
-            .ignore_then(none_of(')').ignored().repeated())
+    let comment = just('#')
+        .ignore_then(just('(')
+            .ignore_then(none_of(')').repeated().collect::<String>())
             .then_ignore(just(")#"))
-            .or(none_of('\n').ignored().repeated()))
-        .padded()
-        .ignored()
-        .repeated();
+            .map(|s| format!("#({})#", s))
+            .or(none_of('\n').repeated().collect::<String>().map(|s| format!("#{}", s))))
+        .map(Intern::new)
+        .map(Token::Comment)
+        .labelled("comment");
 
     let token = choice((
         ctrl,
@@ -309,6 +319,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         r#char,
         intrinsic,
         at,
+        comment,
     ))
         .or(any()
             .map(Token::Error)
@@ -321,7 +332,6 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         .recover_with(skip_then_retry_until([]));
 
     token
-        .padded_by(comments)
         .repeated()
         .padded()
         .then_ignore(end())
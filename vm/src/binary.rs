@@ -0,0 +1,574 @@
+//! The binary format that compiled programs are stored in, so that they can be run without being compiled again.
+//!
+//! All integers are little-endian. A `str` is a `u64` byte length followed by that many bytes of UTF-8, and `[T]` is a
+//! `u64` count followed by that many `T`s.
+//!
+//! ```text
+//! program = "TAOB" version:u32 entry:u64 does_io:u8 effects:[str] instrs:[instr] debug:[debug]
+//! instr   = opcode:u8 operands...          (see `Writer::instr` for the opcode and operands of each)
+//! value   = 0 int:i64
+//!         | 1 real:u64                     (the bits of an IEEE 754 double)
+//!         | 2 char:u32
+//!         | 3 bool:u8
+//!         | 4 items:[value]                (lists and tuples)
+//!         | 5 addr:u64 captures:[value]
+//!         | 6 variant:u64 inner:value
+//!         | 7 universe:u64
+//!         | 8 variant:u64                  (variants with no fields)
+//! debug   = addr:u64 msg:str span
+//! span    = 0 | 1 src:str start:u64 end:u64
+//! ```
+//!
+//! Relative jumps are `i64`s, while counts, indices, and effects are `u64`s. Effect objects and resumptions cannot
+//! appear in a program, since they are only ever created at runtime, and values may be nested at most
+//! [`MAX_VALUE_DEPTH`] deep.
+//!
+//! [`FORMAT_VERSION`] is incremented whenever the format changes, and programs written with any other version are
+//! rejected when loading.
+
+use super::*;
+use std::{convert::TryFrom, rc::Rc};
+
+/// The bytes that every program starts with.
+pub const MAGIC: [u8; 4] = *b"TAOB";
+
+/// The version of the format that programs are written with.
+pub const FORMAT_VERSION: u32 = 7;
+
+/// How deeply values may be nested within a program. Values are decoded, validated and dropped recursively, so
+/// without a limit a malicious program could overflow the native stack while loading.
+pub const MAX_VALUE_DEPTH: usize = 256;
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, x: u8) { self.0.push(x); }
+    fn u32(&mut self, x: u32) { self.0.extend_from_slice(&x.to_le_bytes()); }
+    fn u64(&mut self, x: u64) { self.0.extend_from_slice(&x.to_le_bytes()); }
+    fn i64(&mut self, x: i64) { self.0.extend_from_slice(&x.to_le_bytes()); }
+    fn usize(&mut self, x: usize) { self.u64(x as u64); }
+    fn isize(&mut self, x: isize) { self.i64(x as i64); }
+
+    fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn value(&mut self, val: &Value) {
+        match val {
+            Value::Int(x) => { self.u8(0); self.i64(*x); },
+            Value::Real(x) => { self.u8(1); self.u64(x.to_bits()); },
+            Value::Char(c) => { self.u8(2); self.u32(*c as u32); },
+            Value::Bool(x) => { self.u8(3); self.u8(*x as u8); },
+            Value::List(items) => {
+                self.u8(4);
+                self.usize(items.len());
+                items.iter().for_each(|item| self.value(item));
+            },
+            Value::Func(addr, captures) => {
+                self.u8(5);
+                self.usize(addr.0);
+                self.usize(captures.len());
+                captures.iter().for_each(|capture| self.value(capture));
+            },
+            Value::Sum(variant, inner) => {
+                self.u8(6);
+                self.usize(*variant);
+                self.value(inner);
+            },
+            Value::Universe(x) => { self.u8(7); self.u64(*x); },
+            Value::Tag(variant) => { self.u8(8); self.usize(*variant); },
+            Value::Effect(_) => panic!("Effect objects cannot be stored in a program"),
+            Value::Resumption(_) => panic!("Resumptions cannot be stored in a program"),
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        match instr {
+            Instr::Error(msg) => { self.u8(0); self.str(msg); },
+            Instr::Nop => self.u8(1),
+            Instr::Break => self.u8(2),
+            Instr::Call(n) => { self.u8(3); self.isize(*n); },
+            Instr::Ret => self.u8(4),
+            Instr::MakeFunc(i, n) => { self.u8(5); self.isize(*i); self.usize(*n); },
+            Instr::ApplyFunc => self.u8(6),
+            Instr::MakeList(n) => { self.u8(7); self.usize(*n); },
+            Instr::IndexList(i) => { self.u8(8); self.usize(*i); },
+            Instr::SkipListImm(i) => { self.u8(9); self.usize(*i); },
+            Instr::SetList(i) => { self.u8(10); self.usize(*i); },
+            Instr::LenList => self.u8(11),
+            Instr::JoinList => self.u8(12),
+            Instr::SkipList => self.u8(13),
+            Instr::TrimList => self.u8(14),
+            Instr::MakeSum(variant) => { self.u8(15); self.usize(*variant); },
+            Instr::IndexSum(variant) => { self.u8(16); self.usize(*variant); },
+            Instr::VariantSum => self.u8(17),
+            Instr::Jump(n) => { self.u8(18); self.isize(*n); },
+            Instr::IfNot => self.u8(19),
+            Instr::Imm(x) => { self.u8(20); self.value(x); },
+            Instr::Pop(n) => { self.u8(21); self.usize(*n); },
+            Instr::Replace => self.u8(22),
+            Instr::Dup => self.u8(23),
+            Instr::PushLocal => self.u8(24),
+            Instr::PopLocal(n) => { self.u8(25); self.usize(*n); },
+            Instr::GetLocal(x) => { self.u8(26); self.usize(*x); },
+            Instr::NotBool => self.u8(27),
+            Instr::NegInt => self.u8(28),
+            Instr::NegReal => self.u8(29),
+            Instr::AddInt => self.u8(30),
+            Instr::SubInt => self.u8(31),
+            Instr::MulInt => self.u8(32),
+            Instr::EqInt => self.u8(33),
+            Instr::EqBool => self.u8(34),
+            Instr::EqChar => self.u8(35),
+            Instr::LessInt => self.u8(36),
+            Instr::MoreInt => self.u8(37),
+            Instr::LessEqInt => self.u8(38),
+            Instr::MoreEqInt => self.u8(39),
+            Instr::AndBool => self.u8(40),
+            Instr::Print => self.u8(41),
+            Instr::Input => self.u8(42),
+            Instr::MakeEffect(i, n) => { self.u8(43); self.isize(*i); self.usize(*n); },
+            Instr::Propagate => self.u8(44),
+            Instr::Suspend(eff) => { self.u8(45); self.usize(eff.0); },
+            Instr::Register(eff, resumable) => { self.u8(46); self.usize(eff.0); self.u8(*resumable as u8); },
+            Instr::Host(name) => { self.u8(47); self.str(name); },
+            Instr::DivInt => self.u8(48),
+            Instr::RemInt => self.u8(49),
+            Instr::AddReal => self.u8(50),
+            Instr::SubReal => self.u8(51),
+            Instr::MulReal => self.u8(52),
+            Instr::DivReal => self.u8(53),
+            Instr::EqReal => self.u8(54),
+            Instr::LessReal => self.u8(55),
+            Instr::MoreReal => self.u8(56),
+            Instr::LessEqReal => self.u8(57),
+            Instr::MoreEqReal => self.u8(58),
+            Instr::IntToReal => self.u8(59),
+            Instr::RealToInt => self.u8(60),
+            Instr::RealToNat => self.u8(61),
+            Instr::DisplayReal => self.u8(62),
+            Instr::TailCall(n) => { self.u8(63); self.usize(*n); },
+            Instr::JumpIfNot(n) => { self.u8(64); self.isize(*n); },
+            Instr::JumpIfNotVariant(variant, n) => { self.u8(65); self.usize(*variant); self.isize(*n); },
+            Instr::JumpIfNotEqIntImm(x, n) => { self.u8(66); self.i64(*x); self.isize(*n); },
+            Instr::DupIndexList(i) => { self.u8(67); self.usize(*i); },
+            Instr::GetLocalIndexList(x, i) => { self.u8(68); self.usize(*x); self.usize(*i); },
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if n > self.0.len() {
+            Err(LoadError::UnexpectedEnd)
+        } else {
+            let (bytes, tail) = self.0.split_at(n);
+            self.0 = tail;
+            Ok(bytes)
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        Ok(<[u8; N]>::try_from(self.bytes(N)?).unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> { Ok(self.bytes(1)?[0]) }
+    fn u32(&mut self) -> Result<u32, LoadError> { Ok(u32::from_le_bytes(self.array()?)) }
+    fn u64(&mut self) -> Result<u64, LoadError> { Ok(u64::from_le_bytes(self.array()?)) }
+    fn i64(&mut self) -> Result<i64, LoadError> { Ok(i64::from_le_bytes(self.array()?)) }
+    fn usize(&mut self) -> Result<usize, LoadError> { usize::try_from(self.u64()?).map_err(|_| LoadError::Invalid("integer")) }
+    fn isize(&mut self) -> Result<isize, LoadError> { isize::try_from(self.i64()?).map_err(|_| LoadError::Invalid("integer")) }
+
+    fn bool(&mut self) -> Result<bool, LoadError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(LoadError::Invalid("boolean")),
+        }
+    }
+
+    // Every item takes up at least one byte, so a count larger than the remaining data must be invalid. Checking this
+    // before allocating prevents malicious counts from exhausting memory.
+    fn count(&mut self) -> Result<usize, LoadError> {
+        let n = self.usize()?;
+        if n > self.0.len() {
+            Err(LoadError::UnexpectedEnd)
+        } else {
+            Ok(n)
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, LoadError> {
+        let len = self.usize()?;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| LoadError::Invalid("string"))
+    }
+
+    fn value(&mut self) -> Result<Value, LoadError> { self.nested_value(0) }
+
+    fn nested_value(&mut self, depth: usize) -> Result<Value, LoadError> {
+        if depth >= MAX_VALUE_DEPTH {
+            return Err(LoadError::Invalid("value nesting"));
+        }
+        let depth = depth + 1;
+        Ok(match self.u8()? {
+            0 => Value::Int(self.i64()?),
+            1 => Value::Real(f64::from_bits(self.u64()?)),
+            2 => Value::Char(char::from_u32(self.u32()?).ok_or(LoadError::Invalid("character"))?),
+            3 => Value::Bool(self.bool()?),
+            4 => Value::List((0..self.count()?).map(|_| self.nested_value(depth)).collect::<Result<_, _>>()?),
+            5 => Value::Func(Addr(self.usize()?), (0..self.count()?).map(|_| self.nested_value(depth)).collect::<Result<_, _>>()?),
+            6 => Value::Sum(self.usize()?, Rc::new(self.nested_value(depth)?)),
+            7 => Value::Universe(self.u64()?),
+            8 => Value::Tag(self.usize()?),
+            _ => return Err(LoadError::Invalid("value")),
+        })
+    }
+
+    fn instr(&mut self) -> Result<Instr, LoadError> {
+        Ok(match self.u8()? {
+            0 => Instr::Error(self.str()?.into()),
+            1 => Instr::Nop,
+            2 => Instr::Break,
+            3 => Instr::Call(self.isize()?),
+            4 => Instr::Ret,
+            5 => Instr::MakeFunc(self.isize()?, self.usize()?),
+            6 => Instr::ApplyFunc,
+            7 => Instr::MakeList(self.usize()?),
+            8 => Instr::IndexList(self.usize()?),
+            9 => Instr::SkipListImm(self.usize()?),
+            10 => Instr::SetList(self.usize()?),
+            11 => Instr::LenList,
+            12 => Instr::JoinList,
+            13 => Instr::SkipList,
+            14 => Instr::TrimList,
+            15 => Instr::MakeSum(self.usize()?),
+            16 => Instr::IndexSum(self.usize()?),
+            17 => Instr::VariantSum,
+            18 => Instr::Jump(self.isize()?),
+            19 => Instr::IfNot,
+            20 => Instr::Imm(self.value()?),
+            21 => Instr::Pop(self.usize()?),
+            22 => Instr::Replace,
+            23 => Instr::Dup,
+            24 => Instr::PushLocal,
+            25 => Instr::PopLocal(self.usize()?),
+            26 => Instr::GetLocal(self.usize()?),
+            27 => Instr::NotBool,
+            28 => Instr::NegInt,
+            29 => Instr::NegReal,
+            30 => Instr::AddInt,
+            31 => Instr::SubInt,
+            32 => Instr::MulInt,
+            33 => Instr::EqInt,
+            34 => Instr::EqBool,
+            35 => Instr::EqChar,
+            36 => Instr::LessInt,
+            37 => Instr::MoreInt,
+            38 => Instr::LessEqInt,
+            39 => Instr::MoreEqInt,
+            40 => Instr::AndBool,
+            41 => Instr::Print,
+            42 => Instr::Input,
+            43 => Instr::MakeEffect(self.isize()?, self.usize()?),
+            44 => Instr::Propagate,
+            45 => Instr::Suspend(Eff(self.usize()?)),
+            46 => Instr::Register(Eff(self.usize()?), self.bool()?),
+            47 => Instr::Host(Ident::new(self.str()?)),
+            48 => Instr::DivInt,
+            49 => Instr::RemInt,
+            50 => Instr::AddReal,
+            51 => Instr::SubReal,
+            52 => Instr::MulReal,
+            53 => Instr::DivReal,
+            54 => Instr::EqReal,
+            55 => Instr::LessReal,
+            56 => Instr::MoreReal,
+            57 => Instr::LessEqReal,
+            58 => Instr::MoreEqReal,
+            59 => Instr::IntToReal,
+            60 => Instr::RealToInt,
+            61 => Instr::RealToNat,
+            62 => Instr::DisplayReal,
+            63 => Instr::TailCall(self.usize()?),
+            64 => Instr::JumpIfNot(self.isize()?),
+            65 => Instr::JumpIfNotVariant(self.usize()?, self.isize()?),
+            66 => Instr::JumpIfNotEqIntImm(self.i64()?, self.isize()?),
+            67 => Instr::DupIndexList(self.usize()?),
+            68 => Instr::GetLocalIndexList(self.usize()?, self.usize()?),
+            opcode => return Err(LoadError::InvalidOpcode(opcode)),
+        })
+    }
+}
+
+impl Program {
+    /// Encode the program in the binary format described in [`binary`](crate::binary).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+
+        w.0.extend_from_slice(&MAGIC);
+        w.u32(FORMAT_VERSION);
+        w.usize(self.entry.0);
+        w.u8(self.does_io as u8);
+
+        w.usize(self.effects.len());
+        self.effects.iter().for_each(|name| w.str(name));
+
+        w.usize(self.instrs.len());
+        self.instrs.iter().for_each(|instr| w.instr(instr));
+
+        w.usize(self.debug.len());
+        for (addr, msg, span) in &self.debug {
+            w.usize(addr.0);
+            w.str(msg);
+            if let Some(span) = span {
+                w.u8(1);
+                w.str(&span.src().to_path().to_string_lossy());
+                w.usize(span.range().start);
+                w.usize(span.range().end);
+            } else {
+                w.u8(0);
+            }
+        }
+
+        w.0
+    }
+
+    /// Decode a program that was encoded with [`Program::encode`], checking that it is well-formed.
+    pub fn decode(bytes: &[u8]) -> Result<Self, LoadError> {
+        let mut r = Reader(bytes);
+
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(LoadError::NotAProgram);
+        }
+        let version = r.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::WrongVersion(version));
+        }
+
+        let mut this = Self::default();
+        this.entry = Addr(r.usize()?);
+        this.does_io = r.bool()?;
+
+        this.effects = (0..r.count()?)
+            .map(|_| Ok(Ident::new(r.str()?)))
+            .collect::<Result<_, _>>()?;
+
+        this.instrs = (0..r.count()?)
+            .map(|_| r.instr())
+            .collect::<Result<_, _>>()?;
+
+        this.debug = (0..r.count()?)
+            .map(|_| Ok((
+                Addr(r.usize()?),
+                r.str()?.to_string(),
+                if r.bool()? {
+                    let src = SrcId::from_path(r.str()?);
+                    let (start, end) = (r.usize()?, r.usize()?);
+                    if start > end {
+                        return Err(LoadError::Invalid("span"));
+                    }
+                    Some(Span::new(src, start..end))
+                } else {
+                    None
+                },
+            )))
+            .collect::<Result<_, _>>()?;
+
+        if !r.0.is_empty() {
+            return Err(LoadError::TrailingData);
+        }
+
+        this.validate()?;
+
+        Ok(this)
+    }
+
+    /// Check that every reference to code or effects within the program is in bounds, so that loading a corrupt
+    /// program is an error rather than a failure at some arbitrary point during execution.
+    fn validate(&self) -> Result<(), LoadError> {
+        let len = self.instrs.len();
+        let check_addr = |addr: Addr, tgt: Option<usize>| match tgt {
+            Some(tgt) if tgt < len => Ok(()),
+            _ => Err(LoadError::InvalidJump(addr)),
+        };
+
+        fn check_value(val: &Value, check: &dyn Fn(usize) -> bool) -> bool {
+            match val {
+                Value::List(items) => items.iter().all(|item| check_value(item, check)),
+                Value::Func(addr, captures) => check(addr.0) && captures.iter().all(|capture| check_value(capture, check)),
+                Value::Sum(_, inner) => check_value(inner, check),
+                _ => true,
+            }
+        }
+
+        for (addr, instr) in self.instrs.iter().enumerate().map(|(i, instr)| (Addr(i), instr)) {
+            if let Some(rel) = instr.rel() {
+                check_addr(addr, (addr.0 as isize).checked_add(rel).and_then(|tgt| usize::try_from(tgt).ok()))?;
+            }
+            match instr {
+                Instr::Imm(val) => if !check_value(val, &|tgt| tgt < len) {
+                    return Err(LoadError::InvalidJump(addr));
+                },
+                Instr::Suspend(eff) | Instr::Register(eff, _) => if eff.0 >= self.effects.len() {
+                    return Err(LoadError::InvalidEffect(addr));
+                },
+                _ => {},
+            }
+        }
+
+        check_addr(self.entry, Some(self.entry.0)).map_err(|_| LoadError::InvalidEntry(self.entry))?;
+
+        if self.debug.windows(2).any(|w| w[0].0.0 > w[1].0.0) || self.debug.iter().any(|(addr, _, _)| addr.0 > len) {
+            return Err(LoadError::Invalid("debug table"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(instrs: Vec<Instr>) -> Program {
+        let mut prog = Program::default();
+        prog.effects.push(Ident::new("print"));
+        for instr in instrs {
+            prog.push(instr);
+        }
+        prog
+    }
+
+    fn decode(prog: &Program) -> Result<Program, LoadError> {
+        Program::decode(&prog.encode())
+    }
+
+    fn header() -> Vec<u8> {
+        let mut w = Writer::default();
+        w.0.extend_from_slice(&MAGIC);
+        w.u32(FORMAT_VERSION);
+        w.usize(0);
+        w.u8(0);
+        w.0
+    }
+
+    #[test]
+    fn round_trip() {
+        let prog = program(vec![Instr::Imm(Value::List(vec![Value::Int(1), Value::Tag(2)].into())), Instr::Ret]);
+        let loaded = decode(&prog).unwrap();
+        assert_eq!(loaded.encode(), prog.encode());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = program(vec![Instr::Ret]).encode();
+        bytes[0] = b'X';
+        assert!(matches!(Program::decode(&bytes), Err(LoadError::NotAProgram)));
+        assert!(matches!(Program::decode(b"TA"), Err(LoadError::NotAProgram)));
+    }
+
+    #[test]
+    fn bad_version() {
+        let mut bytes = program(vec![Instr::Ret]).encode();
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(Program::decode(&bytes), Err(LoadError::WrongVersion(v)) if v == FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut prog = program(vec![Instr::Imm(Value::Int(1)), Instr::Ret]);
+        prog.debug("main", None);
+        let bytes = prog.encode();
+        for len in MAGIC.len()..bytes.len() {
+            let res = Program::decode(&bytes[..len]);
+            assert!(matches!(res, Err(LoadError::UnexpectedEnd)), "length {}: {:?}", len, res.err());
+        }
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut bytes = program(vec![Instr::Ret]).encode();
+        bytes.push(0);
+        assert!(matches!(Program::decode(&bytes), Err(LoadError::TrailingData)));
+    }
+
+    #[test]
+    fn huge_counts() {
+        // Effects, then instructions
+        for prefix in [&[][..], &0u64.to_le_bytes()[..]] {
+            let mut bytes = header();
+            bytes.extend_from_slice(prefix);
+            bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+            assert!(matches!(Program::decode(&bytes), Err(LoadError::UnexpectedEnd)));
+        }
+
+        // A list in an immediate value
+        let mut w = Writer(header());
+        w.usize(0);
+        w.usize(1);
+        w.u8(20);
+        w.u8(4);
+        w.u64(u64::MAX);
+        assert!(matches!(Program::decode(&w.0), Err(LoadError::UnexpectedEnd)));
+    }
+
+    #[test]
+    fn out_of_range_jump() {
+        let res = decode(&program(vec![Instr::Jump(100), Instr::Ret]));
+        assert!(matches!(res, Err(LoadError::InvalidJump(Addr(0)))));
+        let res = decode(&program(vec![Instr::Nop, Instr::Jump(-3), Instr::Ret]));
+        assert!(matches!(res, Err(LoadError::InvalidJump(Addr(1)))));
+    }
+
+    #[test]
+    fn out_of_range_func() {
+        let func = Value::List(vec![Value::Func(Addr(50), Vec::new().into())].into());
+        let res = decode(&program(vec![Instr::Imm(func), Instr::Ret]));
+        assert!(matches!(res, Err(LoadError::InvalidJump(Addr(0)))));
+    }
+
+    #[test]
+    fn out_of_range_effect() {
+        let res = decode(&program(vec![Instr::Nop, Instr::Suspend(Eff(1)), Instr::Ret]));
+        assert!(matches!(res, Err(LoadError::InvalidEffect(Addr(1)))));
+    }
+
+    #[test]
+    fn out_of_range_entry() {
+        let mut prog = program(vec![Instr::Ret]);
+        prog.entry = Addr(1);
+        assert!(matches!(decode(&prog), Err(LoadError::InvalidEntry(Addr(1)))));
+    }
+
+    #[test]
+    fn out_of_range_debug() {
+        let mut prog = program(vec![Instr::Ret]);
+        prog.debug.push((Addr(2), "main".to_string(), None));
+        assert!(matches!(decode(&prog), Err(LoadError::Invalid("debug table"))));
+    }
+
+    #[test]
+    fn value_nesting() {
+        let nested = |depth| (1..depth).fold(Value::Int(0), |inner, _| Value::Sum(0, Rc::new(inner)));
+
+        assert!(decode(&program(vec![Instr::Imm(nested(MAX_VALUE_DEPTH)), Instr::Ret])).is_ok());
+
+        let res = decode(&program(vec![Instr::Imm(nested(MAX_VALUE_DEPTH + 1)), Instr::Ret]));
+        assert!(matches!(res, Err(LoadError::Invalid("value nesting"))));
+
+        // Far deeper than the native stack could cope with if decoding did not stop early
+        let mut w = Writer(header());
+        w.usize(0);
+        w.usize(1);
+        w.u8(20);
+        for _ in 0..1_000_000 {
+            w.u8(6);
+            w.usize(0);
+        }
+        assert!(matches!(Program::decode(&w.0), Err(LoadError::Invalid("value nesting"))));
+    }
+}
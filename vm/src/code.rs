@@ -1,9 +1,9 @@
 use super::*;
-use std::io::Write;
+use std::{io::Write, rc::Rc};
 
 #[derive(Clone, Debug)]
 pub enum Instr {
-    Error(&'static str),
+    Error(Rc<str>),
     Nop,
     Break,
 
@@ -12,6 +12,9 @@ pub enum Instr {
     // Make a function using the relative offset and by capturing the last N items on the stack
     MakeFunc(isize, usize),
     ApplyFunc,
+    // Like `ApplyFunc`, but the current function is finished: its last N locals are dropped and the callee reuses its
+    // frame, so tail calls run in constant space
+    TailCall(usize),
 
     MakeList(usize), // T * N => [T]
     IndexList(usize), // Nth field of list/tuple
@@ -29,6 +32,13 @@ pub enum Instr {
     Jump(isize),
     IfNot,
 
+    // Superinstructions, produced by the peephole optimiser from common runs of the instructions above
+    JumpIfNot(isize), // Bool => .., jumping if false (`IfNot` then `Jump`)
+    JumpIfNotVariant(usize, isize), // Sum => .., jumping if the sum is not the given variant
+    JumpIfNotEqIntImm(i64, isize), // Int => .., jumping if the int is not equal to the immediate
+    DupIndexList(usize), // [T] => [T], T (`Dup` then `IndexList`)
+    GetLocalIndexList(usize, usize), // .. => T (`GetLocal` then `IndexList`)
+
     Imm(Value),
     Pop(usize),
     Replace,
@@ -38,12 +48,18 @@ pub enum Instr {
     GetLocal(usize), // Duplicate value in locals position (len - 1 - N) and put on stack
 
     NotBool, // Bool -> Bool
-    NegInt, // Int -> Int
+    NegInt, // Int -> Int, wrapping on overflow
     NegReal, // Real -> Real
 
-    AddInt, // Int -> Int -> Int
-    SubInt, // Int -> Int -> Int
-    MulInt,
+    AddInt, // Int -> Int -> Int, wrapping on overflow
+    SubInt, // Int -> Int -> Int, wrapping on overflow
+    MulInt, // Int -> Int -> Int, wrapping on overflow
+    DivInt, // Int -> Int -> Int, zero when dividing by zero
+    RemInt, // Int -> Int -> Int, the dividend when dividing by zero
+    AddReal, // Real -> Real -> Real
+    SubReal, // Real -> Real -> Real
+    MulReal, // Real -> Real -> Real
+    DivReal, // Real -> Real -> Real
 
     EqInt, // Int -> Int -> Bool
     EqBool, // Bool -> Bool -> Bool
@@ -52,6 +68,16 @@ pub enum Instr {
     MoreInt,
     LessEqInt,
     MoreEqInt,
+    EqReal, // Real -> Real -> Bool
+    LessReal,
+    MoreReal,
+    LessEqReal,
+    MoreEqReal,
+
+    IntToReal, // Int -> Real
+    RealToInt, // Real -> Int, rounding towards zero and saturating
+    RealToNat, // Real -> Int, like `RealToInt` but never negative
+    DisplayReal, // Real -> [Char]
 
     AndBool, // Bool -> Bool -> Bool
 
@@ -61,14 +87,46 @@ pub enum Instr {
     // Make an effect object using the relative offset and by capturing the last N items on the stack
     MakeEffect(isize, usize),
     Propagate,
-    Suspend(EffectId),
-    Register(EffectId),
+    Suspend(Eff),
+    // Run an effect object with the handler below it registered for the given effect, giving the handler the
+    // resumption of the computation if the flag is set
+    Register(Eff, bool),
+
+    // Call the host intrinsic with the given name
+    Host(Ident),
 }
 
 impl Instr {
     pub fn bool(x: bool) -> Self {
         Self::Imm(Value::Bool(x))
     }
+
+    /// The offset of the code that this instruction refers to, relative to the address of the instruction.
+    pub fn rel(&self) -> Option<isize> {
+        match self {
+            Instr::Call(rel)
+            | Instr::Jump(rel)
+            | Instr::MakeFunc(rel, _)
+            | Instr::MakeEffect(rel, _)
+            | Instr::JumpIfNot(rel)
+            | Instr::JumpIfNotVariant(_, rel)
+            | Instr::JumpIfNotEqIntImm(_, rel) => Some(*rel),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn rel_mut(&mut self) -> Option<&mut isize> {
+        match self {
+            Instr::Call(rel)
+            | Instr::Jump(rel)
+            | Instr::MakeFunc(rel, _)
+            | Instr::MakeEffect(rel, _)
+            | Instr::JumpIfNot(rel)
+            | Instr::JumpIfNotVariant(_, rel)
+            | Instr::JumpIfNotEqIntImm(_, rel) => Some(rel),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
@@ -80,17 +138,44 @@ impl Addr {
     pub fn jump_to(self, other: Self) -> isize { other.0 as isize - self.0 as isize }
 }
 
+/// An effect, identified by its index in the program's effect table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Eff(pub usize);
+
 #[derive(Default, Debug)]
 pub struct Program {
-    instrs: Vec<Instr>,
-    debug: Vec<(Addr, String)>,
+    pub(crate) instrs: Vec<Instr>,
+    /// Debugging information for the code starting at each address, in address order.
+    pub(crate) debug: Vec<(Addr, String, Option<Span>)>,
+    /// The name of each effect used by the program, used to find host handlers for them.
+    pub effects: Vec<Ident>,
+    /// The index given to each MIR effect while lowering.
+    effect_ids: HashMap<EffectId, Eff>,
     pub entry: Addr,
     pub does_io: bool,
 }
 
 impl Program {
-    pub fn debug(&mut self, msg: impl ToString) {
-        self.debug.push((self.next_addr(), msg.to_string()));
+    pub fn debug(&mut self, msg: impl ToString, span: Option<Span>) {
+        self.debug.push((self.next_addr(), msg.to_string(), span));
+    }
+
+    /// Find the span of the source code that the instruction at the given address was generated from.
+    pub fn span_of(&self, addr: Addr) -> Option<Span> {
+        self.debug
+            .iter()
+            .rev()
+            .find(|(a, _, _)| a.0 <= addr.0)
+            .and_then(|(_, _, span)| *span)
+    }
+
+    /// Find the index of an effect in the effect table, adding it if it is not already present.
+    pub fn effect(&mut self, mir: &MirContext, eff: EffectId) -> Eff {
+        let effects = &mut self.effects;
+        *self.effect_ids.entry(eff).or_insert_with(|| {
+            effects.push(mir.effect_names[&eff]);
+            Eff(effects.len() - 1)
+        })
     }
 
     pub fn next_addr(&self) -> Addr { Addr(self.instrs.len()) }
@@ -99,7 +184,7 @@ impl Program {
         self.instrs
             .get(ip.0)
             .cloned()
-            .unwrap_or(Instr::Error("out of bounds instruction"))
+            .unwrap_or_else(|| Instr::Error("out of bounds instruction".into()))
     }
 
     pub fn push(&mut self, instr: Instr) -> Addr {
@@ -115,7 +200,7 @@ impl Program {
     pub fn write(&self, mut writer: impl Write) {
         let mut debug = self.debug.iter().peekable();
         for addr in (0..self.instrs.len()).map(Addr) {
-            while debug.peek().map_or(false, |(a, _)| *a == addr) {
+            while debug.peek().map_or(false, |(a, _, _)| *a == addr) {
                 writeln!(writer, " ...  | <--------- {}", debug.next().unwrap().1).unwrap();
             }
 
@@ -130,6 +215,7 @@ impl Program {
                 Instr::Ret => 0,
                 Instr::MakeFunc(_, n) => -(n as isize),
                 Instr::ApplyFunc => 0, // Turns input stack item into output stack item
+                Instr::TailCall(_) => 0,
                 Instr::MakeList(n) => -(n as isize) + 1,
                 Instr::IndexList(_) => 0,
                 Instr::SkipListImm(_) => 0,
@@ -144,15 +230,35 @@ impl Program {
                 Instr::Dup => 1,
                 Instr::Jump(_) => 0,
                 Instr::IfNot => -1,
+                Instr::JumpIfNot(_) => -1,
+                Instr::JumpIfNotVariant(_, _) => -1,
+                Instr::JumpIfNotEqIntImm(_, _) => -1,
+                Instr::DupIndexList(_) => 1,
+                Instr::GetLocalIndexList(_, _) => 1,
                 Instr::PushLocal => -1,
                 Instr::PopLocal(_) => 0,
                 Instr::GetLocal(_) => 1,
                 Instr::NotBool
                 | Instr::NegInt
-                | Instr::NegReal => 0,
+                | Instr::NegReal
+                | Instr::IntToReal
+                | Instr::RealToInt
+                | Instr::RealToNat
+                | Instr::DisplayReal => 0,
                 Instr::AddInt
                 | Instr::SubInt
                 | Instr::MulInt
+                | Instr::DivInt
+                | Instr::RemInt
+                | Instr::AddReal
+                | Instr::SubReal
+                | Instr::MulReal
+                | Instr::DivReal
+                | Instr::EqReal
+                | Instr::LessReal
+                | Instr::MoreReal
+                | Instr::LessEqReal
+                | Instr::MoreEqReal
                 | Instr::EqInt
                 | Instr::EqBool
                 | Instr::EqChar
@@ -166,7 +272,8 @@ impl Program {
                 Instr::MakeEffect(_, n) => -(n as isize),
                 Instr::Propagate => -1,
                 Instr::Suspend(_) => 0,
-                Instr::Register(_) => -1,
+                Instr::Register(_, _) => -1,
+                Instr::Host(_) => 0,
             };
 
             let instr_display = match instr {
@@ -180,6 +287,7 @@ impl Program {
                 Instr::Ret => format!("ret"),
                 Instr::MakeFunc(i, n) => format!("func.make {:+} (0x{:03X}) {}", i, addr.jump(i).0, n),
                 Instr::ApplyFunc => format!("func.apply"),
+                Instr::TailCall(n) => format!("func.apply_tail {}", n),
                 Instr::MakeList(n) => format!("list.make {}", n),
                 Instr::IndexList(i) => format!("list.index #{}", i),
                 Instr::SkipListImm(i) => format!("list.skip_imm #{}", i),
@@ -194,6 +302,11 @@ impl Program {
                 Instr::Dup => format!("dup"),
                 Instr::Jump(x) => format!("jump {:+} (0x{:03X})", x, addr.jump(x).0),
                 Instr::IfNot => format!("if_not"),
+                Instr::JumpIfNot(x) => format!("jump_if_not {:+} (0x{:03X})", x, addr.jump(x).0),
+                Instr::JumpIfNotVariant(i, x) => format!("jump_if_not.variant #{} {:+} (0x{:03X})", i, x, addr.jump(x).0),
+                Instr::JumpIfNotEqIntImm(n, x) => format!("jump_if_not.int_eq_imm `{}i` {:+} (0x{:03X})", n, x, addr.jump(x).0),
+                Instr::DupIndexList(i) => format!("list.dup_index #{}", i),
+                Instr::GetLocalIndexList(x, i) => format!("local.get_index +{} #{}", x, i),
                 Instr::PushLocal => format!("local.push"),
                 Instr::PopLocal(n) => format!("local.pop {}", n),
                 Instr::GetLocal(x) => format!("local.get +{}", x),
@@ -203,6 +316,8 @@ impl Program {
                 Instr::AddInt => format!("int.add"),
                 Instr::SubInt => format!("int.sub"),
                 Instr::MulInt => format!("int.mul"),
+                Instr::DivInt => format!("int.div"),
+                Instr::RemInt => format!("int.rem"),
                 Instr::EqInt => format!("int.eq"),
                 Instr::EqBool => format!("bool.eq"),
                 Instr::EqChar => format!("char.eq"),
@@ -210,13 +325,32 @@ impl Program {
                 Instr::MoreInt => format!("int.more"),
                 Instr::LessEqInt => format!("int.less_eq"),
                 Instr::MoreEqInt => format!("int.more_eq"),
+                Instr::AddReal => format!("real.add"),
+                Instr::SubReal => format!("real.sub"),
+                Instr::MulReal => format!("real.mul"),
+                Instr::DivReal => format!("real.div"),
+                Instr::EqReal => format!("real.eq"),
+                Instr::LessReal => format!("real.less"),
+                Instr::MoreReal => format!("real.more"),
+                Instr::LessEqReal => format!("real.less_eq"),
+                Instr::MoreEqReal => format!("real.more_eq"),
+                Instr::IntToReal => format!("int.to_real"),
+                Instr::RealToInt => format!("real.to_int"),
+                Instr::RealToNat => format!("real.to_nat"),
+                Instr::DisplayReal => format!("real.display"),
                 Instr::AndBool => format!("bool.and"),
                 Instr::Print => format!("io.print"),
                 Instr::Input => format!("io.input"),
                 Instr::MakeEffect(i, n) => format!("eff.make {:+} (0x{:03X}) {}", i, addr.jump(i).0, n),
                 Instr::Propagate => format!("eff.propagate"),
-                Instr::Suspend(eff) => format!("eff.suspend {:?}", eff),
-                Instr::Register(eff) => format!("eff.register {:?}", eff),
+                Instr::Suspend(eff) => format!("eff.suspend #{} ({})", eff.0, self.effects[eff.0]),
+                Instr::Register(eff, resumable) => format!(
+                    "eff.register #{} ({}){}",
+                    eff.0,
+                    self.effects[eff.0],
+                    if resumable { " resumable" } else { "" },
+                ),
+                Instr::Host(name) => format!("host.call \"{}\"", name),
             };
 
             writeln!(writer, "0x{:03X} | {:>+3} | {}", addr.0, stack_diff, instr_display).unwrap();
@@ -0,0 +1,131 @@
+use super::*;
+use std::{fmt, rc::Rc};
+
+#[derive(Clone, Debug)]
+pub enum RuntimeErrorKind {
+    /// The program reached an instruction that should never be executed.
+    Error(Rc<str>),
+    /// An instruction expected a value of a different type.
+    TypeMismatch(&'static str, Value),
+    /// A list or tuple was indexed beyond its end.
+    IndexOutOfRange(usize, usize),
+    /// A sum was unwrapped as a variant other than the one it holds.
+    WrongVariant(usize, usize),
+    /// An instruction expected more values than there are on the stack.
+    StackUnderflow,
+    /// An instruction referred to more locals than are in scope.
+    LocalUnderflow(usize),
+    /// An effect was suspended without a handler in scope.
+    NoHandler(Ident),
+    /// The host handles an effect by a name that several of the program's effects share, so it cannot tell which of
+    /// them the handler is for.
+    AmbiguousEffect(Ident),
+    /// An IO instruction was given a universe that has already been used.
+    UniverseForked,
+    /// The program finished without leaving exactly one value on the stack and no locals in scope.
+    BadExit(usize, usize),
+    Io(String),
+    /// The program called a host intrinsic that the host does not provide.
+    NoIntrinsic(Ident),
+    /// A host effect handler or intrinsic failed.
+    Host(String),
+    /// The program executed more instructions than the given fuel allows.
+    OutOfFuel(u64),
+    /// More calls were in progress at once than the given limit allows.
+    CallDepthExceeded(usize),
+    /// More values were on the stack at once than the given limit allows.
+    StackOverflow(usize),
+    /// More locals were in scope at once than the given limit allows.
+    TooManyLocals(usize),
+    /// The program allocated more values than the given heap budget allows.
+    HeapExhausted(usize),
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::Error(msg) => write!(f, "{}", msg),
+            RuntimeErrorKind::TypeMismatch(expected, found) => write!(f, "expected {}, found `{}`", expected, found),
+            RuntimeErrorKind::IndexOutOfRange(idx, len) => write!(f, "index {} is out of range for length {}", idx, len),
+            RuntimeErrorKind::WrongVariant(expected, found) => write!(f, "expected variant #{}, found variant #{}", expected, found),
+            RuntimeErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            RuntimeErrorKind::LocalUnderflow(n) => write!(f, "local {} is not in scope", n),
+            RuntimeErrorKind::NoHandler(eff) => write!(f, "no handler for effect `{}`", eff),
+            RuntimeErrorKind::AmbiguousEffect(eff) => write!(f, "host handler for effect `{}` is ambiguous, since the program has several effects with that name", eff),
+            RuntimeErrorKind::UniverseForked => write!(f, "universe forked, the thread of prophecy has been broken"),
+            RuntimeErrorKind::BadExit(stack, locals) => write!(f, "program exited with {} stack values and {} locals", stack, locals),
+            RuntimeErrorKind::Io(msg) => write!(f, "IO error: {}", msg),
+            RuntimeErrorKind::NoIntrinsic(name) => write!(f, "host does not provide intrinsic `{}`", name),
+            RuntimeErrorKind::Host(msg) => write!(f, "host error: {}", msg),
+            RuntimeErrorKind::OutOfFuel(fuel) => write!(f, "ran out of fuel after {} instructions", fuel),
+            RuntimeErrorKind::CallDepthExceeded(max) => write!(f, "call depth exceeded the limit of {}", max),
+            RuntimeErrorKind::StackOverflow(max) => write!(f, "stack exceeded the limit of {} values", max),
+            RuntimeErrorKind::TooManyLocals(max) => write!(f, "locals exceeded the limit of {}", max),
+            RuntimeErrorKind::HeapExhausted(max) => write!(f, "heap budget of {} values exhausted", max),
+        }
+    }
+}
+
+/// An error that occurred while executing a program.
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// The address of the instruction that failed.
+    pub addr: Addr,
+    /// The stack at the time of the error, top last.
+    pub stack: Vec<Value>,
+    /// The source code that the failing instruction was generated from, if known.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Runtime error at 0x{:03X}: {}", self.addr.0, self.kind)?;
+        for (i, x) in self.stack.iter().rev().enumerate() {
+            writeln!(f, "{:02} | {}", i, x)?;
+        }
+        Ok(())
+    }
+}
+
+/// An error that occurred while loading a program from its binary format.
+#[derive(Clone, Debug)]
+pub enum LoadError {
+    /// The data does not start with the magic bytes of the format.
+    NotAProgram,
+    /// The program was written with a different version of the format.
+    WrongVersion(u32),
+    /// The data ended partway through the program.
+    UnexpectedEnd,
+    /// There is more data after the end of the program.
+    TrailingData,
+    InvalidOpcode(u8),
+    /// Part of the program could not be decoded.
+    Invalid(&'static str),
+    /// An instruction refers to code outside of the program.
+    InvalidJump(Addr),
+    /// An instruction refers to an effect that is not in the effect table.
+    InvalidEffect(Addr),
+    InvalidEntry(Addr),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotAProgram => write!(f, "not a compiled Tao program"),
+            LoadError::WrongVersion(version) => write!(
+                f,
+                "program was compiled with format version {}, but only version {} is supported",
+                version,
+                binary::FORMAT_VERSION,
+            ),
+            LoadError::UnexpectedEnd => write!(f, "unexpected end of program"),
+            LoadError::TrailingData => write!(f, "unexpected data after the end of the program"),
+            LoadError::InvalidOpcode(opcode) => write!(f, "invalid opcode 0x{:02X}", opcode),
+            LoadError::Invalid(what) => write!(f, "invalid {}", what),
+            LoadError::InvalidJump(addr) => write!(f, "instruction at 0x{:03X} refers to code outside of the program", addr.0),
+            LoadError::InvalidEffect(addr) => write!(f, "instruction at 0x{:03X} refers to an unknown effect", addr.0),
+            LoadError::InvalidEntry(addr) => write!(f, "entry point 0x{:03X} is outside of the program", addr.0),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use super::*;
 use std::{
     fmt,
+    io::{self, Write},
     rc::Rc,
 };
 use im::{Vector, vector};
@@ -9,7 +10,77 @@ use im::{Vector, vector};
 pub struct Effect {
     addr: Addr,
     captures: Vector<Value>,
-    handlers: Vector<(EffectId, Value)>,
+}
+
+/// The heights of the call, value, local, and handler stacks at some point during execution.
+#[derive(Copy, Clone, Debug, Default)]
+struct Heights {
+    frames: usize,
+    values: usize,
+    locals: usize,
+    handlers: usize,
+}
+
+/// An effect handler that is in scope.
+#[derive(Clone, Debug)]
+struct Handler {
+    eff: Eff,
+    func: Value,
+    /// Whether the handler is given the resumption of the computation that it handles, rather than always resuming it
+    /// with the value that the handler produces.
+    resumable: bool,
+    /// The heights of the stacks when the handled computation began. Everything above them belongs to the handled
+    /// computation, and is captured by the resumption given to the handler.
+    base: Heights,
+}
+
+impl Handler {
+    fn rebase(&self, from: Heights, to: Heights) -> Self {
+        Self {
+            base: Heights {
+                frames: self.base.frames - from.frames + to.frames,
+                values: self.base.values - from.values + to.values,
+                locals: self.base.locals - from.locals + to.locals,
+                handlers: self.base.handlers - from.handlers + to.handlers,
+            },
+            ..self.clone()
+        }
+    }
+}
+
+/// A call that has not yet returned.
+#[derive(Clone, Debug)]
+struct Frame {
+    ret: Addr,
+    /// The number of handlers in scope when the call was made, which is restored when it returns.
+    handlers: usize,
+    /// The handlers that were hidden from an effect handler when it was called, which are restored when it returns.
+    hidden: Option<Box<Vector<Handler>>>,
+}
+
+impl Frame {
+    fn rebase(&self, from: Heights, to: Heights) -> Self {
+        Self {
+            ret: self.ret,
+            handlers: self.handlers - from.handlers + to.handlers,
+            hidden: self.hidden
+                .as_ref()
+                .map(|hidden| Box::new(hidden.iter().map(|h| h.rebase(from, to)).collect())),
+        }
+    }
+}
+
+/// The rest of a handled computation from the point at which it suspended an effect, up to and including the handler
+/// of that effect. Resuming it continues the computation from that point with the handler still in place, producing
+/// the result of the computation. It may be resumed any number of times.
+#[derive(Debug)]
+pub struct Resumption {
+    addr: Addr,
+    // Everything that belongs to the computation, relative to the heights of the stacks when it began
+    frames: Vec<Frame>,
+    values: Vec<Value>,
+    locals: Vec<Value>,
+    handlers: Vector<Handler>,
 }
 
 #[derive(Clone, Debug)]
@@ -21,20 +92,39 @@ pub enum Value {
     List(Vector<Self>),
     Func(Addr, Vector<Self>),
     Sum(usize, Rc<Self>),
+    /// A variant of a sum with no fields, which needs no allocation (equivalent to `Sum(variant, unit)`)
+    Tag(usize),
     Universe(u64),
     Effect(Rc<Effect>),
+    Resumption(Rc<Resumption>),
 }
 
 impl Value {
-    pub fn int(self) -> i64 { if let Value::Int(x) = self { x } else { panic!("{}", self) } }
-    pub fn real(self) -> f64 { if let Value::Real(x) = self { x } else { panic!("{}", self) } }
-    pub fn char(self) -> char { if let Value::Char(c) = self { c } else { panic!("{}", self) } }
-    pub fn bool(self) -> bool { if let Value::Bool(x) = self { x } else { panic!("{}", self) } }
-    pub fn list(self) -> Vector<Self> { if let Value::List(xs) = self { xs } else { panic!("{}", self) } }
-    pub fn func(self) -> (Addr, Vector<Self>) { if let Value::Func(f_addr, captures) = self { (f_addr, captures) } else { panic!("{}", self) } }
-    pub fn sum(self) -> (usize, Rc<Self>) { if let Value::Sum(variant, inner) = self { (variant, inner) } else { panic!("{}", self) } }
-    pub fn universe(self) -> u64 { if let Value::Universe(x) = self { x } else { panic!("{}", self) } }
-    pub fn eff(self) -> Rc<Effect> { if let Value::Effect(eff) = self { eff } else { panic!("{}", self) } }
+    pub fn unit() -> Self { Value::List(Vector::new()) }
+    pub fn str(s: &str) -> Self { Value::List(s.chars().map(Value::Char).collect()) }
+
+    pub fn int(self) -> Result<i64, RuntimeErrorKind> { if let Value::Int(x) = self { Ok(x) } else { Err(RuntimeErrorKind::TypeMismatch("int", self)) } }
+    pub fn real(self) -> Result<f64, RuntimeErrorKind> { if let Value::Real(x) = self { Ok(x) } else { Err(RuntimeErrorKind::TypeMismatch("real", self)) } }
+    pub fn char(self) -> Result<char, RuntimeErrorKind> { if let Value::Char(c) = self { Ok(c) } else { Err(RuntimeErrorKind::TypeMismatch("char", self)) } }
+    pub fn bool(self) -> Result<bool, RuntimeErrorKind> { if let Value::Bool(x) = self { Ok(x) } else { Err(RuntimeErrorKind::TypeMismatch("bool", self)) } }
+    pub fn list(self) -> Result<Vector<Self>, RuntimeErrorKind> { if let Value::List(xs) = self { Ok(xs) } else { Err(RuntimeErrorKind::TypeMismatch("list", self)) } }
+    pub fn func(self) -> Result<(Addr, Vector<Self>), RuntimeErrorKind> { if let Value::Func(f_addr, captures) = self { Ok((f_addr, captures)) } else { Err(RuntimeErrorKind::TypeMismatch("function", self)) } }
+    pub fn sum(self) -> Result<(usize, Rc<Self>), RuntimeErrorKind> {
+        match self {
+            Value::Sum(variant, inner) => Ok((variant, inner)),
+            Value::Tag(variant) => Ok((variant, Rc::new(Value::unit()))),
+            _ => Err(RuntimeErrorKind::TypeMismatch("sum", self)),
+        }
+    }
+    pub fn variant(&self) -> Result<usize, RuntimeErrorKind> {
+        match self {
+            Value::Sum(variant, _) | Value::Tag(variant) => Ok(*variant),
+            _ => Err(RuntimeErrorKind::TypeMismatch("sum", self.clone())),
+        }
+    }
+    pub fn universe(self) -> Result<u64, RuntimeErrorKind> { if let Value::Universe(x) = self { Ok(x) } else { Err(RuntimeErrorKind::TypeMismatch("universe", self)) } }
+    pub fn string(self) -> Result<String, RuntimeErrorKind> { self.list()?.into_iter().map(|c| c.char()).collect() }
+    pub fn eff(self) -> Result<Rc<Effect>, RuntimeErrorKind> { if let Value::Effect(eff) = self { Ok(eff) } else { Err(RuntimeErrorKind::TypeMismatch("effect", self)) } }
 }
 
 impl fmt::Display for Value {
@@ -62,6 +152,7 @@ impl fmt::Display for Value {
                 captures.len(),
             ),
             Value::Sum(variant, inner) => write!(f, "#{} {}", variant, inner),
+            Value::Tag(variant) => write!(f, "#{} []", variant),
             Value::Universe(x) => write!(f, "Universe({})", x),
             Value::Effect(eff) => write!(
                 f,
@@ -69,278 +160,1004 @@ impl fmt::Display for Value {
                 eff.addr.0,
                 eff.captures.len(),
             ),
+            Value::Resumption(res) => write!(
+                f,
+                "Resumption(addr = 0x{:03X}, frames = {})",
+                res.addr.0,
+                res.frames.len(),
+            ),
         }
     }
 }
 
-pub fn exec(prog: &Program) -> Option<Value> {
-    let mut addr = prog.entry;
-    let mut universe_counter = 0;
+fn pop(stack: &mut Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    stack.pop().ok_or(RuntimeErrorKind::StackUnderflow)
+}
 
-    let mut funcs = Vec::new();
-    let mut stack = Vec::new();
-    let mut locals = if prog.does_io {
-        vec![Value::Universe(universe_counter)]
+// Pop the top `n` values from the stack, in the order they were pushed
+fn pop_n(stack: &mut Vec<Value>, n: usize) -> Result<Vec<Value>, RuntimeErrorKind> {
+    if n > stack.len() {
+        Err(RuntimeErrorKind::StackUnderflow)
     } else {
-        Vec::new()
-    };
-    let mut handlers = Vector::new();
-
-    let mut tick = 0u64;
-    loop {
-        let mut next_addr = addr.incr();
-
-        // println!("Executing 0x{:03X}... Stack: {}", addr.0, stack.iter().rev().map(|x| format!("{}", x)).collect::<Vec<_>>().join(", "));
-
-        match prog.instr(addr) {
-            Instr::Error(err) => panic!("Error: {}", err),
-            Instr::Nop => {},
-            Instr::Break => {
-                println!("Breakpoint at 0x{:03X?}", addr.0);
-                for (i, x) in stack.iter().rev().enumerate() {
-                    println!("{:02} | {:?}", i, x);
-                }
-            },
-            Instr::Imm(x) => stack.push(x.clone()),
-            Instr::Pop(n) => {
-                assert!(n > 0, "Popped zero items, this is probably a bug");
-                stack.truncate(stack.len().saturating_sub(n));
-            },
-            Instr::Replace => {
-                let x = stack.pop().unwrap();
-                stack.pop();
-                stack.push(x);
-            },
-            Instr::Call(n) => {
-                funcs.push((next_addr, handlers.len()));
-                next_addr = addr.jump(n);
-            },
-            Instr::Ret => if let Some((addr, handler_count)) = funcs.pop() {
-                handlers.truncate(handler_count);
-                next_addr = addr;
-            } else {
-                assert_eq!(locals.len(), 0, "Local stack still has values, this is probably a bug");
-                assert_eq!(stack.len(), 1, "Stack size must be 1 on program exit");
-                println!("Executed {} instructions.", tick);
-                break if prog.does_io {
-                    let mut r = stack.pop().unwrap().list();
-                    assert_eq!(r.remove(0).universe(), universe_counter);
-                    None
-                } else {
-                    stack.pop()
-                };
-            },
-            Instr::MakeFunc(i, n) => {
-                let f_addr = addr.jump(i);
-                let func = Value::Func(f_addr, stack.split_off(stack.len().saturating_sub(n)).into());
-                stack.push(func);
-            },
-            Instr::ApplyFunc => {
-                let (f_addr, mut captures) = stack.pop().unwrap().func();
+        Ok(stack.split_off(stack.len() - n))
+    }
+}
 
-                funcs.push((next_addr, handlers.len()));
-                next_addr = f_addr;
+fn check_index(idx: usize, len: usize) -> Result<(), RuntimeErrorKind> {
+    if idx < len {
+        Ok(())
+    } else {
+        Err(RuntimeErrorKind::IndexOutOfRange(idx, len))
+    }
+}
 
-                locals.extend(captures.into_iter());
-            },
-            Instr::MakeList(n) => {
-                let val = Value::List(stack.split_off(stack.len().saturating_sub(n)).into());
-                stack.push(val);
-            },
-            Instr::IndexList(i) => {
-                let mut x = stack.pop().unwrap().list();
-                if x.len() < i + 1 {
-                    panic!("Removing item {} from list {:?} at 0x{:X}", i, x, addr.0);
-                }
-                stack.push(x.remove(i));
-            },
-            Instr::SkipListImm(i) => {
-                let x = stack.pop().unwrap().list();
-                stack.push(Value::List(x.skip(i)));
-            },
-            Instr::SetList(idx) => {
-                let item = stack.pop().unwrap();
-                let mut xs = stack.pop().unwrap().list();
-                xs[idx] = item;
-                stack.push(Value::List(xs));
-            },
-            Instr::LenList => {
-                let len = stack.pop().unwrap().list().len();
-                stack.push(Value::Int(len as i64));
-            },
-            Instr::JoinList => {
-                let mut y = stack.pop().unwrap().list();
-                let mut x = stack.pop().unwrap().list();
-                x.append(y);
-                stack.push(Value::List(x));
-            },
-            Instr::SkipList => {
-                let i = stack.pop().unwrap().int();
-                let xs = stack.pop().unwrap().list();
-                stack.push(Value::List(xs.skip((i as usize).min(xs.len()))));
-            },
-            Instr::TrimList => {
-                let i = stack.pop().unwrap().int();
-                let mut xs = stack.pop().unwrap().list();
-                xs.truncate((i as usize).min(xs.len()));
-                stack.push(Value::List(xs));
-            },
-            Instr::MakeSum(variant) => {
-                let x = stack.pop().unwrap();
-                stack.push(Value::Sum(variant, Rc::new(x)));
-            },
-            Instr::IndexSum(variant) => {
-                let (v, inner) = stack.pop().unwrap().sum();
-                debug_assert_eq!(variant, v);
-                stack.push((*inner).clone());
-            },
-            Instr::VariantSum => {
-                let (variant, _) = stack.pop().unwrap().sum();
-                stack.push(Value::Int(variant as i64));
-            },
-            Instr::Dup => stack.push(stack.last().unwrap().clone()),
-            Instr::Jump(n) => {
-                next_addr = addr.jump(n);
-            },
-            Instr::IfNot => {
-                if stack.pop().unwrap().bool() {
-                    next_addr = next_addr.jump(1);
-                }
-            },
-            Instr::PushLocal => locals.push(stack.pop().unwrap()),
-            Instr::PopLocal(n) => locals.truncate(locals.len() - n),
-            Instr::GetLocal(x) => stack.push(locals[locals.len() - 1 - x].clone()),
-            Instr::NotBool => {
-                let x = stack.pop().unwrap().bool();
-                stack.push(Value::Bool(!x))
-            },
-            Instr::NegInt => {
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Int(-x))
-            },
-            Instr::NegReal => {
-                let x = stack.pop().unwrap().real();
-                stack.push(Value::Real(-x))
-            },
-            Instr::AddInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Int(x + y))
-            },
-            Instr::SubInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Int(x - y))
-            },
-            Instr::MulInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Int(x * y))
-            },
-            Instr::EqInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Bool(x == y))
-            },
-            Instr::EqBool => {
-                let y = stack.pop().unwrap().bool();
-                let x = stack.pop().unwrap().bool();
-                stack.push(Value::Bool(x == y))
-            },
-            Instr::EqChar => {
-                let y = stack.pop().unwrap().char();
-                let x = stack.pop().unwrap().char();
-                stack.push(Value::Bool(x == y))
-            },
-            Instr::LessInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Bool(x < y))
-            },
-            Instr::MoreInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Bool(x > y))
-            },
-            Instr::LessEqInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Bool(x <= y))
-            },
-            Instr::MoreEqInt => {
-                let y = stack.pop().unwrap().int();
-                let x = stack.pop().unwrap().int();
-                stack.push(Value::Bool(x >= y))
-            },
-            Instr::AndBool => {
-                let y = stack.pop().unwrap().bool();
-                let x = stack.pop().unwrap().bool();
-                stack.push(Value::Bool(x && y))
-            },
-            Instr::Print => {
-                let s = stack.pop().unwrap().list();
-                let universe = stack.pop().unwrap().universe();
-                assert!(universe == universe_counter, "Universe forked, the thread of prophecy has been broken");
-                universe_counter += 1;
-                println!("{}", s.into_iter().map(|c| c.char()).collect::<String>());
-                stack.push(Value::Universe(universe_counter))
-            },
-            Instr::Input => {
-                use std::io::{stdin, stdout, Write};
+// Index a list without taking ownership of it, so that only the item gets cloned
+fn index_list(x: &Value, idx: usize) -> Result<Value, RuntimeErrorKind> {
+    match x {
+        Value::List(xs) => {
+            check_index(idx, xs.len())?;
+            Ok(xs[idx].clone())
+        },
+        _ => Err(RuntimeErrorKind::TypeMismatch("list", x.clone())),
+    }
+}
+
+/// Limits on the resources that a program may use while it runs, allowing untrusted programs to be run safely. A
+/// program that exceeds one of them stops with a distinct [`RuntimeErrorKind`]. By default, nothing is limited.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Limits {
+    /// The number of instructions that the program may execute.
+    pub fuel: Option<u64>,
+    /// The number of calls (including those to effect handlers) that may be in progress at once.
+    pub call_depth: Option<usize>,
+    /// The number of values that may be on the stack at once.
+    pub stack: Option<usize>,
+    /// The number of locals that may be in scope at once.
+    pub locals: Option<usize>,
+    /// An approximate budget for heap memory, as the number of values that the program stores in the lists (including
+    /// tuples and strings), sums, closures, effect objects and resumed computations that it creates.
+    ///
+    /// This is a budget for the lifetime of the program rather than a limit on the memory it holds at once: values are
+    /// not returned to the budget when they are freed, so a long-running program may exhaust it while using little
+    /// memory. Combine it with `fuel` to bound the work that an untrusted program can do.
+    pub heap: Option<usize>,
+}
+
+impl Limits {
+    fn check_heights(&self, funcs: &[Frame], stack: &[Value], locals: &[Value]) -> Result<(), RuntimeErrorKind> {
+        if let Some(max) = self.call_depth.filter(|max| funcs.len() > *max) {
+            Err(RuntimeErrorKind::CallDepthExceeded(max))
+        } else if let Some(max) = self.stack.filter(|max| stack.len() > *max) {
+            Err(RuntimeErrorKind::StackOverflow(max))
+        } else if let Some(max) = self.locals.filter(|max| locals.len() > *max) {
+            Err(RuntimeErrorKind::TooManyLocals(max))
+        } else {
+            Ok(())
+        }
+    }
 
-                let universe = stack.pop().unwrap().universe();
-                assert!(universe == universe_counter, "Universe forked, the thread of prophecy has been broken");
-                universe_counter += 1;
+    // Take the given number of values from the heap budget. The budget only ever shrinks, see `Limits::heap`.
+    fn alloc(&self, used: &mut usize, items: usize) -> Result<(), RuntimeErrorKind> {
+        *used = used.saturating_add(items);
+        match self.heap {
+            Some(max) if *used > max => Err(RuntimeErrorKind::HeapExhausted(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A function provided by the host that is embedding the VM. Errors are reported as runtime errors.
+pub type HostFn<'a> = Box<dyn FnMut(Value) -> Result<Value, String> + 'a>;
+
+/// A virtual machine that runs programs on behalf of a host application.
+///
+/// The host may handle effects that the program suspends but does not handle itself, provide intrinsics that the
+/// program calls with `@host("name", arg)`, and redirect the IO performed by `@print` and `@input`. By default, IO goes
+/// to the standard output and input of the process.
+pub struct Vm<'a> {
+    effects: HashMap<String, HostFn<'a>>,
+    intrinsics: HashMap<String, HostFn<'a>>,
+    print: Box<dyn FnMut(&str) -> io::Result<()> + 'a>,
+    input: Box<dyn FnMut() -> io::Result<String> + 'a>,
+    limits: Limits,
+}
 
+impl<'a> Default for Vm<'a> {
+    fn default() -> Self {
+        Self {
+            effects: HashMap::default(),
+            intrinsics: HashMap::default(),
+            print: Box::new(|s| writeln!(io::stdout(), "{}", s)),
+            input: Box::new(|| {
                 let mut s = String::new();
                 print!("> ");
-                stdout().flush().expect("IO error");
-                stdin().read_line(&mut s).expect("IO error");
+                io::stdout().flush()?;
+                io::stdin().read_line(&mut s)?;
+                Ok(s)
+            }),
+            limits: Limits::default(),
+        }
+    }
+}
 
-                stack.push(Value::List(vector![
-                    Value::Universe(universe_counter),
-                    Value::List(s.trim_end().chars().map(Value::Char).collect()),
-                ]));
-            },
-            Instr::MakeEffect(i, n) => {
-                let addr = addr.jump(i);
-                let func = Value::Effect(Rc::new(Effect {
+impl<'a> Vm<'a> {
+    /// Handle the effect with the given name when it is suspended with no handler for it in the program. The handler
+    /// is given the value sent by the program and returns the value to resume it with.
+    ///
+    /// Effects are identified by their name alone, so a program that has several effects with this name (declared in
+    /// different modules, say) is rejected with [`RuntimeErrorKind::AmbiguousEffect`] before it starts running.
+    pub fn handle_effect(&mut self, name: impl ToString, f: impl FnMut(Value) -> Result<Value, String> + 'a) -> &mut Self {
+        self.effects.insert(name.to_string(), Box::new(f));
+        self
+    }
+
+    /// Provide an intrinsic that the program can call with `@host("name", arg)`.
+    ///
+    /// The optimiser assumes that intrinsics are pure, so an intrinsic whose result goes unused may never be called.
+    /// Use an effect to interact with the host instead.
+    ///
+    /// The type of `@host(...)` is whatever the program expects at the call site. The compiler requires that it, and
+    /// the type of the argument, be known and free of generic types (so an annotation is usually needed), but nothing
+    /// checks the value that the intrinsic returns against it at run time. The host is trusted to return a value of
+    /// the right type: if it does not, the program misbehaves, usually by failing with
+    /// [`RuntimeErrorKind::TypeMismatch`] at some later point.
+    pub fn intrinsic(&mut self, name: impl ToString, f: impl FnMut(Value) -> Result<Value, String> + 'a) -> &mut Self {
+        self.intrinsics.insert(name.to_string(), Box::new(f));
+        self
+    }
+
+    /// Redirect the lines written by `@print`.
+    pub fn on_print(&mut self, f: impl FnMut(&str) -> io::Result<()> + 'a) -> &mut Self {
+        self.print = Box::new(f);
+        self
+    }
+
+    /// Redirect the lines read by `@input`.
+    pub fn on_input(&mut self, f: impl FnMut() -> io::Result<String> + 'a) -> &mut Self {
+        self.input = Box::new(f);
+        self
+    }
+
+    /// Limit the resources that programs may use while they run.
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Run a program to completion, returning the value that it produced (or nothing, if it performs IO).
+    ///
+    /// If the program produces an effect object, it is run too, with the effects it suspends handled by the host.
+    pub fn run(&mut self, prog: &Program) -> Result<Option<Value>, RuntimeError> {
+        let ambiguous = prog.effects
+            .iter()
+            .enumerate()
+            .find(|(i, name)| self.effects.contains_key(name.as_str()) && prog.effects[..*i].contains(name));
+        if let Some((_, name)) = ambiguous {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::AmbiguousEffect(*name),
+                addr: prog.entry,
+                stack: Vec::new(),
+                span: None,
+            });
+        }
+
+        let mut addr = prog.entry;
+        let mut universe_counter = 0;
+        let limits = self.limits;
+        let mut ticks = 0u64;
+        let mut heap_used = 0;
+
+        let mut funcs = Vec::new();
+        let mut stack = Vec::new();
+        let mut locals = if prog.does_io {
+            vec![Value::Universe(universe_counter)]
+        } else {
+            Vec::new()
+        };
+        let mut handlers = Vector::<Handler>::new();
+        let heights = |funcs: &Vec<Frame>, stack: &Vec<Value>, locals: &Vec<Value>, handlers: &Vector<Handler>| Heights {
+            frames: funcs.len(),
+            values: stack.len(),
+            locals: locals.len(),
+            handlers: handlers.len(),
+        };
+
+        loop {
+            let mut next_addr = addr.incr();
+
+            // println!("Executing 0x{:03X}... Stack: {}", addr.0, stack.iter().rev().map(|x| format!("{}", x)).collect::<Vec<_>>().join(", "));
+
+            let mut exit = None;
+            let res: Result<(), RuntimeErrorKind> = try {
+                ticks += 1;
+                if let Some(fuel) = limits.fuel.filter(|fuel| ticks > *fuel) {
+                    Err(RuntimeErrorKind::OutOfFuel(fuel))?;
+                }
+
+                match prog.instr(addr) {
+                    Instr::Error(err) => Err(RuntimeErrorKind::Error(err))?,
+                    Instr::Nop => {},
+                    Instr::Break => {
+                        println!("Breakpoint at 0x{:03X?}", addr.0);
+                        for (i, x) in stack.iter().rev().enumerate() {
+                            println!("{:02} | {:?}", i, x);
+                        }
+                    },
+                    Instr::Imm(x) => {
+                        if let Value::List(xs) = &x {
+                            limits.alloc(&mut heap_used, xs.len())?;
+                        }
+                        stack.push(x)
+                    },
+                    Instr::Pop(n) => {
+                        if n == 0 {
+                            Err(RuntimeErrorKind::Error("popped zero items, this is probably a bug".into()))?;
+                        }
+                        if n > stack.len() {
+                            Err(RuntimeErrorKind::StackUnderflow)?;
+                        }
+                        stack.truncate(stack.len() - n);
+                    },
+                    Instr::Replace => {
+                        let x = pop(&mut stack)?;
+                        pop(&mut stack)?;
+                        stack.push(x);
+                    },
+                    Instr::Call(n) => {
+                        funcs.push(Frame { ret: next_addr, handlers: handlers.len(), hidden: None });
+                        next_addr = addr.jump(n);
+                    },
+                    Instr::Ret => if let Some(frame) = funcs.pop() {
+                        handlers.truncate(frame.handlers);
+                        if let Some(hidden) = frame.hidden {
+                            handlers.append(*hidden);
+                        }
+                        next_addr = frame.ret;
+                    } else {
+                        if locals.len() != 0 || stack.len() != 1 {
+                            Err(RuntimeErrorKind::BadExit(stack.len(), locals.len()))?;
+                        }
+                        if let Some(Value::Effect(eff)) = stack.last() {
+                            // Run effect objects produced by the program, so that the host can handle their effects
+                            let eff = eff.clone();
+                            stack.pop();
+                            next_addr = eff.addr;
+                            locals.extend(eff.captures.iter().cloned());
+                        } else {
+                            exit = Some(if prog.does_io {
+                                let mut r = pop(&mut stack)?.list()?;
+                                check_index(0, r.len())?;
+                                if r.remove(0).universe()? != universe_counter {
+                                    Err(RuntimeErrorKind::UniverseForked)?;
+                                }
+                                None
+                            } else {
+                                stack.pop()
+                            });
+                        }
+                    },
+                    Instr::MakeFunc(i, n) => {
+                        limits.alloc(&mut heap_used, n)?;
+                        let f_addr = addr.jump(i);
+                        let func = Value::Func(f_addr, pop_n(&mut stack, n)?.into());
+                        stack.push(func);
+                    },
+                    instr @ (Instr::ApplyFunc | Instr::TailCall(_)) => match pop(&mut stack)? {
+                        Value::Resumption(res) => {
+                            let arg = locals.pop().ok_or(RuntimeErrorKind::LocalUnderflow(0))?;
+                            let base = heights(&funcs, &stack, &locals, &handlers);
+
+                            // A resumption may be resumed many times, and the computation is copied each time
+                            limits.alloc(&mut heap_used, res.values.len() + res.locals.len())?;
+                            funcs.extend(res.frames.iter().map(|frame| frame.rebase(Heights::default(), base)));
+                            // The computation gives its result to the caller of the resumption
+                            funcs[base.frames].ret = next_addr;
+                            stack.extend(res.values.iter().cloned());
+                            locals.extend(res.locals.iter().cloned());
+                            handlers.extend(res.handlers.iter().map(|h| h.rebase(Heights::default(), base)));
+
+                            stack.push(arg);
+                            next_addr = res.addr;
+                        },
+                        f => {
+                            let (f_addr, captures) = f.func()?;
+
+                            if let Instr::TailCall(n) = instr {
+                                // The callee returns straight to the caller of the current function
+                                let arg = locals.pop().ok_or(RuntimeErrorKind::LocalUnderflow(0))?;
+                                if n > locals.len() {
+                                    Err(RuntimeErrorKind::LocalUnderflow(n))?;
+                                }
+                                locals.truncate(locals.len() - n);
+                                locals.push(arg);
+                            } else {
+                                funcs.push(Frame { ret: next_addr, handlers: handlers.len(), hidden: None });
+                            }
+                            next_addr = f_addr;
+
+                            locals.extend(captures.into_iter());
+                        },
+                    },
+                    Instr::MakeList(n) => {
+                        limits.alloc(&mut heap_used, n)?;
+                        let val = Value::List(pop_n(&mut stack, n)?.into());
+                        stack.push(val);
+                    },
+                    Instr::IndexList(i) => {
+                        let mut x = pop(&mut stack)?.list()?;
+                        check_index(i, x.len())?;
+                        stack.push(x.remove(i));
+                    },
+                    Instr::DupIndexList(i) => {
+                        let item = index_list(stack.last().ok_or(RuntimeErrorKind::StackUnderflow)?, i)?;
+                        stack.push(item);
+                    },
+                    Instr::GetLocalIndexList(x, i) => {
+                        if x >= locals.len() {
+                            Err(RuntimeErrorKind::LocalUnderflow(x))?;
+                        }
+                        stack.push(index_list(&locals[locals.len() - 1 - x], i)?);
+                    },
+                    Instr::SkipListImm(i) => {
+                        let x = pop(&mut stack)?.list()?;
+                        stack.push(Value::List(x.skip(i.min(x.len()))));
+                    },
+                    Instr::SetList(idx) => {
+                        let item = pop(&mut stack)?;
+                        let mut xs = pop(&mut stack)?.list()?;
+                        check_index(idx, xs.len())?;
+                        limits.alloc(&mut heap_used, 1)?;
+                        xs[idx] = item;
+                        stack.push(Value::List(xs));
+                    },
+                    Instr::LenList => {
+                        let len = pop(&mut stack)?.list()?.len();
+                        stack.push(Value::Int(len as i64));
+                    },
+                    Instr::JoinList => {
+                        let y = pop(&mut stack)?.list()?;
+                        let mut x = pop(&mut stack)?.list()?;
+                        limits.alloc(&mut heap_used, y.len())?;
+                        x.append(y);
+                        stack.push(Value::List(x));
+                    },
+                    Instr::SkipList => {
+                        let i = pop(&mut stack)?.int()?;
+                        let xs = pop(&mut stack)?.list()?;
+                        stack.push(Value::List(xs.skip((i as usize).min(xs.len()))));
+                    },
+                    Instr::TrimList => {
+                        let i = pop(&mut stack)?.int()?;
+                        let mut xs = pop(&mut stack)?.list()?;
+                        xs.truncate((i as usize).min(xs.len()));
+                        stack.push(Value::List(xs));
+                    },
+                    Instr::MakeSum(variant) => {
+                        let x = pop(&mut stack)?;
+                        limits.alloc(&mut heap_used, 1)?;
+                        stack.push(Value::Sum(variant, Rc::new(x)));
+                    },
+                    Instr::IndexSum(variant) => {
+                        let x = pop(&mut stack)?;
+                        let v = x.variant()?;
+                        if variant != v {
+                            Err(RuntimeErrorKind::WrongVariant(variant, v))?;
+                        }
+                        stack.push(match x {
+                            Value::Sum(_, inner) => (*inner).clone(),
+                            _ => Value::unit(),
+                        });
+                    },
+                    Instr::VariantSum => {
+                        let variant = pop(&mut stack)?.variant()?;
+                        stack.push(Value::Int(variant as i64));
+                    },
+                    Instr::Dup => stack.push(stack.last().ok_or(RuntimeErrorKind::StackUnderflow)?.clone()),
+                    Instr::Jump(n) => {
+                        next_addr = addr.jump(n);
+                    },
+                    Instr::IfNot => {
+                        if pop(&mut stack)?.bool()? {
+                            next_addr = next_addr.jump(1);
+                        }
+                    },
+                    Instr::JumpIfNot(n) => {
+                        if !pop(&mut stack)?.bool()? {
+                            next_addr = addr.jump(n);
+                        }
+                    },
+                    Instr::JumpIfNotVariant(variant, n) => {
+                        if pop(&mut stack)?.variant()? != variant {
+                            next_addr = addr.jump(n);
+                        }
+                    },
+                    Instr::JumpIfNotEqIntImm(x, n) => {
+                        if pop(&mut stack)?.int()? != x {
+                            next_addr = addr.jump(n);
+                        }
+                    },
+                    Instr::PushLocal => locals.push(pop(&mut stack)?),
+                    Instr::PopLocal(n) => {
+                        if n > locals.len() {
+                            Err(RuntimeErrorKind::LocalUnderflow(n))?;
+                        }
+                        locals.truncate(locals.len() - n);
+                    },
+                    Instr::GetLocal(x) => {
+                        if x >= locals.len() {
+                            Err(RuntimeErrorKind::LocalUnderflow(x))?;
+                        }
+                        stack.push(locals[locals.len() - 1 - x].clone());
+                    },
+                    Instr::NotBool => {
+                        let x = pop(&mut stack)?.bool()?;
+                        stack.push(Value::Bool(!x))
+                    },
+                    Instr::NegInt => {
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(x.wrapping_neg()))
+                    },
+                    Instr::NegReal => {
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Real(-x))
+                    },
+                    Instr::AddInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(x.wrapping_add(y)))
+                    },
+                    Instr::SubInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(x.wrapping_sub(y)))
+                    },
+                    Instr::MulInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(x.wrapping_mul(y)))
+                    },
+                    Instr::DivInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(if y == 0 { 0 } else { x.wrapping_div(y) }))
+                    },
+                    Instr::RemInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Int(if y == 0 { x } else { x.wrapping_rem(y) }))
+                    },
+                    Instr::AddReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Real(x + y))
+                    },
+                    Instr::SubReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Real(x - y))
+                    },
+                    Instr::MulReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Real(x * y))
+                    },
+                    Instr::DivReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Real(x / y))
+                    },
+                    Instr::EqReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Bool(x == y))
+                    },
+                    Instr::LessReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Bool(x < y))
+                    },
+                    Instr::MoreReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Bool(x > y))
+                    },
+                    Instr::LessEqReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Bool(x <= y))
+                    },
+                    Instr::MoreEqReal => {
+                        let y = pop(&mut stack)?.real()?;
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Bool(x >= y))
+                    },
+                    Instr::IntToReal => {
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Real(x as f64))
+                    },
+                    Instr::RealToInt => {
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Int(x as i64))
+                    },
+                    Instr::RealToNat => {
+                        let x = pop(&mut stack)?.real()?;
+                        stack.push(Value::Int((x as i64).max(0)))
+                    },
+                    Instr::DisplayReal => {
+                        let s = format!("{:?}", pop(&mut stack)?.real()?);
+                        limits.alloc(&mut heap_used, s.len())?;
+                        stack.push(Value::List(s.chars().map(Value::Char).collect()))
+                    },
+                    Instr::EqInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Bool(x == y))
+                    },
+                    Instr::EqBool => {
+                        let y = pop(&mut stack)?.bool()?;
+                        let x = pop(&mut stack)?.bool()?;
+                        stack.push(Value::Bool(x == y))
+                    },
+                    Instr::EqChar => {
+                        let y = pop(&mut stack)?.char()?;
+                        let x = pop(&mut stack)?.char()?;
+                        stack.push(Value::Bool(x == y))
+                    },
+                    Instr::LessInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Bool(x < y))
+                    },
+                    Instr::MoreInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Bool(x > y))
+                    },
+                    Instr::LessEqInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Bool(x <= y))
+                    },
+                    Instr::MoreEqInt => {
+                        let y = pop(&mut stack)?.int()?;
+                        let x = pop(&mut stack)?.int()?;
+                        stack.push(Value::Bool(x >= y))
+                    },
+                    Instr::AndBool => {
+                        let y = pop(&mut stack)?.bool()?;
+                        let x = pop(&mut stack)?.bool()?;
+                        stack.push(Value::Bool(x && y))
+                    },
+                    Instr::Print => {
+                        let s = pop(&mut stack)?.string()?;
+                        let universe = pop(&mut stack)?.universe()?;
+                        if universe != universe_counter {
+                            Err(RuntimeErrorKind::UniverseForked)?;
+                        }
+                        universe_counter += 1;
+                        (self.print)(&s).map_err(|e| RuntimeErrorKind::Io(e.to_string()))?;
+                        stack.push(Value::Universe(universe_counter))
+                    },
+                    Instr::Input => {
+                        let universe = pop(&mut stack)?.universe()?;
+                        if universe != universe_counter {
+                            Err(RuntimeErrorKind::UniverseForked)?;
+                        }
+                        universe_counter += 1;
+
+                        let s = (self.input)().map_err(|e| RuntimeErrorKind::Io(e.to_string()))?;
+                        limits.alloc(&mut heap_used, s.len())?;
+
+                        stack.push(Value::List(vector![
+                            Value::Universe(universe_counter),
+                            Value::List(s.trim_end().chars().map(Value::Char).collect()),
+                        ]));
+                    },
+                    Instr::MakeEffect(i, n) => {
+                        limits.alloc(&mut heap_used, n)?;
+                        let addr = addr.jump(i);
+                        let func = Value::Effect(Rc::new(Effect {
+                            addr,
+                            captures: pop_n(&mut stack, n)?.into(),
+                        }));
+                        stack.push(func);
+                    },
+                    Instr::Propagate => {
+                        let eff = pop(&mut stack)?.eff()?;
+
+                        funcs.push(Frame { ret: next_addr, handlers: handlers.len(), hidden: None });
+                        next_addr = eff.addr;
+
+                        locals.extend(eff.captures.iter().cloned());
+                    },
+                    Instr::Suspend(eff) => {
+                        let arg = locals.pop().ok_or(RuntimeErrorKind::LocalUnderflow(0))?;
+                        if let Some(idx) = (0..handlers.len()).rev().find(|idx| handlers[*idx].eff == eff) {
+                            let handler = handlers[idx].clone();
+                            let (f_addr, captures) = handler.func.func()?;
+
+                            if handler.resumable {
+                                // Capture the rest of the handled computation, which is abandoned until the handler
+                                // resumes it. The handler's result becomes the result of the computation.
+                                let base = handler.base;
+                                if base.frames > funcs.len()
+                                    || base.values > stack.len()
+                                    || base.locals > locals.len()
+                                    || base.handlers > handlers.len()
+                                {
+                                    Err(RuntimeErrorKind::Error("handled computation has already finished".into()))?;
+                                }
+                                let frames = funcs.split_off(base.frames);
+                                let ret = frames.first().ok_or_else(|| RuntimeErrorKind::Error("handled computation has no frame".into()))?.ret;
+                                let res = Resumption {
+                                    addr: next_addr,
+                                    frames: frames.iter().map(|frame| frame.rebase(base, Heights::default())).collect(),
+                                    values: stack.split_off(base.values),
+                                    locals: locals.split_off(base.locals),
+                                    handlers: handlers
+                                        .split_off(base.handlers)
+                                        .iter()
+                                        .map(|h| h.rebase(base, Heights::default()))
+                                        .collect(),
+                                };
+
+                                funcs.push(Frame { ret, handlers: handlers.len(), hidden: None });
+                                locals.push(Value::List(vector![arg, Value::Resumption(Rc::new(res))]));
+                            } else {
+                                // The handler resumes the computation with the value it produces, and runs outside the
+                                // computation, so handlers that were registered within it are hidden until it returns
+                                let hidden = handlers.split_off(idx);
+                                funcs.push(Frame { ret: next_addr, handlers: idx, hidden: Some(Box::new(hidden)) });
+                                locals.push(arg);
+                            }
+
+                            next_addr = f_addr;
+                            locals.extend(captures.into_iter());
+                        } else {
+                            // Fall back to the host, if it has a handler for the effect
+                            let name = prog.effects[eff.0];
+                            let handler = self.effects
+                                .get_mut(name.as_str())
+                                .ok_or(RuntimeErrorKind::NoHandler(name))?;
+                            stack.push(handler(arg).map_err(RuntimeErrorKind::Host)?);
+                        }
+                    },
+                    Instr::Register(eff, resumable) => {
+                        let eff_obj = pop(&mut stack)?.eff()?;
+                        let func = pop(&mut stack)?;
+
+                        let base = heights(&funcs, &stack, &locals, &handlers);
+                        funcs.push(Frame { ret: next_addr, handlers: handlers.len(), hidden: None });
+                        handlers.push_back(Handler { eff, func, resumable, base });
+                        next_addr = eff_obj.addr;
+
+                        locals.extend(eff_obj.captures.iter().cloned());
+                    },
+                    Instr::Host(name) => {
+                        let arg = pop(&mut stack)?;
+                        let intrinsic = self.intrinsics
+                            .get_mut(name.as_str())
+                            .ok_or(RuntimeErrorKind::NoIntrinsic(name))?;
+                        stack.push(intrinsic(arg).map_err(RuntimeErrorKind::Host)?);
+                    },
+                }
+
+                limits.check_heights(&funcs, &stack, &locals)?;
+            };
+
+            if let Err(kind) = res {
+                break Err(RuntimeError {
+                    kind,
                     addr,
-                    captures: stack.split_off(stack.len().saturating_sub(n)).into(),
-                    handlers: handlers.clone(),
-                }));
-                stack.push(func);
-            },
-            Instr::Propagate => {
-                let mut eff = stack.pop().unwrap().eff();
+                    stack,
+                    span: prog.span_of(addr),
+                });
+            } else if let Some(result) = exit {
+                break Ok(result);
+            }
 
-                funcs.push((next_addr, handlers.len()));
-                next_addr = eff.addr;
+            addr = next_addr;
+        }
+    }
+}
 
-                handlers.extend(eff.handlers.iter().cloned());
+/// Run a program with the default [`Vm`], which has no host effects or intrinsics and uses the standard output and
+/// input of the process.
+pub fn exec(prog: &Program) -> Result<Option<Value>, RuntimeError> {
+    Vm::default().run(prog)
+}
 
-                locals.extend(eff.captures.iter().cloned());
-            },
-            Instr::Suspend(eff) => {
-                let (f_addr, mut captures) = handlers
-                    .iter()
-                    .rev()
-                    .find(|(e, _)| *e == eff)
-                    .unwrap().1.clone().func();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                funcs.push((next_addr, handlers.len()));
-                next_addr = f_addr;
+    fn span(i: usize) -> Span {
+        Span::new(SrcId::from_path("test.tao"), i * 10..i * 10 + 5)
+    }
 
-                locals.extend(captures.into_iter());
-            },
-            Instr::Register(eff) => {
-                let handler = stack.pop().unwrap();
-                handlers.push_back((eff, handler));
-            },
+    // Load a hand-built program through the binary format, as a host running untrusted bytecode would, and run it
+    fn run(instrs: Vec<Instr>) -> RuntimeError {
+        let mut prog = Program::default();
+        for (i, instr) in instrs.into_iter().enumerate() {
+            prog.debug(format!("instr {}", i), Some(span(i)));
+            prog.push(instr);
         }
+        let prog = Program::decode(&prog.encode()).expect("Failed to load program");
+        Vm::default().run(&prog).expect_err("Program should fail")
+    }
+
+    fn assert_underflow(err: RuntimeError, addr: usize) {
+        assert!(matches!(err.kind, RuntimeErrorKind::StackUnderflow), "{}", err);
+        assert_eq!(err.addr, Addr(addr));
+        assert_eq!(err.span, Some(span(addr)));
+    }
+
+    #[test]
+    fn pop_underflow() {
+        assert_underflow(run(vec![Instr::Imm(Value::Int(1)), Instr::Pop(2), Instr::Ret]), 1);
+    }
+
+    #[test]
+    fn make_list_underflow() {
+        assert_underflow(run(vec![Instr::Imm(Value::Int(1)), Instr::MakeList(2), Instr::Ret]), 1);
+    }
+
+    #[test]
+    fn make_func_underflow() {
+        assert_underflow(run(vec![Instr::Nop, Instr::MakeFunc(1, 1), Instr::Ret]), 1);
+    }
+
+    #[test]
+    fn make_effect_underflow() {
+        assert_underflow(run(vec![Instr::Nop, Instr::MakeEffect(1, 3), Instr::Ret]), 1);
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let err = run(vec![Instr::Imm(Value::Int(1)), Instr::Imm(Value::Bool(true)), Instr::AddInt, Instr::Ret]);
+        assert!(matches!(err.kind, RuntimeErrorKind::TypeMismatch("int", Value::Bool(true))), "{}", err);
+        assert_eq!(err.addr, Addr(2));
+        assert_eq!(err.span, Some(span(2)));
+    }
 
-        tick += 1;
+    #[test]
+    fn int_overflow_wraps() {
+        let eval = |instrs: Vec<Instr>| match Vm::default().run(&program(&[], instrs)) {
+            Ok(Some(Value::Int(x))) => x,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        let binary = |x, instr, y| eval(vec![Instr::Imm(Value::Int(x)), Instr::Imm(Value::Int(y)), instr, Instr::Ret]);
+        assert_eq!(binary(i64::MAX, Instr::AddInt, 1), i64::MIN);
+        assert_eq!(binary(i64::MIN, Instr::SubInt, 1), i64::MAX);
+        assert_eq!(binary(i64::MAX, Instr::MulInt, 2), -2);
+        assert_eq!(binary(i64::MIN, Instr::DivInt, -1), i64::MIN);
+        assert_eq!(eval(vec![Instr::Imm(Value::Int(i64::MIN)), Instr::NegInt, Instr::Ret]), i64::MIN);
+    }
+
+    #[test]
+    fn bad_exit() {
+        let err = run(vec![Instr::Imm(Value::Int(1)), Instr::Imm(Value::Int(2)), Instr::Ret]);
+        assert!(matches!(err.kind, RuntimeErrorKind::BadExit(2, 0)), "{}", err);
+        assert_eq!(err.addr, Addr(2));
+        assert_eq!(err.span, Some(span(2)));
+    }
+
+    fn program(effects: &[&str], instrs: Vec<Instr>) -> Program {
+        let mut prog = Program::default();
+        prog.effects = effects.iter().map(|name| Ident::new(*name)).collect();
+        for (i, instr) in instrs.into_iter().enumerate() {
+            prog.debug(format!("instr {}", i), Some(span(i)));
+            prog.push(instr);
+        }
+        prog
+    }
+
+    // Suspend the first effect with 3, then add 1 to the value that it is resumed with
+    fn suspend_program(effects: &[&str]) -> Program {
+        program(effects, vec![
+            Instr::Imm(Value::Int(3)),
+            Instr::PushLocal,
+            Instr::Suspend(Eff(0)),
+            Instr::Imm(Value::Int(1)),
+            Instr::AddInt,
+            Instr::Ret,
+        ])
+    }
+
+    #[test]
+    fn host_effect() {
+        let mut sent = Vec::new();
+        let res = Vm::default()
+            .handle_effect("log", |x| { sent.push(x.clone()); Ok(Value::Int(x.int().map_err(|e| e.to_string())? * 10)) })
+            .run(&suspend_program(&["log"]));
+        assert!(matches!(res, Ok(Some(Value::Int(31)))), "{:?}", res);
+        assert!(matches!(&sent[..], [Value::Int(3)]), "{:?}", sent);
+    }
+
+    #[test]
+    fn host_effect_error() {
+        let err = Vm::default()
+            .handle_effect("log", |_| Err("failed".to_string()))
+            .run(&suspend_program(&["log"]))
+            .unwrap_err();
+        assert!(matches!(&err.kind, RuntimeErrorKind::Host(msg) if msg == "failed"), "{}", err);
+        assert_eq!(err.addr, Addr(2));
+        assert_eq!(err.span, Some(span(2)));
+    }
+
+    #[test]
+    fn missing_host_effect() {
+        let err = Vm::default()
+            .handle_effect("other", Ok)
+            .run(&suspend_program(&["log"]))
+            .unwrap_err();
+        assert!(matches!(err.kind, RuntimeErrorKind::NoHandler(name) if name == Ident::new("log")), "{}", err);
+        assert_eq!(err.addr, Addr(2));
+        assert_eq!(err.span, Some(span(2)));
+    }
+
+    #[test]
+    fn ambiguous_host_effect() {
+        let mut called = false;
+        let err = Vm::default()
+            .handle_effect("log", |x| { called = true; Ok(x) })
+            .run(&suspend_program(&["log", "log"]))
+            .unwrap_err();
+        assert!(matches!(err.kind, RuntimeErrorKind::AmbiguousEffect(name) if name == Ident::new("log")), "{}", err);
+        assert!(!called);
+
+        // Effects that share a name are fine so long as the host does not try to handle them
+        let err = Vm::default().run(&suspend_program(&["log", "log"])).unwrap_err();
+        assert!(matches!(err.kind, RuntimeErrorKind::NoHandler(_)), "{}", err);
+    }
+
+    #[test]
+    fn host_intrinsic() {
+        let prog = program(&[], vec![Instr::Imm(Value::Int(4)), Instr::Host(Ident::new("double")), Instr::Ret]);
+
+        let mut given = Vec::new();
+        let res = Vm::default()
+            .intrinsic("double", |x| { given.push(x.clone()); Ok(Value::Int(x.int().map_err(|e| e.to_string())? * 2)) })
+            .run(&prog);
+        assert!(matches!(res, Ok(Some(Value::Int(8)))), "{:?}", res);
+        assert!(matches!(&given[..], [Value::Int(4)]), "{:?}", given);
+
+        let err = Vm::default().run(&prog).unwrap_err();
+        assert!(matches!(err.kind, RuntimeErrorKind::NoIntrinsic(name) if name == Ident::new("double")), "{}", err);
+        assert_eq!(err.addr, Addr(1));
+        assert_eq!(err.span, Some(span(1)));
+    }
+
+    #[test]
+    fn print() {
+        let prog = program(&[], vec![Instr::Imm(Value::Universe(0)), Instr::Imm(Value::str("hello")), Instr::Print, Instr::Ret]);
+
+        let mut lines = Vec::new();
+        let res = Vm::default()
+            .on_print(|s| { lines.push(s.to_string()); Ok(()) })
+            .run(&prog);
+        assert!(matches!(res, Ok(Some(Value::Universe(1)))), "{:?}", res);
+        assert_eq!(lines, ["hello"]);
+
+        let err = Vm::default()
+            .on_print(|_| Err(io::Error::new(io::ErrorKind::Other, "closed")))
+            .run(&prog)
+            .unwrap_err();
+        assert!(matches!(&err.kind, RuntimeErrorKind::Io(msg) if msg == "closed"), "{}", err);
+        assert_eq!(err.addr, Addr(2));
+    }
+
+    #[test]
+    fn input() {
+        let prog = program(&[], vec![Instr::Imm(Value::Universe(0)), Instr::Input, Instr::Ret]);
+
+        let res = Vm::default()
+            .on_input(|| Ok("hello\n".to_string()))
+            .run(&prog)
+            .unwrap()
+            .unwrap()
+            .list()
+            .unwrap();
+        assert!(matches!(res[0], Value::Universe(1)), "{:?}", res);
+        assert_eq!(res[1].clone().string().unwrap(), "hello");
+    }
+
+    fn run_limited(limits: Limits, instrs: Vec<Instr>) -> Result<Option<Value>, RuntimeError> {
+        Vm::default().limits(limits).run(&program(&[], instrs))
+    }
+
+    fn assert_limit(res: Result<Option<Value>, RuntimeError>, kind: RuntimeErrorKind) {
+        let err = res.expect_err("Program should exceed its limits");
+        assert_eq!(format!("{:?}", err.kind), format!("{:?}", kind), "{}", err);
+    }
+
+    // `f n = if n = 0 then 0 else 1 + f (n - 1)`, which is not tail-recursive, applied to `n`
+    fn count_down(n: i64) -> Vec<Instr> {
+        vec![
+            Instr::Imm(Value::Int(n)),
+            Instr::PushLocal,
+            Instr::Call(2),
+            Instr::Ret,
+            // f
+            Instr::GetLocal(0),
+            Instr::JumpIfNotEqIntImm(0, 4),
+            Instr::PopLocal(1),
+            Instr::Imm(Value::Int(0)),
+            Instr::Ret,
+            Instr::GetLocal(0),
+            Instr::Imm(Value::Int(1)),
+            Instr::SubInt,
+            Instr::PushLocal,
+            Instr::Call(-9),
+            Instr::Imm(Value::Int(1)),
+            Instr::AddInt,
+            Instr::PopLocal(1),
+            Instr::Ret,
+        ]
+    }
+
+    #[test]
+    fn fuel_limit() {
+        let limits = Limits { fuel: Some(2), ..Limits::default() };
+        let res = run_limited(limits, vec![Instr::Imm(Value::Int(1)), Instr::Ret]);
+        assert!(matches!(res, Ok(Some(Value::Int(1)))), "{:?}", res);
+
+        let limits = Limits { fuel: Some(1000), ..Limits::default() };
+        assert_limit(run_limited(limits, vec![Instr::Jump(0)]), RuntimeErrorKind::OutOfFuel(1000));
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let res = run_limited(Limits { call_depth: Some(101), ..Limits::default() }, count_down(100));
+        assert!(matches!(res, Ok(Some(Value::Int(100)))), "{:?}", res);
+
+        let res = run_limited(Limits { call_depth: Some(50), ..Limits::default() }, count_down(100));
+        assert_limit(res, RuntimeErrorKind::CallDepthExceeded(50));
+    }
+
+    #[test]
+    fn stack_limit() {
+        let limits = Limits { stack: Some(64), ..Limits::default() };
+        let instrs = vec![Instr::Imm(Value::Int(1)), Instr::Jump(-1)];
+        assert_limit(run_limited(limits, instrs), RuntimeErrorKind::StackOverflow(64));
+    }
+
+    #[test]
+    fn locals_limit() {
+        let limits = Limits { locals: Some(64), ..Limits::default() };
+        let instrs = vec![Instr::Imm(Value::Int(1)), Instr::PushLocal, Instr::Jump(-2)];
+        assert_limit(run_limited(limits, instrs), RuntimeErrorKind::TooManyLocals(64));
+    }
+
+    #[test]
+    fn heap_limit() {
+        // `xs ++ xs` forever, which doubles the list each time. The fuel stops the loop if the heap limit doesn't.
+        let limits = Limits { heap: Some(1000), fuel: Some(1_000_000), ..Limits::default() };
+        let instrs = vec![
+            Instr::Imm(Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)].into())),
+            Instr::Dup,
+            Instr::JoinList,
+            Instr::Jump(-2),
+        ];
+        assert_limit(run_limited(limits, instrs), RuntimeErrorKind::HeapExhausted(1000));
+
+        // Setting items copies the list
+        let instrs = vec![
+            Instr::Imm(Value::List(vec![Value::Int(1)].into())),
+            Instr::Imm(Value::Int(2)),
+            Instr::SetList(0),
+            Instr::Jump(-2),
+        ];
+        assert_limit(run_limited(limits, instrs), RuntimeErrorKind::HeapExhausted(1000));
+
+        // Closures and effect objects store their captures
+        for make in [Instr::MakeFunc(0, 1), Instr::MakeEffect(0, 1)] {
+            let instrs = vec![Instr::Imm(Value::Int(1)), make, Instr::Pop(1), Instr::Jump(-3)];
+            assert_limit(run_limited(limits, instrs), RuntimeErrorKind::HeapExhausted(1000));
+        }
 
-        addr = next_addr;
+        // Sums box their inner value, so wrapping a value forever builds an ever-longer chain
+        let instrs = vec![Instr::Imm(Value::Int(0)), Instr::MakeSum(0), Instr::Jump(-1)];
+        assert_limit(run_limited(limits, instrs), RuntimeErrorKind::HeapExhausted(1000));
     }
 }
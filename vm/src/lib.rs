@@ -1,10 +1,16 @@
+#![feature(try_blocks)]
+
+pub mod binary;
 pub mod code;
+pub mod error;
 pub mod exec;
 pub mod lower;
+pub mod peephole;
 
 pub use crate::{
-    code::{Instr, Program, Addr},
-    exec::{exec, Value},
+    code::{Instr, Program, Addr, Eff},
+    error::{RuntimeError, RuntimeErrorKind, LoadError},
+    exec::{exec, Vm, HostFn, Limits, Value},
 };
 use tao_middle::{
     mir,
@@ -14,5 +20,7 @@ use tao_middle::{
     repr,
     Ident,
     EffectId,
+    Span,
+    SrcId,
 };
 use hashbrown::HashMap;
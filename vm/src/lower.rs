@@ -21,7 +21,10 @@ fn litr_to_value(literal: &mir::Literal) -> Option<Value> {
             .iter()
             .map(litr_to_value)
             .collect::<Option<_>>()?),
-        mir::Literal::Sum(variant, inner) => Value::Sum(*variant, Rc::new(litr_to_value(inner)?)),
+        mir::Literal::Sum(variant, inner) => match &**inner {
+            mir::Literal::Tuple(fields) if fields.is_empty() => Value::Tag(*variant),
+            inner => Value::Sum(*variant, Rc::new(litr_to_value(inner)?)),
+        },
         mir::Literal::Data(_, inner) => litr_to_value(inner)?,
     })
 }
@@ -204,6 +207,12 @@ impl Program {
 
                     self.compile_item_matcher(items, true, fail_fixup);
                 },
+                // Only the tag needs checking, so the inner value is never extracted
+                mir::Pat::Variant(variant, inner) if !inner.is_refutable() => {
+                    self.push(Instr::VariantSum);
+                    self.push(Instr::Imm(Value::Int(*variant as i64)));
+                    self.push(Instr::EqInt);
+                },
                 mir::Pat::Variant(variant, inner) => {
                     self.push(Instr::Dup);
                     self.push(Instr::VariantSum);
@@ -244,7 +253,7 @@ impl Program {
 
         // A function with an undefined body doesn't need to be compiled!
         if !matches!(&*body, mir::Expr::Undefined) {
-            self.compile_expr(mir, body, &mut f_stack, proc_fixups);
+            self.compile_expr_in(mir, body, &mut f_stack, proc_fixups, true);
             self.push(Instr::PopLocal(arg.is_some() as usize + captures.len())); // +1 is for the argument
             self.push(Instr::Ret);
         }
@@ -274,6 +283,21 @@ impl Program {
         expr: &mir::Expr,
         stack: &mut Vec<mir::Local>,
         proc_fixups: &mut Vec<(ProcId, Addr)>,
+    ) {
+        self.compile_expr_in(mir, expr, stack, proc_fixups, false)
+    }
+
+    // [..] -> [.., T]
+    //
+    // If `tail` is set, every local in `stack` belongs to the function being compiled, so applications that MIR marks as
+    // being in tail position may reuse the frame of the function.
+    fn compile_expr_in(
+        &mut self,
+        mir: &MirContext,
+        expr: &mir::Expr,
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+        tail: bool,
     ) {
         match &*expr {
             mir::Expr::Undefined => {}, // Do the minimum possible work, execution is undefined anyway
@@ -307,6 +331,12 @@ impl Program {
                     Intrinsic::AddNat | Intrinsic::AddInt => { self.push(Instr::AddInt); },
                     Intrinsic::SubNat | Intrinsic::SubInt => { self.push(Instr::SubInt); },
                     Intrinsic::MulNat | Intrinsic::MulInt => { self.push(Instr::MulInt); },
+                    Intrinsic::DivNat | Intrinsic::DivInt => { self.push(Instr::DivInt); },
+                    Intrinsic::RemNat | Intrinsic::RemInt => { self.push(Instr::RemInt); },
+                    Intrinsic::AddReal => { self.push(Instr::AddReal); },
+                    Intrinsic::SubReal => { self.push(Instr::SubReal); },
+                    Intrinsic::MulReal => { self.push(Instr::MulReal); },
+                    Intrinsic::DivReal => { self.push(Instr::DivReal); },
                     Intrinsic::EqNat | Intrinsic::EqInt => { self.push(Instr::EqInt); },
                     Intrinsic::EqChar => { self.push(Instr::EqChar); },
                     Intrinsic::NotEqNat | Intrinsic::NotEqInt => {
@@ -321,6 +351,19 @@ impl Program {
                     Intrinsic::MoreNat | Intrinsic::MoreInt => { self.push(Instr::MoreInt); },
                     Intrinsic::LessEqNat | Intrinsic::LessEqInt => { self.push(Instr::LessEqInt); },
                     Intrinsic::MoreEqNat | Intrinsic::MoreEqInt => { self.push(Instr::MoreEqInt); },
+                    Intrinsic::EqReal => { self.push(Instr::EqReal); },
+                    Intrinsic::NotEqReal => {
+                        self.push(Instr::EqReal);
+                        self.push(Instr::NotBool);
+                    },
+                    Intrinsic::LessReal => { self.push(Instr::LessReal); },
+                    Intrinsic::MoreReal => { self.push(Instr::MoreReal); },
+                    Intrinsic::LessEqReal => { self.push(Instr::LessEqReal); },
+                    Intrinsic::MoreEqReal => { self.push(Instr::MoreEqReal); },
+                    Intrinsic::NatToReal | Intrinsic::IntToReal => { self.push(Instr::IntToReal); },
+                    Intrinsic::RealToInt => { self.push(Instr::RealToInt); },
+                    Intrinsic::RealToNat => { self.push(Instr::RealToNat); },
+                    Intrinsic::DisplayReal => { self.push(Instr::DisplayReal); },
                     Intrinsic::Join(_) => { self.push(Instr::JoinList); },
                     Intrinsic::AndBool => { self.push(Instr::AndBool); },
                     Intrinsic::Print => { self.push(Instr::Print); },
@@ -331,9 +374,11 @@ impl Program {
                     Intrinsic::TrimList => { self.push(Instr::TrimList); },
                     Intrinsic::Suspend(eff) => {
                         self.push(Instr::PushLocal);
-                        self.push(Instr::Suspend(*eff));
+                        let eff = self.effect(mir, *eff);
+                        self.push(Instr::Suspend(eff));
                     },
                     Intrinsic::Propagate => { self.push(Instr::Propagate); },
+                    Intrinsic::Host(name) => { self.push(Instr::Host(*name)); },
                 };
             },
             mir::Expr::Tuple(fields) => {
@@ -373,7 +418,7 @@ impl Program {
                     let names = binding.binding_names();
                     stack.extend(names.iter().copied()); // Start scope
 
-                    self.compile_expr(mir, body, stack, proc_fixups);
+                    self.compile_expr_in(mir, body, stack, proc_fixups, tail);
 
                     if names.len() > 0 {
                         self.push(Instr::PopLocal(names.len()));
@@ -429,11 +474,25 @@ impl Program {
                 self.push(Instr::IndexSum(DONE_VARIANT));
                 self.push(Instr::Replace);
             },
-            mir::Expr::Apply(f, arg) => {
+            mir::Expr::Apply(f, arg, flags) => {
                 self.compile_expr(mir, f, stack, proc_fixups);
                 self.compile_expr(mir, arg, stack, proc_fixups);
                 self.push(Instr::PushLocal);
-                self.push(Instr::ApplyFunc);
+                if tail && flags.get().is_tail {
+                    self.push(Instr::TailCall(stack.len()));
+                    // Resumptions cannot be tail called, so applying one returns here
+                    if stack.len() > 0 {
+                        self.push(Instr::PopLocal(stack.len()));
+                    }
+                    self.push(Instr::Ret);
+                } else {
+                    self.push(Instr::ApplyFunc);
+                }
+            },
+            // Variants with no fields need no allocation
+            mir::Expr::Variant(variant, inner) if matches!(&**inner, mir::Expr::Tuple(fields) if fields.is_empty())
+                || matches!(&**inner, mir::Expr::Literal(mir::Literal::Tuple(fields)) if fields.is_empty()) => {
+                self.push(Instr::Imm(Value::Tag(*variant)));
             },
             mir::Expr::Variant(variant, inner) => {
                 self.compile_expr(mir, inner, stack, proc_fixups);
@@ -457,21 +516,22 @@ impl Program {
                 let (f_addr, captures_len) = self.compile_body(mir, None, inner, stack, proc_fixups);
                 self.push(Instr::MakeEffect(self.next_addr().jump_to(f_addr), captures_len));
             },
-            mir::Expr::Handle { expr, eff, send, recv } => {
+            mir::Expr::Handle { expr, eff, send, recv, resumable } => {
                 let (h_addr, captures_len) = self.compile_body(mir, Some(**send), recv, stack, proc_fixups);
                 self.push(Instr::MakeFunc(self.next_addr().jump_to(h_addr), captures_len));
-                self.push(Instr::Register(*eff));
 
                 self.compile_expr(mir, expr, stack, proc_fixups);
-                self.push(Instr::Propagate);
+                let eff = self.effect(mir, *eff);
+                self.push(Instr::Register(eff, *resumable));
             },
         }
     }
 
     pub fn compile_proc(&mut self, mir: &MirContext, proc: ProcId, entry_io: bool, proc_fixups: &mut Vec<(ProcId, Addr)>) -> Addr {
-        self.debug(format!("Proc {:?}", proc));
+        let proc_ref = mir.procs.get(proc).unwrap();
+        self.debug(format!("Proc {:?}", proc), proc_ref.span);
         let addr = self.next_addr();
-        self.compile_expr(mir, &mir.procs.get(proc).unwrap().body, &mut Vec::new(), proc_fixups);
+        self.compile_expr_in(mir, &proc_ref.body, &mut Vec::new(), proc_fixups, !entry_io);
         if entry_io {
             self.push(Instr::ApplyFunc);
         }
@@ -483,19 +543,7 @@ impl Program {
         let mut this = Self::default();
 
         let entry = mir.entry.expect("No entry point");
-        this.does_io = if let repr::Repr::Func(i, o) = mir.procs.get(entry).unwrap().body.meta() {
-            if let (repr::Repr::Prim(repr::Prim::Universe), repr::Repr::Tuple(xs)) = (&**i, &**o) {
-                if let [repr::Repr::Prim(repr::Prim::Universe), repr::Repr::Tuple(xs)] = &xs[..] {
-                    xs.len() == 0
-                } else {
-                    false
-                }
-            } else {
-                false
-            }
-        } else {
-            false
-        };
+        this.does_io = mir.entry_does_io();
 
         let mut procs = BTreeMap::new();
         let mut proc_fixups = Vec::new();
@@ -0,0 +1,346 @@
+use super::*;
+use std::rc::Rc;
+
+// Each round of rewriting can expose new opportunities (such as a jump to a branch that has just been fused), but
+// there are rarely any left after a few rounds
+const MAX_ROUNDS: usize = 4;
+// Chains of unconditional jumps are followed no further than this, which also stops infinite loops being followed
+const MAX_JUMP_CHAIN: usize = 16;
+
+// The length and target of the branch at the given address, if there is one: a branch pops a bool and jumps to its
+// target if it is false, otherwise falling through to the instruction after it
+fn branch_if_not(instrs: &[Instr], addr: usize) -> Option<(usize, usize)> {
+    match (instrs.get(addr)?, instrs.get(addr + 1)) {
+        (Instr::IfNot, Some(Instr::Jump(rel))) => Some((2, Addr(addr + 1).jump(*rel).0)),
+        (Instr::JumpIfNot(rel), _) => Some((1, Addr(addr).jump(*rel).0)),
+        _ => None,
+    }
+}
+
+fn func_addrs(val: &Value, f: &mut impl FnMut(Addr)) {
+    match val {
+        Value::List(items) => items.iter().for_each(|item| func_addrs(item, f)),
+        Value::Func(addr, captures) => {
+            f(*addr);
+            captures.iter().for_each(|capture| func_addrs(capture, f));
+        },
+        Value::Sum(_, inner) => func_addrs(inner, f),
+        _ => {},
+    }
+}
+
+fn relocate_funcs(val: &mut Value, relocate: &impl Fn(Addr) -> Addr) {
+    match val {
+        Value::List(items) => items.iter_mut().for_each(|item| relocate_funcs(item, relocate)),
+        Value::Func(addr, captures) => {
+            *addr = relocate(*addr);
+            captures.iter_mut().for_each(|capture| relocate_funcs(capture, relocate));
+        },
+        Value::Sum(_, inner) => relocate_funcs(Rc::make_mut(inner), relocate),
+        _ => {},
+    }
+}
+
+impl Program {
+    /// Rewrite common runs of instructions into the equivalent superinstructions, remove redundant jumps, and make
+    /// jumps to other jumps go straight to their final target.
+    ///
+    /// The resulting program has the same behaviour, but executes fewer instructions.
+    pub fn optimize(&mut self) {
+        for _ in 0..MAX_ROUNDS {
+            let len = self.instrs.len();
+            self.peephole();
+            if self.instrs.len() == len {
+                break;
+            }
+        }
+    }
+
+    // Find every address that execution can arrive at other than by falling through from the previous instruction.
+    // Runs of instructions containing one of these cannot be rewritten.
+    fn targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.instrs.len() + 1];
+        targets[self.entry.0.min(self.instrs.len())] = true;
+        for (addr, instr) in self.instrs.iter().enumerate() {
+            if let Some(rel) = instr.rel() {
+                if let Some(tgt) = targets.get_mut(Addr(addr).jump(rel).0) {
+                    *tgt = true;
+                }
+            }
+            match instr {
+                // Skipping the next instruction arrives at the one after it
+                Instr::IfNot => targets[(addr + 2).min(self.instrs.len())] = true,
+                // Jumps threaded through a branch arrive at the instruction after it
+                Instr::JumpIfNot(_) => targets[addr + 1] = true,
+                Instr::Imm(val) => func_addrs(val, &mut |f_addr| if let Some(tgt) = targets.get_mut(f_addr.0) {
+                    *tgt = true;
+                }),
+                _ => {},
+            }
+        }
+        targets
+    }
+
+    fn peephole(&mut self) {
+        let targets = self.targets();
+        let instrs = std::mem::take(&mut self.instrs);
+        let resolve = |mut addr: usize| {
+            for _ in 0..MAX_JUMP_CHAIN {
+                match instrs.get(addr) {
+                    Some(Instr::Jump(rel)) if *rel != 0 => addr = Addr(addr).jump(*rel).0,
+                    _ => break,
+                }
+            }
+            addr
+        };
+
+        // The rewritten instructions, along with the address of the run of old instructions that each was produced
+        // from. Until relocation, the offset of each instruction is the old address of its target, not a relative one.
+        let mut rewritten = Vec::<(usize, Instr)>::with_capacity(instrs.len());
+        let mut addr = 0;
+        while addr < instrs.len() {
+            // An `IfNot` skips exactly one instruction, so the instruction after one must be left alone
+            let after_if_not = addr > 0 && matches!(instrs[addr - 1], Instr::IfNot);
+            let rewrites = if after_if_not { Vec::new() } else { rewrites(&instrs, addr, resolve) };
+            let rewrite = rewrites
+                .into_iter()
+                .find(|(len, _)| (addr + 1..addr + len).all(|a| !targets[a]));
+
+            if let Some((len, instr)) = rewrite {
+                rewritten.extend(instr.map(|instr| (addr, instr)));
+                addr += len;
+            } else {
+                let mut instr = instrs[addr].clone();
+                if let Some(rel) = instr.rel_mut() {
+                    *rel = Addr(addr).jump(*rel).0 as isize;
+                }
+                rewritten.push((addr, instr));
+                addr += 1;
+            }
+        }
+
+        // The new address of the code at each old address
+        let map = (0..=instrs.len())
+            .map(|addr| rewritten.partition_point(|(start, _)| *start < addr))
+            .collect::<Vec<_>>();
+        let relocate = |addr: usize| map[resolve(addr).min(instrs.len())];
+
+        self.instrs = rewritten
+            .into_iter()
+            .enumerate()
+            .map(|(new_addr, (_, mut instr))| {
+                if let Some(rel) = instr.rel_mut() {
+                    *rel = Addr(new_addr).jump_to(Addr(relocate(*rel as usize)));
+                }
+                if let Instr::Imm(val) = &mut instr {
+                    relocate_funcs(val, &|f_addr: Addr| Addr(relocate(f_addr.0)));
+                }
+                instr
+            })
+            .collect();
+        for (addr, _, _) in &mut self.debug {
+            *addr = Addr(map[addr.0.min(instrs.len())]);
+        }
+        self.entry = Addr(relocate(self.entry.0));
+    }
+}
+
+// The ways in which the run of instructions at the given address could be rewritten, longest first, as the length
+// of the run and the instruction (if any) to replace it with
+fn rewrites(instrs: &[Instr], addr: usize, resolve: impl Fn(usize) -> usize) -> Vec<(usize, Option<Instr>)> {
+    let at = |offset: usize| instrs.get(addr + offset);
+    let mut rewrites = Vec::new();
+
+    match (at(0), at(1), at(2)) {
+        (Some(Instr::VariantSum), Some(Instr::Imm(Value::Int(variant))), Some(Instr::EqInt)) if *variant >= 0 => {
+            if let Some((len, tgt)) = branch_if_not(instrs, addr + 3) {
+                rewrites.push((3 + len, Some(Instr::JumpIfNotVariant(*variant as usize, tgt as isize))));
+            }
+        },
+        _ => {},
+    }
+
+    match (at(0), at(1), at(2)) {
+        // Skipping the start of a copy of a list that then gets dropped does nothing
+        (Some(Instr::Dup), Some(Instr::SkipListImm(_)), Some(Instr::Pop(1))) => rewrites.push((3, None)),
+        _ => {},
+    }
+
+    match (at(0), at(1)) {
+        (Some(Instr::Imm(Value::Int(x))), Some(Instr::EqInt)) => {
+            if let Some((len, tgt)) = branch_if_not(instrs, addr + 2) {
+                rewrites.push((2 + len, Some(Instr::JumpIfNotEqIntImm(*x, tgt as isize))));
+            }
+        },
+        (Some(Instr::Imm(Value::Bool(x))), next) => {
+            // A branch on a constant either always or never jumps
+            if let Some((len, tgt)) = branch_if_not(instrs, addr + 1) {
+                rewrites.push((1 + len, if *x { None } else { Some(Instr::Jump(tgt as isize)) }));
+            } else if let Some(Instr::Jump(rel)) = next {
+                // Jumping to a branch with a constant is the same as jumping to wherever the branch goes
+                let branch = resolve(Addr(addr + 1).jump(*rel).0);
+                if let Some((len, tgt)) = branch_if_not(instrs, branch) {
+                    rewrites.push((2, Some(Instr::Jump((if *x { branch + len } else { tgt }) as isize))));
+                }
+            }
+        },
+        (Some(Instr::IfNot), Some(Instr::Jump(rel))) => {
+            rewrites.push((2, Some(Instr::JumpIfNot(Addr(addr + 1).jump(*rel).0 as isize))));
+        },
+        (Some(Instr::Dup), Some(Instr::IndexList(idx))) => rewrites.push((2, Some(Instr::DupIndexList(*idx)))),
+        (Some(Instr::GetLocal(x)), Some(Instr::IndexList(idx))) => {
+            rewrites.push((2, Some(Instr::GetLocalIndexList(*x, *idx))));
+        },
+        (Some(Instr::Pop(x)), Some(Instr::Pop(y))) => rewrites.push((2, Some(Instr::Pop(x + y)))),
+        (Some(Instr::PopLocal(x)), Some(Instr::PopLocal(y))) => rewrites.push((2, Some(Instr::PopLocal(x + y)))),
+        (Some(Instr::Jump(1)), _) => rewrites.push((1, None)),
+        _ => {},
+    }
+
+    rewrites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(instrs: Vec<Instr>) -> Program {
+        let mut prog = Program::default();
+        instrs.into_iter().for_each(|instr| { prog.push(instr); });
+        prog
+    }
+
+    fn optimized(instrs: Vec<Instr>) -> Program {
+        let mut prog = program(instrs);
+        prog.optimize();
+        prog
+    }
+
+    // `Instr` has no `PartialEq`, since it contains values
+    fn assert_instrs(prog: &Program, expected: Vec<Instr>) {
+        assert_eq!(format!("{:?}", prog.instrs), format!("{:?}", expected));
+    }
+
+    // Check that optimising the program does not change its result
+    fn assert_same_result(instrs: Vec<Instr>) -> Program {
+        let before = Vm::default().run(&program(instrs.clone())).unwrap();
+        let prog = optimized(instrs);
+        let after = Vm::default().run(&prog).unwrap();
+        assert_eq!(format!("{:?}", before), format!("{:?}", after));
+        prog
+    }
+
+    #[test]
+    fn relocate_jumps() {
+        // Count down from 3, looping back over a fused comparison
+        let prog = assert_same_result(vec![
+            Instr::Imm(Value::Int(3)),
+            Instr::Dup,
+            Instr::Imm(Value::Int(0)),
+            Instr::EqInt,
+            Instr::IfNot,
+            Instr::Jump(2),
+            Instr::Ret,
+            Instr::Imm(Value::Int(1)),
+            Instr::SubInt,
+            Instr::Jump(-8),
+        ]);
+        assert_instrs(&prog, vec![
+            Instr::Imm(Value::Int(3)),
+            Instr::Dup,
+            Instr::JumpIfNotEqIntImm(0, 2),
+            Instr::Ret,
+            Instr::Imm(Value::Int(1)),
+            Instr::SubInt,
+            Instr::Jump(-5),
+        ]);
+    }
+
+    #[test]
+    fn relocate_funcs() {
+        let prog = optimized(vec![
+            Instr::Pop(1),
+            Instr::Pop(1),
+            Instr::MakeFunc(4, 0),
+            Instr::Imm(Value::List(vec![Value::Sum(0, Rc::new(Value::Func(Addr(7), Vec::new().into())))].into())),
+            Instr::Ret,
+            Instr::Pop(1),
+            // Both of these are function entry points, so nothing may be fused into them
+            Instr::Pop(1),
+            Instr::Ret,
+        ]);
+        assert_instrs(&prog, vec![
+            Instr::Pop(2),
+            Instr::MakeFunc(4, 0),
+            Instr::Imm(Value::List(vec![Value::Sum(0, Rc::new(Value::Func(Addr(6), Vec::new().into())))].into())),
+            Instr::Ret,
+            Instr::Pop(1),
+            Instr::Pop(1),
+            Instr::Ret,
+        ]);
+    }
+
+    #[test]
+    fn instr_after_if_not_is_never_fused() {
+        // The jump at 0x004 targets 0x002, which stops the `IfNot` being fused with the `Jump` after it. The `Jump(1)`
+        // would otherwise be removed, making the `IfNot` skip the `Ret` instead.
+        let prog = optimized(vec![
+            Instr::GetLocal(0),
+            Instr::IfNot,
+            Instr::Jump(1),
+            Instr::Ret,
+            Instr::Jump(-2),
+        ]);
+        assert_instrs(&prog, vec![
+            Instr::GetLocal(0),
+            Instr::IfNot,
+            Instr::Jump(1),
+            Instr::Ret,
+            Instr::Jump(-1),
+        ]);
+    }
+
+    fn constant_branch(x: bool, branch: Vec<Instr>) -> Vec<Instr> {
+        let mut instrs = vec![Instr::Imm(Value::Bool(x))];
+        instrs.extend(branch);
+        instrs.extend([Instr::Imm(Value::Int(1)), Instr::Ret, Instr::Imm(Value::Int(2)), Instr::Ret]);
+        instrs
+    }
+
+    #[test]
+    fn fold_constant_branches() {
+        for branch in [vec![Instr::IfNot, Instr::Jump(3)], vec![Instr::JumpIfNot(3)]] {
+            // Never jumps
+            let prog = assert_same_result(constant_branch(true, branch.clone()));
+            assert_instrs(&prog, vec![Instr::Imm(Value::Int(1)), Instr::Ret, Instr::Imm(Value::Int(2)), Instr::Ret]);
+
+            // Always jumps
+            let prog = assert_same_result(constant_branch(false, branch));
+            assert_instrs(&prog, vec![
+                Instr::Jump(3),
+                Instr::Imm(Value::Int(1)),
+                Instr::Ret,
+                Instr::Imm(Value::Int(2)),
+                Instr::Ret,
+            ]);
+        }
+    }
+
+    #[test]
+    fn fold_jumps_to_constant_branches() {
+        for x in [true, false] {
+            // Jumps to a branch on the constant, which goes to 0x002 if it is false and 0x005 if it is true
+            let prog = assert_same_result(vec![
+                Instr::Imm(Value::Bool(x)),
+                Instr::Jump(3),
+                Instr::Imm(Value::Int(1)),
+                Instr::Ret,
+                Instr::JumpIfNot(-2),
+                Instr::Imm(Value::Int(2)),
+                Instr::Ret,
+            ]);
+            // The constant is never pushed
+            assert!(!prog.instrs.iter().any(|instr| matches!(instr, Instr::Imm(Value::Bool(_)))), "{:?}", prog.instrs);
+        }
+    }
+}